@@ -0,0 +1,1231 @@
+//! Typed builders for every instruction of the program.
+//!
+//! Builders derive every PDA they can. Instructions that act on existing
+//! accounts take the decoded account instead of its address, because their
+//! remaining addresses depend on its state. For example, a contract's index
+//! pages are stored in the contract itself.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::{system_program, InstructionData, ToAccountMetas};
+use basic::{
+    ExerciseStyle, MarketConfig, OptionContract, OptionOffer, OptionStrategy, OptionType, Quote,
+    StrategyLeg, UserAccount, UserRole,
+};
+
+use crate::pda::*;
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: basic::ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Terms of a contract opened directly or proposed as an offer
+#[derive(Clone, Debug)]
+pub struct ContractTerms {
+    pub underlying_asset: String,
+    pub num_units: u64,
+    pub strike_price: u64,
+    pub expiration_date: i64,
+    pub option_type: OptionType,
+    pub exercise_style: ExerciseStyle,
+    pub premium: u64,
+    pub margin_requirement_bps: u16,
+    pub underlying_price_feed: Pubkey,
+    pub sol_price_feed: Pubkey,
+    /// SPL mint the contract is collateralized in, none for lamports
+    pub collateral_mint: Option<Pubkey>,
+    /// SPL mint delivered on exercise, none for cash settlement. Physically
+    /// settled contracts need a collateral mint to pay the strike in.
+    pub underlying_mint: Option<Pubkey>,
+}
+
+/// Terms of a standing quote posted by a seller
+#[derive(Clone, Debug)]
+pub struct QuoteTerms {
+    pub underlying_asset: String,
+    pub strike_price: u64,
+    pub expiration_date: i64,
+    pub option_type: OptionType,
+    pub exercise_style: ExerciseStyle,
+    pub premium_per_unit: u64,
+    pub max_units: u64,
+    pub margin_requirement_bps: u16,
+    pub valid_until: i64,
+    pub underlying_price_feed: Pubkey,
+    pub sol_price_feed: Pubkey,
+    /// SPL mint the contracts are collateralized in, none for lamports
+    pub collateral_mint: Option<Pubkey>,
+    /// SPL mint delivered on exercise, none for cash settlement
+    pub underlying_mint: Option<Pubkey>,
+}
+
+/// Terms of a multi-leg strategy opened between a buyer and a seller
+#[derive(Clone, Debug)]
+pub struct StrategyTerms {
+    pub underlying_asset: String,
+    pub expiration_date: i64,
+    /// Two to four legs, each held long or short by the buyer
+    pub legs: Vec<StrategyLeg>,
+    /// Net premium the buyer pays for every leg
+    pub premium: u64,
+    /// Margin on calls sold uncovered above the top strike
+    pub margin_requirement_bps: u16,
+    pub underlying_price_feed: Pubkey,
+    pub sol_price_feed: Pubkey,
+    /// SPL mint the strategy is collateralized in, none for lamports
+    pub collateral_mint: Option<Pubkey>,
+}
+
+// Accounts shared by every contract creation path, with rent paid by `payer`.
+// The treasury vault is only passed when a fee is charged on the contract, and
+// the underlying accounts only for physical calls, which lock the underlying.
+fn open_contract_accounts(
+    payer: &Pubkey,
+    buyer_account: &UserAccount,
+    seller_account: &UserAccount,
+    market_config: &MarketConfig,
+    collateral_mint: Option<Pubkey>,
+    underlying_mint: Option<Pubkey>,
+    option_type: OptionType,
+) -> basic::accounts::OpenContractAccounts {
+    let buyer = buyer_account.owner;
+    let seller = seller_account.owner;
+    let contract = contract_address(&buyer, &seller, buyer_account.contract_count);
+    let locked_underlying = underlying_mint.filter(|_| option_type == OptionType::Call);
+
+    basic::accounts::OpenContractAccounts {
+        payer: *payer,
+        market_config: market_config_address(),
+        contract,
+        collateral: collateral_address(&contract),
+        buyer_account: user_account_address(&buyer),
+        seller_account: user_account_address(&seller),
+        buyer_page: user_page_address(&buyer, buyer_account.tail_page()),
+        seller_page: user_page_address(&seller, seller_account.tail_page()),
+        buyer_escrow: escrow_address(&buyer),
+        seller_escrow: escrow_address(&seller),
+        collateral_mint,
+        buyer_token_escrow: collateral_mint.map(|mint| token_escrow_address(&buyer, &mint)),
+        seller_token_escrow: collateral_mint.map(|mint| token_escrow_address(&seller, &mint)),
+        collateral_vault: collateral_mint.map(|_| collateral_vault_address(&contract)),
+        treasury: treasury_address(),
+        treasury_vault: collateral_mint
+            .filter(|_| market_config.premium_fee_bps > 0)
+            .map(|mint| treasury_vault_address(&mint)),
+        underlying_mint,
+        seller_underlying_escrow: locked_underlying
+            .map(|mint| token_escrow_address(&seller, &mint)),
+        underlying_vault: locked_underlying.map(|_| underlying_vault_address(&contract)),
+        token_program: collateral_mint.map(|_| anchor_spl::token::ID),
+        system_program: system_program::ID,
+    }
+}
+
+// Token accounts used to pay out an open contract. The underlying accounts
+// are only set for physical calls, whose locked underlying returns to the seller.
+struct PayoutTokenAccounts {
+    collateral_vault: Option<Pubkey>,
+    treasury_vault: Option<Pubkey>,
+    buyer_token_escrow: Option<Pubkey>,
+    seller_token_escrow: Option<Pubkey>,
+    underlying_vault: Option<Pubkey>,
+    seller_underlying_escrow: Option<Pubkey>,
+    token_program: Option<Pubkey>,
+}
+
+impl PayoutTokenAccounts {
+    fn new(contract_address: &Pubkey, contract: &OptionContract) -> Self {
+        let mint = contract.collateral_mint;
+        let locked_underlying = contract.underlying_mint.filter(|_| contract.locks_underlying());
+        Self {
+            collateral_vault: mint.map(|_| collateral_vault_address(contract_address)),
+            treasury_vault: mint
+                .filter(|_| contract.settlement_fee_bps > 0)
+                .map(|mint| treasury_vault_address(&mint)),
+            buyer_token_escrow: mint.map(|mint| token_escrow_address(&contract.buyer, &mint)),
+            seller_token_escrow: mint.map(|mint| token_escrow_address(&contract.seller, &mint)),
+            underlying_vault: locked_underlying.map(|_| underlying_vault_address(contract_address)),
+            seller_underlying_escrow: locked_underlying
+                .map(|mint| token_escrow_address(&contract.seller, &mint)),
+            token_program: mint.map(|_| anchor_spl::token::ID),
+        }
+    }
+}
+
+/// Address of a decoded contract
+pub fn address_of_contract(contract: &OptionContract) -> Pubkey {
+    contract_address(&contract.original_buyer, &contract.seller, contract.contract_id)
+}
+
+/// Address of a decoded strategy
+pub fn address_of_strategy(strategy: &OptionStrategy) -> Pubkey {
+    strategy_address(&strategy.buyer, &strategy.seller, strategy.strategy_id)
+}
+
+pub fn initialize_user(user: &Pubkey) -> Instruction {
+    build(
+        basic::accounts::InitializeUser {
+            user: *user,
+            user_account: user_account_address(user),
+            system_program: system_program::ID,
+        },
+        basic::instruction::InitializeUser {},
+    )
+}
+
+/// Must be signed by the program's upgrade authority, which becomes the admin
+pub fn initialize_market_config(
+    admin: &Pubkey,
+    min_margin_bps: u16,
+    max_margin_bps: u16,
+    min_expiry_seconds: i64,
+    max_expiry_seconds: i64,
+) -> Instruction {
+    build(
+        basic::accounts::InitializeMarketConfig {
+            admin: *admin,
+            market_config: market_config_address(),
+            treasury: treasury_address(),
+            program: basic::ID,
+            program_data: program_data_address(),
+            system_program: system_program::ID,
+        },
+        basic::instruction::InitializeMarketConfig {
+            min_margin_bps,
+            max_margin_bps,
+            min_expiry_seconds,
+            max_expiry_seconds,
+        },
+    )
+}
+
+fn update_market_config_accounts(admin: &Pubkey) -> basic::accounts::UpdateMarketConfig {
+    basic::accounts::UpdateMarketConfig {
+        admin: *admin,
+        market_config: market_config_address(),
+    }
+}
+
+pub fn update_market_config(
+    admin: &Pubkey,
+    min_margin_bps: u16,
+    max_margin_bps: u16,
+    min_expiry_seconds: i64,
+    max_expiry_seconds: i64,
+) -> Instruction {
+    build(
+        update_market_config_accounts(admin),
+        basic::instruction::UpdateMarketConfig {
+            min_margin_bps,
+            max_margin_bps,
+            min_expiry_seconds,
+            max_expiry_seconds,
+        },
+    )
+}
+
+pub fn set_market_paused(admin: &Pubkey, paused: bool) -> Instruction {
+    build(
+        update_market_config_accounts(admin),
+        basic::instruction::SetMarketPaused { paused },
+    )
+}
+
+pub fn set_protocol_fees(
+    admin: &Pubkey,
+    premium_fee_bps: u16,
+    settlement_fee_bps: u16,
+) -> Instruction {
+    build(
+        update_market_config_accounts(admin),
+        basic::instruction::SetProtocolFees {
+            premium_fee_bps,
+            settlement_fee_bps,
+        },
+    )
+}
+
+/// Sets the keeper bounty charged on contracts opened afterwards
+pub fn set_crank_fee(admin: &Pubkey, crank_fee_bps: u16) -> Instruction {
+    build(
+        update_market_config_accounts(admin),
+        basic::instruction::SetCrankFee { crank_fee_bps },
+    )
+}
+
+/// Sets the publisher whose price feeds new contracts must settle against
+pub fn set_oracle_authority(admin: &Pubkey, oracle_authority: &Pubkey) -> Instruction {
+    build(
+        update_market_config_accounts(admin),
+        basic::instruction::SetOracleAuthority {
+            oracle_authority: *oracle_authority,
+        },
+    )
+}
+
+/// Registers `ticker` with strikes and prices in units of 10^price_expo USD
+pub fn register_underlying_asset(admin: &Pubkey, ticker: &str, price_expo: i32) -> Instruction {
+    build(
+        basic::accounts::RegisterUnderlyingAsset {
+            admin: *admin,
+            market_config: market_config_address(),
+            asset: underlying_asset_address(ticker),
+            system_program: system_program::ID,
+        },
+        basic::instruction::RegisterUnderlyingAsset {
+            ticker: ticker.to_string(),
+            price_expo,
+        },
+    )
+}
+
+pub fn initialize_treasury_vault(payer: &Pubkey, mint: &Pubkey) -> Instruction {
+    build(
+        basic::accounts::InitializeTreasuryVault {
+            payer: *payer,
+            treasury: treasury_address(),
+            mint: *mint,
+            treasury_vault: treasury_vault_address(mint),
+            token_program: anchor_spl::token::ID,
+            system_program: system_program::ID,
+        },
+        basic::instruction::InitializeTreasuryVault {},
+    )
+}
+
+/// Moves lamport fees out of the treasury to `destination`
+pub fn collect_fees(admin: &Pubkey, destination: &Pubkey, amount: u64) -> Instruction {
+    build(
+        basic::accounts::CollectFees {
+            admin: *admin,
+            market_config: market_config_address(),
+            treasury: treasury_address(),
+            destination: *destination,
+            treasury_vault: None,
+            destination_token_account: None,
+            token_program: None,
+        },
+        basic::instruction::CollectFees { amount },
+    )
+}
+
+/// Moves token fees out of the treasury vault for `mint`
+pub fn collect_token_fees(
+    admin: &Pubkey,
+    mint: &Pubkey,
+    destination_token_account: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    build(
+        basic::accounts::CollectFees {
+            admin: *admin,
+            market_config: market_config_address(),
+            treasury: treasury_address(),
+            destination: *admin,
+            treasury_vault: Some(treasury_vault_address(mint)),
+            destination_token_account: Some(*destination_token_account),
+            token_program: Some(anchor_spl::token::ID),
+        },
+        basic::instruction::CollectFees { amount },
+    )
+}
+
+pub fn initialize_insurance_fund(payer: &Pubkey) -> Instruction {
+    build(
+        basic::accounts::InitializeInsuranceFund {
+            payer: *payer,
+            insurance_fund: insurance_fund_address(),
+            system_program: system_program::ID,
+        },
+        basic::instruction::InitializeInsuranceFund {},
+    )
+}
+
+pub fn initialize_insurance_vault(payer: &Pubkey, mint: &Pubkey) -> Instruction {
+    build(
+        basic::accounts::InitializeInsuranceVault {
+            payer: *payer,
+            insurance_fund: insurance_fund_address(),
+            mint: *mint,
+            insurance_vault: insurance_vault_address(mint),
+            token_program: anchor_spl::token::ID,
+            system_program: system_program::ID,
+        },
+        basic::instruction::InitializeInsuranceVault {},
+    )
+}
+
+/// Moves lamport fees from the treasury into the insurance fund
+pub fn fund_insurance(admin: &Pubkey, amount: u64) -> Instruction {
+    build(
+        basic::accounts::FundInsurance {
+            admin: *admin,
+            market_config: market_config_address(),
+            treasury: treasury_address(),
+            insurance_fund: insurance_fund_address(),
+            treasury_vault: None,
+            insurance_vault: None,
+            token_program: None,
+        },
+        basic::instruction::FundInsurance { amount },
+    )
+}
+
+/// Moves token fees for `mint` from the treasury vault into the insurance vault
+pub fn fund_insurance_tokens(admin: &Pubkey, mint: &Pubkey, amount: u64) -> Instruction {
+    build(
+        basic::accounts::FundInsurance {
+            admin: *admin,
+            market_config: market_config_address(),
+            treasury: treasury_address(),
+            insurance_fund: insurance_fund_address(),
+            treasury_vault: Some(treasury_vault_address(mint)),
+            insurance_vault: Some(insurance_vault_address(mint)),
+            token_program: Some(anchor_spl::token::ID),
+        },
+        basic::instruction::FundInsurance { amount },
+    )
+}
+
+pub fn propose_admin(admin: &Pubkey, new_admin: &Pubkey) -> Instruction {
+    build(
+        update_market_config_accounts(admin),
+        basic::instruction::ProposeAdmin {
+            new_admin: *new_admin,
+        },
+    )
+}
+
+pub fn accept_admin(new_admin: &Pubkey) -> Instruction {
+    build(
+        basic::accounts::AcceptAdmin {
+            new_admin: *new_admin,
+            market_config: market_config_address(),
+        },
+        basic::instruction::AcceptAdmin {},
+    )
+}
+
+pub fn initialize_escrow(user: &Pubkey) -> Instruction {
+    build(
+        basic::accounts::InitializeEscrow {
+            user: *user,
+            user_escrow: escrow_address(user),
+            system_program: system_program::ID,
+        },
+        basic::instruction::InitializeEscrow {},
+    )
+}
+
+pub fn deposit(user: &Pubkey, amount: u64) -> Instruction {
+    build(
+        basic::accounts::Deposit {
+            user: *user,
+            user_escrow: escrow_address(user),
+            system_program: system_program::ID,
+        },
+        basic::instruction::Deposit { amount },
+    )
+}
+
+pub fn initialize_token_escrow(user: &Pubkey, mint: &Pubkey) -> Instruction {
+    build(
+        basic::accounts::InitializeTokenEscrow {
+            user: *user,
+            user_escrow: escrow_address(user),
+            mint: *mint,
+            token_escrow: token_escrow_address(user, mint),
+            token_program: anchor_spl::token::ID,
+            system_program: system_program::ID,
+        },
+        basic::instruction::InitializeTokenEscrow {},
+    )
+}
+
+pub fn deposit_tokens(
+    user: &Pubkey,
+    mint: &Pubkey,
+    user_token_account: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    build(
+        basic::accounts::DepositTokens {
+            user: *user,
+            user_token_account: *user_token_account,
+            token_escrow: token_escrow_address(user, mint),
+            token_program: anchor_spl::token::ID,
+        },
+        basic::instruction::DepositTokens { amount },
+    )
+}
+
+/// Opens a contract signed by both parties. The contract address and index
+/// pages come from the parties' user accounts, and the market config decides
+/// whether a token contract needs the treasury vault.
+pub fn create_contract(
+    buyer_account: &UserAccount,
+    seller_account: &UserAccount,
+    market_config: &MarketConfig,
+    terms: &ContractTerms,
+) -> Instruction {
+    let buyer = buyer_account.owner;
+
+    build(
+        basic::accounts::CreateContract {
+            buyer,
+            seller: seller_account.owner,
+            asset: underlying_asset_address(&terms.underlying_asset),
+            underlying_price_feed: terms.underlying_price_feed,
+            sol_price_feed: terms.sol_price_feed,
+            open: open_contract_accounts(
+                &buyer,
+                buyer_account,
+                seller_account,
+                market_config,
+                terms.collateral_mint,
+                terms.underlying_mint,
+                terms.option_type,
+            ),
+        },
+        basic::instruction::CreateContract {
+            underlying_asset: terms.underlying_asset.clone(),
+            num_units: terms.num_units,
+            strike_price: terms.strike_price,
+            expiration_date: terms.expiration_date,
+            option_type: terms.option_type,
+            exercise_style: terms.exercise_style,
+            premium: terms.premium,
+            margin_requirement_bps: terms.margin_requirement_bps,
+        },
+    )
+}
+
+/// Offers `terms` to `seller`, who can accept them until `valid_until`
+pub fn propose_contract(
+    buyer: &Pubkey,
+    seller: &Pubkey,
+    offer_id: u64,
+    terms: &ContractTerms,
+    valid_until: i64,
+) -> Instruction {
+    build(
+        basic::accounts::ProposeContract {
+            buyer: *buyer,
+            seller: *seller,
+            offer: offer_address(buyer, seller, offer_id),
+            buyer_account: user_account_address(buyer),
+            seller_account: user_account_address(seller),
+            asset: underlying_asset_address(&terms.underlying_asset),
+            underlying_price_feed: terms.underlying_price_feed,
+            sol_price_feed: terms.sol_price_feed,
+            collateral_mint: terms.collateral_mint,
+            underlying_mint: terms.underlying_mint,
+            system_program: system_program::ID,
+        },
+        basic::instruction::ProposeContract {
+            offer_id,
+            underlying_asset: terms.underlying_asset.clone(),
+            num_units: terms.num_units,
+            strike_price: terms.strike_price,
+            expiration_date: terms.expiration_date,
+            option_type: terms.option_type,
+            exercise_style: terms.exercise_style,
+            premium: terms.premium,
+            margin_requirement_bps: terms.margin_requirement_bps,
+            valid_until,
+        },
+    )
+}
+
+/// Opens a contract from `offer`, signed by its seller
+pub fn accept_contract(
+    offer: &OptionOffer,
+    buyer_account: &UserAccount,
+    seller_account: &UserAccount,
+    market_config: &MarketConfig,
+) -> Instruction {
+    let buyer = offer.buyer;
+    let seller = offer.seller;
+
+    build(
+        basic::accounts::AcceptContract {
+            seller,
+            buyer,
+            offer: offer_address(&buyer, &seller, offer.offer_id),
+            underlying_price_feed: offer.underlying_price_feed,
+            sol_price_feed: offer.sol_price_feed,
+            open: open_contract_accounts(
+                &seller,
+                buyer_account,
+                seller_account,
+                market_config,
+                offer.collateral_mint,
+                offer.underlying_mint,
+                offer.option_type,
+            ),
+        },
+        basic::instruction::AcceptContract {},
+    )
+}
+
+pub fn reject_offer(offer: &OptionOffer) -> Instruction {
+    build(
+        basic::accounts::RejectOffer {
+            seller: offer.seller,
+            buyer: offer.buyer,
+            offer: offer_address(&offer.buyer, &offer.seller, offer.offer_id),
+        },
+        basic::instruction::RejectOffer {},
+    )
+}
+
+pub fn cancel_offer(offer: &OptionOffer) -> Instruction {
+    build(
+        basic::accounts::CancelOffer {
+            buyer: offer.buyer,
+            offer: offer_address(&offer.buyer, &offer.seller, offer.offer_id),
+        },
+        basic::instruction::CancelOffer {},
+    )
+}
+
+pub fn post_quote(seller: &Pubkey, quote_id: u64, terms: &QuoteTerms) -> Instruction {
+    build(
+        basic::accounts::PostQuote {
+            seller: *seller,
+            quote: quote_address(seller, quote_id),
+            seller_account: user_account_address(seller),
+            asset: underlying_asset_address(&terms.underlying_asset),
+            underlying_price_feed: terms.underlying_price_feed,
+            sol_price_feed: terms.sol_price_feed,
+            collateral_mint: terms.collateral_mint,
+            underlying_mint: terms.underlying_mint,
+            system_program: system_program::ID,
+        },
+        basic::instruction::PostQuote {
+            quote_id,
+            underlying_asset: terms.underlying_asset.clone(),
+            strike_price: terms.strike_price,
+            expiration_date: terms.expiration_date,
+            option_type: terms.option_type,
+            exercise_style: terms.exercise_style,
+            premium_per_unit: terms.premium_per_unit,
+            max_units: terms.max_units,
+            margin_requirement_bps: terms.margin_requirement_bps,
+            valid_until: terms.valid_until,
+        },
+    )
+}
+
+/// Writes a contract for `units` of `quote`, signed by the buyer
+pub fn fill_quote(
+    quote: &Quote,
+    buyer_account: &UserAccount,
+    seller_account: &UserAccount,
+    market_config: &MarketConfig,
+    units: u64,
+) -> Instruction {
+    let buyer = buyer_account.owner;
+    let seller = quote.seller;
+
+    build(
+        basic::accounts::FillQuote {
+            buyer,
+            seller,
+            quote: quote_address(&seller, quote.quote_id),
+            underlying_price_feed: quote.underlying_price_feed,
+            sol_price_feed: quote.sol_price_feed,
+            open: open_contract_accounts(
+                &buyer,
+                buyer_account,
+                seller_account,
+                market_config,
+                quote.collateral_mint,
+                quote.underlying_mint,
+                quote.option_type,
+            ),
+        },
+        basic::instruction::FillQuote { units },
+    )
+}
+
+pub fn cancel_quote(quote: &Quote) -> Instruction {
+    build(
+        basic::accounts::CancelQuote {
+            seller: quote.seller,
+            quote: quote_address(&quote.seller, quote.quote_id),
+        },
+        basic::instruction::CancelQuote {},
+    )
+}
+
+pub fn close_expired_quote(caller: &Pubkey, quote: &Quote) -> Instruction {
+    build(
+        basic::accounts::CloseExpiredQuote {
+            caller: *caller,
+            seller: quote.seller,
+            quote: quote_address(&quote.seller, quote.quote_id),
+        },
+        basic::instruction::CloseExpiredQuote {},
+    )
+}
+
+/// Exercises every open unit of `contract`, signed by its current buyer
+pub fn exercise(contract: &OptionContract) -> Instruction {
+    let address = address_of_contract(contract);
+    let token = PayoutTokenAccounts::new(&address, contract);
+
+    build(
+        basic::accounts::Exercise {
+            buyer: contract.buyer,
+            market_config: market_config_address(),
+            contract: address,
+            buyer_page: user_page_address(&contract.buyer, contract.buyer_page),
+            seller_page: user_page_address(&contract.seller, contract.seller_page),
+            collateral: collateral_address(&address),
+            seller_escrow: escrow_address(&contract.seller),
+            collateral_vault: token.collateral_vault,
+            seller_token_escrow: token.seller_token_escrow,
+            token_program: token.token_program,
+            underlying_price_feed: contract.underlying_price_feed,
+            sol_price_feed: contract.sol_price_feed,
+        },
+        basic::instruction::Exercise {},
+    )
+}
+
+/// Exercises and settles `units` of `contract`, signed by its current buyer
+pub fn exercise_units(contract: &OptionContract, units: u64) -> Instruction {
+    let address = address_of_contract(contract);
+    let token = PayoutTokenAccounts::new(&address, contract);
+
+    build(
+        basic::accounts::ExerciseUnits {
+            buyer: contract.buyer,
+            market_config: market_config_address(),
+            contract: address,
+            collateral: collateral_address(&address),
+            buyer_page: user_page_address(&contract.buyer, contract.buyer_page),
+            seller_page: user_page_address(&contract.seller, contract.seller_page),
+            buyer_escrow: escrow_address(&contract.buyer),
+            seller_escrow: escrow_address(&contract.seller),
+            collateral_vault: token.collateral_vault,
+            treasury: treasury_address(),
+            treasury_vault: token.treasury_vault,
+            buyer_token_escrow: token.buyer_token_escrow,
+            seller_token_escrow: token.seller_token_escrow,
+            token_program: token.token_program,
+            underlying_price_feed: contract.underlying_price_feed,
+            sol_price_feed: contract.sol_price_feed,
+            system_program: system_program::ID,
+        },
+        basic::instruction::ExerciseUnits { units },
+    )
+}
+
+/// Delivers every unit of a physically settled `contract`, signed by its
+/// current buyer. Both parties need token escrows for the collateral mint,
+/// the buyer one for the underlying mint, and the seller of a put one too.
+pub fn exercise_physical(contract: &OptionContract) -> Instruction {
+    let address = address_of_contract(contract);
+    let collateral_mint = contract.collateral_mint.unwrap_or_default();
+    let underlying_mint = contract.underlying_mint.unwrap_or_default();
+    let is_call = contract.option_type == OptionType::Call;
+
+    build(
+        basic::accounts::ExercisePhysical {
+            buyer: contract.buyer,
+            market_config: market_config_address(),
+            contract: address,
+            collateral: collateral_address(&address),
+            buyer_page: user_page_address(&contract.buyer, contract.buyer_page),
+            seller_page: user_page_address(&contract.seller, contract.seller_page),
+            buyer_escrow: escrow_address(&contract.buyer),
+            seller_escrow: escrow_address(&contract.seller),
+            collateral_vault: collateral_vault_address(&address),
+            buyer_token_escrow: token_escrow_address(&contract.buyer, &collateral_mint),
+            seller_token_escrow: token_escrow_address(&contract.seller, &collateral_mint),
+            underlying_vault: is_call.then(|| underlying_vault_address(&address)),
+            buyer_underlying_escrow: token_escrow_address(&contract.buyer, &underlying_mint),
+            seller_underlying_escrow: (!is_call)
+                .then(|| token_escrow_address(&contract.seller, &underlying_mint)),
+            treasury: treasury_address(),
+            token_program: anchor_spl::token::ID,
+            system_program: system_program::ID,
+        },
+        basic::instruction::ExercisePhysical {},
+    )
+}
+
+/// Settles an exercised contract without the insurance fund. Only the buyer
+/// can settle this way if the seller defaults.
+pub fn settle(caller: &Pubkey, contract: &OptionContract) -> Instruction {
+    settle_contract(caller, contract, false)
+}
+
+/// Settles an exercised contract, drawing any default from the insurance fund
+pub fn settle_with_insurance(caller: &Pubkey, contract: &OptionContract) -> Instruction {
+    settle_contract(caller, contract, true)
+}
+
+fn settle_contract(caller: &Pubkey, contract: &OptionContract, insured: bool) -> Instruction {
+    let address = address_of_contract(contract);
+    let token = PayoutTokenAccounts::new(&address, contract);
+
+    build(
+        basic::accounts::Settle {
+            caller: *caller,
+            market_config: market_config_address(),
+            contract: address,
+            collateral: collateral_address(&address),
+            buyer_page: user_page_address(&contract.buyer, contract.buyer_page),
+            seller_page: user_page_address(&contract.seller, contract.seller_page),
+            buyer_escrow: escrow_address(&contract.buyer),
+            seller_escrow: escrow_address(&contract.seller),
+            collateral_vault: token.collateral_vault,
+            treasury: treasury_address(),
+            treasury_vault: token.treasury_vault,
+            buyer_token_escrow: token.buyer_token_escrow,
+            seller_token_escrow: token.seller_token_escrow,
+            insurance_fund: insured.then(insurance_fund_address),
+            insurance_vault: contract
+                .collateral_mint
+                .filter(|_| insured)
+                .map(|mint| insurance_vault_address(&mint)),
+            token_program: token.token_program,
+            system_program: system_program::ID,
+        },
+        basic::instruction::Settle {},
+    )
+}
+
+pub fn expire(caller: &Pubkey, contract: &OptionContract) -> Instruction {
+    let address = address_of_contract(contract);
+    let token = PayoutTokenAccounts::new(&address, contract);
+
+    build(
+        basic::accounts::Expire {
+            caller: *caller,
+            market_config: market_config_address(),
+            contract: address,
+            collateral: collateral_address(&address),
+            buyer_page: user_page_address(&contract.buyer, contract.buyer_page),
+            seller_page: user_page_address(&contract.seller, contract.seller_page),
+            seller_escrow: escrow_address(&contract.seller),
+            collateral_vault: token.collateral_vault,
+            seller_token_escrow: token.seller_token_escrow,
+            underlying_vault: token.underlying_vault,
+            seller_underlying_escrow: token.seller_underlying_escrow,
+            token_program: token.token_program,
+            underlying_price_feed: contract.underlying_price_feed,
+            sol_price_feed: contract.sol_price_feed,
+        },
+        basic::instruction::Expire {},
+    )
+}
+
+/// Exercises or expires a cash settled `contract` past its expiry, paying
+/// `keeper` the bounty in lamports, or into `keeper_token_account` for token
+/// collateral
+pub fn crank_expiry(
+    keeper: &Pubkey,
+    contract: &OptionContract,
+    keeper_token_account: Option<Pubkey>,
+) -> Instruction {
+    let address = address_of_contract(contract);
+    let token = PayoutTokenAccounts::new(&address, contract);
+
+    build(
+        basic::accounts::CrankExpiry {
+            caller: *keeper,
+            market_config: market_config_address(),
+            contract: address,
+            collateral: collateral_address(&address),
+            buyer_page: user_page_address(&contract.buyer, contract.buyer_page),
+            seller_page: user_page_address(&contract.seller, contract.seller_page),
+            buyer_escrow: escrow_address(&contract.buyer),
+            seller_escrow: escrow_address(&contract.seller),
+            collateral_vault: token.collateral_vault,
+            treasury: treasury_address(),
+            treasury_vault: token.treasury_vault,
+            buyer_token_escrow: token.buyer_token_escrow,
+            seller_token_escrow: token.seller_token_escrow,
+            caller_token_account: keeper_token_account,
+            token_program: token.token_program,
+            underlying_price_feed: contract.underlying_price_feed,
+            sol_price_feed: contract.sol_price_feed,
+            system_program: system_program::ID,
+        },
+        basic::instruction::CrankExpiry {},
+    )
+}
+
+pub fn mark_to_market(caller: &Pubkey, contract: &OptionContract) -> Instruction {
+    let address = address_of_contract(contract);
+
+    build(
+        basic::accounts::MarkToMarket {
+            caller: *caller,
+            market_config: market_config_address(),
+            contract: address,
+            collateral: collateral_address(&address),
+            collateral_vault: contract
+                .collateral_mint
+                .map(|_| collateral_vault_address(&address)),
+            underlying_price_feed: contract.underlying_price_feed,
+            sol_price_feed: contract.sol_price_feed,
+        },
+        basic::instruction::MarkToMarket {},
+    )
+}
+
+/// Moves `amount` of the seller's escrow into the contract's margin
+pub fn top_up_margin(contract: &OptionContract, amount: u64) -> Instruction {
+    let address = address_of_contract(contract);
+    let token = PayoutTokenAccounts::new(&address, contract);
+
+    build(
+        basic::accounts::TopUpMargin {
+            seller: contract.seller,
+            contract: address,
+            collateral: collateral_address(&address),
+            seller_escrow: escrow_address(&contract.seller),
+            collateral_vault: token.collateral_vault,
+            seller_token_escrow: token.seller_token_escrow,
+            token_program: token.token_program,
+            system_program: system_program::ID,
+        },
+        basic::instruction::TopUpMargin { amount },
+    )
+}
+
+/// Liquidates `contract` after a missed margin call without the insurance
+/// fund. Only the buyer can liquidate this way if the seller defaults. Token
+/// contracts pay the liquidation bonus to `liquidator_token_account`, which
+/// must hold the contract's collateral mint.
+pub fn liquidate(
+    liquidator: &Pubkey,
+    contract: &OptionContract,
+    liquidator_token_account: Option<Pubkey>,
+) -> Instruction {
+    liquidate_contract(liquidator, contract, liquidator_token_account, false)
+}
+
+/// Liquidates `contract` after a missed margin call, drawing any default from
+/// the insurance fund
+pub fn liquidate_with_insurance(
+    liquidator: &Pubkey,
+    contract: &OptionContract,
+    liquidator_token_account: Option<Pubkey>,
+) -> Instruction {
+    liquidate_contract(liquidator, contract, liquidator_token_account, true)
+}
+
+fn liquidate_contract(
+    liquidator: &Pubkey,
+    contract: &OptionContract,
+    liquidator_token_account: Option<Pubkey>,
+    insured: bool,
+) -> Instruction {
+    let address = address_of_contract(contract);
+    let token = PayoutTokenAccounts::new(&address, contract);
+
+    build(
+        basic::accounts::Liquidate {
+            liquidator: *liquidator,
+            market_config: market_config_address(),
+            contract: address,
+            collateral: collateral_address(&address),
+            buyer_page: user_page_address(&contract.buyer, contract.buyer_page),
+            seller_page: user_page_address(&contract.seller, contract.seller_page),
+            buyer_escrow: escrow_address(&contract.buyer),
+            seller_escrow: escrow_address(&contract.seller),
+            collateral_vault: token.collateral_vault,
+            treasury: treasury_address(),
+            treasury_vault: token.treasury_vault,
+            buyer_token_escrow: token.buyer_token_escrow,
+            seller_token_escrow: token.seller_token_escrow,
+            liquidator_token_account,
+            insurance_fund: insured.then(insurance_fund_address),
+            insurance_vault: contract
+                .collateral_mint
+                .filter(|_| insured)
+                .map(|mint| insurance_vault_address(&mint)),
+            token_program: token.token_program,
+            underlying_price_feed: contract.underlying_price_feed,
+            sol_price_feed: contract.sol_price_feed,
+            system_program: system_program::ID,
+        },
+        basic::instruction::Liquidate {},
+    )
+}
+
+pub fn close_contract(caller: &Pubkey, contract: &OptionContract) -> Instruction {
+    let address = address_of_contract(contract);
+    let token = PayoutTokenAccounts::new(&address, contract);
+
+    build(
+        basic::accounts::CloseContract {
+            caller: *caller,
+            rent_payer: contract.rent_payer,
+            contract: address,
+            collateral: collateral_address(&address),
+            buyer_page: user_page_address(&contract.buyer, contract.buyer_page),
+            seller_page: user_page_address(&contract.seller, contract.seller_page),
+            collateral_vault: token.collateral_vault,
+            underlying_vault: token.underlying_vault,
+            token_program: token.token_program,
+        },
+        basic::instruction::CloseContract {},
+    )
+}
+
+/// Assigns the long side of `contract` to the owner of `new_holder_account`,
+/// signed by both the current and the new holder
+pub fn transfer_position(
+    contract: &OptionContract,
+    new_holder_account: &UserAccount,
+    sale_price: u64,
+) -> Instruction {
+    let address = address_of_contract(contract);
+    let holder = contract.buyer;
+    let new_holder = new_holder_account.owner;
+    let mint = contract.collateral_mint;
+
+    build(
+        basic::accounts::TransferPosition {
+            holder,
+            new_holder,
+            market_config: market_config_address(),
+            contract: address,
+            holder_page: user_page_address(&holder, contract.buyer_page),
+            new_holder_account: user_account_address(&new_holder),
+            new_holder_page: user_page_address(&new_holder, new_holder_account.tail_page()),
+            holder_escrow: escrow_address(&holder),
+            new_holder_escrow: escrow_address(&new_holder),
+            holder_token_escrow: mint.map(|mint| token_escrow_address(&holder, &mint)),
+            new_holder_token_escrow: mint.map(|mint| token_escrow_address(&new_holder, &mint)),
+            token_program: mint.map(|_| anchor_spl::token::ID),
+            system_program: system_program::ID,
+        },
+        basic::instruction::TransferPosition { sale_price },
+    )
+}
+
+/// Unwinds an active contract, signed by both parties. `payment` is paid by
+/// `paid_by` to the other side.
+pub fn terminate_contract(
+    contract: &OptionContract,
+    payment: u64,
+    paid_by: UserRole,
+) -> Instruction {
+    let address = address_of_contract(contract);
+    let token = PayoutTokenAccounts::new(&address, contract);
+
+    build(
+        basic::accounts::TerminateContract {
+            buyer: contract.buyer,
+            seller: contract.seller,
+            market_config: market_config_address(),
+            contract: address,
+            collateral: collateral_address(&address),
+            buyer_page: user_page_address(&contract.buyer, contract.buyer_page),
+            seller_page: user_page_address(&contract.seller, contract.seller_page),
+            buyer_escrow: escrow_address(&contract.buyer),
+            seller_escrow: escrow_address(&contract.seller),
+            collateral_vault: token.collateral_vault,
+            buyer_token_escrow: token.buyer_token_escrow,
+            seller_token_escrow: token.seller_token_escrow,
+            underlying_vault: token.underlying_vault,
+            seller_underlying_escrow: token.seller_underlying_escrow,
+            treasury: treasury_address(),
+            token_program: token.token_program,
+            system_program: system_program::ID,
+        },
+        basic::instruction::TerminateContract { payment, paid_by },
+    )
+}
+
+/// Opens every leg of a strategy, signed by both parties
+pub fn create_strategy(
+    buyer_account: &UserAccount,
+    seller_account: &UserAccount,
+    market_config: &MarketConfig,
+    terms: &StrategyTerms,
+) -> Instruction {
+    let buyer = buyer_account.owner;
+    let seller = seller_account.owner;
+    let strategy = strategy_address(&buyer, &seller, buyer_account.contract_count);
+    let mint = terms.collateral_mint;
+
+    build(
+        basic::accounts::CreateStrategy {
+            buyer,
+            seller,
+            market_config: market_config_address(),
+            asset: underlying_asset_address(&terms.underlying_asset),
+            strategy,
+            collateral: collateral_address(&strategy),
+            buyer_account: user_account_address(&buyer),
+            seller_account: user_account_address(&seller),
+            buyer_escrow: escrow_address(&buyer),
+            seller_escrow: escrow_address(&seller),
+            collateral_mint: mint,
+            buyer_token_escrow: mint.map(|mint| token_escrow_address(&buyer, &mint)),
+            seller_token_escrow: mint.map(|mint| token_escrow_address(&seller, &mint)),
+            collateral_vault: mint.map(|_| collateral_vault_address(&strategy)),
+            treasury: treasury_address(),
+            treasury_vault: mint
+                .filter(|_| market_config.premium_fee_bps > 0)
+                .map(|mint| treasury_vault_address(&mint)),
+            token_program: mint.map(|_| anchor_spl::token::ID),
+            underlying_price_feed: terms.underlying_price_feed,
+            sol_price_feed: terms.sol_price_feed,
+            system_program: system_program::ID,
+        },
+        basic::instruction::CreateStrategy {
+            underlying_asset: terms.underlying_asset.clone(),
+            expiration_date: terms.expiration_date,
+            legs: terms.legs.clone(),
+            premium: terms.premium,
+            margin_requirement_bps: terms.margin_requirement_bps,
+        },
+    )
+}
+
+/// Settles every leg of `strategy`, signed by its buyer or seller. Only the
+/// side owed the payoff can settle this way if the other side defaults.
+pub fn exercise_strategy(caller: &Pubkey, strategy: &OptionStrategy) -> Instruction {
+    build(
+        settle_strategy_accounts(caller, strategy, false),
+        basic::instruction::ExerciseStrategy {},
+    )
+}
+
+/// Settles every leg of `strategy`, drawing any default from the insurance fund
+pub fn exercise_strategy_with_insurance(
+    caller: &Pubkey,
+    strategy: &OptionStrategy,
+) -> Instruction {
+    build(
+        settle_strategy_accounts(caller, strategy, true),
+        basic::instruction::ExerciseStrategy {},
+    )
+}
+
+/// Expires `strategy` after the exercise window, settling it if still in the money
+pub fn expire_strategy(caller: &Pubkey, strategy: &OptionStrategy) -> Instruction {
+    build(
+        settle_strategy_accounts(caller, strategy, false),
+        basic::instruction::ExpireStrategy {},
+    )
+}
+
+fn settle_strategy_accounts(
+    caller: &Pubkey,
+    strategy: &OptionStrategy,
+    insured: bool,
+) -> basic::accounts::SettleStrategy {
+    let address = address_of_strategy(strategy);
+    let mint = strategy.collateral_mint;
+
+    basic::accounts::SettleStrategy {
+        caller: *caller,
+        market_config: market_config_address(),
+        strategy: address,
+        collateral: collateral_address(&address),
+        buyer_escrow: escrow_address(&strategy.buyer),
+        seller_escrow: escrow_address(&strategy.seller),
+        collateral_vault: mint.map(|_| collateral_vault_address(&address)),
+        treasury: treasury_address(),
+        treasury_vault: mint
+            .filter(|_| strategy.settlement_fee_bps > 0)
+            .map(|mint| treasury_vault_address(&mint)),
+        buyer_token_escrow: mint.map(|mint| token_escrow_address(&strategy.buyer, &mint)),
+        seller_token_escrow: mint.map(|mint| token_escrow_address(&strategy.seller, &mint)),
+        insurance_fund: insured.then(insurance_fund_address),
+        insurance_vault: mint
+            .filter(|_| insured)
+            .map(|mint| insurance_vault_address(&mint)),
+        token_program: mint.map(|_| anchor_spl::token::ID),
+        underlying_price_feed: strategy.underlying_price_feed,
+        sol_price_feed: strategy.sol_price_feed,
+        system_program: system_program::ID,
+    }
+}
+
+pub fn close_strategy(caller: &Pubkey, strategy: &OptionStrategy) -> Instruction {
+    let address = address_of_strategy(strategy);
+    let mint = strategy.collateral_mint;
+
+    build(
+        basic::accounts::CloseStrategy {
+            caller: *caller,
+            rent_payer: strategy.rent_payer,
+            strategy: address,
+            collateral: collateral_address(&address),
+            collateral_vault: mint.map(|_| collateral_vault_address(&address)),
+            token_program: mint.map(|_| anchor_spl::token::ID),
+        },
+        basic::instruction::CloseStrategy {},
+    )
+}
+
+pub fn withdraw(user: &Pubkey, amount: u64) -> Instruction {
+    build(
+        basic::accounts::Withdraw {
+            user: *user,
+            user_escrow: escrow_address(user),
+            system_program: system_program::ID,
+        },
+        basic::instruction::Withdraw { amount },
+    )
+}
+
+pub fn withdraw_tokens(
+    user: &Pubkey,
+    mint: &Pubkey,
+    user_token_account: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    build(
+        basic::accounts::WithdrawTokens {
+            user: *user,
+            user_escrow: escrow_address(user),
+            token_escrow: token_escrow_address(user, mint),
+            user_token_account: *user_token_account,
+            token_program: anchor_spl::token::ID,
+        },
+        basic::instruction::WithdrawTokens { amount },
+    )
+}
+
+/// Sets the offset the test clock adds to the cluster time. Instructions only
+/// read it when [`with_test_clock`] adds the clock to them.
+#[cfg(feature = "test-clock")]
+pub fn warp_clock(payer: &Pubkey, offset_seconds: i64) -> Instruction {
+    build(
+        basic::accounts::WarpClock {
+            payer: *payer,
+            test_clock: test_clock_address(),
+            system_program: system_program::ID,
+        },
+        basic::instruction::WarpClock { offset_seconds },
+    )
+}
+
+/// Appends the test clock to an instruction's remaining accounts
+#[cfg(feature = "test-clock")]
+pub fn with_test_clock(mut instruction: Instruction) -> Instruction {
+    instruction
+        .accounts
+        .push(anchor_lang::solana_program::instruction::AccountMeta::new_readonly(
+            test_clock_address(),
+            false,
+        ));
+    instruction
+}
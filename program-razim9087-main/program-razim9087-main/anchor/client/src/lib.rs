@@ -0,0 +1,17 @@
+//! Rust client for the `basic` options program: PDA helpers, typed
+//! instruction builders, account decoders and RPC reads.
+//!
+//! Enable the `test-clock` feature to build `warp_clock` instructions against
+//! a program compiled with the same feature.
+
+pub mod instruction;
+pub mod pda;
+pub mod rpc;
+pub mod state;
+
+pub use basic::{
+    ContractStatus, ExerciseStyle, LegSide, MarketConfig, OptionContract, OptionOffer,
+    OptionStrategy, OptionType, Quote, SettlementKind, StrategyLeg, UnderlyingAsset, UserAccount,
+    UserContract, UserContractPage, UserRole, ID as PROGRAM_ID,
+};
+pub use rpc::{fetch_account, fetch_user_contracts, ClientError};
@@ -0,0 +1,133 @@
+//! Addresses of the program's PDAs, derived with the same seeds as the
+//! account constraints in `programs/basic`.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::bpf_loader_upgradeable;
+
+fn find(seeds: &[&[u8]]) -> Pubkey {
+    Pubkey::find_program_address(seeds, &basic::ID).0
+}
+
+/// `[b"user", owner]`
+pub fn user_account_address(owner: &Pubkey) -> Pubkey {
+    find(&[b"user", owner.as_ref()])
+}
+
+/// `[b"escrow", owner]`
+pub fn escrow_address(owner: &Pubkey) -> Pubkey {
+    find(&[b"escrow", owner.as_ref()])
+}
+
+/// `[b"token_escrow", owner, mint]`, a token account owned by the user's escrow
+pub fn token_escrow_address(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    find(&[b"token_escrow", owner.as_ref(), mint.as_ref()])
+}
+
+/// `[b"contract", original_buyer, seller, contract_id]`. The contract keeps its
+/// address when the long side is transferred, so pass the buyer at creation.
+pub fn contract_address(original_buyer: &Pubkey, seller: &Pubkey, contract_id: u64) -> Pubkey {
+    find(&[
+        b"contract",
+        original_buyer.as_ref(),
+        seller.as_ref(),
+        &contract_id.to_le_bytes(),
+    ])
+}
+
+/// `[b"strategy", buyer, seller, strategy_id]`. Strategy ids come from the
+/// buyer's contract counter.
+pub fn strategy_address(buyer: &Pubkey, seller: &Pubkey, strategy_id: u64) -> Pubkey {
+    find(&[
+        b"strategy",
+        buyer.as_ref(),
+        seller.as_ref(),
+        &strategy_id.to_le_bytes(),
+    ])
+}
+
+/// `[b"collateral", contract]`, holding the seller's margin in lamports
+pub fn collateral_address(contract: &Pubkey) -> Pubkey {
+    find(&[b"collateral", contract.as_ref()])
+}
+
+/// `[b"collateral_vault", contract]`, holding the margin of a token contract
+pub fn collateral_vault_address(contract: &Pubkey) -> Pubkey {
+    find(&[b"collateral_vault", contract.as_ref()])
+}
+
+/// `[b"underlying_vault", contract]`, holding the underlying locked for a
+/// physically settled call
+pub fn underlying_vault_address(contract: &Pubkey) -> Pubkey {
+    find(&[b"underlying_vault", contract.as_ref()])
+}
+
+/// `[b"user_page", owner, page_no]`, one page of a user's contract index
+pub fn user_page_address(owner: &Pubkey, page_no: u32) -> Pubkey {
+    find(&[b"user_page", owner.as_ref(), &page_no.to_le_bytes()])
+}
+
+/// `[b"offer", buyer, seller, offer_id]`
+pub fn offer_address(buyer: &Pubkey, seller: &Pubkey, offer_id: u64) -> Pubkey {
+    find(&[
+        b"offer",
+        buyer.as_ref(),
+        seller.as_ref(),
+        &offer_id.to_le_bytes(),
+    ])
+}
+
+/// `[b"quote", seller, quote_id]`
+pub fn quote_address(seller: &Pubkey, quote_id: u64) -> Pubkey {
+    find(&[b"quote", seller.as_ref(), &quote_id.to_le_bytes()])
+}
+
+/// `[b"market_config"]`
+pub fn market_config_address() -> Pubkey {
+    find(&[b"market_config"])
+}
+
+/// `[b"treasury"]`, collecting lamport fees
+pub fn treasury_address() -> Pubkey {
+    find(&[b"treasury"])
+}
+
+/// `[b"treasury_vault", mint]`, collecting token fees
+pub fn treasury_vault_address(mint: &Pubkey) -> Pubkey {
+    find(&[b"treasury_vault", mint.as_ref()])
+}
+
+/// `[b"insurance_fund"]`, covering lamport payoffs of defaulted sellers
+pub fn insurance_fund_address() -> Pubkey {
+    find(&[b"insurance_fund"])
+}
+
+/// `[b"insurance_vault", mint]`, covering token payoffs of defaulted sellers
+pub fn insurance_vault_address(mint: &Pubkey) -> Pubkey {
+    find(&[b"insurance_vault", mint.as_ref()])
+}
+
+/// `[b"underlying_asset", ticker]`, the price precision registered for a ticker
+pub fn underlying_asset_address(ticker: &str) -> Pubkey {
+    find(&[b"underlying_asset", ticker.as_bytes()])
+}
+
+/// `[b"test_clock"]`, only present in builds with the `test-clock` feature
+#[cfg(feature = "test-clock")]
+pub fn test_clock_address() -> Pubkey {
+    find(&[b"test_clock"])
+}
+
+/// Program data account of the upgradeable program, checked when the market
+/// config is created
+pub fn program_data_address() -> Pubkey {
+    Pubkey::find_program_address(&[basic::ID.as_ref()], &bpf_loader_upgradeable::ID).0
+}
+
+/// Mock oracle feed published by `authority` for `symbol`
+pub fn price_feed_address(authority: &Pubkey, symbol: &str) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"price_feed", authority.as_ref(), symbol.as_bytes()],
+        &mock_oracle::ID,
+    )
+    .0
+}
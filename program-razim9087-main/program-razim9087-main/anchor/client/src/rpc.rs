@@ -0,0 +1,67 @@
+//! Account reads over any RPC endpoint, including a local test validator.
+
+use std::fmt;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::AccountDeserialize;
+use basic::{UserAccount, UserContract, UserContractPage};
+use solana_rpc_client::rpc_client::RpcClient;
+use solana_rpc_client_api::client_error::Error as RpcError;
+
+use crate::pda::{user_account_address, user_page_address};
+use crate::state::decode_account;
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// The RPC request failed or the account does not exist
+    Rpc(Box<RpcError>),
+    /// The account exists but does not hold the expected type
+    Decode(anchor_lang::error::Error),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Rpc(error) => write!(f, "rpc error: {error}"),
+            ClientError::Decode(error) => write!(f, "failed to decode account: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<RpcError> for ClientError {
+    fn from(error: RpcError) -> Self {
+        ClientError::Rpc(Box::new(error))
+    }
+}
+
+impl From<anchor_lang::error::Error> for ClientError {
+    fn from(error: anchor_lang::error::Error) -> Self {
+        ClientError::Decode(error)
+    }
+}
+
+/// Fetches and decodes a program account
+pub fn fetch_account<T: AccountDeserialize>(
+    client: &RpcClient,
+    address: &Pubkey,
+) -> Result<T, ClientError> {
+    let data = client.get_account_data(address)?;
+    Ok(decode_account(&data)?)
+}
+
+/// Lists a user's contract index entries, reading every page of the index
+pub fn fetch_user_contracts(
+    client: &RpcClient,
+    owner: &Pubkey,
+) -> Result<Vec<UserContract>, ClientError> {
+    let user_account: UserAccount = fetch_account(client, &user_account_address(owner))?;
+
+    let mut contracts = Vec::new();
+    for page_no in 0..user_account.page_count {
+        let page: UserContractPage = fetch_account(client, &user_page_address(owner, page_no))?;
+        contracts.extend(page.contracts);
+    }
+    Ok(contracts)
+}
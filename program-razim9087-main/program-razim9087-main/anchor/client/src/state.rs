@@ -0,0 +1,44 @@
+//! Decoders for the program's accounts.
+
+use anchor_lang::AccountDeserialize;
+use basic::{
+    MarketConfig, OptionContract, OptionOffer, OptionStrategy, Quote, UnderlyingAsset, UserAccount,
+    UserContractPage,
+};
+
+/// Decodes raw account data, checking the account discriminator
+pub fn decode_account<T: AccountDeserialize>(data: &[u8]) -> anchor_lang::Result<T> {
+    T::try_deserialize(&mut &data[..])
+}
+
+pub fn decode_option_contract(data: &[u8]) -> anchor_lang::Result<OptionContract> {
+    decode_account(data)
+}
+
+pub fn decode_option_strategy(data: &[u8]) -> anchor_lang::Result<OptionStrategy> {
+    decode_account(data)
+}
+
+pub fn decode_user_account(data: &[u8]) -> anchor_lang::Result<UserAccount> {
+    decode_account(data)
+}
+
+pub fn decode_user_contract_page(data: &[u8]) -> anchor_lang::Result<UserContractPage> {
+    decode_account(data)
+}
+
+pub fn decode_option_offer(data: &[u8]) -> anchor_lang::Result<OptionOffer> {
+    decode_account(data)
+}
+
+pub fn decode_quote(data: &[u8]) -> anchor_lang::Result<Quote> {
+    decode_account(data)
+}
+
+pub fn decode_market_config(data: &[u8]) -> anchor_lang::Result<MarketConfig> {
+    decode_account(data)
+}
+
+pub fn decode_underlying_asset(data: &[u8]) -> anchor_lang::Result<UnderlyingAsset> {
+    decode_account(data)
+}
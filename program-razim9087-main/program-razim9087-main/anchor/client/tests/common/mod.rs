@@ -0,0 +1,631 @@
+//! In-process test harness: runs `basic` and `mock-oracle` as native programs
+//! inside `solana-program-test`, so no validator or SBF build is needed.
+//!
+//! The market config is written at genesis, because `initialize_market_config`
+//! checks the upgradeable loader's program data, which native programs lack.
+//! Time moves by rewriting the `Clock` sysvar.
+
+#![allow(dead_code)]
+
+use anchor_lang::prelude::{AccountInfo, Pubkey};
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::{AccountDeserialize, AccountSerialize, InstructionData, Space, ToAccountMetas};
+use anchor_spl::token::spl_token;
+use basic::{
+    ExerciseStyle, MarketConfig, OptionContract, OptionStrategy, OptionType, Treasury, UserAccount,
+};
+use basic_client::instruction::{self, ContractTerms, StrategyTerms};
+use basic_client::pda;
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::account::Account;
+use solana_sdk::clock::Clock;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::instruction::{Instruction, InstructionError};
+use solana_sdk::rent::Rent;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::{Transaction, TransactionError};
+
+pub const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
+pub const DAY: i64 = 24 * 60 * 60;
+pub const MIN_MARGIN_BPS: u16 = 500;
+pub const MAX_MARGIN_BPS: u16 = 5000;
+pub const MAX_EXPIRY_SECONDS: i64 = 30 * DAY;
+// SOL at $100, so one dollar of payoff is 0.01 SOL
+pub const SOL_PRICE: i64 = 10000;
+pub const DEPOSIT: u64 = 5 * LAMPORTS_PER_SOL;
+pub const STRIKE: u64 = 10000;
+
+// Anchor's entry ties the account slice to the account lifetimes, which the
+// builtin signature does not, so hand it a leaked copy of the slice
+fn process_basic(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    basic::entry(program_id, accounts, data)
+}
+
+fn process_mock_oracle(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    mock_oracle::entry(program_id, accounts, data)
+}
+
+fn program_account<T: AccountSerialize>(account: &T, space: usize) -> Account {
+    let mut data = Vec::with_capacity(space);
+    account.try_serialize(&mut data).unwrap();
+    data.resize(space, 0);
+    Account {
+        lamports: Rent::default().minimum_balance(space),
+        data,
+        owner: basic::ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+pub struct TestEnv {
+    pub context: ProgramTestContext,
+    pub admin: Keypair,
+    // Makes otherwise identical transactions distinct
+    nonce: u64,
+}
+
+impl TestEnv {
+    pub async fn start() -> Self {
+        let admin = Keypair::new();
+
+        let mut program_test = ProgramTest::default();
+        program_test.prefer_bpf(false);
+        program_test.add_program("basic", basic::ID, processor!(process_basic));
+        program_test.add_program(
+            "mock_oracle",
+            mock_oracle::ID,
+            processor!(process_mock_oracle),
+        );
+
+        let market_config = MarketConfig {
+            bump: Pubkey::find_program_address(&[b"market_config"], &basic::ID).1,
+            admin: admin.pubkey(),
+            pending_admin: None,
+            oracle_authority: admin.pubkey(),
+            paused: false,
+            min_margin_bps: MIN_MARGIN_BPS,
+            max_margin_bps: MAX_MARGIN_BPS,
+            min_expiry_seconds: 0,
+            max_expiry_seconds: MAX_EXPIRY_SECONDS,
+            premium_fee_bps: 0,
+            settlement_fee_bps: 0,
+            crank_fee_bps: 0,
+        };
+        program_test.add_account(
+            pda::market_config_address(),
+            program_account(&market_config, 8 + MarketConfig::INIT_SPACE),
+        );
+        let treasury = Treasury {
+            bump: Pubkey::find_program_address(&[b"treasury"], &basic::ID).1,
+        };
+        program_test.add_account(
+            pda::treasury_address(),
+            program_account(&treasury, 8 + Treasury::INIT_SPACE),
+        );
+        program_test.add_account(
+            admin.pubkey(),
+            Account::new(LAMPORTS_PER_SOL, 0, &solana_sdk::system_program::ID),
+        );
+
+        let context = program_test.start_with_context().await;
+        let mut env = Self {
+            context,
+            admin,
+            nonce: 0,
+        };
+        // The context payer publishes every price feed
+        let admin = env.admin.insecure_clone();
+        let payer = env.payer();
+        env.process(
+            &[instruction::set_oracle_authority(&admin.pubkey(), &payer)],
+            &[&admin],
+        )
+        .await
+        .unwrap();
+        // Tests trade AAPL with prices in cents unless they register their own asset
+        env.register_asset("AAPL", -2).await;
+        env
+    }
+
+    /// Registers `ticker` with prices in units of 10^price_expo USD
+    pub async fn register_asset(&mut self, ticker: &str, price_expo: i32) {
+        let admin = self.admin.insecure_clone();
+        self.process(
+            &[instruction::register_underlying_asset(
+                &admin.pubkey(),
+                ticker,
+                price_expo,
+            )],
+            &[&admin],
+        )
+        .await
+        .unwrap();
+    }
+
+    pub fn payer(&self) -> Pubkey {
+        self.context.payer.pubkey()
+    }
+
+    /// Sends `instructions` in one transaction paid for by the context payer
+    pub async fn process(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<(), BanksClientError> {
+        self.nonce += 1;
+        let mut all_instructions =
+            vec![ComputeBudgetInstruction::set_compute_unit_price(self.nonce)];
+        all_instructions.extend_from_slice(instructions);
+
+        let payer = self.context.payer.insecure_clone();
+        let mut all_signers = vec![&payer];
+        all_signers.extend(
+            signers
+                .iter()
+                .copied()
+                .filter(|s| s.pubkey() != payer.pubkey()),
+        );
+
+        let blockhash = self.context.banks_client.get_latest_blockhash().await?;
+        let transaction = Transaction::new_signed_with_payer(
+            &all_instructions,
+            Some(&payer.pubkey()),
+            &all_signers,
+            blockhash,
+        );
+        self.context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+    }
+
+    pub async fn fund(&mut self, to: &Pubkey, lamports: u64) {
+        let payer = self.payer();
+        self.process(&[system_instruction::transfer(&payer, to, lamports)], &[])
+            .await
+            .unwrap();
+    }
+
+    /// A funded wallet with a user account and escrow holding `deposit`
+    pub async fn new_user(&mut self, deposit: u64) -> Keypair {
+        let user = Keypair::new();
+        self.fund(&user.pubkey(), deposit + LAMPORTS_PER_SOL).await;
+        let mut instructions = vec![
+            instruction::initialize_user(&user.pubkey()),
+            instruction::initialize_escrow(&user.pubkey()),
+        ];
+        if deposit > 0 {
+            instructions.push(instruction::deposit(&user.pubkey(), deposit));
+        }
+        self.process(&instructions, &[&user]).await.unwrap();
+        user
+    }
+
+    pub async fn now(&mut self) -> i64 {
+        self.clock().await.unix_timestamp
+    }
+
+    async fn clock(&mut self) -> Clock {
+        self.context
+            .banks_client
+            .get_sysvar::<Clock>()
+            .await
+            .unwrap()
+    }
+
+    /// Moves the cluster clock to `unix_timestamp`
+    pub async fn warp_to(&mut self, unix_timestamp: i64) {
+        let mut clock = self.clock().await;
+        clock.unix_timestamp = unix_timestamp;
+        self.context.set_sysvar(&clock);
+    }
+
+    /// Publishes `price` in USD cents for `symbol` at the current cluster time
+    pub async fn set_price(&mut self, symbol: &str, price: i64) -> Pubkey {
+        self.set_quote(symbol, price, 1, None).await
+    }
+
+    pub async fn set_quote(
+        &mut self,
+        symbol: &str,
+        price: i64,
+        conf: u64,
+        publish_time: Option<i64>,
+    ) -> Pubkey {
+        self.publish(symbol, price, conf, -2, publish_time).await
+    }
+
+    /// Publishes `price * 10^expo` USD for `symbol`
+    pub async fn set_scaled_price(&mut self, symbol: &str, price: i64, expo: i32) -> Pubkey {
+        self.publish(symbol, price, 1, expo, None).await
+    }
+
+    async fn publish(
+        &mut self,
+        symbol: &str,
+        price: i64,
+        conf: u64,
+        expo: i32,
+        publish_time: Option<i64>,
+    ) -> Pubkey {
+        let authority = self.payer();
+        let price_feed = pda::price_feed_address(&authority, symbol);
+        let mut instructions = Vec::new();
+
+        if self
+            .try_account::<mock_oracle::PriceFeed>(&price_feed)
+            .await
+            .is_none()
+        {
+            instructions.push(oracle_instruction(
+                mock_oracle::accounts::InitializeFeed {
+                    authority,
+                    price_feed,
+                    system_program: solana_sdk::system_program::ID,
+                },
+                mock_oracle::instruction::InitializeFeed {
+                    symbol: symbol.to_string(),
+                    price,
+                    conf,
+                    expo,
+                },
+            ));
+        }
+        instructions.push(oracle_instruction(
+            mock_oracle::accounts::UpdatePrice {
+                authority,
+                price_feed,
+            },
+            mock_oracle::instruction::UpdatePrice {
+                price,
+                conf,
+                expo,
+                publish_time,
+            },
+        ));
+
+        self.process(&instructions, &[]).await.unwrap();
+        price_feed
+    }
+
+    pub async fn try_account<T: AccountDeserialize>(&mut self, address: &Pubkey) -> Option<T> {
+        let account = self
+            .context
+            .banks_client
+            .get_account(*address)
+            .await
+            .unwrap()?;
+        T::try_deserialize(&mut &account.data[..]).ok()
+    }
+
+    pub async fn account<T: AccountDeserialize>(&mut self, address: &Pubkey) -> T {
+        self.try_account(address)
+            .await
+            .unwrap_or_else(|| panic!("missing account {address}"))
+    }
+
+    pub async fn lamports(&mut self, address: &Pubkey) -> u64 {
+        self.context
+            .banks_client
+            .get_balance(*address)
+            .await
+            .unwrap()
+    }
+
+    pub async fn escrow_balance(&mut self, owner: &Pubkey) -> u64 {
+        self.lamports(&pda::escrow_address(owner)).await
+    }
+
+    pub async fn user_account(&mut self, owner: &Pubkey) -> UserAccount {
+        self.account(&pda::user_account_address(owner)).await
+    }
+
+    pub async fn market_config(&mut self) -> MarketConfig {
+        self.account(&pda::market_config_address()).await
+    }
+
+    pub async fn contract(&mut self, address: &Pubkey) -> OptionContract {
+        self.account(address).await
+    }
+
+    pub async fn strategy(&mut self, address: &Pubkey) -> OptionStrategy {
+        self.account(address).await
+    }
+
+    /// A new mint with `decimals`, its authority being the context payer
+    pub async fn create_mint(&mut self, decimals: u8) -> Pubkey {
+        let mint = Keypair::new();
+        let payer = self.payer();
+        let space = spl_token::state::Mint::LEN;
+        self.process(
+            &[
+                system_instruction::create_account(
+                    &payer,
+                    &mint.pubkey(),
+                    Rent::default().minimum_balance(space),
+                    space as u64,
+                    &spl_token::ID,
+                ),
+                spl_token::instruction::initialize_mint2(
+                    &spl_token::ID,
+                    &mint.pubkey(),
+                    &payer,
+                    None,
+                    decimals,
+                )
+                .unwrap(),
+            ],
+            &[&mint],
+        )
+        .await
+        .unwrap();
+        mint.pubkey()
+    }
+
+    /// A token account for `owner` holding `amount` freshly minted tokens
+    pub async fn token_account(&mut self, owner: &Pubkey, mint: &Pubkey, amount: u64) -> Pubkey {
+        let account = Keypair::new();
+        let payer = self.payer();
+        let space = spl_token::state::Account::LEN;
+        self.process(
+            &[
+                system_instruction::create_account(
+                    &payer,
+                    &account.pubkey(),
+                    Rent::default().minimum_balance(space),
+                    space as u64,
+                    &spl_token::ID,
+                ),
+                spl_token::instruction::initialize_account3(
+                    &spl_token::ID,
+                    &account.pubkey(),
+                    mint,
+                    owner,
+                )
+                .unwrap(),
+                spl_token::instruction::mint_to(
+                    &spl_token::ID,
+                    mint,
+                    &account.pubkey(),
+                    &payer,
+                    &[],
+                    amount,
+                )
+                .unwrap(),
+            ],
+            &[&account],
+        )
+        .await
+        .unwrap();
+        account.pubkey()
+    }
+
+    /// A registered user whose token escrow for `mint` holds `deposit`
+    pub async fn new_token_user(&mut self, mint: &Pubkey, deposit: u64) -> Keypair {
+        let user = self.new_user(0).await;
+        self.fund_token_escrow(&user, mint, deposit).await;
+        user
+    }
+
+    /// Opens `user`'s token escrow for `mint` and deposits `deposit` freshly
+    /// minted tokens into it
+    pub async fn fund_token_escrow(&mut self, user: &Keypair, mint: &Pubkey, deposit: u64) {
+        let mut instructions = vec![instruction::initialize_token_escrow(&user.pubkey(), mint)];
+        if deposit > 0 {
+            let user_token_account = self.token_account(&user.pubkey(), mint, deposit).await;
+            instructions.push(instruction::deposit_tokens(
+                &user.pubkey(),
+                mint,
+                &user_token_account,
+                deposit,
+            ));
+        }
+        self.process(&instructions, &[user]).await.unwrap();
+    }
+
+    /// Overwrites a contract's state, to reach checks that no sequence of
+    /// instructions can
+    pub async fn set_contract(&mut self, address: &Pubkey, contract: &OptionContract) {
+        let mut account = self
+            .context
+            .banks_client
+            .get_account(*address)
+            .await
+            .unwrap()
+            .unwrap();
+        let mut data = Vec::new();
+        contract.try_serialize(&mut data).unwrap();
+        account.data[..data.len()].copy_from_slice(&data);
+        self.context.set_account(address, &account.into());
+    }
+
+    pub async fn token_balance(&mut self, address: &Pubkey) -> u64 {
+        let account = self
+            .context
+            .banks_client
+            .get_account(*address)
+            .await
+            .unwrap()
+            .unwrap_or_else(|| panic!("missing token account {address}"));
+        spl_token::state::Account::unpack(&account.data)
+            .unwrap()
+            .amount
+    }
+
+    /// Opens a contract signed by both parties and returns its address
+    pub async fn create_contract(
+        &mut self,
+        buyer: &Keypair,
+        seller: &Keypair,
+        terms: &ContractTerms,
+    ) -> Result<Pubkey, BanksClientError> {
+        let buyer_account = self.user_account(&buyer.pubkey()).await;
+        let seller_account = self.user_account(&seller.pubkey()).await;
+        let market_config = self.market_config().await;
+        let contract = pda::contract_address(
+            &buyer.pubkey(),
+            &seller.pubkey(),
+            buyer_account.contract_count,
+        );
+
+        self.process(
+            &[instruction::create_contract(
+                &buyer_account,
+                &seller_account,
+                &market_config,
+                terms,
+            )],
+            &[buyer, seller],
+        )
+        .await?;
+        Ok(contract)
+    }
+
+    /// Opens a strategy signed by both parties and returns its address
+    pub async fn create_strategy(
+        &mut self,
+        buyer: &Keypair,
+        seller: &Keypair,
+        terms: &StrategyTerms,
+    ) -> Result<Pubkey, BanksClientError> {
+        let buyer_account = self.user_account(&buyer.pubkey()).await;
+        let seller_account = self.user_account(&seller.pubkey()).await;
+        let market_config = self.market_config().await;
+        let strategy = pda::strategy_address(
+            &buyer.pubkey(),
+            &seller.pubkey(),
+            buyer_account.contract_count,
+        );
+
+        self.process(
+            &[instruction::create_strategy(
+                &buyer_account,
+                &seller_account,
+                &market_config,
+                terms,
+            )],
+            &[buyer, seller],
+        )
+        .await?;
+        Ok(strategy)
+    }
+}
+
+/// A buyer and a seller with `DEPOSIT` lamports in escrow, and quotes
+/// for AAPL at the strike and for SOL
+pub struct Market {
+    pub env: TestEnv,
+    pub buyer: Keypair,
+    pub seller: Keypair,
+    pub underlying_feed: Pubkey,
+    pub sol_feed: Pubkey,
+}
+
+impl Market {
+    pub async fn open() -> Self {
+        let mut env = TestEnv::start().await;
+        let buyer = env.new_user(DEPOSIT).await;
+        let seller = env.new_user(DEPOSIT).await;
+        let underlying_feed = env.set_price("AAPL", STRIKE as i64).await;
+        let sol_feed = env.set_price("SOL", SOL_PRICE).await;
+        Self {
+            env,
+            buyer,
+            seller,
+            underlying_feed,
+            sol_feed,
+        }
+    }
+
+    pub async fn create(
+        &mut self,
+        option_type: OptionType,
+        exercise_style: ExerciseStyle,
+        expiration_date: i64,
+    ) -> Pubkey {
+        let terms = self.terms(option_type, exercise_style, expiration_date);
+        self.env
+            .create_contract(&self.buyer, &self.seller, &terms)
+            .await
+            .unwrap()
+    }
+
+    /// Lamport-collateralized terms for 10 units with a 20% margin requirement
+    pub fn terms(
+        &self,
+        option_type: OptionType,
+        exercise_style: ExerciseStyle,
+        expiration_date: i64,
+    ) -> ContractTerms {
+        ContractTerms {
+            underlying_asset: "AAPL".to_string(),
+            num_units: 10,
+            strike_price: STRIKE,
+            expiration_date,
+            option_type,
+            exercise_style,
+            premium: LAMPORTS_PER_SOL / 10,
+            margin_requirement_bps: 2000,
+            underlying_price_feed: self.underlying_feed,
+            sol_price_feed: self.sol_feed,
+            collateral_mint: None,
+            underlying_mint: None,
+        }
+    }
+
+    // Publishes fresh quotes, needed after every clock warp
+    pub async fn quote(&mut self, underlying_price: i64) {
+        self.env.set_price("AAPL", underlying_price).await;
+        self.env.set_price("SOL", SOL_PRICE).await;
+    }
+}
+
+fn oracle_instruction(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: mock_oracle::ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Lamports an empty escrow PDA holds to stay rent exempt
+pub fn rent_exempt_escrow() -> u64 {
+    Rent::default().minimum_balance(0)
+}
+
+/// Payoff in lamports of `units` that are `profit_per_unit` cents in the money
+pub fn payoff_lamports(profit_per_unit: u64, units: u64) -> u64 {
+    profit_per_unit * units * LAMPORTS_PER_SOL / SOL_PRICE as u64
+}
+
+/// Lamports locked for the 20% margin of `units` struck at `STRIKE`
+pub fn margin_lamports(units: u64) -> u64 {
+    payoff_lamports(STRIKE * 2000 / 10000, units)
+}
+
+/// Asserts that a transaction failed with the custom error `expected`
+#[track_caller]
+pub fn assert_error<T: std::fmt::Debug>(
+    result: Result<T, BanksClientError>,
+    expected: impl Into<u32>,
+) {
+    let expected = expected.into();
+    match result {
+        Err(error) => match error.unwrap() {
+            TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+                assert_eq!(code, expected, "expected error {expected}, got {code}")
+            }
+            other => panic!("expected error {expected}, got {other:?}"),
+        },
+        Ok(value) => panic!("expected error {expected}, got Ok({value:?})"),
+    }
+}
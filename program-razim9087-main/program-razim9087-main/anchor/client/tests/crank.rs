@@ -0,0 +1,180 @@
+//! Contracts cranked by a keeper once their exercise window has passed. The
+//! keeper is paid a bounty out of the margin for exercising or expiring them.
+
+mod common;
+
+use basic::{ContractStatus, ErrorCode, ExerciseStyle, OptionContract, OptionType};
+use basic_client::instruction;
+use common::*;
+use solana_sdk::signature::{Keypair, Signer};
+
+// 1% of the payoff, or of the released margin
+const CRANK_FEE_BPS: u16 = 100;
+
+// Sets the crank fee before opening a European call expiring in a day
+async fn open_cranked_call(market: &mut Market) -> OptionContract {
+    let admin = market.env.admin.insecure_clone();
+    market
+        .env
+        .process(
+            &[instruction::set_crank_fee(&admin.pubkey(), CRANK_FEE_BPS)],
+            &[&admin],
+        )
+        .await
+        .unwrap();
+
+    let expiration_date = market.env.now().await + DAY;
+    let address = market
+        .create(OptionType::Call, ExerciseStyle::European, expiration_date)
+        .await;
+    let contract = market.env.contract(&address).await;
+    assert_eq!(contract.crank_fee_bps, CRANK_FEE_BPS);
+    contract
+}
+
+async fn crank(market: &mut Market, keeper: &Keypair, contract: &OptionContract) {
+    market
+        .env
+        .process(
+            &[instruction::crank_expiry(&keeper.pubkey(), contract, None)],
+            &[keeper],
+        )
+        .await
+        .unwrap();
+}
+
+async fn new_keeper(market: &mut Market) -> Keypair {
+    let keeper = Keypair::new();
+    market.env.fund(&keeper.pubkey(), LAMPORTS_PER_SOL).await;
+    keeper
+}
+
+#[tokio::test]
+async fn in_the_money_contract_is_exercised_after_the_window() {
+    let mut market = Market::open().await;
+    let contract = open_cranked_call(&mut market).await;
+    let address = instruction::address_of_contract(&contract);
+    let buyer = market.buyer.pubkey();
+    let seller = market.seller.pubkey();
+    let buyer_escrow = market.env.escrow_balance(&buyer).await;
+    let seller_escrow = market.env.escrow_balance(&seller).await;
+
+    // The buyer can exercise without paying the bounty until the window closes
+    market.env.warp_to(contract.expiration_date).await;
+    market.quote(STRIKE as i64 + 1).await;
+    let keeper = new_keeper(&mut market).await;
+    let result = market
+        .env
+        .process(
+            &[instruction::crank_expiry(&keeper.pubkey(), &contract, None)],
+            &[&keeper],
+        )
+        .await;
+    assert_error(result, ErrorCode::GracePeriodNotElapsed);
+
+    // The buyer never exercises, so a keeper does it for them
+    market.env.warp_to(contract.expiration_date + DAY).await;
+    market.quote(STRIKE as i64 + 1).await;
+    crank(&mut market, &keeper, &contract).await;
+
+    let payoff = payoff_lamports(1, 10);
+    let bounty = payoff / 100;
+    let cranked = market.env.contract(&address).await;
+    assert_eq!(cranked.status, ContractStatus::Settled);
+    assert_eq!(cranked.units_exercised, cranked.num_units);
+    assert_eq!(
+        market.env.lamports(&keeper.pubkey()).await,
+        LAMPORTS_PER_SOL + bounty
+    );
+    assert_eq!(
+        market.env.escrow_balance(&buyer).await,
+        buyer_escrow + payoff - bounty
+    );
+    assert_eq!(
+        market.env.escrow_balance(&seller).await,
+        seller_escrow + contract.margin_amount - payoff
+    );
+}
+
+#[tokio::test]
+async fn out_of_the_money_contract_expires_after_the_window() {
+    let mut market = Market::open().await;
+    let contract = open_cranked_call(&mut market).await;
+    let address = instruction::address_of_contract(&contract);
+    let seller = market.seller.pubkey();
+    let seller_escrow = market.env.escrow_balance(&seller).await;
+    let keeper = new_keeper(&mut market).await;
+
+    // The buyer keeps the exercise window in case the price recovers
+    market.env.warp_to(contract.expiration_date).await;
+    market.quote(STRIKE as i64 - 1000).await;
+    let result = market
+        .env
+        .process(
+            &[instruction::crank_expiry(&keeper.pubkey(), &contract, None)],
+            &[&keeper],
+        )
+        .await;
+    assert_error(result, ErrorCode::GracePeriodNotElapsed);
+
+    market.env.warp_to(contract.expiration_date + DAY).await;
+    market.quote(STRIKE as i64 - 1000).await;
+    crank(&mut market, &keeper, &contract).await;
+
+    let bounty = contract.margin_amount / 100;
+    assert_eq!(
+        market.env.contract(&address).await.status,
+        ContractStatus::Expired
+    );
+    assert_eq!(
+        market.env.lamports(&keeper.pubkey()).await,
+        LAMPORTS_PER_SOL + bounty
+    );
+    assert_eq!(
+        market.env.escrow_balance(&seller).await,
+        seller_escrow + contract.margin_amount - bounty
+    );
+}
+
+#[tokio::test]
+async fn uncovered_payoff_is_left_to_settle() {
+    let mut market = Market::open().await;
+    let contract = open_cranked_call(&mut market).await;
+    let address = instruction::address_of_contract(&contract);
+    let withdrawal = DEPOSIT - contract.margin_amount;
+    market
+        .env
+        .process(
+            &[instruction::withdraw(&market.seller.pubkey(), withdrawal)],
+            &[&market.seller],
+        )
+        .await
+        .unwrap();
+
+    market.env.warp_to(contract.expiration_date + DAY).await;
+    market.quote(13000).await;
+    let keeper = new_keeper(&mut market).await;
+    crank(&mut market, &keeper, &contract).await;
+
+    // The margin left after the bounty cannot cover the payoff, which stays pending
+    let payoff = payoff_lamports(3000, 10);
+    let bounty = payoff / 100;
+    let cranked = market.env.contract(&address).await;
+    assert_eq!(cranked.status, ContractStatus::Exercised);
+    assert_eq!(cranked.seller_pending_balance, payoff - bounty);
+    assert_eq!(
+        market.env.lamports(&keeper.pubkey()).await,
+        LAMPORTS_PER_SOL + bounty
+    );
+
+    let buyer = market.buyer.pubkey();
+    market
+        .env
+        .process(&[instruction::settle(&buyer, &cranked)], &[&market.buyer])
+        .await
+        .unwrap();
+    assert_eq!(
+        market.env.contract(&address).await.status,
+        ContractStatus::Defaulted
+    );
+}
@@ -0,0 +1,231 @@
+//! Settlements the seller's margin and escrow cannot cover, paid as far as
+//! the collateral and the insurance fund go.
+
+mod common;
+
+use basic::{ContractStatus, ErrorCode, ExerciseStyle, OptionContract, OptionType};
+use basic_client::{instruction, pda};
+use common::*;
+use solana_sdk::signature::Signer;
+
+// Premium fee charged in the insured test, which funds the insurance fund
+const PREMIUM_FEE_BPS: u16 = 1000;
+
+// Exercises a call far enough in the money that the seller, having withdrawn
+// everything but their margin, cannot pay the payoff
+async fn exercise_uncovered_call(market: &mut Market) -> OptionContract {
+    let expiration_date = market.env.now().await + 7 * DAY;
+    let address = market
+        .create(OptionType::Call, ExerciseStyle::American, expiration_date)
+        .await;
+    let contract = market.env.contract(&address).await;
+
+    let withdrawal = DEPOSIT - contract.margin_amount;
+    market
+        .env
+        .process(
+            &[instruction::withdraw(&market.seller.pubkey(), withdrawal)],
+            &[&market.seller],
+        )
+        .await
+        .unwrap();
+
+    market.quote(13000).await;
+    market
+        .env
+        .process(&[instruction::exercise(&contract)], &[&market.buyer])
+        .await
+        .unwrap();
+    market.env.contract(&address).await
+}
+
+#[tokio::test]
+async fn default_pays_the_available_collateral() {
+    let mut market = Market::open().await;
+    let contract = exercise_uncovered_call(&mut market).await;
+    let address = instruction::address_of_contract(&contract);
+    let buyer = market.buyer.pubkey();
+    let buyer_escrow = market.env.escrow_balance(&buyer).await;
+
+    // Without the insurance fund the buyer takes the loss of the default
+    market
+        .env
+        .process(&[instruction::settle(&buyer, &contract)], &[&market.buyer])
+        .await
+        .unwrap();
+
+    let payoff = payoff_lamports(3000, 10);
+    let settled = market.env.contract(&address).await;
+    assert_eq!(settled.status, ContractStatus::Defaulted);
+    assert_eq!(settled.seller_pending_balance, 0);
+    assert_eq!(settled.default_shortfall, payoff - contract.margin_amount);
+    assert_eq!(settled.paid_from_insurance, 0);
+    assert_eq!(
+        market.env.escrow_balance(&buyer).await,
+        buyer_escrow + contract.margin_amount
+    );
+    assert_eq!(
+        market.env.escrow_balance(&market.seller.pubkey()).await,
+        rent_exempt_escrow()
+    );
+
+    // A defaulted contract is finished and can be closed
+    market
+        .env
+        .process(
+            &[instruction::close_contract(&buyer, &settled)],
+            &[&market.buyer],
+        )
+        .await
+        .unwrap();
+    assert!(market
+        .env
+        .try_account::<OptionContract>(&address)
+        .await
+        .is_none());
+}
+
+#[tokio::test]
+async fn insurance_fund_covers_part_of_a_default() {
+    let mut market = Market::open().await;
+    let admin = market.env.admin.insecure_clone();
+    market
+        .env
+        .process(
+            &[
+                instruction::initialize_insurance_fund(&admin.pubkey()),
+                instruction::set_protocol_fees(&admin.pubkey(), PREMIUM_FEE_BPS, 0),
+            ],
+            &[&admin],
+        )
+        .await
+        .unwrap();
+
+    let contract = exercise_uncovered_call(&mut market).await;
+    let address = instruction::address_of_contract(&contract);
+    market
+        .env
+        .process(
+            &[instruction::fund_insurance(
+                &admin.pubkey(),
+                contract.premium_fee,
+            )],
+            &[&admin],
+        )
+        .await
+        .unwrap();
+    let insurance_fund = pda::insurance_fund_address();
+    let insurance_reserve = market.env.lamports(&insurance_fund).await - contract.premium_fee;
+
+    // Any keeper can settle the default against the insurance fund
+    let buyer = market.buyer.pubkey();
+    let buyer_escrow = market.env.escrow_balance(&buyer).await;
+    let keeper = market.env.payer();
+    market
+        .env
+        .process(
+            &[instruction::settle_with_insurance(&keeper, &contract)],
+            &[],
+        )
+        .await
+        .unwrap();
+
+    let settled = market.env.contract(&address).await;
+    assert_eq!(settled.status, ContractStatus::Defaulted);
+    assert_eq!(
+        settled.default_shortfall,
+        payoff_lamports(3000, 10) - contract.margin_amount
+    );
+    assert_eq!(settled.paid_from_insurance, contract.premium_fee);
+    assert_eq!(
+        market.env.escrow_balance(&buyer).await,
+        buyer_escrow + contract.margin_amount + contract.premium_fee
+    );
+    assert_eq!(
+        market.env.lamports(&insurance_fund).await,
+        insurance_reserve
+    );
+}
+
+#[tokio::test]
+async fn liquidation_default_draws_on_the_insurance_fund() {
+    let mut market = Market::open().await;
+    let admin = market.env.admin.insecure_clone();
+    market
+        .env
+        .process(
+            &[
+                instruction::initialize_insurance_fund(&admin.pubkey()),
+                instruction::set_protocol_fees(&admin.pubkey(), PREMIUM_FEE_BPS, 0),
+            ],
+            &[&admin],
+        )
+        .await
+        .unwrap();
+
+    let expiration_date = market.env.now().await + 7 * DAY;
+    let address = market
+        .create(OptionType::Call, ExerciseStyle::American, expiration_date)
+        .await;
+    let contract = market.env.contract(&address).await;
+    let seller = market.seller.pubkey();
+    market
+        .env
+        .process(
+            &[
+                instruction::withdraw(&seller, DEPOSIT - contract.margin_amount),
+                instruction::fund_insurance(&admin.pubkey(), contract.premium_fee),
+            ],
+            &[&market.seller, &admin],
+        )
+        .await
+        .unwrap();
+
+    // The seller ignores the margin call with nothing left in their escrow
+    market.quote(13000).await;
+    let keeper = market.env.payer();
+    market
+        .env
+        .process(&[instruction::mark_to_market(&keeper, &contract)], &[])
+        .await
+        .unwrap();
+    let contract = market.env.contract(&address).await;
+    market
+        .env
+        .warp_to(contract.margin_call_deadline.unwrap())
+        .await;
+    market.quote(13000).await;
+
+    // A keeper cannot leave the shortfall with the buyer
+    let result = market
+        .env
+        .process(&[instruction::liquidate(&keeper, &contract, None)], &[])
+        .await;
+    assert_error(result, ErrorCode::InsuranceFundRequired);
+
+    let buyer = market.buyer.pubkey();
+    let buyer_escrow = market.env.escrow_balance(&buyer).await;
+    market
+        .env
+        .process(
+            &[instruction::liquidate_with_insurance(&keeper, &contract, None)],
+            &[],
+        )
+        .await
+        .unwrap();
+
+    let bonus = contract.margin_amount * 500 / 10000;
+    let covered = contract.margin_amount - bonus;
+    let liquidated = market.env.contract(&address).await;
+    assert_eq!(liquidated.status, ContractStatus::Defaulted);
+    assert_eq!(liquidated.margin_call_deadline, None);
+    assert_eq!(
+        liquidated.default_shortfall,
+        payoff_lamports(3000, 10) - covered
+    );
+    assert_eq!(liquidated.paid_from_insurance, contract.premium_fee);
+    assert_eq!(
+        market.env.escrow_balance(&buyer).await,
+        buyer_escrow + covered + contract.premium_fee
+    );
+}
@@ -0,0 +1,1183 @@
+//! One test for every `ErrorCode` the program can return.
+
+mod common;
+
+use anchor_lang::InstructionData;
+use basic::{
+    ContractStatus, ErrorCode, ExerciseStyle, LegSide, OptionOffer, OptionType, Quote, StrategyLeg,
+    UserRole,
+};
+use basic_client::instruction::{self, QuoteTerms, StrategyTerms};
+use basic_client::pda;
+use common::*;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+
+// An American call, which can be exercised as soon as it is opened
+async fn open_american_call(market: &mut Market) -> basic::OptionContract {
+    let expiration_date = market.env.now().await + 7 * DAY;
+    let address = market
+        .create(OptionType::Call, ExerciseStyle::American, expiration_date)
+        .await;
+    market.env.contract(&address).await
+}
+
+// A call with a margin call running against it
+async fn open_margin_call(market: &mut Market) -> basic::OptionContract {
+    let contract = open_american_call(market).await;
+    market.quote(12000).await;
+    let keeper = market.env.payer();
+    market
+        .env
+        .process(&[instruction::mark_to_market(&keeper, &contract)], &[])
+        .await
+        .unwrap();
+    market
+        .env
+        .contract(&instruction::address_of_contract(&contract))
+        .await
+}
+
+fn quote_terms(market: &Market, valid_until: i64, expiration_date: i64) -> QuoteTerms {
+    QuoteTerms {
+        underlying_asset: "AAPL".to_string(),
+        strike_price: STRIKE,
+        expiration_date,
+        option_type: OptionType::Call,
+        exercise_style: ExerciseStyle::European,
+        premium_per_unit: LAMPORTS_PER_SOL / 100,
+        max_units: 5,
+        margin_requirement_bps: 2000,
+        valid_until,
+        underlying_price_feed: market.underlying_feed,
+        sol_price_feed: market.sol_feed,
+        collateral_mint: None,
+        underlying_mint: None,
+    }
+}
+
+async fn post_quote(market: &mut Market, valid_until: i64) -> Quote {
+    let expiration_date = market.env.now().await + 7 * DAY;
+    let terms = quote_terms(market, valid_until, expiration_date);
+    let seller = market.seller.pubkey();
+    market
+        .env
+        .process(
+            &[instruction::post_quote(&seller, 0, &terms)],
+            &[&market.seller],
+        )
+        .await
+        .unwrap();
+    market.env.account(&pda::quote_address(&seller, 0)).await
+}
+
+async fn fill_quote(
+    market: &mut Market,
+    quote: &Quote,
+    units: u64,
+) -> Result<(), solana_program_test::BanksClientError> {
+    let buyer_account = market.env.user_account(&market.buyer.pubkey()).await;
+    let seller_account = market.env.user_account(&market.seller.pubkey()).await;
+    let market_config = market.env.market_config().await;
+    let fill = instruction::fill_quote(
+        quote,
+        &buyer_account,
+        &seller_account,
+        &market_config,
+        units,
+    );
+    market.env.process(&[fill], &[&market.buyer]).await
+}
+
+#[tokio::test]
+async fn insufficient_balance() {
+    let mut market = Market::open().await;
+    let buyer = market.buyer.pubkey();
+    let result = market
+        .env
+        .process(
+            &[instruction::withdraw(&buyer, 2 * DEPOSIT)],
+            &[&market.buyer],
+        )
+        .await;
+    assert_error(result, ErrorCode::InsufficientBalance);
+}
+
+#[tokio::test]
+async fn contract_not_expired() {
+    let mut market = Market::open().await;
+    let expiration_date = market.env.now().await + DAY;
+    let address = market
+        .create(OptionType::Call, ExerciseStyle::European, expiration_date)
+        .await;
+    let contract = market.env.contract(&address).await;
+
+    let result = market
+        .env
+        .process(&[instruction::exercise(&contract)], &[&market.buyer])
+        .await;
+    assert_error(result, ErrorCode::ContractNotExpired);
+}
+
+#[tokio::test]
+async fn contract_not_active() {
+    let mut market = Market::open().await;
+    let contract = open_american_call(&mut market).await;
+
+    // Exercising out of the money expires the contract straight away
+    market.quote(9000).await;
+    market
+        .env
+        .process(&[instruction::exercise(&contract)], &[&market.buyer])
+        .await
+        .unwrap();
+
+    let result = market
+        .env
+        .process(&[instruction::exercise(&contract)], &[&market.buyer])
+        .await;
+    assert_error(result, ErrorCode::ContractNotActive);
+}
+
+#[tokio::test]
+async fn unauthorized_exercise() {
+    let mut market = Market::open().await;
+    let contract = open_american_call(&mut market).await;
+
+    let mut exercise = instruction::exercise(&contract);
+    exercise.accounts[0].pubkey = market.seller.pubkey();
+    let result = market.env.process(&[exercise], &[&market.seller]).await;
+    assert_error(result, ErrorCode::UnauthorizedExercise);
+}
+
+#[tokio::test]
+async fn not_exercised() {
+    let mut market = Market::open().await;
+    let contract = open_american_call(&mut market).await;
+
+    let caller = market.env.payer();
+    let result = market
+        .env
+        .process(&[instruction::settle(&caller, &contract)], &[])
+        .await;
+    assert_error(result, ErrorCode::NotExercised);
+}
+
+#[tokio::test]
+async fn no_pending_balance() {
+    let mut market = Market::open().await;
+    let mut contract = open_american_call(&mut market).await;
+
+    // Exercise never leaves an exercised contract without a payoff, so write
+    // that state directly
+    contract.status = ContractStatus::Exercised;
+    let address = instruction::address_of_contract(&contract);
+    market.env.set_contract(&address, &contract).await;
+
+    let caller = market.env.payer();
+    let result = market
+        .env
+        .process(&[instruction::settle(&caller, &contract)], &[])
+        .await;
+    assert_error(result, ErrorCode::NoPendingBalance);
+}
+
+#[tokio::test]
+async fn insufficient_seller_escrow() {
+    let mut market = Market::open().await;
+    let contract = open_american_call(&mut market).await;
+
+    // The seller empties their escrow down to the rent reserve
+    let seller = market.seller.pubkey();
+    let withdrawal = DEPOSIT - contract.margin_amount;
+    market
+        .env
+        .process(
+            &[instruction::withdraw(&seller, withdrawal)],
+            &[&market.seller],
+        )
+        .await
+        .unwrap();
+
+    market.quote(13000).await;
+    // Partial exercises still need the seller to cover the payoff in full
+    let result = market
+        .env
+        .process(
+            &[instruction::exercise_units(&contract, contract.num_units)],
+            &[&market.buyer],
+        )
+        .await;
+    assert_error(result, ErrorCode::InsufficientSellerEscrow);
+}
+
+#[tokio::test]
+async fn insurance_fund_required() {
+    let mut market = Market::open().await;
+    let contract = open_american_call(&mut market).await;
+
+    // The seller empties their escrow down to the rent reserve
+    let seller = market.seller.pubkey();
+    let withdrawal = DEPOSIT - contract.margin_amount;
+    market
+        .env
+        .process(
+            &[instruction::withdraw(&seller, withdrawal)],
+            &[&market.seller],
+        )
+        .await
+        .unwrap();
+
+    market.quote(13000).await;
+    market
+        .env
+        .process(&[instruction::exercise(&contract)], &[&market.buyer])
+        .await
+        .unwrap();
+
+    let contract = market
+        .env
+        .contract(&instruction::address_of_contract(&contract))
+        .await;
+    // Only the buyer may settle the default without the insurance fund
+    let result = market
+        .env
+        .process(
+            &[instruction::settle(&seller, &contract)],
+            &[&market.seller],
+        )
+        .await;
+    assert_error(result, ErrorCode::InsuranceFundRequired);
+}
+
+#[tokio::test]
+async fn calculation_error() {
+    let mut market = Market::open().await;
+    let expiration_date = market.env.now().await + DAY;
+    let mut terms = market.terms(OptionType::Call, ExerciseStyle::European, expiration_date);
+    terms.num_units = u64::MAX;
+
+    let result = market
+        .env
+        .create_contract(&market.buyer, &market.seller, &terms)
+        .await;
+    assert_error(result, ErrorCode::CalculationError);
+}
+
+#[tokio::test]
+async fn unregistered_asset() {
+    let mut market = Market::open().await;
+    let expiration_date = market.env.now().await + DAY;
+    let mut terms = market.terms(OptionType::Call, ExerciseStyle::European, expiration_date);
+    // Tickers longer than a PDA seed can never be registered either
+    terms.underlying_asset = "MSFT".to_string();
+
+    let result = market
+        .env
+        .create_contract(&market.buyer, &market.seller, &terms)
+        .await;
+    assert_error(result, anchor_lang::error::ErrorCode::AccountNotInitialized);
+}
+
+#[tokio::test]
+async fn asset_ticker_too_long() {
+    let mut market = Market::open().await;
+    let expiration_date = market.env.now().await + DAY;
+    let terms = market.terms(OptionType::Call, ExerciseStyle::European, expiration_date);
+    let buyer_account = market.env.user_account(&market.buyer.pubkey()).await;
+    let seller_account = market.env.user_account(&market.seller.pubkey()).await;
+    let market_config = market.env.market_config().await;
+
+    // No asset PDA can be derived from a ticker longer than a seed, so the
+    // instruction keeps the AAPL accounts and only the ticker changes
+    let mut create =
+        instruction::create_contract(&buyer_account, &seller_account, &market_config, &terms);
+    create.data = basic::instruction::CreateContract {
+        underlying_asset: "A".repeat(33),
+        num_units: terms.num_units,
+        strike_price: terms.strike_price,
+        expiration_date,
+        option_type: terms.option_type,
+        exercise_style: terms.exercise_style,
+        premium: terms.premium,
+        margin_requirement_bps: terms.margin_requirement_bps,
+    }
+    .data();
+    let result = market
+        .env
+        .process(&[create], &[&market.buyer, &market.seller])
+        .await;
+    assert_error(result, ErrorCode::AssetTickerTooLong);
+}
+
+#[tokio::test]
+async fn unauthorized_termination() {
+    let mut market = Market::open().await;
+    let contract = open_american_call(&mut market).await;
+
+    // A third party cannot sign in place of the buyer
+    let impostor = Keypair::new();
+    let mut terminate = instruction::terminate_contract(&contract, 0, UserRole::Buyer);
+    terminate.accounts[0].pubkey = impostor.pubkey();
+    let result = market
+        .env
+        .process(&[terminate], &[&impostor, &market.seller])
+        .await;
+    assert_error(result, ErrorCode::UnauthorizedTermination);
+}
+
+#[tokio::test]
+async fn price_expo_out_of_range() {
+    let mut market = Market::open().await;
+    let admin = market.env.admin.insecure_clone();
+    let result = market
+        .env
+        .process(
+            &[instruction::register_underlying_asset(&admin.pubkey(), "MSFT", -9)],
+            &[&admin],
+        )
+        .await;
+    assert_error(result, ErrorCode::PriceExpoOutOfRange);
+}
+
+#[tokio::test]
+async fn invalid_deposit_amount() {
+    let mut market = Market::open().await;
+    let buyer = market.buyer.pubkey();
+    let result = market
+        .env
+        .process(&[instruction::deposit(&buyer, 0)], &[&market.buyer])
+        .await;
+    assert_error(result, ErrorCode::InvalidDepositAmount);
+}
+
+#[tokio::test]
+async fn price_feed_mismatch() {
+    let mut market = Market::open().await;
+    let mut contract = open_american_call(&mut market).await;
+
+    // Value the underlying with the SOL feed instead of the one fixed at creation
+    contract.underlying_price_feed = market.sol_feed;
+    let result = market
+        .env
+        .process(&[instruction::exercise(&contract)], &[&market.buyer])
+        .await;
+    assert_error(result, ErrorCode::PriceFeedMismatch);
+}
+
+#[tokio::test]
+async fn invalid_oracle_price() {
+    let mut market = Market::open().await;
+    let contract = open_american_call(&mut market).await;
+
+    market.env.set_price("AAPL", 0).await;
+    let result = market
+        .env
+        .process(&[instruction::exercise(&contract)], &[&market.buyer])
+        .await;
+    assert_error(result, ErrorCode::InvalidOraclePrice);
+}
+
+#[tokio::test]
+async fn stale_oracle_price() {
+    let mut market = Market::open().await;
+    let contract = open_american_call(&mut market).await;
+
+    let published = market.env.now().await - 61;
+    market
+        .env
+        .set_quote("AAPL", 12000, 1, Some(published))
+        .await;
+    let result = market
+        .env
+        .process(&[instruction::exercise(&contract)], &[&market.buyer])
+        .await;
+    assert_error(result, ErrorCode::StaleOraclePrice);
+}
+
+#[tokio::test]
+async fn oracle_confidence_too_wide() {
+    let mut market = Market::open().await;
+    let contract = open_american_call(&mut market).await;
+
+    // The widest accepted interval is 2% of the price
+    market.env.set_quote("AAPL", 12000, 241, None).await;
+    let result = market
+        .env
+        .process(&[instruction::exercise(&contract)], &[&market.buyer])
+        .await;
+    assert_error(result, ErrorCode::OracleConfidenceTooWide);
+}
+
+#[tokio::test]
+async fn future_oracle_price() {
+    let mut market = Market::open().await;
+    let contract = open_american_call(&mut market).await;
+
+    let published = market.env.now().await + 60;
+    market
+        .env
+        .set_quote("AAPL", 12000, 1, Some(published))
+        .await;
+    let result = market
+        .env
+        .process(&[instruction::exercise(&contract)], &[&market.buyer])
+        .await;
+    assert_error(result, ErrorCode::FutureOraclePrice);
+}
+
+#[tokio::test]
+async fn untrusted_price_feed() {
+    let mut market = Market::open().await;
+    let expiration_date = market.env.now().await + DAY;
+    let mut terms = market.terms(OptionType::Call, ExerciseStyle::European, expiration_date);
+
+    // The SOL feed is the oracle's, but not for AAPL
+    terms.underlying_price_feed = terms.sol_price_feed;
+    let result = market
+        .env
+        .create_contract(&market.buyer, &market.seller, &terms)
+        .await;
+    assert_error(result, ErrorCode::UntrustedPriceFeed);
+
+    // Feeds published by anyone but the market oracle are not trusted either
+    let admin = market.env.admin.insecure_clone();
+    market
+        .env
+        .process(
+            &[instruction::set_oracle_authority(
+                &admin.pubkey(),
+                &Pubkey::new_unique(),
+            )],
+            &[&admin],
+        )
+        .await
+        .unwrap();
+    let terms = market.terms(OptionType::Call, ExerciseStyle::European, expiration_date);
+    let result = market
+        .env
+        .create_contract(&market.buyer, &market.seller, &terms)
+        .await;
+    assert_error(result, ErrorCode::UntrustedPriceFeed);
+}
+
+#[tokio::test]
+async fn offer_party_mismatch() {
+    let mut market = Market::open().await;
+    let expiration_date = market.env.now().await + DAY;
+    let terms = market.terms(OptionType::Put, ExerciseStyle::European, expiration_date);
+    let buyer = market.buyer.pubkey();
+    let seller = market.seller.pubkey();
+    market
+        .env
+        .process(
+            &[instruction::propose_contract(
+                &buyer,
+                &seller,
+                0,
+                &terms,
+                expiration_date,
+            )],
+            &[&market.buyer],
+        )
+        .await
+        .unwrap();
+    let offer: OptionOffer = market
+        .env
+        .account(&pda::offer_address(&buyer, &seller, 0))
+        .await;
+
+    // Only the seller named in the offer can reject it
+    let stranger = market.env.new_user(0).await;
+    let mut reject = instruction::reject_offer(&offer);
+    reject.accounts[0].pubkey = stranger.pubkey();
+    let result = market.env.process(&[reject], &[&stranger]).await;
+    assert_error(result, ErrorCode::OfferPartyMismatch);
+}
+
+#[tokio::test]
+async fn offer_expired() {
+    let mut market = Market::open().await;
+    let now = market.env.now().await;
+    let terms = market.terms(OptionType::Put, ExerciseStyle::European, now + DAY);
+    let buyer = market.buyer.pubkey();
+    let seller = market.seller.pubkey();
+    let valid_until = now + 60;
+    market
+        .env
+        .process(
+            &[instruction::propose_contract(
+                &buyer,
+                &seller,
+                0,
+                &terms,
+                valid_until,
+            )],
+            &[&market.buyer],
+        )
+        .await
+        .unwrap();
+    let offer: OptionOffer = market
+        .env
+        .account(&pda::offer_address(&buyer, &seller, 0))
+        .await;
+
+    // The seller can no longer take the premium once the offer lapses
+    market.env.warp_to(valid_until).await;
+    market.quote(STRIKE as i64).await;
+    let buyer_account = market.env.user_account(&buyer).await;
+    let seller_account = market.env.user_account(&seller).await;
+    let market_config = market.env.market_config().await;
+    let accept =
+        instruction::accept_contract(&offer, &buyer_account, &seller_account, &market_config);
+    let result = market.env.process(&[accept], &[&market.seller]).await;
+    assert_error(result, ErrorCode::OfferExpired);
+}
+
+#[tokio::test]
+async fn grace_period_not_elapsed() {
+    let mut market = Market::open().await;
+    let expiration_date = market.env.now().await + DAY;
+    let address = market
+        .create(OptionType::Call, ExerciseStyle::European, expiration_date)
+        .await;
+    let contract = market.env.contract(&address).await;
+
+    // The buyer still has the exercise window to act in
+    market.env.warp_to(expiration_date + DAY - 1).await;
+    let caller = market.env.payer();
+    let result = market
+        .env
+        .process(&[instruction::expire(&caller, &contract)], &[])
+        .await;
+    assert_error(result, ErrorCode::GracePeriodNotElapsed);
+}
+
+#[tokio::test]
+async fn contract_in_the_money() {
+    let mut market = Market::open().await;
+    let expiration_date = market.env.now().await + DAY;
+    let address = market
+        .create(OptionType::Call, ExerciseStyle::European, expiration_date)
+        .await;
+    let contract = market.env.contract(&address).await;
+
+    // The seller cannot take back the margin the buyer is still owed once the
+    // window has passed, which is left to the keeper crank
+    market.env.warp_to(expiration_date + DAY).await;
+    market.quote(12000).await;
+    let seller = market.seller.pubkey();
+    let result = market
+        .env
+        .process(
+            &[instruction::expire(&seller, &contract)],
+            &[&market.seller],
+        )
+        .await;
+    assert_error(result, ErrorCode::ContractInTheMoney);
+}
+
+#[tokio::test]
+async fn contract_not_finished() {
+    let mut market = Market::open().await;
+    let contract = open_american_call(&mut market).await;
+
+    let buyer = market.buyer.pubkey();
+    let result = market
+        .env
+        .process(
+            &[instruction::close_contract(&buyer, &contract)],
+            &[&market.buyer],
+        )
+        .await;
+    assert_error(result, ErrorCode::ContractNotFinished);
+}
+
+#[tokio::test]
+async fn unauthorized_close() {
+    let mut market = Market::open().await;
+    let contract = open_american_call(&mut market).await;
+    market.quote(9000).await;
+    market
+        .env
+        .process(&[instruction::exercise(&contract)], &[&market.buyer])
+        .await
+        .unwrap();
+
+    let stranger = Keypair::new();
+    let result = market
+        .env
+        .process(
+            &[instruction::close_contract(&stranger.pubkey(), &contract)],
+            &[&stranger],
+        )
+        .await;
+    assert_error(result, ErrorCode::UnauthorizedClose);
+}
+
+#[tokio::test]
+async fn exercise_window_closed() {
+    let mut market = Market::open().await;
+    let expiration_date = market.env.now().await + DAY;
+    let address = market
+        .create(OptionType::Call, ExerciseStyle::European, expiration_date)
+        .await;
+    let contract = market.env.contract(&address).await;
+
+    market.env.warp_to(expiration_date + DAY).await;
+    market.quote(12000).await;
+    let result = market
+        .env
+        .process(&[instruction::exercise(&contract)], &[&market.buyer])
+        .await;
+    assert_error(result, ErrorCode::ExerciseWindowClosed);
+}
+
+#[tokio::test]
+async fn missing_token_account() {
+    let mut env = TestEnv::start().await;
+    let mint = env.create_mint(6).await;
+    let buyer = env.new_token_user(&mint, 1_000_000_000).await;
+    let seller = env.new_token_user(&mint, 1_000_000_000).await;
+    let underlying_feed = env.set_price("AAPL", STRIKE as i64).await;
+    let sol_feed = env.set_price("SOL", SOL_PRICE).await;
+    let expiration_date = env.now().await + 7 * DAY;
+    let terms = instruction::ContractTerms {
+        underlying_asset: "AAPL".to_string(),
+        num_units: 10,
+        strike_price: STRIKE,
+        expiration_date,
+        option_type: OptionType::Call,
+        exercise_style: ExerciseStyle::American,
+        premium: 10_000_000,
+        margin_requirement_bps: 2000,
+        underlying_price_feed: underlying_feed,
+        sol_price_feed: sol_feed,
+        collateral_mint: Some(mint),
+        underlying_mint: None,
+    };
+    let address = env.create_contract(&buyer, &seller, &terms).await.unwrap();
+
+    env.set_price("AAPL", 12000).await;
+    let keeper = env.payer();
+    let contract = env.contract(&address).await;
+    env.process(&[instruction::mark_to_market(&keeper, &contract)], &[])
+        .await
+        .unwrap();
+    let contract = env.contract(&address).await;
+    env.warp_to(contract.margin_call_deadline.unwrap()).await;
+    env.set_price("AAPL", 12000).await;
+
+    // A token contract pays the liquidation bonus into a token account
+    let result = env
+        .process(&[instruction::liquidate(&keeper, &contract, None)], &[])
+        .await;
+    assert_error(result, ErrorCode::MissingTokenAccount);
+}
+
+#[tokio::test]
+async fn collateral_mint_mismatch() {
+    let mut market = Market::open().await;
+    let mint = market.env.create_mint(6).await;
+    let buyer = market.buyer.pubkey();
+    let seller = market.seller.pubkey();
+    market
+        .env
+        .process(
+            &[
+                instruction::initialize_token_escrow(&buyer, &mint),
+                instruction::initialize_token_escrow(&seller, &mint),
+            ],
+            &[&market.buyer, &market.seller],
+        )
+        .await
+        .unwrap();
+
+    let expiration_date = market.env.now().await + DAY;
+    let terms = market.terms(OptionType::Put, ExerciseStyle::European, expiration_date);
+    market
+        .env
+        .process(
+            &[instruction::propose_contract(
+                &buyer,
+                &seller,
+                0,
+                &terms,
+                expiration_date,
+            )],
+            &[&market.buyer],
+        )
+        .await
+        .unwrap();
+    let mut offer: OptionOffer = market
+        .env
+        .account(&pda::offer_address(&buyer, &seller, 0))
+        .await;
+
+    // The offer is for lamports, so accepting it against token collateral fails
+    offer.collateral_mint = Some(mint);
+    let buyer_account = market.env.user_account(&buyer).await;
+    let seller_account = market.env.user_account(&seller).await;
+    let market_config = market.env.market_config().await;
+    let accept =
+        instruction::accept_contract(&offer, &buyer_account, &seller_account, &market_config);
+    let result = market.env.process(&[accept], &[&market.seller]).await;
+    assert_error(result, ErrorCode::CollateralMintMismatch);
+}
+
+#[tokio::test]
+async fn invalid_exercise_units() {
+    let mut market = Market::open().await;
+    let contract = open_american_call(&mut market).await;
+
+    market.quote(12000).await;
+    let result = market
+        .env
+        .process(
+            &[instruction::exercise_units(&contract, 11)],
+            &[&market.buyer],
+        )
+        .await;
+    assert_error(result, ErrorCode::InvalidExerciseUnits);
+}
+
+#[tokio::test]
+async fn unauthorized_transfer() {
+    let mut market = Market::open().await;
+    let contract = open_american_call(&mut market).await;
+    let stranger = market.env.new_user(0).await;
+    let new_holder = market.env.new_user(0).await;
+    let new_holder_account = market.env.user_account(&new_holder.pubkey()).await;
+
+    let mut transfer = instruction::transfer_position(&contract, &new_holder_account, 0);
+    transfer.accounts[0].pubkey = stranger.pubkey();
+    let result = market
+        .env
+        .process(&[transfer], &[&stranger, &new_holder])
+        .await;
+    assert_error(result, ErrorCode::UnauthorizedTransfer);
+}
+
+#[tokio::test]
+async fn invalid_transfer_recipient() {
+    let mut market = Market::open().await;
+    let contract = open_american_call(&mut market).await;
+
+    // Handing the long side to the seller would net the position out
+    let seller_account = market.env.user_account(&market.seller.pubkey()).await;
+    let result = market
+        .env
+        .process(
+            &[instruction::transfer_position(
+                &contract,
+                &seller_account,
+                0,
+            )],
+            &[&market.buyer, &market.seller],
+        )
+        .await;
+    assert_error(result, ErrorCode::InvalidTransferRecipient);
+}
+
+#[tokio::test]
+async fn quote_expired() {
+    let mut market = Market::open().await;
+    let valid_until = market.env.now().await + 60;
+    let quote = post_quote(&mut market, valid_until).await;
+
+    market.env.warp_to(valid_until).await;
+    let result = fill_quote(&mut market, &quote, 1).await;
+    assert_error(result, ErrorCode::QuoteExpired);
+}
+
+#[tokio::test]
+async fn quote_not_expired() {
+    let mut market = Market::open().await;
+    let valid_until = market.env.now().await + 60;
+    let quote = post_quote(&mut market, valid_until).await;
+
+    let caller = market.env.payer();
+    let result = market
+        .env
+        .process(&[instruction::close_expired_quote(&caller, &quote)], &[])
+        .await;
+    assert_error(result, ErrorCode::QuoteNotExpired);
+}
+
+#[tokio::test]
+async fn invalid_quote_units() {
+    let mut market = Market::open().await;
+    let valid_until = market.env.now().await + 60;
+    let quote = post_quote(&mut market, valid_until).await;
+
+    let result = fill_quote(&mut market, &quote, quote.remaining_units + 1).await;
+    assert_error(result, ErrorCode::InvalidQuoteUnits);
+}
+
+#[tokio::test]
+async fn unauthorized_top_up() {
+    let mut market = Market::open().await;
+    let contract = open_american_call(&mut market).await;
+
+    let mut top_up = instruction::top_up_margin(&contract, 1000);
+    top_up.accounts[0].pubkey = market.buyer.pubkey();
+    let result = market.env.process(&[top_up], &[&market.buyer]).await;
+    assert_error(result, ErrorCode::UnauthorizedTopUp);
+}
+
+#[tokio::test]
+async fn no_margin_call() {
+    let mut market = Market::open().await;
+    let contract = open_american_call(&mut market).await;
+
+    let liquidator = market.env.payer();
+    let result = market
+        .env
+        .process(&[instruction::liquidate(&liquidator, &contract, None)], &[])
+        .await;
+    assert_error(result, ErrorCode::NoMarginCall);
+}
+
+#[tokio::test]
+async fn margin_call_not_due() {
+    let mut market = Market::open().await;
+    let contract = open_margin_call(&mut market).await;
+
+    market
+        .env
+        .warp_to(contract.margin_call_deadline.unwrap() - 1)
+        .await;
+    market.quote(12000).await;
+    let liquidator = market.env.payer();
+    let result = market
+        .env
+        .process(&[instruction::liquidate(&liquidator, &contract, None)], &[])
+        .await;
+    assert_error(result, ErrorCode::MarginCallNotDue);
+}
+
+#[tokio::test]
+async fn contract_not_undercollateralized() {
+    let mut market = Market::open().await;
+    let contract = open_margin_call(&mut market).await;
+
+    // The price falls back to the strike before the deadline runs out
+    market
+        .env
+        .warp_to(contract.margin_call_deadline.unwrap())
+        .await;
+    market.quote(STRIKE as i64).await;
+    let liquidator = market.env.payer();
+    let result = market
+        .env
+        .process(&[instruction::liquidate(&liquidator, &contract, None)], &[])
+        .await;
+    assert_error(result, ErrorCode::ContractNotUndercollateralized);
+}
+
+#[tokio::test]
+async fn unauthorized_admin() {
+    let mut env = TestEnv::start().await;
+    let stranger = Keypair::new();
+    let result = env
+        .process(
+            &[instruction::set_market_paused(&stranger.pubkey(), true)],
+            &[&stranger],
+        )
+        .await;
+    assert_error(result, ErrorCode::UnauthorizedAdmin);
+}
+
+#[tokio::test]
+async fn invalid_market_config() {
+    let mut env = TestEnv::start().await;
+    let admin = env.admin.insecure_clone();
+    let update = instruction::update_market_config(&admin.pubkey(), 5000, 500, 0, DAY);
+    let result = env.process(&[update], &[&admin]).await;
+    assert_error(result, ErrorCode::InvalidMarketConfig);
+}
+
+#[tokio::test]
+async fn market_paused() {
+    let mut market = Market::open().await;
+    let contract = open_american_call(&mut market).await;
+    let new_holder = market.env.new_user(DEPOSIT).await;
+    let new_holder_account = market.env.user_account(&new_holder.pubkey()).await;
+    let admin = market.env.admin.insecure_clone();
+    market
+        .env
+        .process(
+            &[instruction::set_market_paused(&admin.pubkey(), true)],
+            &[&admin],
+        )
+        .await
+        .unwrap();
+
+    let expiration_date = market.env.now().await + DAY;
+    let terms = market.terms(OptionType::Call, ExerciseStyle::European, expiration_date);
+    let result = market
+        .env
+        .create_contract(&market.buyer, &market.seller, &terms)
+        .await;
+    assert_error(result, ErrorCode::MarketPaused);
+
+    // Open positions are frozen too
+    let result = market
+        .env
+        .process(
+            &[instruction::transfer_position(
+                &contract,
+                &new_holder_account,
+                0,
+            )],
+            &[&market.buyer, &new_holder],
+        )
+        .await;
+    assert_error(result, ErrorCode::MarketPaused);
+
+    let keeper = market.env.payer();
+    let result = market
+        .env
+        .process(&[instruction::liquidate(&keeper, &contract, None)], &[])
+        .await;
+    assert_error(result, ErrorCode::MarketPaused);
+
+    market.env.warp_to(contract.expiration_date + DAY).await;
+    let result = market
+        .env
+        .process(&[instruction::expire(&keeper, &contract)], &[])
+        .await;
+    assert_error(result, ErrorCode::MarketPaused);
+}
+
+#[tokio::test]
+async fn margin_out_of_range() {
+    let mut market = Market::open().await;
+    let expiration_date = market.env.now().await + DAY;
+    let mut terms = market.terms(OptionType::Call, ExerciseStyle::European, expiration_date);
+    terms.margin_requirement_bps = MIN_MARGIN_BPS - 1;
+
+    let result = market
+        .env
+        .create_contract(&market.buyer, &market.seller, &terms)
+        .await;
+    assert_error(result, ErrorCode::MarginOutOfRange);
+}
+
+#[tokio::test]
+async fn expiry_out_of_range() {
+    let mut market = Market::open().await;
+    let expiration_date = market.env.now().await + MAX_EXPIRY_SECONDS + 1;
+    let terms = market.terms(OptionType::Call, ExerciseStyle::European, expiration_date);
+
+    let result = market
+        .env
+        .create_contract(&market.buyer, &market.seller, &terms)
+        .await;
+    assert_error(result, ErrorCode::ExpiryOutOfRange);
+}
+
+#[tokio::test]
+async fn fee_too_high() {
+    let mut env = TestEnv::start().await;
+    let admin = env.admin.insecure_clone();
+    let result = env
+        .process(
+            &[instruction::set_protocol_fees(&admin.pubkey(), 1001, 0)],
+            &[&admin],
+        )
+        .await;
+    assert_error(result, ErrorCode::FeeTooHigh);
+}
+
+#[tokio::test]
+async fn insufficient_treasury_balance() {
+    let mut env = TestEnv::start().await;
+    let admin = env.admin.insecure_clone();
+    let result = env
+        .process(
+            &[instruction::collect_fees(
+                &admin.pubkey(),
+                &admin.pubkey(),
+                1,
+            )],
+            &[&admin],
+        )
+        .await;
+    assert_error(result, ErrorCode::InsufficientTreasuryBalance);
+}
+
+// An American put delivering a fresh underlying mint against a stablecoin
+// strike, returned with its buyer and the underlying mint
+async fn open_physical_put() -> (TestEnv, Keypair, basic::OptionContract, Pubkey) {
+    let mut env = TestEnv::start().await;
+    let mint = env.create_mint(6).await;
+    let underlying = env.create_mint(9).await;
+    let buyer = env.new_token_user(&mint, 1_000_000_000).await;
+    let seller = env.new_token_user(&mint, 1_000_000_000).await;
+    env.fund_token_escrow(&seller, &underlying, 0).await;
+    let underlying_feed = env.set_price("AAPL", STRIKE as i64).await;
+    let sol_feed = env.set_price("SOL", SOL_PRICE).await;
+    let expiration_date = env.now().await + 7 * DAY;
+    let terms = instruction::ContractTerms {
+        underlying_asset: "AAPL".to_string(),
+        num_units: 10,
+        strike_price: STRIKE,
+        expiration_date,
+        option_type: OptionType::Put,
+        exercise_style: ExerciseStyle::American,
+        premium: 10_000_000,
+        margin_requirement_bps: 2000,
+        underlying_price_feed: underlying_feed,
+        sol_price_feed: sol_feed,
+        collateral_mint: Some(mint),
+        underlying_mint: Some(underlying),
+    };
+    let address = env.create_contract(&buyer, &seller, &terms).await.unwrap();
+    let contract = env.contract(&address).await;
+    (env, buyer, contract, underlying)
+}
+
+#[tokio::test]
+async fn settlement_kind_mismatch() {
+    let (mut env, buyer, contract, _) = open_physical_put().await;
+
+    // Physically settled contracts are exercised by delivery, not for cash
+    let result = env
+        .process(&[instruction::exercise(&contract)], &[&buyer])
+        .await;
+    assert_error(result, ErrorCode::SettlementKindMismatch);
+}
+
+#[tokio::test]
+async fn underlying_mint_mismatch() {
+    let (mut env, buyer, contract, underlying) = open_physical_put().await;
+
+    // Deliver out of the buyer's stablecoin escrow instead of the underlying
+    let mut ix = instruction::exercise_physical(&contract);
+    let underlying_escrow = pda::token_escrow_address(&buyer.pubkey(), &underlying);
+    let collateral_escrow =
+        pda::token_escrow_address(&buyer.pubkey(), &contract.collateral_mint.unwrap());
+    ix.accounts
+        .iter_mut()
+        .find(|meta| meta.pubkey == underlying_escrow)
+        .unwrap()
+        .pubkey = collateral_escrow;
+    let result = env.process(&[ix], &[&buyer]).await;
+    assert_error(result, ErrorCode::UnderlyingMintMismatch);
+}
+
+#[tokio::test]
+async fn physical_settlement_requires_mint() {
+    let mut market = Market::open().await;
+    let underlying = market.env.create_mint(9).await;
+    let expiration_date = market.env.now().await + DAY;
+    let mut terms = market.terms(OptionType::Put, ExerciseStyle::European, expiration_date);
+
+    // A lamport contract has no stablecoin to pay the strike in
+    terms.underlying_mint = Some(underlying);
+    let result = market
+        .env
+        .create_contract(&market.buyer, &market.seller, &terms)
+        .await;
+    assert_error(result, ErrorCode::PhysicalSettlementRequiresMint);
+}
+
+// Lamport-collateralized strategy terms for a bull call spread on AAPL
+fn spread_terms(market: &Market, legs: Vec<StrategyLeg>, expiration_date: i64) -> StrategyTerms {
+    StrategyTerms {
+        underlying_asset: "AAPL".to_string(),
+        expiration_date,
+        legs,
+        premium: LAMPORTS_PER_SOL / 10,
+        margin_requirement_bps: 2000,
+        underlying_price_feed: market.underlying_feed,
+        sol_price_feed: market.sol_feed,
+        collateral_mint: None,
+    }
+}
+
+fn call_leg(side: LegSide, strike_price: u64) -> StrategyLeg {
+    StrategyLeg {
+        option_type: OptionType::Call,
+        side,
+        strike_price,
+        num_units: 10,
+    }
+}
+
+#[tokio::test]
+async fn invalid_strategy_legs() {
+    let mut market = Market::open().await;
+    let expiration_date = market.env.now().await + DAY;
+
+    // A single leg is a plain contract
+    let terms = spread_terms(
+        &market,
+        vec![call_leg(LegSide::Long, STRIKE)],
+        expiration_date,
+    );
+    let result = market
+        .env
+        .create_strategy(&market.buyer, &market.seller, &terms)
+        .await;
+    assert_error(result, ErrorCode::InvalidStrategyLegs);
+}
+
+#[tokio::test]
+async fn not_strategy_counterparty() {
+    let mut market = Market::open().await;
+    let expiration_date = market.env.now().await + DAY;
+    let terms = spread_terms(
+        &market,
+        vec![
+            call_leg(LegSide::Long, STRIKE),
+            call_leg(LegSide::Short, STRIKE + 1000),
+        ],
+        expiration_date,
+    );
+    let address = market
+        .env
+        .create_strategy(&market.buyer, &market.seller, &terms)
+        .await
+        .unwrap();
+    let strategy = market.env.strategy(&address).await;
+
+    market.env.warp_to(expiration_date).await;
+    market.quote(STRIKE as i64).await;
+    let outsider = market.env.new_user(0).await;
+    let result = market
+        .env
+        .process(
+            &[instruction::exercise_strategy(
+                &outsider.pubkey(),
+                &strategy,
+            )],
+            &[&outsider],
+        )
+        .await;
+    assert_error(result, ErrorCode::NotStrategyCounterparty);
+}
+
+#[tokio::test]
+async fn user_account_mismatch() {
+    let mut market = Market::open().await;
+    let expiration_date = market.env.now().await + DAY;
+    let terms = market.terms(OptionType::Call, ExerciseStyle::European, expiration_date);
+    let outsider = market.env.new_user(DEPOSIT).await;
+    let outsider_account = market.env.user_account(&outsider.pubkey()).await;
+    let seller_account = market.env.user_account(&market.seller.pubkey()).await;
+    let market_config = market.env.market_config().await;
+
+    // The buyer signs but opens the contract against another user's accounts
+    let mut create =
+        instruction::create_contract(&outsider_account, &seller_account, &market_config, &terms);
+    let buyer = market.buyer.pubkey();
+    create.accounts[0].pubkey = buyer;
+    create.accounts[5].pubkey = buyer;
+    let result = market
+        .env
+        .process(&[create], &[&market.buyer, &market.seller])
+        .await;
+    assert_error(result, ErrorCode::UserAccountMismatch);
+}
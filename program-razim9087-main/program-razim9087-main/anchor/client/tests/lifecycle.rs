@@ -0,0 +1,339 @@
+//! Full contract lifecycles run against the program in-process.
+
+mod common;
+
+use basic::{ContractStatus, ExerciseStyle, OptionType};
+use basic_client::instruction;
+use common::*;
+use solana_sdk::signature::{Keypair, Signer};
+
+async fn exercise_and_settle_in_the_money(
+    option_type: OptionType,
+    exercise_style: ExerciseStyle,
+    underlying_price: i64,
+) {
+    let mut market = Market::open().await;
+    let expiration_date = market.env.now().await + DAY;
+    let seller_wallet = market.seller.pubkey();
+    let seller_wallet_before = market.env.lamports(&seller_wallet).await;
+
+    let address = market
+        .create(option_type, exercise_style, expiration_date)
+        .await;
+
+    let contract = market.env.contract(&address).await;
+    let premium = contract.premium;
+    let margin = contract.margin_amount;
+    assert_eq!(contract.status, ContractStatus::Active);
+    assert_eq!(margin, margin_lamports(10));
+    assert_eq!(
+        market.env.escrow_balance(&market.buyer.pubkey()).await,
+        DEPOSIT + rent_exempt_escrow() - premium
+    );
+    assert_eq!(
+        market.env.escrow_balance(&seller_wallet).await,
+        DEPOSIT + rent_exempt_escrow() - margin
+    );
+    assert_eq!(
+        market.env.lamports(&seller_wallet).await,
+        seller_wallet_before + premium
+    );
+
+    // European contracts only become exercisable at expiry
+    if exercise_style == ExerciseStyle::European {
+        market.env.warp_to(expiration_date).await;
+    }
+    market.quote(underlying_price).await;
+
+    market
+        .env
+        .process(&[instruction::exercise(&contract)], &[&market.buyer])
+        .await
+        .unwrap();
+
+    let profit_per_unit = (underlying_price as u64).abs_diff(STRIKE);
+    let payoff = payoff_lamports(profit_per_unit, 10);
+    let contract = market.env.contract(&address).await;
+    assert_eq!(contract.status, ContractStatus::Exercised);
+    assert_eq!(contract.buyer_pending_balance, payoff);
+    assert_eq!(contract.seller_pending_balance, payoff);
+
+    market
+        .env
+        .process(
+            &[instruction::settle(&seller_wallet, &contract)],
+            &[&market.seller],
+        )
+        .await
+        .unwrap();
+
+    // The buyer is paid out of the margin and the rest of it is released
+    let contract = market.env.contract(&address).await;
+    assert_eq!(contract.status, ContractStatus::Settled);
+    assert_eq!(contract.seller_pending_balance, 0);
+    let buyer_escrow = DEPOSIT + rent_exempt_escrow() - premium + payoff;
+    assert_eq!(
+        market.env.escrow_balance(&market.buyer.pubkey()).await,
+        buyer_escrow
+    );
+    assert_eq!(
+        market.env.escrow_balance(&seller_wallet).await,
+        DEPOSIT + rent_exempt_escrow() - payoff
+    );
+
+    let buyer_wallet = market.buyer.pubkey();
+    let buyer_wallet_before = market.env.lamports(&buyer_wallet).await;
+    let withdrawal = buyer_escrow - rent_exempt_escrow();
+    market
+        .env
+        .process(
+            &[instruction::withdraw(&buyer_wallet, withdrawal)],
+            &[&market.buyer],
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        market.env.lamports(&buyer_wallet).await,
+        buyer_wallet_before + withdrawal
+    );
+
+    market
+        .env
+        .process(
+            &[instruction::close_contract(&buyer_wallet, &contract)],
+            &[&market.buyer],
+        )
+        .await
+        .unwrap();
+    assert!(market
+        .env
+        .try_account::<basic::OptionContract>(&address)
+        .await
+        .is_none());
+}
+
+#[tokio::test]
+async fn european_call_in_the_money_settles() {
+    exercise_and_settle_in_the_money(OptionType::Call, ExerciseStyle::European, 12000).await;
+}
+
+#[tokio::test]
+async fn european_put_in_the_money_settles() {
+    exercise_and_settle_in_the_money(OptionType::Put, ExerciseStyle::European, 8000).await;
+}
+
+#[tokio::test]
+async fn american_call_exercises_before_expiry() {
+    exercise_and_settle_in_the_money(OptionType::Call, ExerciseStyle::American, 11000).await;
+}
+
+#[tokio::test]
+async fn american_put_exercises_before_expiry() {
+    exercise_and_settle_in_the_money(OptionType::Put, ExerciseStyle::American, 9500).await;
+}
+
+#[tokio::test]
+async fn out_of_the_money_exercise_releases_margin() {
+    let mut market = Market::open().await;
+    let expiration_date = market.env.now().await + DAY;
+    let address = market
+        .create(OptionType::Call, ExerciseStyle::European, expiration_date)
+        .await;
+    let contract = market.env.contract(&address).await;
+
+    market.env.warp_to(expiration_date).await;
+    market.quote(9000).await;
+    market
+        .env
+        .process(&[instruction::exercise(&contract)], &[&market.buyer])
+        .await
+        .unwrap();
+
+    let contract = market.env.contract(&address).await;
+    assert_eq!(contract.status, ContractStatus::Expired);
+    assert_eq!(contract.buyer_pending_balance, 0);
+    assert_eq!(
+        market.env.escrow_balance(&market.seller.pubkey()).await,
+        DEPOSIT + rent_exempt_escrow()
+    );
+}
+
+#[tokio::test]
+async fn unexercised_contract_expires_after_the_window() {
+    let mut market = Market::open().await;
+    let expiration_date = market.env.now().await + DAY;
+    let address = market
+        .create(OptionType::Put, ExerciseStyle::European, expiration_date)
+        .await;
+    let contract = market.env.contract(&address).await;
+
+    market.env.warp_to(expiration_date + DAY).await;
+    market.quote(STRIKE as i64).await;
+    let keeper = market.env.payer();
+    market
+        .env
+        .process(&[instruction::expire(&keeper, &contract)], &[])
+        .await
+        .unwrap();
+
+    let contract = market.env.contract(&address).await;
+    assert_eq!(contract.status, ContractStatus::Expired);
+    assert_eq!(
+        market.env.escrow_balance(&market.seller.pubkey()).await,
+        DEPOSIT + rent_exempt_escrow()
+    );
+
+    let seller = market.seller.pubkey();
+    market
+        .env
+        .process(
+            &[instruction::close_contract(&seller, &contract)],
+            &[&market.seller],
+        )
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn partial_exercise_settles_each_tranche() {
+    let mut market = Market::open().await;
+    let expiration_date = market.env.now().await + DAY;
+    let address = market
+        .create(OptionType::Call, ExerciseStyle::American, expiration_date)
+        .await;
+    market.quote(11000).await;
+
+    let contract = market.env.contract(&address).await;
+    market
+        .env
+        .process(
+            &[instruction::exercise_units(&contract, 4)],
+            &[&market.buyer],
+        )
+        .await
+        .unwrap();
+
+    let contract = market.env.contract(&address).await;
+    assert_eq!(contract.status, ContractStatus::Active);
+    assert_eq!(contract.units_exercised, 4);
+    let premium = contract.premium;
+    assert_eq!(
+        market.env.escrow_balance(&market.buyer.pubkey()).await,
+        DEPOSIT + rent_exempt_escrow() - premium + payoff_lamports(1000, 4)
+    );
+
+    market
+        .env
+        .process(
+            &[instruction::exercise_units(&contract, 6)],
+            &[&market.buyer],
+        )
+        .await
+        .unwrap();
+
+    let contract = market.env.contract(&address).await;
+    assert_eq!(contract.status, ContractStatus::Settled);
+    assert_eq!(contract.units_exercised, 10);
+    assert_eq!(
+        market.env.escrow_balance(&market.buyer.pubkey()).await,
+        DEPOSIT + rent_exempt_escrow() - premium + payoff_lamports(1000, 10)
+    );
+}
+
+#[tokio::test]
+async fn missed_margin_call_is_liquidated() {
+    let mut market = Market::open().await;
+    let expiration_date = market.env.now().await + 7 * DAY;
+    let address = market
+        .create(OptionType::Call, ExerciseStyle::European, expiration_date)
+        .await;
+
+    // The call moves deep into the money and the locked margin no longer covers it
+    market.quote(12000).await;
+    let keeper = Keypair::new();
+    market.env.fund(&keeper.pubkey(), LAMPORTS_PER_SOL).await;
+    let contract = market.env.contract(&address).await;
+    market
+        .env
+        .process(
+            &[instruction::mark_to_market(&keeper.pubkey(), &contract)],
+            &[&keeper],
+        )
+        .await
+        .unwrap();
+
+    let contract = market.env.contract(&address).await;
+    let deadline = contract.margin_call_deadline.expect("margin call");
+    assert_eq!(deadline, market.env.now().await + 3600);
+    assert_eq!(
+        contract.required_margin,
+        contract.margin_amount + payoff_lamports(2000, 10)
+    );
+
+    market.env.warp_to(deadline).await;
+    market.quote(12000).await;
+    let keeper_before = market.env.lamports(&keeper.pubkey()).await;
+    market
+        .env
+        .process(
+            &[instruction::liquidate(&keeper.pubkey(), &contract, None)],
+            &[&keeper],
+        )
+        .await
+        .unwrap();
+
+    // The keeper earns 5% of the locked margin and the buyer the full payoff
+    let contract = market.env.contract(&address).await;
+    assert_eq!(contract.status, ContractStatus::Liquidated);
+    assert_eq!(contract.margin_call_deadline, None);
+    let bonus = contract.margin_amount * 500 / 10000;
+    assert_eq!(
+        market.env.lamports(&keeper.pubkey()).await,
+        keeper_before + bonus
+    );
+    assert_eq!(
+        market.env.escrow_balance(&market.buyer.pubkey()).await,
+        DEPOSIT + rent_exempt_escrow() - contract.premium + payoff_lamports(2000, 10)
+    );
+}
+
+#[tokio::test]
+async fn topped_up_margin_answers_the_margin_call() {
+    let mut market = Market::open().await;
+    let expiration_date = market.env.now().await + 7 * DAY;
+    let address = market
+        .create(OptionType::Put, ExerciseStyle::American, expiration_date)
+        .await;
+
+    market.quote(9000).await;
+    let keeper = market.env.payer();
+    let contract = market.env.contract(&address).await;
+    market
+        .env
+        .process(&[instruction::mark_to_market(&keeper, &contract)], &[])
+        .await
+        .unwrap();
+    let contract = market.env.contract(&address).await;
+    assert!(contract.margin_call_deadline.is_some());
+    assert_eq!(
+        contract.required_margin,
+        contract.margin_amount + payoff_lamports(1000, 10)
+    );
+
+    let shortfall = contract.required_margin - contract.margin_amount;
+    market
+        .env
+        .process(
+            &[instruction::top_up_margin(&contract, shortfall)],
+            &[&market.seller],
+        )
+        .await
+        .unwrap();
+
+    let contract = market.env.contract(&address).await;
+    assert_eq!(contract.margin_call_deadline, None);
+    assert_eq!(
+        market.env.escrow_balance(&market.seller.pubkey()).await,
+        DEPOSIT + rent_exempt_escrow() - contract.required_margin
+    );
+}
@@ -0,0 +1,333 @@
+//! Physically settled contracts, delivering a tokenized underlying against a
+//! strike paid in a USD stablecoin.
+
+mod common;
+
+use basic::{ContractStatus, ExerciseStyle, OptionType, SettlementKind, UserRole};
+use basic_client::instruction::{self, ContractTerms};
+use basic_client::pda;
+use common::*;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+
+// One whole underlying token per unit, at 9 decimals
+const UNDERLYING: u64 = 1_000_000_000;
+// The stablecoin has 6 decimals, so one cent is 10_000 base units
+const CENT: u64 = 10_000;
+const PREMIUM: u64 = 500 * CENT;
+const UNITS: u64 = 10;
+const STRIKE_PAYMENT: u64 = UNITS * STRIKE * CENT;
+
+struct Delivery {
+    env: TestEnv,
+    buyer: Keypair,
+    seller: Keypair,
+    usdc: Pubkey,
+    underlying: Pubkey,
+    underlying_feed: Pubkey,
+    sol_feed: Pubkey,
+}
+
+impl Delivery {
+    // Both parties hold `usdc` in escrow and have an escrow for the underlying,
+    // funded with `buyer_underlying` and `seller_underlying`
+    async fn open(usdc: u64, buyer_underlying: u64, seller_underlying: u64) -> Self {
+        let mut env = TestEnv::start().await;
+        let usdc_mint = env.create_mint(6).await;
+        let underlying = env.create_mint(9).await;
+        let buyer = env.new_token_user(&usdc_mint, usdc).await;
+        let seller = env.new_token_user(&usdc_mint, usdc).await;
+        env.fund_token_escrow(&buyer, &underlying, buyer_underlying)
+            .await;
+        env.fund_token_escrow(&seller, &underlying, seller_underlying)
+            .await;
+        let underlying_feed = env.set_price("AAPL", STRIKE as i64).await;
+        let sol_feed = env.set_price("SOL", SOL_PRICE).await;
+        Self {
+            env,
+            buyer,
+            seller,
+            usdc: usdc_mint,
+            underlying,
+            underlying_feed,
+            sol_feed,
+        }
+    }
+
+    fn terms(
+        &self,
+        option_type: OptionType,
+        exercise_style: ExerciseStyle,
+        expiration_date: i64,
+    ) -> ContractTerms {
+        ContractTerms {
+            underlying_asset: "AAPL".to_string(),
+            num_units: UNITS,
+            strike_price: STRIKE,
+            expiration_date,
+            option_type,
+            exercise_style,
+            premium: PREMIUM,
+            margin_requirement_bps: 2000,
+            underlying_price_feed: self.underlying_feed,
+            sol_price_feed: self.sol_feed,
+            collateral_mint: Some(self.usdc),
+            underlying_mint: Some(self.underlying),
+        }
+    }
+
+    async fn create(
+        &mut self,
+        option_type: OptionType,
+        exercise_style: ExerciseStyle,
+        expiration_date: i64,
+    ) -> Pubkey {
+        let terms = self.terms(option_type, exercise_style, expiration_date);
+        self.env
+            .create_contract(&self.buyer, &self.seller, &terms)
+            .await
+            .unwrap()
+    }
+
+    async fn balance(&mut self, user: &Pubkey, mint: &Pubkey) -> u64 {
+        self.env
+            .token_balance(&pda::token_escrow_address(user, mint))
+            .await
+    }
+}
+
+#[tokio::test]
+async fn physical_call_delivers_the_underlying_for_the_strike() {
+    let mut delivery = Delivery::open(2 * STRIKE_PAYMENT, 0, UNITS * UNDERLYING).await;
+    let buyer = delivery.buyer.pubkey();
+    let seller = delivery.seller.pubkey();
+    let (usdc, underlying) = (delivery.usdc, delivery.underlying);
+    let expiration_date = delivery.env.now().await + DAY;
+
+    let address = delivery
+        .create(OptionType::Call, ExerciseStyle::American, expiration_date)
+        .await;
+
+    // The seller's underlying is locked instead of a margin
+    let contract = delivery.env.contract(&address).await;
+    assert_eq!(contract.settlement_kind, SettlementKind::Physical);
+    assert_eq!(contract.underlying_mint, Some(underlying));
+    assert_eq!(contract.margin_amount, 0);
+    let underlying_vault = pda::underlying_vault_address(&address);
+    assert_eq!(
+        delivery.env.token_balance(&underlying_vault).await,
+        UNITS * UNDERLYING
+    );
+    assert_eq!(delivery.balance(&seller, &underlying).await, 0);
+
+    // Physical exercise reads no oracle prices
+    delivery
+        .env
+        .process(
+            &[instruction::exercise_physical(&contract)],
+            &[&delivery.buyer],
+        )
+        .await
+        .unwrap();
+
+    let contract = delivery.env.contract(&address).await;
+    assert_eq!(contract.status, ContractStatus::Settled);
+    assert_eq!(contract.units_exercised, UNITS);
+    assert_eq!(
+        delivery.balance(&buyer, &underlying).await,
+        UNITS * UNDERLYING
+    );
+    assert_eq!(
+        delivery.balance(&buyer, &usdc).await,
+        STRIKE_PAYMENT - PREMIUM
+    );
+    assert_eq!(
+        delivery.balance(&seller, &usdc).await,
+        3 * STRIKE_PAYMENT + PREMIUM
+    );
+    assert_eq!(delivery.env.token_balance(&underlying_vault).await, 0);
+
+    // Closing also reclaims the emptied underlying vault
+    delivery
+        .env
+        .process(
+            &[instruction::close_contract(&buyer, &contract)],
+            &[&delivery.buyer],
+        )
+        .await
+        .unwrap();
+    assert_eq!(delivery.env.lamports(&address).await, 0);
+    assert_eq!(delivery.env.lamports(&underlying_vault).await, 0);
+    assert_eq!(
+        delivery
+            .env
+            .lamports(&pda::collateral_vault_address(&address))
+            .await,
+        0
+    );
+}
+
+#[tokio::test]
+async fn physical_put_pays_the_strike_for_the_underlying() {
+    let mut delivery = Delivery::open(STRIKE_PAYMENT, UNITS * UNDERLYING, 0).await;
+    let buyer = delivery.buyer.pubkey();
+    let seller = delivery.seller.pubkey();
+    let (usdc, underlying) = (delivery.usdc, delivery.underlying);
+    let expiration_date = delivery.env.now().await + DAY;
+
+    let address = delivery
+        .create(OptionType::Put, ExerciseStyle::European, expiration_date)
+        .await;
+
+    // Puts lock margin like cash contracts, and no underlying
+    let contract = delivery.env.contract(&address).await;
+    let margin = STRIKE_PAYMENT * 2000 / 10000;
+    assert_eq!(contract.margin_amount, margin);
+    assert_eq!(
+        delivery.balance(&seller, &usdc).await,
+        STRIKE_PAYMENT + PREMIUM - margin
+    );
+    assert_eq!(
+        delivery
+            .env
+            .lamports(&pda::underlying_vault_address(&address))
+            .await,
+        0
+    );
+
+    delivery.env.warp_to(expiration_date).await;
+    delivery
+        .env
+        .process(
+            &[instruction::exercise_physical(&contract)],
+            &[&delivery.buyer],
+        )
+        .await
+        .unwrap();
+
+    // The margin pays the first part of the strike, the seller escrow the rest
+    let contract = delivery.env.contract(&address).await;
+    assert_eq!(contract.status, ContractStatus::Settled);
+    assert_eq!(delivery.balance(&buyer, &underlying).await, 0);
+    assert_eq!(
+        delivery.balance(&seller, &underlying).await,
+        UNITS * UNDERLYING
+    );
+    assert_eq!(
+        delivery.balance(&buyer, &usdc).await,
+        2 * STRIKE_PAYMENT - PREMIUM
+    );
+    assert_eq!(delivery.balance(&seller, &usdc).await, PREMIUM);
+    assert_eq!(
+        delivery
+            .env
+            .token_balance(&pda::collateral_vault_address(&address))
+            .await,
+        0
+    );
+}
+
+#[tokio::test]
+async fn unexercised_physical_call_returns_the_underlying() {
+    let mut delivery = Delivery::open(STRIKE_PAYMENT, 0, UNITS * UNDERLYING).await;
+    let seller = delivery.seller.pubkey();
+    let underlying = delivery.underlying;
+    let expiration_date = delivery.env.now().await + DAY;
+
+    let address = delivery
+        .create(OptionType::Call, ExerciseStyle::European, expiration_date)
+        .await;
+    let contract = delivery.env.contract(&address).await;
+
+    delivery.env.warp_to(expiration_date + DAY).await;
+    delivery
+        .env
+        .process(
+            &[instruction::expire(&seller, &contract)],
+            &[&delivery.seller],
+        )
+        .await
+        .unwrap();
+
+    let contract = delivery.env.contract(&address).await;
+    assert_eq!(contract.status, ContractStatus::Expired);
+    assert_eq!(
+        delivery.balance(&seller, &underlying).await,
+        UNITS * UNDERLYING
+    );
+
+    delivery
+        .env
+        .process(
+            &[instruction::close_contract(&seller, &contract)],
+            &[&delivery.seller],
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        delivery
+            .env
+            .lamports(&pda::underlying_vault_address(&address))
+            .await,
+        0
+    );
+}
+
+#[tokio::test]
+async fn terminated_physical_call_returns_the_underlying() {
+    let mut delivery = Delivery::open(STRIKE_PAYMENT, 0, UNITS * UNDERLYING).await;
+    let buyer = delivery.buyer.pubkey();
+    let seller = delivery.seller.pubkey();
+    let usdc = delivery.usdc;
+    let underlying = delivery.underlying;
+    let expiration_date = delivery.env.now().await + DAY;
+
+    let address = delivery
+        .create(OptionType::Call, ExerciseStyle::American, expiration_date)
+        .await;
+    let contract = delivery.env.contract(&address).await;
+    let buyer_usdc = delivery.balance(&buyer, &usdc).await;
+    let seller_usdc = delivery.balance(&seller, &usdc).await;
+
+    // The seller buys the call back for the premium it was sold at
+    delivery
+        .env
+        .process(
+            &[instruction::terminate_contract(
+                &contract,
+                PREMIUM,
+                UserRole::Seller,
+            )],
+            &[&delivery.buyer, &delivery.seller],
+        )
+        .await
+        .unwrap();
+
+    let contract = delivery.env.contract(&address).await;
+    assert_eq!(contract.status, ContractStatus::Terminated);
+    assert_eq!(delivery.balance(&buyer, &usdc).await, buyer_usdc + PREMIUM);
+    assert_eq!(delivery.balance(&seller, &usdc).await, seller_usdc - PREMIUM);
+    assert_eq!(
+        delivery.balance(&seller, &underlying).await,
+        UNITS * UNDERLYING
+    );
+}
+
+#[tokio::test]
+async fn physical_call_needs_the_strike_in_escrow() {
+    let mut delivery = Delivery::open(PREMIUM, 0, UNITS * UNDERLYING).await;
+    let expiration_date = delivery.env.now().await + DAY;
+    let address = delivery
+        .create(OptionType::Call, ExerciseStyle::American, expiration_date)
+        .await;
+    let contract = delivery.env.contract(&address).await;
+
+    let result = delivery
+        .env
+        .process(
+            &[instruction::exercise_physical(&contract)],
+            &[&delivery.buyer],
+        )
+        .await;
+    assert_error(result, basic::ErrorCode::InsufficientBalance);
+}
@@ -0,0 +1,139 @@
+//! Assets registered with a finer price precision than cents, with oracle
+//! quotes rescaled to it and payoffs rounded down.
+
+mod common;
+
+use basic::{ContractStatus, ExerciseStyle, OptionType};
+use basic_client::instruction::{self, ContractTerms};
+use basic_client::pda;
+use common::*;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+
+// NVDA strikes and prices are in hundredths of a cent
+const PRICE_EXPO: i32 = -4;
+// $187.3525
+const STRIKE_PRICE: u64 = 1_873_525;
+// $190.12345678 at the oracle's precision, $190.1234 once rounded down
+const ORACLE_PRICE: i64 = 19_012_345_678;
+const ORACLE_EXPO: i32 = -8;
+const UNITS: u64 = 10;
+// The stablecoin has 6 decimals, so one dollar is 1_000_000 base units
+const USD: u64 = 1_000_000;
+
+fn terms(
+    underlying_feed: Pubkey,
+    sol_feed: Pubkey,
+    expiration_date: i64,
+    premium: u64,
+    collateral_mint: Option<Pubkey>,
+) -> ContractTerms {
+    ContractTerms {
+        underlying_asset: "NVDA".to_string(),
+        num_units: UNITS,
+        strike_price: STRIKE_PRICE,
+        expiration_date,
+        option_type: OptionType::Call,
+        exercise_style: ExerciseStyle::American,
+        premium,
+        margin_requirement_bps: 2000,
+        underlying_price_feed: underlying_feed,
+        sol_price_feed: sol_feed,
+        collateral_mint,
+        underlying_mint: None,
+    }
+}
+
+#[tokio::test]
+async fn sub_cent_strike_settles_in_stablecoin() {
+    let mut env = TestEnv::start().await;
+    env.register_asset("NVDA", PRICE_EXPO).await;
+    let usdc = env.create_mint(6).await;
+    let buyer = env.new_token_user(&usdc, 500 * USD).await;
+    let seller = env.new_token_user(&usdc, 1000 * USD).await;
+    let underlying_feed = env
+        .set_scaled_price("NVDA", 18_735_250_000, ORACLE_EXPO)
+        .await;
+    let sol_feed = env.set_price("SOL", SOL_PRICE).await;
+    let expiration_date = env.now().await + DAY;
+
+    let terms = terms(
+        underlying_feed,
+        sol_feed,
+        expiration_date,
+        20 * USD,
+        Some(usdc),
+    );
+    let address = env.create_contract(&buyer, &seller, &terms).await.unwrap();
+
+    // 20% of 10 units at $187.3525 is $374.705
+    let contract = env.contract(&address).await;
+    assert_eq!(contract.price_expo, PRICE_EXPO);
+    assert_eq!(contract.margin_amount, 374_705_000);
+
+    env.set_scaled_price("NVDA", ORACLE_PRICE, ORACLE_EXPO)
+        .await;
+    env.process(&[instruction::exercise_units(&contract, UNITS)], &[&buyer])
+        .await
+        .unwrap();
+
+    // $190.1234 - $187.3525 = $2.7709 a unit, paid to the base unit
+    let payoff = 27_709_000;
+    let contract = env.contract(&address).await;
+    assert_eq!(contract.status, ContractStatus::Settled);
+    assert_eq!(
+        env.token_balance(&pda::token_escrow_address(&buyer.pubkey(), &usdc))
+            .await,
+        480 * USD + payoff
+    );
+    assert_eq!(
+        env.token_balance(&pda::token_escrow_address(&seller.pubkey(), &usdc))
+            .await,
+        1020 * USD - payoff
+    );
+}
+
+#[tokio::test]
+async fn lamport_payoff_rounds_down_at_the_sol_price() {
+    let mut env = TestEnv::start().await;
+    env.register_asset("NVDA", PRICE_EXPO).await;
+    let buyer = env.new_user(DEPOSIT).await;
+    let seller = env.new_user(DEPOSIT).await;
+    let underlying_feed = env
+        .set_scaled_price("NVDA", 18_735_250_000, ORACLE_EXPO)
+        .await;
+    // $123.45678901, read as $123.4567
+    let sol_feed = env
+        .set_scaled_price("SOL", 12_345_678_901, ORACLE_EXPO)
+        .await;
+    let expiration_date = env.now().await + DAY;
+
+    let terms = terms(
+        underlying_feed,
+        sol_feed,
+        expiration_date,
+        LAMPORTS_PER_SOL / 10,
+        None,
+    );
+    let address = env.create_contract(&buyer, &seller, &terms).await.unwrap();
+
+    // The $374.705 requirement at $123.4567 a SOL is 3_035_112_715.04
+    // lamports, rounded up
+    let contract = env.contract(&address).await;
+    assert_eq!(contract.margin_amount, 3_035_112_716);
+
+    env.set_scaled_price("NVDA", ORACLE_PRICE, ORACLE_EXPO)
+        .await;
+    env.set_scaled_price("SOL", 12_345_678_901, ORACLE_EXPO)
+        .await;
+    let buyer_escrow = env.escrow_balance(&buyer.pubkey()).await;
+    env.process(&[instruction::exercise_units(&contract, UNITS)], &[&buyer])
+        .await
+        .unwrap();
+
+    // $27.709 at $123.4567 a SOL is 224_443_063.84 lamports
+    assert_eq!(
+        env.escrow_balance(&buyer.pubkey()).await,
+        buyer_escrow + 224_443_063
+    );
+}
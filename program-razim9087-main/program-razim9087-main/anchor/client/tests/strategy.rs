@@ -0,0 +1,384 @@
+//! Multi-leg strategies, margined at each side's maximum loss and settled as
+//! a whole at expiry.
+
+mod common;
+
+use basic::{ContractStatus, ErrorCode, LegSide, OptionStrategy, OptionType, StrategyLeg};
+use basic_client::instruction::{self, StrategyTerms};
+use basic_client::pda;
+use common::*;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+
+// The stablecoin has 6 decimals, so one dollar is 1_000_000 base units
+const USD: u64 = 1_000_000;
+const UNITS: u64 = 10;
+
+fn leg(option_type: OptionType, side: LegSide, strike_price: u64) -> StrategyLeg {
+    StrategyLeg {
+        option_type,
+        side,
+        strike_price,
+        num_units: UNITS,
+    }
+}
+
+// A buyer and a seller holding USDC in escrow, with AAPL quoted at the strike
+struct Book {
+    env: TestEnv,
+    buyer: Keypair,
+    seller: Keypair,
+    usdc: Pubkey,
+    underlying_feed: Pubkey,
+    sol_feed: Pubkey,
+}
+
+impl Book {
+    async fn open(buyer_usdc: u64, seller_usdc: u64) -> Self {
+        let mut env = TestEnv::start().await;
+        let usdc = env.create_mint(6).await;
+        let buyer = env.new_token_user(&usdc, buyer_usdc).await;
+        let seller = env.new_token_user(&usdc, seller_usdc).await;
+        let underlying_feed = env.set_price("AAPL", STRIKE as i64).await;
+        let sol_feed = env.set_price("SOL", SOL_PRICE).await;
+        Self {
+            env,
+            buyer,
+            seller,
+            usdc,
+            underlying_feed,
+            sol_feed,
+        }
+    }
+
+    fn terms(&self, legs: Vec<StrategyLeg>, premium: u64, expiration_date: i64) -> StrategyTerms {
+        StrategyTerms {
+            underlying_asset: "AAPL".to_string(),
+            expiration_date,
+            legs,
+            premium,
+            margin_requirement_bps: 2000,
+            underlying_price_feed: self.underlying_feed,
+            sol_price_feed: self.sol_feed,
+            collateral_mint: Some(self.usdc),
+        }
+    }
+
+    async fn create(
+        &mut self,
+        legs: Vec<StrategyLeg>,
+        premium: u64,
+        expiration_date: i64,
+    ) -> Pubkey {
+        let terms = self.terms(legs, premium, expiration_date);
+        self.env
+            .create_strategy(&self.buyer, &self.seller, &terms)
+            .await
+            .unwrap()
+    }
+
+    async fn balance(&mut self, user: &Pubkey) -> u64 {
+        let usdc = self.usdc;
+        self.env
+            .token_balance(&pda::token_escrow_address(user, &usdc))
+            .await
+    }
+
+    // Moves to expiry and quotes AAPL at `underlying_price`
+    async fn expire_at(&mut self, expiration_date: i64, underlying_price: i64) {
+        self.env.warp_to(expiration_date).await;
+        self.env.set_price("AAPL", underlying_price).await;
+    }
+}
+
+#[tokio::test]
+async fn bull_call_spread_locks_only_the_spread_width() {
+    let mut book = Book::open(500 * USD, 500 * USD).await;
+    let buyer = book.buyer.pubkey();
+    let seller = book.seller.pubkey();
+    let expiration_date = book.env.now().await + DAY;
+
+    let legs = vec![
+        leg(OptionType::Call, LegSide::Long, STRIKE),
+        leg(OptionType::Call, LegSide::Short, STRIKE + 1000),
+    ];
+    let address = book.create(legs, 40 * USD, expiration_date).await;
+
+    // The short call caps the seller's loss at $10 a unit, with no naked margin
+    let strategy = book.env.strategy(&address).await;
+    assert_eq!(strategy.status, ContractStatus::Active);
+    assert_eq!(strategy.seller_margin, 100 * USD);
+    assert_eq!(strategy.buyer_margin, 0);
+    assert_eq!(book.balance(&buyer).await, 460 * USD);
+    assert_eq!(book.balance(&seller).await, 440 * USD);
+
+    // Strategies settle at expiry only
+    let result = book
+        .env
+        .process(
+            &[instruction::exercise_strategy(&buyer, &strategy)],
+            &[&book.buyer],
+        )
+        .await;
+    assert_error(result, ErrorCode::ContractNotExpired);
+
+    // At $125 both calls are in the money and the buyer earns the full width
+    book.expire_at(expiration_date, 12500).await;
+    book.env
+        .process(
+            &[instruction::exercise_strategy(&buyer, &strategy)],
+            &[&book.buyer],
+        )
+        .await
+        .unwrap();
+
+    let strategy = book.env.strategy(&address).await;
+    assert_eq!(strategy.status, ContractStatus::Settled);
+    assert_eq!(book.balance(&buyer).await, 560 * USD);
+    assert_eq!(book.balance(&seller).await, 440 * USD);
+    let collateral_vault = pda::collateral_vault_address(&address);
+    assert_eq!(book.env.token_balance(&collateral_vault).await, 0);
+
+    book.env
+        .process(
+            &[instruction::close_strategy(&seller, &strategy)],
+            &[&book.seller],
+        )
+        .await
+        .unwrap();
+    assert_eq!(book.env.lamports(&address).await, 0);
+    assert_eq!(book.env.lamports(&collateral_vault).await, 0);
+}
+
+#[tokio::test]
+async fn collar_buyer_pays_the_seller_above_the_short_call() {
+    let mut book = Book::open(500 * USD, 1000 * USD).await;
+    let buyer = book.buyer.pubkey();
+    let seller = book.seller.pubkey();
+    let expiration_date = book.env.now().await + DAY;
+
+    // A zero-cost collar: the buyer sells a $110 call to pay for a $90 put
+    let legs = vec![
+        leg(OptionType::Put, LegSide::Long, STRIKE - 1000),
+        leg(OptionType::Call, LegSide::Short, STRIKE + 1000),
+    ];
+    let address = book.create(legs, 0, expiration_date).await;
+
+    // The seller covers the put down to zero, the buyer margins the open call
+    let strategy = book.env.strategy(&address).await;
+    assert_eq!(strategy.seller_margin, 900 * USD);
+    assert_eq!(strategy.buyer_margin, 220 * USD);
+    assert_eq!(book.balance(&buyer).await, 280 * USD);
+    assert_eq!(book.balance(&seller).await, 100 * USD);
+
+    // At $120 the buyer owes $10 a unit on the short call
+    book.expire_at(expiration_date, 12000).await;
+    book.env
+        .process(
+            &[instruction::exercise_strategy(&seller, &strategy)],
+            &[&book.seller],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        book.env.strategy(&address).await.status,
+        ContractStatus::Settled
+    );
+    assert_eq!(book.balance(&buyer).await, 400 * USD);
+    assert_eq!(book.balance(&seller).await, 1100 * USD);
+    assert_eq!(
+        book.env
+            .token_balance(&pda::collateral_vault_address(&address))
+            .await,
+        0
+    );
+}
+
+#[tokio::test]
+async fn collar_buyer_default_is_left_with_the_seller() {
+    let mut book = Book::open(500 * USD, 1000 * USD).await;
+    let buyer = book.buyer.pubkey();
+    let seller = book.seller.pubkey();
+    let expiration_date = book.env.now().await + DAY;
+
+    let legs = vec![
+        leg(OptionType::Put, LegSide::Long, STRIKE - 1000),
+        leg(OptionType::Call, LegSide::Short, STRIKE + 1000),
+    ];
+    let address = book.create(legs, 0, expiration_date).await;
+    let strategy = book.env.strategy(&address).await;
+
+    // At $200 the buyer owes $900 but only holds its $220 margin and $280 escrow
+    book.expire_at(expiration_date, 20000).await;
+    let result = book
+        .env
+        .process(
+            &[instruction::exercise_strategy(&buyer, &strategy)],
+            &[&book.buyer],
+        )
+        .await;
+    assert_error(result, ErrorCode::InsuranceFundRequired);
+
+    book.env
+        .process(
+            &[instruction::exercise_strategy(&seller, &strategy)],
+            &[&book.seller],
+        )
+        .await
+        .unwrap();
+
+    let defaulted = book.env.strategy(&address).await;
+    assert_eq!(defaulted.status, ContractStatus::Defaulted);
+    assert_eq!(defaulted.default_shortfall, 400 * USD);
+    assert_eq!(defaulted.paid_from_insurance, 0);
+    assert_eq!(book.balance(&buyer).await, 0);
+    assert_eq!(book.balance(&seller).await, 1500 * USD);
+
+    book.env
+        .process(
+            &[instruction::close_strategy(&seller, &defaulted)],
+            &[&book.seller],
+        )
+        .await
+        .unwrap();
+    assert_eq!(book.env.lamports(&address).await, 0);
+}
+
+// A lamport straddle at the strike, for which the seller locks its maximum
+// loss at the SOL price
+async fn open_straddle(market: &mut Market) -> OptionStrategy {
+    let expiration_date = market.env.now().await + DAY;
+    let seller = market.seller.pubkey();
+    // That loss is more than the seller's usual deposit
+    market.env.fund(&seller, 2 * DEPOSIT).await;
+    market
+        .env
+        .process(
+            &[instruction::deposit(&seller, 2 * DEPOSIT)],
+            &[&market.seller],
+        )
+        .await
+        .unwrap();
+    let seller_escrow = market.env.escrow_balance(&seller).await;
+
+    let terms = StrategyTerms {
+        underlying_asset: "AAPL".to_string(),
+        expiration_date,
+        legs: vec![
+            leg(OptionType::Call, LegSide::Long, STRIKE),
+            leg(OptionType::Put, LegSide::Long, STRIKE),
+        ],
+        premium: LAMPORTS_PER_SOL / 10,
+        margin_requirement_bps: 2000,
+        underlying_price_feed: market.underlying_feed,
+        sol_price_feed: market.sol_feed,
+        collateral_mint: None,
+    };
+    let address = market
+        .env
+        .create_strategy(&market.buyer, &market.seller, &terms)
+        .await
+        .unwrap();
+
+    // The put is covered down to zero and the open call margined at 20%
+    let strategy = market.env.strategy(&address).await;
+    let seller_margin = payoff_lamports(STRIKE + STRIKE * 2000 / 10000, UNITS);
+    assert_eq!(strategy.seller_margin, seller_margin);
+    assert!(seller_margin >= payoff_lamports(STRIKE, UNITS));
+    assert_eq!(strategy.buyer_margin, 0);
+    assert_eq!(
+        market.env.escrow_balance(&seller).await,
+        seller_escrow - seller_margin
+    );
+    strategy
+}
+
+#[tokio::test]
+async fn unsettled_straddle_at_the_strike_returns_both_margins() {
+    let mut market = Market::open().await;
+    let buyer = market.buyer.pubkey();
+    let seller = market.seller.pubkey();
+    let strategy = open_straddle(&mut market).await;
+    let address = instruction::address_of_strategy(&strategy);
+    let seller_escrow = market.env.escrow_balance(&seller).await + strategy.seller_margin;
+
+    // Nobody settles within the exercise window
+    market.env.warp_to(strategy.expiration_date + DAY).await;
+    market.quote(STRIKE as i64).await;
+    let result = market
+        .env
+        .process(
+            &[instruction::exercise_strategy(&buyer, &strategy)],
+            &[&market.buyer],
+        )
+        .await;
+    assert_error(result, ErrorCode::ExerciseWindowClosed);
+
+    let keeper = market.env.payer();
+    market
+        .env
+        .process(&[instruction::expire_strategy(&keeper, &strategy)], &[])
+        .await
+        .unwrap();
+
+    assert_eq!(
+        market.env.strategy(&address).await.status,
+        ContractStatus::Expired
+    );
+    assert_eq!(market.env.escrow_balance(&seller).await, seller_escrow);
+
+    market
+        .env
+        .process(
+            &[instruction::close_strategy(&buyer, &strategy)],
+            &[&market.buyer],
+        )
+        .await
+        .unwrap();
+    assert_eq!(market.env.lamports(&address).await, 0);
+    assert_eq!(
+        market
+            .env
+            .lamports(&pda::collateral_address(&address))
+            .await,
+        0
+    );
+}
+
+#[tokio::test]
+async fn unsettled_straddle_in_the_money_is_paid_at_expiry() {
+    let mut market = Market::open().await;
+    let buyer = market.buyer.pubkey();
+    let seller = market.seller.pubkey();
+    let strategy = open_straddle(&mut market).await;
+    let address = instruction::address_of_strategy(&strategy);
+    let buyer_escrow = market.env.escrow_balance(&buyer).await;
+    let seller_escrow = market.env.escrow_balance(&seller).await + strategy.seller_margin;
+
+    // The buyer is still owed the call's payoff after the window lapses
+    market.env.warp_to(strategy.expiration_date + DAY).await;
+    market.quote(12000).await;
+    let keeper = market.env.payer();
+    market
+        .env
+        .process(&[instruction::expire_strategy(&keeper, &strategy)], &[])
+        .await
+        .unwrap();
+
+    // The payoff comes out of the margin, leaving the rest of the escrow alone
+    let payoff = payoff_lamports(2000, UNITS);
+    assert!(payoff <= strategy.seller_margin);
+    assert_eq!(
+        market.env.strategy(&address).await.status,
+        ContractStatus::Settled
+    );
+    assert_eq!(
+        market.env.escrow_balance(&buyer).await,
+        buyer_escrow + payoff
+    );
+    assert_eq!(
+        market.env.escrow_balance(&seller).await,
+        seller_escrow - payoff
+    );
+}
@@ -0,0 +1,148 @@
+//! Active contracts unwound before expiry by agreement of both sides.
+
+mod common;
+
+use basic::{ContractStatus, ErrorCode, ExerciseStyle, OptionContract, OptionType, UserRole};
+use basic_client::{instruction, pda, UserContractPage};
+use common::*;
+use solana_sdk::signature::Signer;
+
+async fn open_call(market: &mut Market) -> OptionContract {
+    let expiration_date = market.env.now().await + 7 * DAY;
+    let address = market
+        .create(OptionType::Call, ExerciseStyle::American, expiration_date)
+        .await;
+    market.env.contract(&address).await
+}
+
+#[tokio::test]
+async fn buyer_pays_to_unwind_a_contract() {
+    let mut market = Market::open().await;
+    let contract = open_call(&mut market).await;
+    let address = instruction::address_of_contract(&contract);
+    let buyer = market.buyer.pubkey();
+    let seller = market.seller.pubkey();
+    let buyer_escrow = market.env.escrow_balance(&buyer).await;
+    let seller_escrow = market.env.escrow_balance(&seller).await;
+
+    let payment = LAMPORTS_PER_SOL / 20;
+    market
+        .env
+        .process(
+            &[instruction::terminate_contract(
+                &contract,
+                payment,
+                UserRole::Buyer,
+            )],
+            &[&market.buyer, &market.seller],
+        )
+        .await
+        .unwrap();
+
+    let terminated = market.env.contract(&address).await;
+    assert_eq!(terminated.status, ContractStatus::Terminated);
+    assert_eq!(
+        market.env.escrow_balance(&buyer).await,
+        buyer_escrow - payment
+    );
+    assert_eq!(
+        market.env.escrow_balance(&seller).await,
+        seller_escrow + payment + contract.margin_amount
+    );
+
+    // Both index entries move to the new status
+    for (owner, page_no) in [
+        (buyer, terminated.buyer_page),
+        (seller, terminated.seller_page),
+    ] {
+        let page: UserContractPage = market
+            .env
+            .account(&pda::user_page_address(&owner, page_no))
+            .await;
+        let entry = page
+            .contracts
+            .iter()
+            .find(|entry| entry.contract_address == address)
+            .unwrap();
+        assert_eq!(entry.status, ContractStatus::Terminated);
+    }
+
+    // A terminated contract is finished and can be closed
+    market
+        .env
+        .process(
+            &[instruction::close_contract(&buyer, &terminated)],
+            &[&market.buyer],
+        )
+        .await
+        .unwrap();
+    assert!(market
+        .env
+        .try_account::<OptionContract>(&address)
+        .await
+        .is_none());
+}
+
+#[tokio::test]
+async fn seller_pays_out_of_the_margin_then_the_escrow() {
+    let mut market = Market::open().await;
+    let contract = open_call(&mut market).await;
+    let address = instruction::address_of_contract(&contract);
+    let buyer = market.buyer.pubkey();
+    let seller = market.seller.pubkey();
+    let buyer_escrow = market.env.escrow_balance(&buyer).await;
+    let seller_escrow = market.env.escrow_balance(&seller).await;
+
+    // More than the margin, so the seller escrow covers the rest
+    let payment = contract.margin_amount + LAMPORTS_PER_SOL / 2;
+    market
+        .env
+        .process(
+            &[instruction::terminate_contract(
+                &contract,
+                payment,
+                UserRole::Seller,
+            )],
+            &[&market.buyer, &market.seller],
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        market.env.contract(&address).await.status,
+        ContractStatus::Terminated
+    );
+    assert_eq!(
+        market.env.escrow_balance(&buyer).await,
+        buyer_escrow + payment
+    );
+    assert_eq!(
+        market.env.escrow_balance(&seller).await,
+        seller_escrow + contract.margin_amount - payment
+    );
+}
+
+#[tokio::test]
+async fn terminated_contract_cannot_be_exercised() {
+    let mut market = Market::open().await;
+    let contract = open_call(&mut market).await;
+    market
+        .env
+        .process(
+            &[instruction::terminate_contract(
+                &contract,
+                0,
+                UserRole::Buyer,
+            )],
+            &[&market.buyer, &market.seller],
+        )
+        .await
+        .unwrap();
+
+    market.quote(12000).await;
+    let result = market
+        .env
+        .process(&[instruction::exercise(&contract)], &[&market.buyer])
+        .await;
+    assert_error(result, ErrorCode::ContractNotActive);
+}
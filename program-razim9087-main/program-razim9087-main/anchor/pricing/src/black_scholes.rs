@@ -0,0 +1,178 @@
+use std::f64::consts::{PI, SQRT_2};
+
+use crate::{to_collateral_units, Collateral, OptionType, PricingInputs};
+
+/// Sensitivities of the price of one unit, in USD cents
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Greeks {
+    /// Change in price per cent move of the underlying
+    pub delta: f64,
+    /// Change in delta per cent move of the underlying
+    pub gamma: f64,
+    /// Change in price per percentage point of volatility
+    pub vega: f64,
+    /// Change in price per calendar day
+    pub theta: f64,
+    /// Change in price per percentage point of the risk-free rate
+    pub rho: f64,
+}
+
+// Complementary error function, with a fractional error below 1.2e-7
+// (Numerical Recipes, erfcc)
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = -z * z - 1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807
+                            + t * (-1.13520398
+                                + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277))))))));
+    let erfc = t * poly.exp();
+    if x >= 0.0 {
+        erfc
+    } else {
+        2.0 - erfc
+    }
+}
+
+fn normal_cdf(x: f64) -> f64 {
+    0.5 * erfc(-x / SQRT_2)
+}
+
+fn normal_pdf(x: f64) -> f64 {
+    (-0.5 * x * x).exp() / (2.0 * PI).sqrt()
+}
+
+// Inputs of the closed form
+struct Terms {
+    spot: f64,
+    discounted_strike: f64,
+    years: f64,
+    sqrt_years: f64,
+    d1: f64,
+    d2: f64,
+}
+
+// None once nothing is left uncertain, because the contract has expired or
+// volatility is zero
+fn terms(inputs: &PricingInputs) -> Option<Terms> {
+    let spot = inputs.spot_price as f64;
+    let strike = inputs.strike_price as f64;
+    let years = inputs.years_to_expiry();
+    let volatility = inputs.volatility();
+    let std_dev = volatility * years.sqrt();
+    if std_dev == 0.0 || spot == 0.0 || strike == 0.0 {
+        return None;
+    }
+
+    let d1 =
+        ((spot / strike).ln() + (inputs.rate() + 0.5 * volatility * volatility) * years) / std_dev;
+    Some(Terms {
+        spot,
+        discounted_strike: strike * (-inputs.rate() * years).exp(),
+        years,
+        sqrt_years: years.sqrt(),
+        d1,
+        d2: d1 - std_dev,
+    })
+}
+
+// Value of a contract that can no longer move: the discounted intrinsic value
+fn deterministic_price(inputs: &PricingInputs) -> f64 {
+    let spot = inputs.spot_price as f64;
+    let discounted_strike =
+        inputs.strike_price as f64 * (-inputs.rate() * inputs.years_to_expiry()).exp();
+    match inputs.option_type {
+        OptionType::Call => (spot - discounted_strike).max(0.0),
+        OptionType::Put => (discounted_strike - spot).max(0.0),
+    }
+}
+
+/// Fair value of one unit in USD cents
+pub fn price(inputs: &PricingInputs) -> f64 {
+    let Some(terms) = terms(inputs) else {
+        return deterministic_price(inputs);
+    };
+
+    match inputs.option_type {
+        OptionType::Call => {
+            terms.spot * normal_cdf(terms.d1) - terms.discounted_strike * normal_cdf(terms.d2)
+        }
+        OptionType::Put => {
+            terms.discounted_strike * normal_cdf(-terms.d2) - terms.spot * normal_cdf(-terms.d1)
+        }
+    }
+}
+
+/// Fair premium for `num_units` in USD cents, rounded to the nearest cent
+pub fn premium_cents(inputs: &PricingInputs, num_units: u64) -> u64 {
+    // The float to integer cast saturates instead of wrapping
+    (price(inputs) * num_units as f64).round() as u64
+}
+
+/// Fair premium for `num_units` in the contract's collateral units, ready to
+/// pass as the `premium` of `create_contract`
+pub fn premium(inputs: &PricingInputs, num_units: u64, collateral: Collateral) -> Option<u64> {
+    to_collateral_units(premium_cents(inputs, num_units), collateral)
+}
+
+/// Greeks of one unit
+pub fn greeks(inputs: &PricingInputs) -> Greeks {
+    let Some(terms) = terms(inputs) else {
+        return deterministic_greeks(inputs);
+    };
+
+    let volatility = inputs.volatility();
+    let rate = inputs.rate();
+    let density = normal_pdf(terms.d1);
+    let gamma = density / (terms.spot * volatility * terms.sqrt_years);
+    let vega = terms.spot * density * terms.sqrt_years / 100.0;
+    // Time decay common to calls and puts
+    let decay = -terms.spot * density * volatility / (2.0 * terms.sqrt_years);
+
+    let (delta, theta, rho) = match inputs.option_type {
+        OptionType::Call => (
+            normal_cdf(terms.d1),
+            decay - rate * terms.discounted_strike * normal_cdf(terms.d2),
+            terms.discounted_strike * terms.years * normal_cdf(terms.d2),
+        ),
+        OptionType::Put => (
+            normal_cdf(terms.d1) - 1.0,
+            decay + rate * terms.discounted_strike * normal_cdf(-terms.d2),
+            -terms.discounted_strike * terms.years * normal_cdf(-terms.d2),
+        ),
+    };
+
+    Greeks {
+        delta,
+        gamma,
+        vega,
+        theta: theta / 365.0,
+        rho: rho / 100.0,
+    }
+}
+
+// Greeks of the discounted intrinsic value, which only the rate and time move
+fn deterministic_greeks(inputs: &PricingInputs) -> Greeks {
+    if deterministic_price(inputs) == 0.0 {
+        return Greeks::default();
+    }
+
+    let years = inputs.years_to_expiry();
+    let rate = inputs.rate();
+    let discounted_strike = inputs.strike_price as f64 * (-rate * years).exp();
+    let sign = match inputs.option_type {
+        OptionType::Call => 1.0,
+        OptionType::Put => -1.0,
+    };
+    Greeks {
+        delta: sign,
+        gamma: 0.0,
+        vega: 0.0,
+        theta: -sign * rate * discounted_strike / 365.0,
+        rho: sign * discounted_strike * years / 100.0,
+    }
+}
@@ -0,0 +1,39 @@
+use crate::{price, PricingInputs};
+
+// Search range, 0.01% to 1000% a year
+const MIN_VOLATILITY_BPS: u32 = 1;
+const MAX_VOLATILITY_BPS: u32 = 100_000;
+
+/// Annualized volatility in basis points at which one unit is worth
+/// `price_per_unit` USD cents; `inputs.volatility_bps` is ignored.
+///
+/// Returns none when no volatility in the search range reproduces the price,
+/// e.g. a price below the discounted intrinsic value.
+pub fn implied_volatility_bps(inputs: &PricingInputs, price_per_unit: f64) -> Option<u32> {
+    let price_at = |volatility_bps| {
+        price(&PricingInputs {
+            volatility_bps,
+            ..*inputs
+        })
+    };
+
+    // The price rises with volatility, so bisect on whole basis points
+    let mut low = MIN_VOLATILITY_BPS;
+    let mut high = MAX_VOLATILITY_BPS;
+    if !(price_at(low)..=price_at(high)).contains(&price_per_unit) {
+        return None;
+    }
+    while high - low > 1 {
+        let middle = low + (high - low) / 2;
+        if price_at(middle) < price_per_unit {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+
+    // Pick the closer of the two neighbouring basis points
+    let low_error = (price_at(low) - price_per_unit).abs();
+    let high_error = (price_at(high) - price_per_unit).abs();
+    Some(if low_error <= high_error { low } else { high })
+}
@@ -0,0 +1,65 @@
+//! Off-chain Black-Scholes pricing for `basic` options contracts: fair
+//! premiums, the Greeks, implied volatility and suggested margin requirements.
+//!
+//! Inputs use the integer units of `OptionContract`: prices in USD cents,
+//! times as unix timestamps, and volatility and rates in basis points. Premiums
+//! convert to lamports or token base units with the same rounding the program
+//! uses for payoffs.
+//!
+//! Contracts are priced as European options. This is exact for American calls,
+//! which are never worth exercising early without dividends, and a lower bound
+//! for American puts.
+
+mod black_scholes;
+mod implied_volatility;
+mod margin;
+mod units;
+
+pub use black_scholes::{greeks, premium, premium_cents, price, Greeks};
+pub use implied_volatility::implied_volatility_bps;
+pub use margin::{suggested_margin_bps, DEFAULT_STRESS_DEVIATIONS};
+pub use units::{to_collateral_units, Collateral};
+
+const SECONDS_PER_YEAR: f64 = 365.0 * 24.0 * 60.0 * 60.0;
+
+/// Mirrors `basic::OptionType` without depending on the program crate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptionType {
+    Call,
+    Put,
+}
+
+/// Market inputs for one unit of an option
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PricingInputs {
+    pub option_type: OptionType,
+    /// Oracle price of the underlying, in USD cents
+    pub spot_price: u64,
+    /// In USD cents, like `OptionContract::strike_price`
+    pub strike_price: u64,
+    pub current_time: i64,
+    /// Unix timestamp, like `OptionContract::expiration_date`
+    pub expiration_date: i64,
+    /// Annualized volatility, 8000 for 80%
+    pub volatility_bps: u32,
+    /// Annualized continuously compounded risk-free rate, 500 for 5%
+    pub risk_free_rate_bps: i32,
+}
+
+impl PricingInputs {
+    // Time to expiry in years; expired contracts have none left
+    fn years_to_expiry(&self) -> f64 {
+        self.expiration_date
+            .saturating_sub(self.current_time)
+            .max(0) as f64
+            / SECONDS_PER_YEAR
+    }
+
+    fn volatility(&self) -> f64 {
+        self.volatility_bps as f64 / 10000.0
+    }
+
+    fn rate(&self) -> f64 {
+        self.risk_free_rate_bps as f64 / 10000.0
+    }
+}
@@ -0,0 +1,28 @@
+use crate::{OptionType, PricingInputs};
+
+/// One-sided 99% move of a lognormal price
+pub const DEFAULT_STRESS_DEVIATIONS: f64 = 2.33;
+
+/// Margin requirement in basis points of the strike notional that covers the
+/// payoff after the underlying moves `stress_deviations` standard deviations
+/// against the seller before expiry, rounded up.
+///
+/// Far out-of-the-money contracts can come out at zero, so clamp the result
+/// to the `MarketConfig` margin limits before passing it to `create_contract`.
+pub fn suggested_margin_bps(inputs: &PricingInputs, stress_deviations: f64) -> u16 {
+    let strike = inputs.strike_price as f64;
+    if strike == 0.0 {
+        return 0;
+    }
+
+    let spot = inputs.spot_price as f64;
+    let move_size =
+        (stress_deviations * inputs.volatility() * inputs.years_to_expiry().sqrt()).exp();
+    let stressed_payoff = match inputs.option_type {
+        OptionType::Call => (spot * move_size - strike).max(0.0),
+        OptionType::Put => (strike - spot / move_size).max(0.0),
+    };
+
+    // The float to integer cast saturates at u16::MAX
+    (stressed_payoff / strike * 10000.0).ceil() as u16
+}
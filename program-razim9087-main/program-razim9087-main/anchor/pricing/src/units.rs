@@ -0,0 +1,24 @@
+const LAMPORTS_PER_SOL: u128 = 1_000_000_000;
+
+/// What a contract is collateralized with, and so what its premium is paid in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Collateral {
+    /// Lamports, converted at the SOL price in USD cents
+    Lamports { sol_price: u64 },
+    /// Base units of a USD stablecoin mint
+    Token { decimals: u8 },
+}
+
+/// Converts USD cents to collateral units, rounding down like the program
+/// does for payoffs. Returns none on a zero SOL price or an overflow.
+pub fn to_collateral_units(amount_usd: u64, collateral: Collateral) -> Option<u64> {
+    let units = match collateral {
+        Collateral::Lamports { sol_price } => (amount_usd as u128)
+            .checked_mul(LAMPORTS_PER_SOL)?
+            .checked_div(sol_price as u128)?,
+        Collateral::Token { decimals } => {
+            (amount_usd as u128).checked_mul(10u128.checked_pow(decimals as u32)?)? / 100
+        }
+    };
+    u64::try_from(units).ok()
+}
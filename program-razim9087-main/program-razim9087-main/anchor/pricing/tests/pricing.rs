@@ -0,0 +1,195 @@
+use basic_pricing::*;
+
+const YEAR: i64 = 365 * 24 * 60 * 60;
+const NOW: i64 = 1_700_000_000;
+
+// $100 underlying and strike, one year out, 20% volatility and a 5% rate
+fn at_the_money(option_type: OptionType) -> PricingInputs {
+    PricingInputs {
+        option_type,
+        spot_price: 10000,
+        strike_price: 10000,
+        current_time: NOW,
+        expiration_date: NOW + YEAR,
+        volatility_bps: 2000,
+        risk_free_rate_bps: 500,
+    }
+}
+
+fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+    assert!(
+        (actual - expected).abs() <= tolerance,
+        "expected {expected} ± {tolerance}, got {actual}"
+    );
+}
+
+#[test]
+fn prices_match_reference_values() {
+    assert_close(price(&at_the_money(OptionType::Call)), 1045.06, 0.01);
+    assert_close(price(&at_the_money(OptionType::Put)), 557.35, 0.01);
+}
+
+#[test]
+fn calls_and_puts_satisfy_put_call_parity() {
+    for spot_price in [5000, 9000, 10000, 12500, 30000] {
+        let call = PricingInputs {
+            spot_price,
+            ..at_the_money(OptionType::Call)
+        };
+        let put = PricingInputs {
+            option_type: OptionType::Put,
+            ..call
+        };
+        let discounted_strike = 10000.0 * (-0.05f64).exp();
+        assert_close(
+            price(&call) - price(&put),
+            spot_price as f64 - discounted_strike,
+            1e-3,
+        );
+    }
+}
+
+#[test]
+fn expired_contracts_are_worth_their_intrinsic_value() {
+    let expired = PricingInputs {
+        spot_price: 12000,
+        expiration_date: NOW - 60,
+        ..at_the_money(OptionType::Call)
+    };
+    assert_eq!(price(&expired), 2000.0);
+    assert_eq!(greeks(&expired).delta, 1.0);
+    assert_eq!(greeks(&expired).gamma, 0.0);
+
+    let put = PricingInputs {
+        option_type: OptionType::Put,
+        ..expired
+    };
+    assert_eq!(price(&put), 0.0);
+    assert_eq!(greeks(&put), Greeks::default());
+}
+
+#[test]
+fn greeks_match_finite_differences() {
+    for option_type in [OptionType::Call, OptionType::Put] {
+        let inputs = PricingInputs {
+            spot_price: 10500,
+            ..at_the_money(option_type)
+        };
+        let greeks = greeks(&inputs);
+        let bump = |change: &dyn Fn(&mut PricingInputs)| {
+            let mut bumped = inputs;
+            change(&mut bumped);
+            price(&bumped)
+        };
+
+        let up = bump(&|i| i.spot_price += 1);
+        let down = bump(&|i| i.spot_price -= 1);
+        assert_close(greeks.delta, (up - down) / 2.0, 1e-4);
+        assert_close(greeks.gamma, up - 2.0 * price(&inputs) + down, 1e-5);
+
+        // Half a percentage point either side
+        let vega = bump(&|i| i.volatility_bps += 50) - bump(&|i| i.volatility_bps -= 50);
+        assert_close(greeks.vega, vega, 1e-2);
+
+        let day = 24 * 60 * 60;
+        let theta = bump(&|i| i.current_time += day) - price(&inputs);
+        assert_close(greeks.theta, theta, 1e-2);
+
+        let rho = bump(&|i| i.risk_free_rate_bps += 50) - bump(&|i| i.risk_free_rate_bps -= 50);
+        assert_close(greeks.rho, rho, 1e-2);
+    }
+}
+
+#[test]
+fn delta_matches_the_reference_value() {
+    assert_close(greeks(&at_the_money(OptionType::Call)).delta, 0.6368, 1e-4);
+    assert_close(greeks(&at_the_money(OptionType::Put)).delta, -0.3632, 1e-4);
+}
+
+#[test]
+fn implied_volatility_recovers_the_pricing_volatility() {
+    for volatility_bps in [500, 2000, 8000, 15000] {
+        for option_type in [OptionType::Call, OptionType::Put] {
+            let inputs = PricingInputs {
+                volatility_bps,
+                spot_price: 9000,
+                ..at_the_money(option_type)
+            };
+            assert_eq!(
+                implied_volatility_bps(&inputs, price(&inputs)),
+                Some(volatility_bps)
+            );
+        }
+    }
+}
+
+#[test]
+fn implied_volatility_rejects_prices_outside_the_range() {
+    let inputs = PricingInputs {
+        spot_price: 12000,
+        ..at_the_money(OptionType::Call)
+    };
+    // Below the discounted intrinsic value, and above the underlying itself
+    assert_eq!(implied_volatility_bps(&inputs, 1000.0), None);
+    assert_eq!(implied_volatility_bps(&inputs, 12001.0), None);
+}
+
+#[test]
+fn premiums_convert_like_program_payoffs() {
+    let inputs = at_the_money(OptionType::Call);
+    let cents = premium_cents(&inputs, 10);
+    assert_eq!(cents, 10451);
+
+    // At $100 a SOL one cent is 100_000 lamports
+    assert_eq!(
+        premium(&inputs, 10, Collateral::Lamports { sol_price: 10000 }),
+        Some(cents * 100_000)
+    );
+    assert_eq!(
+        premium(&inputs, 10, Collateral::Token { decimals: 6 }),
+        Some(cents * 10_000)
+    );
+    assert_eq!(
+        to_collateral_units(cents, Collateral::Lamports { sol_price: 0 }),
+        None
+    );
+    assert_eq!(
+        to_collateral_units(u64::MAX, Collateral::Token { decimals: 18 }),
+        None
+    );
+}
+
+#[test]
+fn suggested_margin_covers_the_stressed_payoff() {
+    let call = at_the_money(OptionType::Call);
+    let margin_bps = suggested_margin_bps(&call, DEFAULT_STRESS_DEVIATIONS);
+    // exp(2.33 * 0.2) - 1 of the strike, rounded up
+    assert_eq!(margin_bps, 5937);
+
+    let put = at_the_money(OptionType::Put);
+    assert_eq!(suggested_margin_bps(&put, DEFAULT_STRESS_DEVIATIONS), 3725);
+}
+
+#[test]
+fn suggested_margin_grows_with_volatility_and_time() {
+    let call = at_the_money(OptionType::Call);
+    let calmer = PricingInputs {
+        volatility_bps: 1000,
+        ..call
+    };
+    let sooner = PricingInputs {
+        expiration_date: NOW + YEAR / 12,
+        ..call
+    };
+    let margin_bps = suggested_margin_bps(&call, DEFAULT_STRESS_DEVIATIONS);
+    assert!(suggested_margin_bps(&calmer, DEFAULT_STRESS_DEVIATIONS) < margin_bps);
+    assert!(suggested_margin_bps(&sooner, DEFAULT_STRESS_DEVIATIONS) < margin_bps);
+
+    // A far out-of-the-money put needs no margin beyond the market minimum
+    let far_put = PricingInputs {
+        option_type: OptionType::Put,
+        spot_price: 40000,
+        ..sooner
+    };
+    assert_eq!(suggested_margin_bps(&far_put, DEFAULT_STRESS_DEVIATIONS), 0);
+}
@@ -0,0 +1,317 @@
+use anchor_lang::prelude::*;
+
+use crate::{ContractStatus, ExerciseStyle, OptionType, SettlementKind, StrategyLeg, UserRole};
+
+#[event]
+pub struct UserInitializedEvent {
+    pub user: Pubkey,
+    pub user_account: Pubkey,
+}
+
+// Full market configuration after every admin change
+#[event]
+pub struct MarketConfigUpdatedEvent {
+    pub admin: Pubkey,
+    pub oracle_authority: Pubkey,
+    pub paused: bool,
+    pub min_margin_bps: u16,
+    pub max_margin_bps: u16,
+    pub min_expiry_seconds: i64,
+    pub max_expiry_seconds: i64,
+    pub premium_fee_bps: u16,
+    pub settlement_fee_bps: u16,
+    pub crank_fee_bps: u16,
+}
+
+#[event]
+pub struct UnderlyingAssetRegisteredEvent {
+    pub admin: Pubkey,
+    pub asset: Pubkey,
+    pub ticker: String,
+    pub price_expo: i32,
+}
+
+#[event]
+pub struct EscrowInitializedEvent {
+    pub user: Pubkey,
+    pub escrow: Pubkey,
+}
+
+// `mint` is set for token escrow deposits and withdrawals, none for lamports
+#[event]
+pub struct DepositEvent {
+    pub user: Pubkey,
+    pub escrow: Pubkey,
+    pub mint: Option<Pubkey>,
+    pub amount: u64,
+}
+
+#[event]
+pub struct WithdrawEvent {
+    pub user: Pubkey,
+    pub escrow: Pubkey,
+    pub mint: Option<Pubkey>,
+    pub amount: u64,
+}
+
+#[event]
+pub struct ContractCreatedEvent {
+    pub contract: Pubkey,
+    pub contract_id: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub underlying_asset: String,
+    pub num_units: u64,
+    pub strike_price: u64,
+    pub expiration_date: i64,
+    pub option_type: OptionType,
+    pub exercise_style: ExerciseStyle,
+    pub premium: u64,
+    pub margin_amount: u64,
+    pub premium_fee: u64,
+    pub collateral_mint: Option<Pubkey>,
+    pub settlement_kind: SettlementKind,
+    pub underlying_mint: Option<Pubkey>,
+    pub price_expo: i32,
+}
+
+// Prices are in units of 10^price_expo USD, like the strike; `sol_price` is
+// only read for lamport contracts
+#[event]
+pub struct ContractExercisedEvent {
+    pub contract: Pubkey,
+    pub contract_id: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub units: u64,
+    pub underlying_price: u64,
+    pub sol_price: Option<u64>,
+    pub payoff_amount: u64,
+    pub status: ContractStatus,
+}
+
+// Funds moved when exercised units are paid out, in the contract's collateral
+// units; the buyer receives the amounts paid less the settlement fee
+#[event]
+pub struct ContractSettledEvent {
+    pub contract: Pubkey,
+    pub contract_id: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub paid_from_margin: u64,
+    pub paid_from_seller_escrow: u64,
+    pub margin_released: u64,
+    pub settlement_fee: u64,
+    pub status: ContractStatus,
+}
+
+// Tokens swapped when a physically settled contract is exercised. The strike
+// payment is in the collateral mint and flows from the buyer of a call, or to
+// the buyer of a put out of the seller's margin and escrow.
+#[event]
+pub struct ContractDeliveredEvent {
+    pub contract: Pubkey,
+    pub contract_id: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub option_type: OptionType,
+    pub units: u64,
+    pub underlying_mint: Pubkey,
+    pub underlying_amount: u64,
+    pub strike_payment: u64,
+    pub margin_released: u64,
+}
+
+// A keeper cranked an expired contract into `status` and was paid `bounty` out
+// of its margin, in the contract's collateral units
+#[event]
+pub struct ContractCrankedEvent {
+    pub contract: Pubkey,
+    pub contract_id: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub keeper: Pubkey,
+    pub bounty: u64,
+    pub status: ContractStatus,
+}
+
+// The seller's margin and escrow fell short of a settled payoff. The shortfall
+// is the part of the payoff they could not cover, of which the insurance fund
+// paid `paid_from_insurance` to the buyer.
+#[event]
+pub struct ContractDefaultedEvent {
+    pub contract: Pubkey,
+    pub contract_id: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub shortfall: u64,
+    pub paid_from_insurance: u64,
+}
+
+#[event]
+pub struct ContractExpiredEvent {
+    pub contract: Pubkey,
+    pub contract_id: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub margin_released: u64,
+    // Underlying returned from the vault of a physical call
+    pub underlying_released: u64,
+}
+
+#[event]
+pub struct MarginMarkedEvent {
+    pub contract: Pubkey,
+    pub contract_id: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub underlying_price: u64,
+    pub sol_price: Option<u64>,
+    pub required_margin: u64,
+    pub locked_margin: u64,
+    pub margin_call_deadline: Option<i64>,
+}
+
+#[event]
+pub struct MarginToppedUpEvent {
+    pub contract: Pubkey,
+    pub contract_id: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub amount: u64,
+    pub margin_call_deadline: Option<i64>,
+}
+
+#[event]
+pub struct ContractLiquidatedEvent {
+    pub contract: Pubkey,
+    pub contract_id: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub liquidator: Pubkey,
+    pub underlying_price: u64,
+    pub sol_price: Option<u64>,
+    pub liquidation_bonus: u64,
+    pub paid_from_margin: u64,
+    pub paid_from_seller_escrow: u64,
+    pub margin_released: u64,
+    pub settlement_fee: u64,
+}
+
+#[event]
+pub struct PositionTransferredEvent {
+    pub contract: Pubkey,
+    pub contract_id: u64,
+    pub seller: Pubkey,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub sale_price: u64,
+}
+
+// Both sides unwound an active contract. `payment` moved from `paid_by` to the
+// other side before the margin and any locked underlying went back to the seller.
+#[event]
+pub struct ContractTerminatedEvent {
+    pub contract: Pubkey,
+    pub contract_id: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub paid_by: UserRole,
+    pub payment: u64,
+    pub margin_released: u64,
+    pub underlying_released: u64,
+}
+
+#[event]
+pub struct ContractClosedEvent {
+    pub contract: Pubkey,
+    pub contract_id: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub rent_payer: Pubkey,
+}
+
+#[event]
+pub struct StrategyCreatedEvent {
+    pub strategy: Pubkey,
+    pub strategy_id: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub underlying_asset: String,
+    pub expiration_date: i64,
+    pub legs: Vec<StrategyLeg>,
+    pub premium: u64,
+    pub buyer_margin: u64,
+    pub seller_margin: u64,
+    pub premium_fee: u64,
+    pub collateral_mint: Option<Pubkey>,
+    pub price_expo: i32,
+}
+
+// Net payoff of every leg, paid to `payee` by the other side in the
+// strategy's collateral units. `margin_released` covers both sides.
+#[event]
+pub struct StrategySettledEvent {
+    pub strategy: Pubkey,
+    pub strategy_id: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub underlying_price: u64,
+    pub sol_price: Option<u64>,
+    pub payee: Pubkey,
+    pub payoff_amount: u64,
+    pub paid_from_margin: u64,
+    pub paid_from_escrow: u64,
+    pub margin_released: u64,
+    pub settlement_fee: u64,
+    pub status: ContractStatus,
+}
+
+// The paying side of a strategy fell short of its net payoff. The insurance
+// fund paid `paid_from_insurance` of the shortfall to the payee.
+#[event]
+pub struct StrategyDefaultedEvent {
+    pub strategy: Pubkey,
+    pub strategy_id: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub payee: Pubkey,
+    pub shortfall: u64,
+    pub paid_from_insurance: u64,
+}
+
+#[event]
+pub struct StrategyExpiredEvent {
+    pub strategy: Pubkey,
+    pub strategy_id: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub buyer_margin_released: u64,
+    pub seller_margin_released: u64,
+}
+
+#[event]
+pub struct StrategyClosedEvent {
+    pub strategy: Pubkey,
+    pub strategy_id: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub rent_payer: Pubkey,
+}
+
+// `mint` is set for fees collected from a treasury vault, none for lamports
+#[event]
+pub struct FeesCollectedEvent {
+    pub admin: Pubkey,
+    pub mint: Option<Pubkey>,
+    pub amount: u64,
+}
+
+// Collected fees moved from the treasury into the insurance fund; `mint` is
+// none for lamports
+#[event]
+pub struct InsuranceFundedEvent {
+    pub admin: Pubkey,
+    pub mint: Option<Pubkey>,
+    pub amount: u64,
+}
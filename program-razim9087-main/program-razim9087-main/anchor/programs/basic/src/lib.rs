@@ -1,12 +1,79 @@
 use anchor_lang::prelude::*;
 use anchor_lang::system_program;
 use anchor_lang::solana_program::rent::Rent;
+use anchor_spl::token::{self, CloseAccount, Mint, Token, TokenAccount, Transfer};
+use mock_oracle::PriceFeed;
+
+mod events;
+#[cfg(feature = "test-clock")]
+mod test_clock;
+
+use events::*;
+#[cfg(feature = "test-clock")]
+pub use test_clock::*;
 
 declare_id!("AhcabRVb9LjuirKvfpeJatRsJFq3zsrrp8vAKGTaTTr4");
 
-const MAX_CONTRACTS: usize = 100;
+const USER_PAGE_SIZE: usize = 32;
 const MAX_TICKER_LENGTH: usize = 32;
 const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
+// Symbol of the oracle feed lamport amounts are converted at
+const SOL_SYMBOL: &str = "SOL";
+// Oracle quotes older than this are rejected at exercise
+const MAX_PRICE_AGE_SECONDS: i64 = 60;
+// Maximum confidence interval as a share of the quoted price
+const MAX_PRICE_CONFIDENCE_BPS: u64 = 200;
+// Price exponents an underlying asset can be registered with. Strike and
+// settlement prices are in units of 10^price_expo USD.
+const MIN_PRICE_EXPO: i32 = -8;
+const MAX_PRICE_EXPO: i32 = 0;
+// Time the buyer has after expiration to exercise before anyone can expire the contract
+const EXERCISE_WINDOW_SECONDS: i64 = 24 * 60 * 60;
+// Time a seller has to answer a margin call before the contract can be liquidated
+const MARGIN_CALL_PERIOD_SECONDS: i64 = 60 * 60;
+// Share of the locked margin paid to the keeper that liquidates a contract
+const LIQUIDATION_BONUS_BPS: u64 = 500;
+// Upper bound on the protocol fees the admin can set
+const MAX_PROTOCOL_FEE_BPS: u16 = 1000;
+// Legs a strategy can combine; single legs are opened as contracts
+const MIN_STRATEGY_LEGS: usize = 2;
+const MAX_STRATEGY_LEGS: usize = 4;
+
+// Builds the `CollateralPayout` of `contract` from an instruction context whose
+// payout accounts use the standard names and optional token accounts. A macro
+// so the contract can stay mutably borrowed next to the accounts it pays from.
+// Terminations pass `without_fee`, as they charge no settlement fee.
+macro_rules! collateral_payout {
+    ($ctx:expr, $contract:expr) => {
+        collateral_payout!(
+            $ctx,
+            $contract,
+            $ctx.accounts.treasury_vault.as_deref(),
+            $contract.settlement_fee_bps
+        )
+    };
+    ($ctx:expr, $contract:expr, without_fee) => {
+        collateral_payout!($ctx, $contract, None, 0)
+    };
+    ($ctx:expr, $contract:expr, $treasury_vault:expr, $fee_bps:expr) => {
+        CollateralPayout {
+            collateral_mint: $contract.collateral_mint,
+            seller: $contract.seller,
+            collateral: &$ctx.accounts.collateral,
+            buyer_escrow: &$ctx.accounts.buyer_escrow,
+            seller_escrow: &$ctx.accounts.seller_escrow,
+            seller_escrow_bump: $ctx.bumps.seller_escrow,
+            system_program: &$ctx.accounts.system_program,
+            collateral_vault: $ctx.accounts.collateral_vault.as_deref(),
+            buyer_token_escrow: $ctx.accounts.buyer_token_escrow.as_deref(),
+            seller_token_escrow: $ctx.accounts.seller_token_escrow.as_deref(),
+            token_program: $ctx.accounts.token_program.as_ref(),
+            treasury: &$ctx.accounts.treasury.to_account_info(),
+            treasury_vault: $treasury_vault,
+            fee_bps: $fee_bps,
+        }
+    };
+}
 
 #[program]
 pub mod basic {
@@ -17,7 +84,262 @@ pub mod basic {
         user_account.bump = ctx.bumps.user_account;
         user_account.owner = ctx.accounts.user.key();
         user_account.contract_count = 0;
-        user_account.contracts = Vec::new();
+        user_account.entry_count = 0;
+        user_account.page_count = 0;
+
+        emit!(UserInitializedEvent {
+            user: user_account.owner,
+            user_account: user_account.key(),
+        });
+        Ok(())
+    }
+
+    // Creates the market-wide configuration; only the program's upgrade
+    // authority can do this, and becomes the first admin
+    pub fn initialize_market_config(
+        ctx: Context<InitializeMarketConfig>,
+        min_margin_bps: u16,
+        max_margin_bps: u16,
+        min_expiry_seconds: i64,
+        max_expiry_seconds: i64,
+    ) -> Result<()> {
+        let market_config = &mut ctx.accounts.market_config;
+        market_config.bump = ctx.bumps.market_config;
+        market_config.admin = ctx.accounts.admin.key();
+        market_config.pending_admin = None;
+        market_config.oracle_authority = ctx.accounts.admin.key();
+        market_config.paused = false;
+        market_config.premium_fee_bps = 0;
+        market_config.settlement_fee_bps = 0;
+        market_config.crank_fee_bps = 0;
+        market_config.set_limits(
+            min_margin_bps,
+            max_margin_bps,
+            min_expiry_seconds,
+            max_expiry_seconds,
+        )?;
+        ctx.accounts.treasury.bump = ctx.bumps.treasury;
+
+        emit_market_config(market_config);
+        Ok(())
+    }
+
+    pub fn update_market_config(
+        ctx: Context<UpdateMarketConfig>,
+        min_margin_bps: u16,
+        max_margin_bps: u16,
+        min_expiry_seconds: i64,
+        max_expiry_seconds: i64,
+    ) -> Result<()> {
+        let market_config = &mut ctx.accounts.market_config;
+        market_config.set_limits(
+            min_margin_bps,
+            max_margin_bps,
+            min_expiry_seconds,
+            max_expiry_seconds,
+        )?;
+
+        emit_market_config(market_config);
+        Ok(())
+    }
+
+    // Pausing stops new contracts, exercise and settlement; withdrawals stay open
+    pub fn set_market_paused(ctx: Context<UpdateMarketConfig>, paused: bool) -> Result<()> {
+        let market_config = &mut ctx.accounts.market_config;
+        market_config.paused = paused;
+
+        emit_market_config(market_config);
+        Ok(())
+    }
+
+    // Contracts can only be opened against feeds published by this authority.
+    // Open contracts keep settling against the feeds they were opened with.
+    pub fn set_oracle_authority(
+        ctx: Context<UpdateMarketConfig>,
+        oracle_authority: Pubkey,
+    ) -> Result<()> {
+        let market_config = &mut ctx.accounts.market_config;
+        market_config.oracle_authority = oracle_authority;
+
+        emit_market_config(market_config);
+        Ok(())
+    }
+
+    // Fees apply to contracts opened afterwards; open contracts keep the
+    // settlement fee they were created with
+    pub fn set_protocol_fees(
+        ctx: Context<UpdateMarketConfig>,
+        premium_fee_bps: u16,
+        settlement_fee_bps: u16,
+    ) -> Result<()> {
+        require!(
+            premium_fee_bps <= MAX_PROTOCOL_FEE_BPS && settlement_fee_bps <= MAX_PROTOCOL_FEE_BPS,
+            ErrorCode::FeeTooHigh
+        );
+
+        let market_config = &mut ctx.accounts.market_config;
+        market_config.premium_fee_bps = premium_fee_bps;
+        market_config.settlement_fee_bps = settlement_fee_bps;
+
+        emit_market_config(market_config);
+        Ok(())
+    }
+
+    // Sets the keeper bounty for cranking expired contracts opened afterwards
+    pub fn set_crank_fee(ctx: Context<UpdateMarketConfig>, crank_fee_bps: u16) -> Result<()> {
+        require!(crank_fee_bps <= MAX_PROTOCOL_FEE_BPS, ErrorCode::FeeTooHigh);
+
+        let market_config = &mut ctx.accounts.market_config;
+        market_config.crank_fee_bps = crank_fee_bps;
+
+        emit_market_config(market_config);
+        Ok(())
+    }
+
+    // Registers the price precision of an underlying asset. Contracts, quotes
+    // and strategies on the ticker copy it when they are opened.
+    pub fn register_underlying_asset(
+        ctx: Context<RegisterUnderlyingAsset>,
+        ticker: String,
+        price_expo: i32,
+    ) -> Result<()> {
+        require!(
+            ticker.len() <= MAX_TICKER_LENGTH,
+            ErrorCode::AssetTickerTooLong
+        );
+        require!(
+            (MIN_PRICE_EXPO..=MAX_PRICE_EXPO).contains(&price_expo),
+            ErrorCode::PriceExpoOutOfRange
+        );
+
+        let asset = &mut ctx.accounts.asset;
+        asset.bump = ctx.bumps.asset;
+        asset.ticker = ticker;
+        asset.price_expo = price_expo;
+
+        emit!(UnderlyingAssetRegisteredEvent {
+            admin: ctx.accounts.admin.key(),
+            asset: asset.key(),
+            ticker: asset.ticker.clone(),
+            price_expo,
+        });
+        Ok(())
+    }
+
+    // The treasury vault for a mint is created by the account constraints
+    pub fn initialize_treasury_vault(_ctx: Context<InitializeTreasuryVault>) -> Result<()> {
+        Ok(())
+    }
+
+    // Moves collected fees out of the treasury, in lamports or from a treasury vault
+    pub fn collect_fees(ctx: Context<CollectFees>, amount: u64) -> Result<()> {
+        let treasury = &ctx.accounts.treasury;
+
+        let mint = match ctx.accounts.treasury_vault.as_deref() {
+            None => {
+                let treasury_info = treasury.to_account_info();
+                require!(
+                    collateral_balance(&treasury_info)? >= amount,
+                    ErrorCode::InsufficientTreasuryBalance
+                );
+                debit_collateral(&treasury_info, &ctx.accounts.destination, amount)?;
+                None
+            }
+            Some(treasury_vault) => {
+                require!(
+                    treasury_vault.amount >= amount,
+                    ErrorCode::InsufficientTreasuryBalance
+                );
+                transfer_tokens_from_treasury(
+                    treasury_vault,
+                    treasury,
+                    required_token_account(ctx.accounts.destination_token_account.as_deref())?,
+                    required_token_account(ctx.accounts.token_program.as_ref())?,
+                    amount,
+                )?;
+                Some(treasury_vault.mint)
+            }
+        };
+
+        emit!(FeesCollectedEvent {
+            admin: ctx.accounts.admin.key(),
+            mint,
+            amount,
+        });
+        Ok(())
+    }
+
+    pub fn initialize_insurance_fund(ctx: Context<InitializeInsuranceFund>) -> Result<()> {
+        ctx.accounts.insurance_fund.bump = ctx.bumps.insurance_fund;
+        Ok(())
+    }
+
+    // The insurance vault for a mint is created by the account constraints
+    pub fn initialize_insurance_vault(_ctx: Context<InitializeInsuranceVault>) -> Result<()> {
+        Ok(())
+    }
+
+    // Moves collected fees from the treasury into the insurance fund, which
+    // covers payoffs that defaulted sellers cannot pay
+    pub fn fund_insurance(ctx: Context<FundInsurance>, amount: u64) -> Result<()> {
+        let treasury = &ctx.accounts.treasury;
+
+        let mint = match ctx.accounts.treasury_vault.as_deref() {
+            None => {
+                let treasury_info = treasury.to_account_info();
+                require!(
+                    collateral_balance(&treasury_info)? >= amount,
+                    ErrorCode::InsufficientTreasuryBalance
+                );
+                debit_collateral(
+                    &treasury_info,
+                    &ctx.accounts.insurance_fund.to_account_info(),
+                    amount,
+                )?;
+                None
+            }
+            Some(treasury_vault) => {
+                require!(
+                    treasury_vault.amount >= amount,
+                    ErrorCode::InsufficientTreasuryBalance
+                );
+                let insurance_vault =
+                    required_token_account(ctx.accounts.insurance_vault.as_deref())?;
+                require!(
+                    insurance_vault.mint == treasury_vault.mint,
+                    ErrorCode::CollateralMintMismatch
+                );
+                transfer_tokens_from_treasury(
+                    treasury_vault,
+                    treasury,
+                    insurance_vault,
+                    required_token_account(ctx.accounts.token_program.as_ref())?,
+                    amount,
+                )?;
+                Some(treasury_vault.mint)
+            }
+        };
+
+        emit!(InsuranceFundedEvent {
+            admin: ctx.accounts.admin.key(),
+            mint,
+            amount,
+        });
+        Ok(())
+    }
+
+    // First step of an admin handover; the new admin has to accept it
+    pub fn propose_admin(ctx: Context<UpdateMarketConfig>, new_admin: Pubkey) -> Result<()> {
+        ctx.accounts.market_config.pending_admin = Some(new_admin);
+        Ok(())
+    }
+
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        let market_config = &mut ctx.accounts.market_config;
+        market_config.admin = ctx.accounts.new_admin.key();
+        market_config.pending_admin = None;
+
+        emit_market_config(market_config);
         Ok(())
     }
 
@@ -50,7 +372,11 @@ pub mod basic {
             ],
             signer_seeds,
         )?;
-        
+
+        emit!(EscrowInitializedEvent {
+            user: user_key,
+            escrow: escrow_pda,
+        });
         Ok(())
     }
 
@@ -68,9 +394,45 @@ pub mod basic {
             amount,
         )?;
 
+        emit!(DepositEvent {
+            user: ctx.accounts.user.key(),
+            escrow: ctx.accounts.user_escrow.key(),
+            mint: None,
+            amount,
+        });
+        Ok(())
+    }
+
+    // The token escrow is created by the account constraints, owned by the escrow PDA
+    pub fn initialize_token_escrow(_ctx: Context<InitializeTokenEscrow>) -> Result<()> {
+        Ok(())
+    }
+
+    pub fn deposit_tokens(ctx: Context<DepositTokens>, amount: u64) -> Result<()> {
+        require!(amount > 0, ErrorCode::InvalidDepositAmount);
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.user_token_account.to_account_info(),
+                    to: ctx.accounts.token_escrow.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            amount,
+        )?;
+
+        emit!(DepositEvent {
+            user: ctx.accounts.user.key(),
+            escrow: ctx.accounts.token_escrow.key(),
+            mint: Some(ctx.accounts.token_escrow.mint),
+            amount,
+        });
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn create_contract(
         ctx: Context<CreateContract>,
         underlying_asset: String,
@@ -78,190 +440,472 @@ pub mod basic {
         strike_price: u64,
         expiration_date: i64,
         option_type: OptionType,
+        exercise_style: ExerciseStyle,
         premium: u64,
         margin_requirement_bps: u16,
-        is_test: bool,
     ) -> Result<()> {
-        require!(
-            underlying_asset.len() <= MAX_TICKER_LENGTH,
-            ErrorCode::AssetTickerTooLong
-        );
+        let terms = ContractTerms {
+            underlying_asset,
+            num_units,
+            strike_price,
+            expiration_date,
+            option_type,
+            exercise_style,
+            premium,
+            margin_requirement_bps,
+            underlying_price_feed: ctx.accounts.underlying_price_feed.key(),
+            sol_price_feed: ctx.accounts.sol_price_feed.key(),
+            collateral_mint: ctx.accounts.open.collateral_mint.as_ref().map(|mint| mint.key()),
+            underlying_mint: ctx.accounts.open.underlying_mint.as_ref().map(|mint| mint.key()),
+            price_expo: ctx.accounts.asset.price_expo,
+        };
 
-        let buyer_account = &mut ctx.accounts.buyer_account;
-        let seller_account = &mut ctx.accounts.seller_account;
+        open_contract(
+            OpenContract::from_accounts(
+                &mut ctx.accounts.open,
+                &ctx.bumps.open,
+                &ctx.accounts.seller.to_account_info(),
+                &ctx.accounts.underlying_price_feed,
+                &ctx.accounts.sol_price_feed,
+                current_time(ctx.remaining_accounts)?,
+            )?,
+            terms,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn propose_contract(
+        ctx: Context<ProposeContract>,
+        offer_id: u64,
+        underlying_asset: String,
+        num_units: u64,
+        strike_price: u64,
+        expiration_date: i64,
+        option_type: OptionType,
+        exercise_style: ExerciseStyle,
+        premium: u64,
+        margin_requirement_bps: u16,
+        valid_until: i64,
+    ) -> Result<()> {
+        let offer = &mut ctx.accounts.offer;
+        offer.bump = ctx.bumps.offer;
+        offer.offer_id = offer_id;
+        offer.buyer = ctx.accounts.buyer.key();
+        offer.seller = ctx.accounts.seller.key();
+        offer.created_at = current_time(ctx.remaining_accounts)?;
+        offer.valid_until = valid_until;
+        offer.underlying_asset = underlying_asset;
+        offer.num_units = num_units;
+        offer.strike_price = strike_price;
+        offer.expiration_date = expiration_date;
+        offer.option_type = option_type;
+        offer.exercise_style = exercise_style;
+        offer.premium = premium;
+        offer.margin_requirement_bps = margin_requirement_bps;
+        offer.underlying_price_feed = ctx.accounts.underlying_price_feed.key();
+        offer.sol_price_feed = ctx.accounts.sol_price_feed.key();
+        offer.collateral_mint = ctx.accounts.collateral_mint.as_ref().map(|mint| mint.key());
+        offer.underlying_mint = ctx.accounts.underlying_mint.as_ref().map(|mint| mint.key());
+        offer.price_expo = ctx.accounts.asset.price_expo;
+
+        Ok(())
+    }
+
+    pub fn accept_contract(ctx: Context<AcceptContract>) -> Result<()> {
+        let offer = &ctx.accounts.offer;
+        let current_time = current_time(ctx.remaining_accounts)?;
+
+        // The buyer only agreed to pay the premium until the offer lapses
+        require!(current_time < offer.valid_until, ErrorCode::OfferExpired);
+
+        let terms = ContractTerms {
+            underlying_asset: offer.underlying_asset.clone(),
+            num_units: offer.num_units,
+            strike_price: offer.strike_price,
+            expiration_date: offer.expiration_date,
+            option_type: offer.option_type,
+            exercise_style: offer.exercise_style,
+            premium: offer.premium,
+            margin_requirement_bps: offer.margin_requirement_bps,
+            underlying_price_feed: offer.underlying_price_feed,
+            sol_price_feed: offer.sol_price_feed,
+            collateral_mint: offer.collateral_mint,
+            underlying_mint: offer.underlying_mint,
+            price_expo: offer.price_expo,
+        };
+
+        open_contract(
+            OpenContract::from_accounts(
+                &mut ctx.accounts.open,
+                &ctx.bumps.open,
+                &ctx.accounts.seller.to_account_info(),
+                &ctx.accounts.underlying_price_feed,
+                &ctx.accounts.sol_price_feed,
+                current_time,
+            )?,
+            terms,
+        )
+    }
+
+    // The offer account is closed back to the buyer by the account constraints
+    pub fn reject_offer(_ctx: Context<RejectOffer>) -> Result<()> {
+        Ok(())
+    }
+
+    pub fn cancel_offer(_ctx: Context<CancelOffer>) -> Result<()> {
+        Ok(())
+    }
+
+    // Posts a standing ask that any registered buyer can fill up to `max_units`
+    #[allow(clippy::too_many_arguments)]
+    pub fn post_quote(
+        ctx: Context<PostQuote>,
+        quote_id: u64,
+        underlying_asset: String,
+        strike_price: u64,
+        expiration_date: i64,
+        option_type: OptionType,
+        exercise_style: ExerciseStyle,
+        premium_per_unit: u64,
+        max_units: u64,
+        margin_requirement_bps: u16,
+        valid_until: i64,
+    ) -> Result<()> {
+        require!(max_units > 0, ErrorCode::InvalidQuoteUnits);
+
+        let quote = &mut ctx.accounts.quote;
+        quote.bump = ctx.bumps.quote;
+        quote.quote_id = quote_id;
+        quote.seller = ctx.accounts.seller.key();
+        quote.created_at = current_time(ctx.remaining_accounts)?;
+        quote.valid_until = valid_until;
+        quote.underlying_asset = underlying_asset;
+        quote.strike_price = strike_price;
+        quote.expiration_date = expiration_date;
+        quote.option_type = option_type;
+        quote.exercise_style = exercise_style;
+        quote.premium_per_unit = premium_per_unit;
+        quote.remaining_units = max_units;
+        quote.margin_requirement_bps = margin_requirement_bps;
+        quote.underlying_price_feed = ctx.accounts.underlying_price_feed.key();
+        quote.sol_price_feed = ctx.accounts.sol_price_feed.key();
+        quote.collateral_mint = ctx.accounts.collateral_mint.as_ref().map(|mint| mint.key());
+        quote.underlying_mint = ctx.accounts.underlying_mint.as_ref().map(|mint| mint.key());
+        quote.price_expo = ctx.accounts.asset.price_expo;
+
+        Ok(())
+    }
+
+    // Writes a contract for `units` of a quote; the seller consented by posting it
+    pub fn fill_quote(ctx: Context<FillQuote>, units: u64) -> Result<()> {
+        let quote = &mut ctx.accounts.quote;
 
         require!(
-            buyer_account.contracts.len() < MAX_CONTRACTS,
-            ErrorCode::MaxContractsReached
+            current_time(ctx.remaining_accounts)? < quote.valid_until,
+            ErrorCode::QuoteExpired
         );
         require!(
-            seller_account.contracts.len() < MAX_CONTRACTS,
-            ErrorCode::MaxContractsReached
+            units > 0 && units <= quote.remaining_units,
+            ErrorCode::InvalidQuoteUnits
         );
+        quote.remaining_units -= units;
 
-        // Calculate margin amount
-        let margin_amount = num_units
-            .checked_mul(strike_price)
-            .ok_or(ErrorCode::CalculationError)?
-            .checked_mul(margin_requirement_bps as u64)
-            .ok_or(ErrorCode::CalculationError)?
-            .checked_div(10000)
-            .ok_or(ErrorCode::CalculationError)?;
+        let terms = ContractTerms {
+            underlying_asset: quote.underlying_asset.clone(),
+            num_units: units,
+            strike_price: quote.strike_price,
+            expiration_date: quote.expiration_date,
+            option_type: quote.option_type,
+            exercise_style: quote.exercise_style,
+            premium: quote
+                .premium_per_unit
+                .checked_mul(units)
+                .ok_or(ErrorCode::CalculationError)?,
+            margin_requirement_bps: quote.margin_requirement_bps,
+            underlying_price_feed: quote.underlying_price_feed,
+            sol_price_feed: quote.sol_price_feed,
+            collateral_mint: quote.collateral_mint,
+            underlying_mint: quote.underlying_mint,
+            price_expo: quote.price_expo,
+        };
 
-        // Check buyer escrow has sufficient premium
-        let buyer_escrow_balance = ctx.accounts.buyer_escrow.lamports();
-        require!(
-            buyer_escrow_balance >= premium,
-            ErrorCode::InsufficientBalance
-        );
+        open_contract(
+            OpenContract::from_accounts(
+                &mut ctx.accounts.open,
+                &ctx.bumps.open,
+                &ctx.accounts.seller.to_account_info(),
+                &ctx.accounts.underlying_price_feed,
+                &ctx.accounts.sol_price_feed,
+                current_time(ctx.remaining_accounts)?,
+            )?,
+            terms,
+        )?;
 
-        // Check seller escrow has sufficient margin
-        let seller_escrow_balance = ctx.accounts.seller_escrow.lamports();
+        // A fully filled quote is closed and its rent returned to the seller
+        if ctx.accounts.quote.remaining_units == 0 {
+            ctx.accounts.quote.close(ctx.accounts.seller.to_account_info())?;
+        }
+
+        Ok(())
+    }
+
+    // The quote account is closed back to the seller by the account constraints
+    pub fn cancel_quote(_ctx: Context<CancelQuote>) -> Result<()> {
+        Ok(())
+    }
+
+    pub fn close_expired_quote(ctx: Context<CloseExpiredQuote>) -> Result<()> {
         require!(
-            seller_escrow_balance >= margin_amount,
-            ErrorCode::InsufficientBalance
+            current_time(ctx.remaining_accounts)? >= ctx.accounts.quote.valid_until,
+            ErrorCode::QuoteNotExpired
         );
+        Ok(())
+    }
 
-        // Transfer premium from buyer escrow to seller wallet using PDA signing
-        let buyer_key = ctx.accounts.buyer.key();
-        let buyer_escrow_seeds = &[
-            b"escrow".as_ref(),
-            buyer_key.as_ref(),
-            &[ctx.bumps.buyer_escrow],
-        ];
-        let buyer_escrow_signer = &[&buyer_escrow_seeds[..]];
-        
-        anchor_lang::solana_program::program::invoke_signed(
-            &anchor_lang::solana_program::system_instruction::transfer(
-                ctx.accounts.buyer_escrow.key,
-                ctx.accounts.seller.key,
-                premium,
-            ),
-            &[
-                ctx.accounts.buyer_escrow.to_account_info(),
-                ctx.accounts.seller.to_account_info(),
-                ctx.accounts.system_program.to_account_info(),
-            ],
-            buyer_escrow_signer,
+    pub fn exercise(ctx: Context<Exercise>) -> Result<()> {
+        let contract = &mut ctx.accounts.contract;
+        contract.check_settlement_kind(SettlementKind::Cash)?;
+        let current_time = current_time(ctx.remaining_accounts)?;
+        contract.check_exercisable(ctx.accounts.buyer.key(), current_time)?;
+
+        // Exercise every unit that has not already been exercised in part
+        let units = contract.unexercised_units();
+        let prices = settlement_prices(
+            contract.collateral_mint,
+            contract.price_expo,
+            &ctx.accounts.underlying_price_feed,
+            &ctx.accounts.sol_price_feed,
+            current_time,
         )?;
+        let payoff_amount = exercise_payoff(contract, units, &prices)?;
 
-        // Initialize contract
-        let contract = &mut ctx.accounts.contract;
-        contract.bump = ctx.bumps.contract;
-        contract.contract_id = buyer_account.contract_count;
-        contract.creation_date = Clock::get()?.unix_timestamp;
-        contract.underlying_asset = underlying_asset;
-        contract.num_units = num_units;
-        contract.strike_price = strike_price;
-        contract.expiration_date = expiration_date;
-        contract.option_type = option_type;
-        contract.premium = premium;
-        contract.buyer = ctx.accounts.buyer.key();
-        contract.seller = ctx.accounts.seller.key();
-        contract.buyer_escrow = ctx.accounts.buyer_escrow.key();
-        contract.seller_escrow = ctx.accounts.seller_escrow.key();
-        contract.seller_pending_balance = 0;
-        contract.buyer_pending_balance = 0;
-        contract.status = ContractStatus::Active;
-        contract.margin_requirement_bps = margin_requirement_bps;
-        contract.margin_amount = margin_amount;
-        contract.is_test = is_test;
+        // An out-of-the-money exercise has nothing to settle, so the contract
+        // expires immediately and the seller's margin is released
+        let status = if payoff_amount > 0 {
+            ContractStatus::Exercised
+        } else {
+            let margin_released = release_margin(
+                contract.collateral_mint,
+                &ctx.accounts.collateral,
+                &ctx.accounts.seller_escrow,
+                ctx.accounts.collateral_vault.as_deref(),
+                ctx.accounts.seller_token_escrow.as_deref(),
+                ctx.accounts.token_program.as_ref(),
+            )?;
+            emit!(ContractExpiredEvent {
+                contract: contract.key(),
+                contract_id: contract.contract_id,
+                buyer: contract.buyer,
+                seller: contract.seller,
+                margin_released,
+                underlying_released: 0,
+            });
+            ContractStatus::Expired
+        };
 
-        // Add contract to buyer's account
-        buyer_account.contracts.push(UserContract {
-            contract_address: contract.key(),
-            role: UserRole::Buyer,
-            status: ContractStatus::Active,
-        });
-        buyer_account.contract_count += 1;
+        contract.seller_pending_balance = payoff_amount;
+        contract.buyer_pending_balance = payoff_amount;
+        contract.units_exercised = contract.num_units;
+        contract.status = status;
 
-        // Add contract to seller's account
-        seller_account.contracts.push(UserContract {
-            contract_address: contract.key(),
-            role: UserRole::Seller,
-            status: ContractStatus::Active,
-        });
+        // Update status in user accounts
+        let contract_key = contract.key();
+        ctx.accounts.buyer_page.set_contract_status(contract_key, status);
+        ctx.accounts.seller_page.set_contract_status(contract_key, status);
 
+        emit!(ContractExercisedEvent {
+            contract: contract_key,
+            contract_id: contract.contract_id,
+            buyer: contract.buyer,
+            seller: contract.seller,
+            units,
+            underlying_price: prices.underlying,
+            sol_price: prices.sol,
+            payoff_amount,
+            status,
+        });
         Ok(())
     }
 
-    pub fn exercise(
-        ctx: Context<Exercise>,
-        underlying_price_usd: u64,
-        sol_price_usd: u64,
-    ) -> Result<()> {
+    pub fn exercise_units(ctx: Context<ExerciseUnits>, units: u64) -> Result<()> {
         let contract = &mut ctx.accounts.contract;
+        contract.check_settlement_kind(SettlementKind::Cash)?;
+        let current_time = current_time(ctx.remaining_accounts)?;
+        contract.check_exercisable(ctx.accounts.buyer.key(), current_time)?;
 
+        let unexercised_units = contract.unexercised_units();
         require!(
-            contract.status == ContractStatus::Active,
-            ErrorCode::ContractNotActive
-        );
-        require!(
-            contract.buyer == ctx.accounts.buyer.key(),
-            ErrorCode::UnauthorizedExercise
+            units > 0 && units <= unexercised_units,
+            ErrorCode::InvalidExerciseUnits
         );
 
-        let current_time = Clock::get()?.unix_timestamp;
-        require!(
-            contract.is_test || current_time >= contract.expiration_date,
-            ErrorCode::ContractNotExpired
-        );
+        let prices = settlement_prices(
+            contract.collateral_mint,
+            contract.price_expo,
+            &ctx.accounts.underlying_price_feed,
+            &ctx.accounts.sol_price_feed,
+            current_time,
+        )?;
+        let payoff_amount = exercise_payoff(contract, units, &prices)?;
+
+        // Settle the exercised units straight away against their share of the margin
+        let payout = collateral_payout!(ctx, contract);
+        let locked_margin = payout.locked_margin()?;
+        let margin_share = (locked_margin as u128)
+            .checked_mul(units as u128)
+            .ok_or(ErrorCode::CalculationError)?
+            / unexercised_units as u128;
+        let amounts = payout.pay_buyer(margin_share as u64, payoff_amount)?;
 
-        // Calculate position
-        let position_lamports = match contract.option_type {
+        contract.units_exercised += units;
+        contract.settlement_fee += amounts.fee;
+
+        // The contract is finished once every unit has been exercised
+        let contract_key = contract.key();
+        if contract.units_exercised == contract.num_units {
+            contract.status = ContractStatus::Settled;
+
+            ctx.accounts.buyer_page.set_contract_status(contract_key, ContractStatus::Settled);
+            ctx.accounts.seller_page.set_contract_status(contract_key, ContractStatus::Settled);
+        }
+
+        emit!(ContractExercisedEvent {
+            contract: contract_key,
+            contract_id: contract.contract_id,
+            buyer: contract.buyer,
+            seller: contract.seller,
+            units,
+            underlying_price: prices.underlying,
+            sol_price: prices.sol,
+            payoff_amount,
+            status: contract.status,
+        });
+        emit!(ContractSettledEvent {
+            contract: contract_key,
+            contract_id: contract.contract_id,
+            buyer: contract.buyer,
+            seller: contract.seller,
+            paid_from_margin: amounts.paid_from_margin,
+            paid_from_seller_escrow: amounts.paid_from_seller_escrow,
+            margin_released: amounts.margin_released,
+            settlement_fee: amounts.fee,
+            status: contract.status,
+        });
+        Ok(())
+    }
+
+    // Delivers every unit of a physically settled contract. The buyer of a call
+    // pays the strike and receives the underlying the seller locked at creation;
+    // the buyer of a put delivers the underlying and is paid the strike out of
+    // the seller's margin and escrow. No settlement fee is charged on deliveries.
+    pub fn exercise_physical(ctx: Context<ExercisePhysical>) -> Result<()> {
+        let contract = &mut ctx.accounts.contract;
+        contract.check_settlement_kind(SettlementKind::Physical)?;
+        let current_time = current_time(ctx.remaining_accounts)?;
+        contract.check_exercisable(ctx.accounts.buyer.key(), current_time)?;
+
+        let units = contract.unexercised_units();
+        let underlying_amount = underlying_amount(units, contract.underlying_decimals)?;
+        let strike_notional = (contract.strike_price as u128)
+            .checked_mul(units as u128)
+            .ok_or(ErrorCode::CalculationError)?;
+        let strike_payment = usd_to_token_units(
+            strike_notional,
+            contract.price_expo,
+            contract.collateral_decimals,
+            Rounding::Down,
+        )?;
+        let token_program = &ctx.accounts.token_program;
+
+        let margin_released = match contract.option_type {
             OptionType::Call => {
-                if underlying_price_usd > contract.strike_price {
-                    let profit_per_share = underlying_price_usd - contract.strike_price;
-                    let total_profit_usd = profit_per_share
-                        .checked_mul(contract.num_units)
-                        .ok_or(ErrorCode::CalculationError)?;
-                    total_profit_usd
-                        .checked_mul(LAMPORTS_PER_SOL)
-                        .ok_or(ErrorCode::CalculationError)?
-                        .checked_div(sol_price_usd)
-                        .ok_or(ErrorCode::CalculationError)?
-                } else {
-                    0
-                }
+                let buyer_token_escrow = &ctx.accounts.buyer_token_escrow;
+                require!(
+                    buyer_token_escrow.amount >= strike_payment,
+                    ErrorCode::InsufficientBalance
+                );
+                transfer_tokens_from_escrow(
+                    buyer_token_escrow,
+                    &ctx.accounts.buyer_escrow,
+                    &contract.buyer,
+                    ctx.bumps.buyer_escrow,
+                    &ctx.accounts.seller_token_escrow,
+                    token_program,
+                    strike_payment,
+                )?;
+                transfer_tokens_from_collateral(
+                    required_token_account(ctx.accounts.underlying_vault.as_deref())?,
+                    &ctx.accounts.collateral,
+                    &ctx.accounts.buyer_underlying_escrow,
+                    token_program,
+                    underlying_amount,
+                )?;
+                release_margin(
+                    contract.collateral_mint,
+                    &ctx.accounts.collateral,
+                    &ctx.accounts.seller_escrow,
+                    Some(ctx.accounts.collateral_vault.as_ref()),
+                    Some(ctx.accounts.seller_token_escrow.as_ref()),
+                    Some(token_program),
+                )?
             }
             OptionType::Put => {
-                if contract.strike_price > underlying_price_usd {
-                    let profit_per_share = contract.strike_price - underlying_price_usd;
-                    let total_profit_usd = profit_per_share
-                        .checked_mul(contract.num_units)
-                        .ok_or(ErrorCode::CalculationError)?;
-                    total_profit_usd
-                        .checked_mul(LAMPORTS_PER_SOL)
-                        .ok_or(ErrorCode::CalculationError)?
-                        .checked_div(sol_price_usd)
-                        .ok_or(ErrorCode::CalculationError)?
-                } else {
-                    0
-                }
+                let buyer_underlying_escrow = &ctx.accounts.buyer_underlying_escrow;
+                require!(
+                    buyer_underlying_escrow.amount >= underlying_amount,
+                    ErrorCode::InsufficientBalance
+                );
+                transfer_tokens_from_escrow(
+                    buyer_underlying_escrow,
+                    &ctx.accounts.buyer_escrow,
+                    &contract.buyer,
+                    ctx.bumps.buyer_escrow,
+                    required_token_account(ctx.accounts.seller_underlying_escrow.as_deref())?,
+                    token_program,
+                    underlying_amount,
+                )?;
+
+                let payout = CollateralPayout {
+                    collateral_mint: contract.collateral_mint,
+                    seller: contract.seller,
+                    collateral: &ctx.accounts.collateral,
+                    buyer_escrow: &ctx.accounts.buyer_escrow,
+                    seller_escrow: &ctx.accounts.seller_escrow,
+                    seller_escrow_bump: ctx.bumps.seller_escrow,
+                    system_program: &ctx.accounts.system_program,
+                    collateral_vault: Some(ctx.accounts.collateral_vault.as_ref()),
+                    buyer_token_escrow: Some(ctx.accounts.buyer_token_escrow.as_ref()),
+                    seller_token_escrow: Some(ctx.accounts.seller_token_escrow.as_ref()),
+                    token_program: Some(token_program),
+                    treasury: &ctx.accounts.treasury.to_account_info(),
+                    treasury_vault: None,
+                    fee_bps: 0,
+                };
+                let locked_margin = payout.locked_margin()?;
+                payout.pay_buyer(locked_margin, strike_payment)?.margin_released
             }
         };
 
-        contract.seller_pending_balance = position_lamports;
-        contract.buyer_pending_balance = position_lamports;
-        contract.status = ContractStatus::Exercised;
+        contract.units_exercised = contract.num_units;
+        contract.status = ContractStatus::Settled;
 
         // Update status in user accounts
-        let buyer_account = &mut ctx.accounts.buyer_account;
-        for user_contract in buyer_account.contracts.iter_mut() {
-            if user_contract.contract_address == contract.key() {
-                user_contract.status = ContractStatus::Exercised;
-                break;
-            }
-        }
-
-        let seller_account = &mut ctx.accounts.seller_account;
-        for user_contract in seller_account.contracts.iter_mut() {
-            if user_contract.contract_address == contract.key() {
-                user_contract.status = ContractStatus::Exercised;
-                break;
-            }
-        }
+        let contract_key = contract.key();
+        ctx.accounts.buyer_page.set_contract_status(contract_key, ContractStatus::Settled);
+        ctx.accounts.seller_page.set_contract_status(contract_key, ContractStatus::Settled);
 
+        emit!(ContractDeliveredEvent {
+            contract: contract_key,
+            contract_id: contract.contract_id,
+            buyer: contract.buyer,
+            seller: contract.seller,
+            option_type: contract.option_type,
+            units,
+            underlying_mint: ctx.accounts.buyer_underlying_escrow.mint,
+            underlying_amount,
+            strike_payment,
+            margin_released,
+        });
         Ok(())
     }
 
@@ -277,152 +921,4147 @@ pub mod basic {
             ErrorCode::NoPendingBalance
         );
 
-        let seller_escrow_balance = ctx.accounts.seller_escrow.lamports();
-        require!(
-            seller_escrow_balance >= contract.seller_pending_balance,
-            ErrorCode::InsufficientSellerEscrow
-        );
+        // Pay out of the locked margin first, then the seller escrow. Whatever
+        // the seller cannot cover is a default, drawn from the insurance fund
+        // when it is passed
+        let payout = collateral_payout!(ctx, contract);
+        let locked_margin = payout.locked_margin()?;
+        let covered = payout
+            .seller_collateral(locked_margin)?
+            .min(contract.seller_pending_balance);
+        let shortfall = contract.seller_pending_balance - covered;
+        let amounts = payout.pay_buyer(locked_margin, covered)?;
 
-        // Transfer from seller escrow to buyer escrow using PDA signing
-        let seller_escrow_seeds = &[
-            b"escrow".as_ref(),
-            contract.seller.as_ref(),
-            &[ctx.bumps.seller_escrow],
-        ];
-        let seller_escrow_signer = &[&seller_escrow_seeds[..]];
-        
-        anchor_lang::solana_program::program::invoke_signed(
-            &anchor_lang::solana_program::system_instruction::transfer(
-                ctx.accounts.seller_escrow.key,
-                ctx.accounts.buyer_escrow.key,
-                contract.seller_pending_balance,
-            ),
-            &[
-                ctx.accounts.seller_escrow.to_account_info(),
-                ctx.accounts.buyer_escrow.to_account_info(),
-                ctx.accounts.system_program.to_account_info(),
-            ],
-            seller_escrow_signer,
-        )?;
+        let paid_from_insurance = match ctx.accounts.insurance_fund.as_ref() {
+            _ if shortfall == 0 => 0,
+            Some(insurance_fund) => draw_insurance(
+                contract.collateral_mint,
+                insurance_fund,
+                ctx.accounts.insurance_vault.as_deref(),
+                &ctx.accounts.buyer_escrow,
+                ctx.accounts.buyer_token_escrow.as_deref(),
+                ctx.accounts.token_program.as_ref(),
+                shortfall,
+            )?,
+            // Only the buyer, who bears the loss, can settle a default
+            // without drawing on the insurance fund
+            None => {
+                require!(
+                    ctx.accounts.caller.key() == contract.buyer,
+                    ErrorCode::InsuranceFundRequired
+                );
+                0
+            }
+        };
 
+        let status = if shortfall > 0 {
+            ContractStatus::Defaulted
+        } else {
+            ContractStatus::Settled
+        };
+        contract.settlement_fee += amounts.fee;
         contract.seller_pending_balance = 0;
         contract.buyer_pending_balance = 0;
-        contract.status = ContractStatus::Settled;
+        contract.default_shortfall = shortfall;
+        contract.paid_from_insurance = paid_from_insurance;
+        contract.status = status;
 
         // Update status in user accounts
-        let buyer_account = &mut ctx.accounts.buyer_account;
-        for user_contract in buyer_account.contracts.iter_mut() {
-            if user_contract.contract_address == contract.key() {
-                user_contract.status = ContractStatus::Settled;
-                break;
-            }
-        }
+        let contract_key = contract.key();
+        ctx.accounts.buyer_page.set_contract_status(contract_key, status);
+        ctx.accounts.seller_page.set_contract_status(contract_key, status);
 
-        let seller_account = &mut ctx.accounts.seller_account;
-        for user_contract in seller_account.contracts.iter_mut() {
-            if user_contract.contract_address == contract.key() {
-                user_contract.status = ContractStatus::Settled;
-                break;
-            }
+        emit!(ContractSettledEvent {
+            contract: contract_key,
+            contract_id: contract.contract_id,
+            buyer: contract.buyer,
+            seller: contract.seller,
+            paid_from_margin: amounts.paid_from_margin,
+            paid_from_seller_escrow: amounts.paid_from_seller_escrow,
+            margin_released: amounts.margin_released,
+            settlement_fee: amounts.fee,
+            status,
+        });
+        if shortfall > 0 {
+            emit!(ContractDefaultedEvent {
+                contract: contract_key,
+                contract_id: contract.contract_id,
+                buyer: contract.buyer,
+                seller: contract.seller,
+                shortfall,
+                paid_from_insurance,
+            });
         }
-
         Ok(())
     }
 
-    pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
-        let escrow_balance = ctx.accounts.user_escrow.lamports();
-        require!(escrow_balance >= amount, ErrorCode::InsufficientBalance);
+    pub fn expire(ctx: Context<Expire>) -> Result<()> {
+        let contract = &mut ctx.accounts.contract;
 
-        // Transfer from user escrow to user wallet using PDA signing
-        let user_key = ctx.accounts.user.key();
-        let escrow_seeds = &[
-            b"escrow".as_ref(),
-            user_key.as_ref(),
-            &[ctx.bumps.user_escrow],
-        ];
-        let escrow_signer = &[&escrow_seeds[..]];
-        
-        anchor_lang::solana_program::program::invoke_signed(
-            &anchor_lang::solana_program::system_instruction::transfer(
-                ctx.accounts.user_escrow.key,
-                ctx.accounts.user.key,
-                amount,
+        require!(
+            contract.status == ContractStatus::Active,
+            ErrorCode::ContractNotActive
+        );
+
+        let current_time = current_time(ctx.remaining_accounts)?;
+        let expiry_deadline = contract
+            .expiration_date
+            .checked_add(EXERCISE_WINDOW_SECONDS)
+            .ok_or(ErrorCode::CalculationError)?;
+        require!(
+            current_time >= expiry_deadline,
+            ErrorCode::GracePeriodNotElapsed
+        );
+
+        // A cash contract still in the money is owed to the buyer, so it is
+        // left to `crank_expiry` to exercise rather than released to the seller
+        if contract.settlement_kind == SettlementKind::Cash {
+            let prices = settlement_prices(
+                contract.collateral_mint,
+                contract.price_expo,
+                &ctx.accounts.underlying_price_feed,
+                &ctx.accounts.sol_price_feed,
+                current_time,
+            )?;
+            let payoff_amount = exercise_payoff(contract, contract.unexercised_units(), &prices)?;
+            require!(payoff_amount == 0, ErrorCode::ContractInTheMoney);
+        }
+
+        // Release the whole locked margin back to the seller
+        let margin_released = release_margin(
+            contract.collateral_mint,
+            &ctx.accounts.collateral,
+            &ctx.accounts.seller_escrow,
+            ctx.accounts.collateral_vault.as_deref(),
+            ctx.accounts.seller_token_escrow.as_deref(),
+            ctx.accounts.token_program.as_ref(),
+        )?;
+
+        // Physical calls also return the locked underlying to the seller
+        let underlying_released = if contract.locks_underlying() {
+            let underlying_vault =
+                required_token_account(ctx.accounts.underlying_vault.as_deref())?;
+            transfer_tokens_from_collateral(
+                underlying_vault,
+                &ctx.accounts.collateral,
+                required_token_account(ctx.accounts.seller_underlying_escrow.as_deref())?,
+                required_token_account(ctx.accounts.token_program.as_ref())?,
+                underlying_vault.amount,
+            )?;
+            underlying_vault.amount
+        } else {
+            0
+        };
+
+        contract.status = ContractStatus::Expired;
+
+        // Update status in user accounts
+        let contract_key = contract.key();
+        ctx.accounts.buyer_page.set_contract_status(contract_key, ContractStatus::Expired);
+        ctx.accounts.seller_page.set_contract_status(contract_key, ContractStatus::Expired);
+
+        emit!(ContractExpiredEvent {
+            contract: contract_key,
+            contract_id: contract.contract_id,
+            buyer: contract.buyer,
+            seller: contract.seller,
+            margin_released,
+            underlying_released,
+        });
+        Ok(())
+    }
+
+    // Permissionless keeper crank for contracts whose exercise window has
+    // passed, so buyers who do not exercise still get their payoff. Buyers
+    // keep the whole window to exercise themselves without paying the bounty.
+    // In-the-money contracts are exercised at the current oracle prices and
+    // settled if the seller can cover them, otherwise they are left Exercised
+    // for `settle`. The rest expire. The keeper is paid the contract's crank
+    // fee on the payoff, or on the released margin, out of the locked margin.
+    pub fn crank_expiry(ctx: Context<CrankExpiry>) -> Result<()> {
+        let contract = &mut ctx.accounts.contract;
+        contract.check_settlement_kind(SettlementKind::Cash)?;
+
+        require!(
+            contract.status == ContractStatus::Active,
+            ErrorCode::ContractNotActive
+        );
+        let current_time = current_time(ctx.remaining_accounts)?;
+        let expiry_deadline = contract
+            .expiration_date
+            .checked_add(EXERCISE_WINDOW_SECONDS)
+            .ok_or(ErrorCode::CalculationError)?;
+        require!(
+            current_time >= expiry_deadline,
+            ErrorCode::GracePeriodNotElapsed
+        );
+
+        let units = contract.unexercised_units();
+        let prices = settlement_prices(
+            contract.collateral_mint,
+            contract.price_expo,
+            &ctx.accounts.underlying_price_feed,
+            &ctx.accounts.sol_price_feed,
+            current_time,
+        )?;
+        let payoff_amount = exercise_payoff(contract, units, &prices)?;
+
+        // The bounty comes out of the margin and so out of the buyer's payoff,
+        // or out of the margin released to the seller
+        let locked_margin = locked_collateral(
+            contract.collateral_mint,
+            &ctx.accounts.collateral,
+            ctx.accounts.collateral_vault.as_deref(),
+        )?;
+        let bounty_base = if payoff_amount > 0 {
+            payoff_amount
+        } else {
+            locked_margin
+        };
+        let bounty = fee_amount(bounty_base, contract.crank_fee_bps)?.min(locked_margin);
+        if bounty > 0 {
+            match contract.collateral_mint {
+                None => debit_collateral(
+                    &ctx.accounts.collateral.to_account_info(),
+                    &ctx.accounts.caller.to_account_info(),
+                    bounty,
+                )?,
+                Some(_) => {
+                    transfer_tokens_from_collateral(
+                        required_token_account(ctx.accounts.collateral_vault.as_deref())?,
+                        &ctx.accounts.collateral,
+                        required_token_account(ctx.accounts.caller_token_account.as_deref())?,
+                        required_token_account(ctx.accounts.token_program.as_ref())?,
+                        bounty,
+                    )?;
+                    if let Some(collateral_vault) = ctx.accounts.collateral_vault.as_mut() {
+                        collateral_vault.reload()?;
+                    }
+                }
+            }
+        }
+
+        let contract_key = contract.key();
+        let status = if payoff_amount == 0 {
+            let margin_released = release_margin(
+                contract.collateral_mint,
+                &ctx.accounts.collateral,
+                &ctx.accounts.seller_escrow,
+                ctx.accounts.collateral_vault.as_deref(),
+                ctx.accounts.seller_token_escrow.as_deref(),
+                ctx.accounts.token_program.as_ref(),
+            )?;
+            emit!(ContractExpiredEvent {
+                contract: contract_key,
+                contract_id: contract.contract_id,
+                buyer: contract.buyer,
+                seller: contract.seller,
+                margin_released,
+                underlying_released: 0,
+            });
+            ContractStatus::Expired
+        } else {
+            let owed = payoff_amount - bounty;
+            let payout = collateral_payout!(ctx, contract);
+            let margin = payout.locked_margin()?;
+
+            // A seller who cannot cover the payoff is left to `settle`, which
+            // handles the default
+            let status = if payout.seller_collateral(margin)? >= owed {
+                ContractStatus::Settled
+            } else {
+                ContractStatus::Exercised
+            };
+            emit!(ContractExercisedEvent {
+                contract: contract_key,
+                contract_id: contract.contract_id,
+                buyer: contract.buyer,
+                seller: contract.seller,
+                units,
+                underlying_price: prices.underlying,
+                sol_price: prices.sol,
+                payoff_amount,
+                status,
+            });
+            if status == ContractStatus::Settled {
+                let amounts = payout.pay_buyer(margin, owed)?;
+                contract.settlement_fee += amounts.fee;
+                emit!(ContractSettledEvent {
+                    contract: contract_key,
+                    contract_id: contract.contract_id,
+                    buyer: contract.buyer,
+                    seller: contract.seller,
+                    paid_from_margin: amounts.paid_from_margin,
+                    paid_from_seller_escrow: amounts.paid_from_seller_escrow,
+                    margin_released: amounts.margin_released,
+                    settlement_fee: amounts.fee,
+                    status,
+                });
+            } else {
+                contract.seller_pending_balance = owed;
+                contract.buyer_pending_balance = owed;
+            }
+            contract.units_exercised = contract.num_units;
+            status
+        };
+        contract.margin_call_deadline = None;
+        contract.status = status;
+
+        // Update status in user accounts
+        ctx.accounts.buyer_page.set_contract_status(contract_key, status);
+        ctx.accounts.seller_page.set_contract_status(contract_key, status);
+
+        emit!(ContractCrankedEvent {
+            contract: contract_key,
+            contract_id: contract.contract_id,
+            buyer: contract.buyer,
+            seller: contract.seller,
+            keeper: ctx.accounts.caller.key(),
+            bounty,
+            status,
+        });
+        Ok(())
+    }
+
+    // Recomputes the margin a seller must hold at the current oracle prices and
+    // opens a margin call when the locked margin falls short of it
+    pub fn mark_to_market(ctx: Context<MarkToMarket>) -> Result<()> {
+        let contract = &mut ctx.accounts.contract;
+
+        require!(
+            contract.status == ContractStatus::Active,
+            ErrorCode::ContractNotActive
+        );
+        contract.check_settlement_kind(SettlementKind::Cash)?;
+
+        let current_time = current_time(ctx.remaining_accounts)?;
+        let prices = settlement_prices(
+            contract.collateral_mint,
+            contract.price_expo,
+            &ctx.accounts.underlying_price_feed,
+            &ctx.accounts.sol_price_feed,
+            current_time,
+        )?;
+        let payoff_amount = exercise_payoff(contract, contract.unexercised_units(), &prices)?;
+        let required_margin = maintenance_margin(contract, payoff_amount)?;
+        let locked_margin = locked_collateral(
+            contract.collateral_mint,
+            &ctx.accounts.collateral,
+            ctx.accounts.collateral_vault.as_deref(),
+        )?;
+
+        // A running margin call keeps its original deadline until it is covered
+        contract.required_margin = required_margin;
+        if locked_margin >= required_margin {
+            contract.margin_call_deadline = None;
+        } else if contract.margin_call_deadline.is_none() {
+            contract.margin_call_deadline = Some(current_time + MARGIN_CALL_PERIOD_SECONDS);
+        }
+
+        emit!(MarginMarkedEvent {
+            contract: contract.key(),
+            contract_id: contract.contract_id,
+            buyer: contract.buyer,
+            seller: contract.seller,
+            underlying_price: prices.underlying,
+            sol_price: prices.sol,
+            required_margin,
+            locked_margin,
+            margin_call_deadline: contract.margin_call_deadline,
+        });
+        Ok(())
+    }
+
+    pub fn top_up_margin(ctx: Context<TopUpMargin>, amount: u64) -> Result<()> {
+        let contract = &mut ctx.accounts.contract;
+
+        require!(amount > 0, ErrorCode::InvalidDepositAmount);
+        require!(
+            contract.status == ContractStatus::Active,
+            ErrorCode::ContractNotActive
+        );
+
+        let locked_margin = locked_collateral(
+            contract.collateral_mint,
+            &ctx.accounts.collateral,
+            ctx.accounts.collateral_vault.as_deref(),
+        )?;
+
+        match contract.collateral_mint {
+            None => {
+                require!(
+                    ctx.accounts.seller_escrow.lamports() >= amount,
+                    ErrorCode::InsufficientBalance
+                );
+                transfer_from_escrow(
+                    &ctx.accounts.seller_escrow,
+                    &contract.seller,
+                    ctx.bumps.seller_escrow,
+                    &ctx.accounts.collateral.to_account_info(),
+                    &ctx.accounts.system_program.to_account_info(),
+                    amount,
+                )?;
+            }
+            Some(_) => {
+                let seller_token_escrow =
+                    required_token_account(ctx.accounts.seller_token_escrow.as_deref())?;
+                require!(
+                    seller_token_escrow.amount >= amount,
+                    ErrorCode::InsufficientBalance
+                );
+                transfer_tokens_from_escrow(
+                    seller_token_escrow,
+                    &ctx.accounts.seller_escrow,
+                    &contract.seller,
+                    ctx.bumps.seller_escrow,
+                    required_token_account(ctx.accounts.collateral_vault.as_deref())?,
+                    required_token_account(ctx.accounts.token_program.as_ref())?,
+                    amount,
+                )?;
+            }
+        }
+
+        // Covering the last marked requirement answers the margin call
+        let locked_margin = locked_margin
+            .checked_add(amount)
+            .ok_or(ErrorCode::CalculationError)?;
+        if locked_margin >= contract.required_margin {
+            contract.margin_call_deadline = None;
+        }
+
+        emit!(MarginToppedUpEvent {
+            contract: contract.key(),
+            contract_id: contract.contract_id,
+            buyer: contract.buyer,
+            seller: contract.seller,
+            amount,
+            margin_call_deadline: contract.margin_call_deadline,
+        });
+        Ok(())
+    }
+
+    // Closes out a contract whose margin call has run out, paying the buyer the
+    // current payoff and the keeper a bonus out of the locked margin
+    pub fn liquidate(ctx: Context<Liquidate>) -> Result<()> {
+        let contract = &mut ctx.accounts.contract;
+
+        require!(
+            contract.status == ContractStatus::Active,
+            ErrorCode::ContractNotActive
+        );
+        contract.check_settlement_kind(SettlementKind::Cash)?;
+
+        let current_time = current_time(ctx.remaining_accounts)?;
+        let margin_call_deadline = contract
+            .margin_call_deadline
+            .ok_or(ErrorCode::NoMarginCall)?;
+        require!(
+            current_time >= margin_call_deadline,
+            ErrorCode::MarginCallNotDue
+        );
+        let exercise_deadline = contract
+            .expiration_date
+            .checked_add(EXERCISE_WINDOW_SECONDS)
+            .ok_or(ErrorCode::CalculationError)?;
+        require!(
+            current_time < exercise_deadline,
+            ErrorCode::ExerciseWindowClosed
+        );
+
+        let prices = settlement_prices(
+            contract.collateral_mint,
+            contract.price_expo,
+            &ctx.accounts.underlying_price_feed,
+            &ctx.accounts.sol_price_feed,
+            current_time,
+        )?;
+        let payoff_amount = exercise_payoff(contract, contract.unexercised_units(), &prices)?;
+        let required_margin = maintenance_margin(contract, payoff_amount)?;
+
+        let payout = collateral_payout!(ctx, contract);
+        let locked_margin = payout.locked_margin()?;
+
+        // The price may have recovered since the margin call was made
+        require!(
+            locked_margin < required_margin,
+            ErrorCode::ContractNotUndercollateralized
+        );
+
+        // Pay the keeper first, then the buyer as far as the rest of the margin
+        // and the seller escrow cover the payoff. Whatever the seller cannot
+        // cover is a default, handled as in `settle`
+        let liquidation_bonus = locked_margin
+            .checked_mul(LIQUIDATION_BONUS_BPS)
+            .ok_or(ErrorCode::CalculationError)?
+            / 10000;
+        match contract.collateral_mint {
+            None => debit_collateral(
+                &ctx.accounts.collateral.to_account_info(),
+                &ctx.accounts.liquidator,
+                liquidation_bonus,
+            )?,
+            Some(_) => transfer_tokens_from_collateral(
+                required_token_account(ctx.accounts.collateral_vault.as_deref())?,
+                &ctx.accounts.collateral,
+                required_token_account(ctx.accounts.liquidator_token_account.as_deref())?,
+                required_token_account(ctx.accounts.token_program.as_ref())?,
+                liquidation_bonus,
+            )?,
+        }
+        let remaining_margin = locked_margin - liquidation_bonus;
+        let covered = payout.seller_collateral(remaining_margin)?.min(payoff_amount);
+        let shortfall = payoff_amount - covered;
+        let amounts = payout.pay_buyer(remaining_margin, covered)?;
+
+        let paid_from_insurance = match ctx.accounts.insurance_fund.as_ref() {
+            _ if shortfall == 0 => 0,
+            Some(insurance_fund) => draw_insurance(
+                contract.collateral_mint,
+                insurance_fund,
+                ctx.accounts.insurance_vault.as_deref(),
+                &ctx.accounts.buyer_escrow,
+                ctx.accounts.buyer_token_escrow.as_deref(),
+                ctx.accounts.token_program.as_ref(),
+                shortfall,
+            )?,
+            // Only the buyer, who bears the loss, can liquidate into a default
+            // without drawing on the insurance fund
+            None => {
+                require!(
+                    ctx.accounts.liquidator.key() == contract.buyer,
+                    ErrorCode::InsuranceFundRequired
+                );
+                0
+            }
+        };
+
+        let status = if shortfall > 0 {
+            ContractStatus::Defaulted
+        } else {
+            ContractStatus::Liquidated
+        };
+        contract.settlement_fee += amounts.fee;
+        contract.margin_call_deadline = None;
+        contract.default_shortfall = shortfall;
+        contract.paid_from_insurance = paid_from_insurance;
+        contract.status = status;
+
+        // Update status in user accounts
+        let contract_key = contract.key();
+        ctx.accounts.buyer_page.set_contract_status(contract_key, status);
+        ctx.accounts.seller_page.set_contract_status(contract_key, status);
+
+        emit!(ContractLiquidatedEvent {
+            contract: contract_key,
+            contract_id: contract.contract_id,
+            buyer: contract.buyer,
+            seller: contract.seller,
+            liquidator: ctx.accounts.liquidator.key(),
+            underlying_price: prices.underlying,
+            sol_price: prices.sol,
+            liquidation_bonus,
+            paid_from_margin: amounts.paid_from_margin,
+            paid_from_seller_escrow: amounts.paid_from_seller_escrow,
+            margin_released: amounts.margin_released,
+            settlement_fee: amounts.fee,
+        });
+        if shortfall > 0 {
+            emit!(ContractDefaultedEvent {
+                contract: contract_key,
+                contract_id: contract.contract_id,
+                buyer: contract.buyer,
+                seller: contract.seller,
+                shortfall,
+                paid_from_insurance,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn close_contract(ctx: Context<CloseContract>) -> Result<()> {
+        let contract = &ctx.accounts.contract;
+
+        require!(
+            matches!(
+                contract.status,
+                ContractStatus::Settled
+                    | ContractStatus::Expired
+                    | ContractStatus::Liquidated
+                    | ContractStatus::Defaulted
+                    | ContractStatus::Terminated
             ),
-            &[
-                ctx.accounts.user_escrow.to_account_info(),
-                ctx.accounts.user.to_account_info(),
-                ctx.accounts.system_program.to_account_info(),
-            ],
-            escrow_signer,
+            ErrorCode::ContractNotFinished
+        );
+        let caller = ctx.accounts.caller.key();
+        require!(
+            caller == contract.buyer || caller == contract.seller,
+            ErrorCode::UnauthorizedClose
+        );
+
+        // Token contracts also close their emptied collateral vault, and
+        // physical calls their emptied underlying vault
+        if contract.collateral_mint.is_some() {
+            close_collateral_vault(
+                required_token_account(ctx.accounts.collateral_vault.as_deref())?,
+                &ctx.accounts.collateral,
+                &ctx.accounts.rent_payer,
+                required_token_account(ctx.accounts.token_program.as_ref())?,
+            )?;
+        }
+        if contract.locks_underlying() {
+            close_collateral_vault(
+                required_token_account(ctx.accounts.underlying_vault.as_deref())?,
+                &ctx.accounts.collateral,
+                &ctx.accounts.rent_payer,
+                required_token_account(ctx.accounts.token_program.as_ref())?,
+            )?;
+        }
+
+        // Contract and collateral rent go back to the rent payer through the close constraints
+        let contract_key = contract.key();
+        ctx.accounts.buyer_page.remove_contract(contract_key);
+        ctx.accounts.seller_page.remove_contract(contract_key);
+
+        emit!(ContractClosedEvent {
+            contract: contract_key,
+            contract_id: contract.contract_id,
+            buyer: contract.buyer,
+            seller: contract.seller,
+            rent_payer: contract.rent_payer,
+        });
+        Ok(())
+    }
+
+    // Assigns the long side to another registered user, optionally for a sale
+    // price paid from the new holder's escrow to the current holder's escrow
+    pub fn transfer_position(ctx: Context<TransferPosition>, sale_price: u64) -> Result<()> {
+        let contract = &mut ctx.accounts.contract;
+
+        require!(
+            contract.status == ContractStatus::Active,
+            ErrorCode::ContractNotActive
+        );
+        let new_holder = ctx.accounts.new_holder.key();
+        require!(
+            new_holder != contract.buyer && new_holder != contract.seller,
+            ErrorCode::InvalidTransferRecipient
+        );
+
+        if sale_price > 0 {
+            match contract.collateral_mint {
+                None => {
+                    require!(
+                        ctx.accounts.new_holder_escrow.lamports() >= sale_price,
+                        ErrorCode::InsufficientBalance
+                    );
+                    transfer_from_escrow(
+                        &ctx.accounts.new_holder_escrow,
+                        &new_holder,
+                        ctx.bumps.new_holder_escrow,
+                        &ctx.accounts.holder_escrow,
+                        &ctx.accounts.system_program.to_account_info(),
+                        sale_price,
+                    )?;
+                }
+                Some(_) => {
+                    let new_holder_token_escrow =
+                        required_token_account(ctx.accounts.new_holder_token_escrow.as_deref())?;
+                    require!(
+                        new_holder_token_escrow.amount >= sale_price,
+                        ErrorCode::InsufficientBalance
+                    );
+                    transfer_tokens_from_escrow(
+                        new_holder_token_escrow,
+                        &ctx.accounts.new_holder_escrow,
+                        &new_holder,
+                        ctx.bumps.new_holder_escrow,
+                        required_token_account(ctx.accounts.holder_token_escrow.as_deref())?,
+                        required_token_account(ctx.accounts.token_program.as_ref())?,
+                        sale_price,
+                    )?;
+                }
+            }
+        }
+
+        // Move the buyer entry from the old holder's index to the new holder's
+        let contract_key = contract.key();
+        ctx.accounts.holder_page.remove_contract(contract_key);
+        contract.buyer_page = ctx.accounts.new_holder_account.push_contract(
+            &mut ctx.accounts.new_holder_page,
+            ctx.bumps.new_holder_page,
+            UserContract {
+                contract_address: contract_key,
+                role: UserRole::Buyer,
+                status: contract.status,
+            },
+        );
+
+        emit!(PositionTransferredEvent {
+            contract: contract_key,
+            contract_id: contract.contract_id,
+            seller: contract.seller,
+            from: contract.buyer,
+            to: new_holder,
+            sale_price,
+        });
+
+        contract.buyer = new_holder;
+        contract.buyer_escrow = ctx.accounts.new_holder_escrow.key();
+
+        Ok(())
+    }
+
+    // Unwinds an active contract before expiry when both sides sign. The agreed
+    // payment moves from `paid_by` to the other side, then the seller's margin
+    // and any locked underlying go back to the seller. No fee is charged.
+    pub fn terminate_contract(
+        ctx: Context<TerminateContract>,
+        payment: u64,
+        paid_by: UserRole,
+    ) -> Result<()> {
+        let contract = &mut ctx.accounts.contract;
+
+        require!(
+            contract.status == ContractStatus::Active,
+            ErrorCode::ContractNotActive
+        );
+
+        let margin_released = match paid_by {
+            // A seller pays out of the margin first, then their escrow
+            UserRole::Seller => {
+                let payout = collateral_payout!(ctx, contract, without_fee);
+                let locked_margin = payout.locked_margin()?;
+                payout.pay_buyer(locked_margin, payment)?.margin_released
+            }
+            UserRole::Buyer => {
+                match contract.collateral_mint {
+                    None => {
+                        require!(
+                            ctx.accounts.buyer_escrow.lamports() >= payment,
+                            ErrorCode::InsufficientBalance
+                        );
+                        transfer_from_escrow(
+                            &ctx.accounts.buyer_escrow,
+                            &contract.buyer,
+                            ctx.bumps.buyer_escrow,
+                            &ctx.accounts.seller_escrow,
+                            &ctx.accounts.system_program.to_account_info(),
+                            payment,
+                        )?;
+                    }
+                    Some(_) => {
+                        let buyer_token_escrow =
+                            required_token_account(ctx.accounts.buyer_token_escrow.as_deref())?;
+                        require!(
+                            buyer_token_escrow.amount >= payment,
+                            ErrorCode::InsufficientBalance
+                        );
+                        transfer_tokens_from_escrow(
+                            buyer_token_escrow,
+                            &ctx.accounts.buyer_escrow,
+                            &contract.buyer,
+                            ctx.bumps.buyer_escrow,
+                            required_token_account(ctx.accounts.seller_token_escrow.as_deref())?,
+                            required_token_account(ctx.accounts.token_program.as_ref())?,
+                            payment,
+                        )?;
+                    }
+                }
+                release_margin(
+                    contract.collateral_mint,
+                    &ctx.accounts.collateral,
+                    &ctx.accounts.seller_escrow,
+                    ctx.accounts.collateral_vault.as_deref(),
+                    ctx.accounts.seller_token_escrow.as_deref(),
+                    ctx.accounts.token_program.as_ref(),
+                )?
+            }
+        };
+
+        // Physical calls also return the locked underlying to the seller
+        let underlying_released = if contract.locks_underlying() {
+            let underlying_vault =
+                required_token_account(ctx.accounts.underlying_vault.as_deref())?;
+            transfer_tokens_from_collateral(
+                underlying_vault,
+                &ctx.accounts.collateral,
+                required_token_account(ctx.accounts.seller_underlying_escrow.as_deref())?,
+                required_token_account(ctx.accounts.token_program.as_ref())?,
+                underlying_vault.amount,
+            )?;
+            underlying_vault.amount
+        } else {
+            0
+        };
+
+        contract.margin_call_deadline = None;
+        contract.status = ContractStatus::Terminated;
+
+        // Update status in user accounts
+        let contract_key = contract.key();
+        ctx.accounts.buyer_page.set_contract_status(contract_key, ContractStatus::Terminated);
+        ctx.accounts.seller_page.set_contract_status(contract_key, ContractStatus::Terminated);
+
+        emit!(ContractTerminatedEvent {
+            contract: contract_key,
+            contract_id: contract.contract_id,
+            buyer: contract.buyer,
+            seller: contract.seller,
+            paid_by,
+            payment,
+            margin_released,
+            underlying_released,
+        });
+        Ok(())
+    }
+
+    // Opens every leg of a strategy at once. Each side locks its maximum loss
+    // across all legs instead of a margin per leg.
+    pub fn create_strategy(
+        ctx: Context<CreateStrategy>,
+        underlying_asset: String,
+        expiration_date: i64,
+        legs: Vec<StrategyLeg>,
+        premium: u64,
+        margin_requirement_bps: u16,
+    ) -> Result<()> {
+        require!(
+            (MIN_STRATEGY_LEGS..=MAX_STRATEGY_LEGS).contains(&legs.len())
+                && legs
+                    .iter()
+                    .all(|leg| leg.num_units > 0 && leg.strike_price > 0),
+            ErrorCode::InvalidStrategyLegs
+        );
+        let current_time = current_time(ctx.remaining_accounts)?;
+        let market_config = &ctx.accounts.market_config;
+        market_config.check_terms(margin_requirement_bps, expiration_date, current_time)?;
+        market_config.check_price_feeds(
+            &underlying_asset,
+            &ctx.accounts.underlying_price_feed,
+            &ctx.accounts.sol_price_feed,
+        )?;
+
+        let token = TokenCollateral::from_accounts(
+            ctx.accounts.collateral_mint.as_deref(),
+            ctx.accounts.buyer_token_escrow.as_deref(),
+            ctx.accounts.seller_token_escrow.as_deref(),
+            ctx.accounts.collateral_vault.as_deref(),
+            ctx.accounts.treasury_vault.as_deref(),
+            ctx.accounts.token_program.as_ref(),
+        )?;
+
+        // Margins are held in the mint's base units for token strategies and in
+        // lamports at the current SOL price otherwise
+        let price_expo = ctx.accounts.asset.price_expo;
+        let prices = settlement_prices(
+            token.as_ref().map(|token| token.mint.key()),
+            price_expo,
+            &ctx.accounts.underlying_price_feed,
+            &ctx.accounts.sol_price_feed,
+            current_time,
+        )?;
+        let losses = strategy_max_losses(&legs, margin_requirement_bps)?;
+        let collateral_decimals = token.as_ref().map_or(0, |token| token.mint.decimals);
+        let buyer_margin =
+            usd_to_collateral(losses.buyer, &prices, collateral_decimals, Rounding::Up)?;
+        let seller_margin =
+            usd_to_collateral(losses.seller, &prices, collateral_decimals, Rounding::Up)?;
+        let buyer_debit = premium
+            .checked_add(buyer_margin)
+            .ok_or(ErrorCode::CalculationError)?;
+
+        // The protocol fee is taken out of the premium before it reaches the seller
+        let premium_fee = fee_amount(premium, market_config.premium_fee_bps)?;
+        let seller_premium = premium - premium_fee;
+
+        let buyer = ctx.accounts.buyer.key();
+        let seller = ctx.accounts.seller.key();
+        let buyer_escrow = &ctx.accounts.buyer_escrow;
+        let seller_escrow = &ctx.accounts.seller_escrow;
+        let collateral = &ctx.accounts.collateral;
+        match &token {
+            None => {
+                require!(
+                    buyer_escrow.lamports() >= buyer_debit,
+                    ErrorCode::InsufficientBalance
+                );
+                require!(
+                    seller_escrow.lamports() >= seller_margin,
+                    ErrorCode::InsufficientBalance
+                );
+
+                let system_program = ctx.accounts.system_program.to_account_info();
+                for (to, amount) in [
+                    (ctx.accounts.seller.to_account_info(), seller_premium),
+                    (ctx.accounts.treasury.to_account_info(), premium_fee),
+                    (collateral.to_account_info(), buyer_margin),
+                ] {
+                    transfer_from_escrow(
+                        buyer_escrow,
+                        &buyer,
+                        ctx.bumps.buyer_escrow,
+                        &to,
+                        &system_program,
+                        amount,
+                    )?;
+                }
+                transfer_from_escrow(
+                    seller_escrow,
+                    &seller,
+                    ctx.bumps.seller_escrow,
+                    &collateral.to_account_info(),
+                    &system_program,
+                    seller_margin,
+                )?;
+            }
+            Some(token) => {
+                require!(
+                    token.buyer_token_escrow.amount >= buyer_debit,
+                    ErrorCode::InsufficientBalance
+                );
+                require!(
+                    token.seller_token_escrow.amount >= seller_margin,
+                    ErrorCode::InsufficientBalance
+                );
+
+                let treasury_vault = match premium_fee {
+                    0 => None,
+                    _ => Some(required_token_account(token.treasury_vault)?),
+                };
+                for (to, amount) in [
+                    (Some(token.seller_token_escrow), seller_premium),
+                    (treasury_vault, premium_fee),
+                    (Some(token.collateral_vault), buyer_margin),
+                ] {
+                    if let Some(to) = to {
+                        transfer_tokens_from_escrow(
+                            token.buyer_token_escrow,
+                            buyer_escrow,
+                            &buyer,
+                            ctx.bumps.buyer_escrow,
+                            to,
+                            token.token_program,
+                            amount,
+                        )?;
+                    }
+                }
+                transfer_tokens_from_escrow(
+                    token.seller_token_escrow,
+                    seller_escrow,
+                    &seller,
+                    ctx.bumps.seller_escrow,
+                    token.collateral_vault,
+                    token.token_program,
+                    seller_margin,
+                )?;
+            }
+        }
+
+        let buyer_account = &mut ctx.accounts.buyer_account;
+        let strategy = &mut ctx.accounts.strategy;
+        strategy.bump = ctx.bumps.strategy;
+        strategy.strategy_id = buyer_account.contract_count;
+        strategy.creation_date = current_time;
+        strategy.buyer = buyer;
+        strategy.seller = seller;
+        strategy.underlying_asset = underlying_asset;
+        strategy.expiration_date = expiration_date;
+        strategy.legs = legs;
+        strategy.premium = premium;
+        strategy.margin_requirement_bps = margin_requirement_bps;
+        strategy.buyer_margin = buyer_margin;
+        strategy.seller_margin = seller_margin;
+        strategy.underlying_price_feed = ctx.accounts.underlying_price_feed.key();
+        strategy.sol_price_feed = ctx.accounts.sol_price_feed.key();
+        strategy.collateral_mint = token.as_ref().map(|token| token.mint.key());
+        strategy.collateral_decimals = collateral_decimals;
+        strategy.status = ContractStatus::Active;
+        strategy.rent_payer = buyer;
+        strategy.settlement_fee_bps = market_config.settlement_fee_bps;
+        strategy.premium_fee = premium_fee;
+        strategy.settlement_fee = 0;
+        strategy.price_expo = price_expo;
+        buyer_account.contract_count += 1;
+
+        let collateral = &mut ctx.accounts.collateral;
+        collateral.bump = ctx.bumps.collateral;
+        collateral.contract = strategy.key();
+
+        emit!(StrategyCreatedEvent {
+            strategy: strategy.key(),
+            strategy_id: strategy.strategy_id,
+            buyer,
+            seller,
+            underlying_asset: strategy.underlying_asset.clone(),
+            expiration_date,
+            legs: strategy.legs.clone(),
+            premium,
+            buyer_margin,
+            seller_margin,
+            premium_fee,
+            collateral_mint: strategy.collateral_mint,
+            price_expo,
+        });
+        Ok(())
+    }
+
+    // Settles every leg at the current oracle price during the exercise window
+    // after expiry
+    pub fn exercise_strategy(ctx: Context<SettleStrategy>) -> Result<()> {
+        let strategy = &ctx.accounts.strategy;
+        require!(
+            strategy.status == ContractStatus::Active,
+            ErrorCode::ContractNotActive
+        );
+        strategy.check_counterparty(ctx.accounts.caller.key())?;
+
+        let current_time = current_time(ctx.remaining_accounts)?;
+        require!(
+            current_time >= strategy.expiration_date,
+            ErrorCode::ContractNotExpired
+        );
+        let exercise_deadline = strategy
+            .expiration_date
+            .checked_add(EXERCISE_WINDOW_SECONDS)
+            .ok_or(ErrorCode::CalculationError)?;
+        require!(
+            current_time < exercise_deadline,
+            ErrorCode::ExerciseWindowClosed
+        );
+
+        let prices = settlement_prices(
+            strategy.collateral_mint,
+            strategy.price_expo,
+            &ctx.accounts.underlying_price_feed,
+            &ctx.accounts.sol_price_feed,
+            current_time,
         )?;
+        let payoff_usd = strategy_payoff(&strategy.legs, prices.underlying)?;
+        settle_strategy(ctx, prices, payoff_usd)
+    }
+
+    // Closes out a strategy nobody settled within the exercise window. One that
+    // is still in the money is settled at the current oracle prices, since the
+    // side it is owed to keeps its claim; otherwise both margins are returned.
+    pub fn expire_strategy(ctx: Context<SettleStrategy>) -> Result<()> {
+        let strategy = &ctx.accounts.strategy;
+        require!(
+            strategy.status == ContractStatus::Active,
+            ErrorCode::ContractNotActive
+        );
+
+        let current_time = current_time(ctx.remaining_accounts)?;
+        let expiry_deadline = strategy
+            .expiration_date
+            .checked_add(EXERCISE_WINDOW_SECONDS)
+            .ok_or(ErrorCode::CalculationError)?;
+        require!(
+            current_time >= expiry_deadline,
+            ErrorCode::GracePeriodNotElapsed
+        );
+
+        let prices = settlement_prices(
+            strategy.collateral_mint,
+            strategy.price_expo,
+            &ctx.accounts.underlying_price_feed,
+            &ctx.accounts.sol_price_feed,
+            current_time,
+        )?;
+        let payoff_usd = strategy_payoff(&strategy.legs, prices.underlying)?;
+        if payoff_usd != 0 {
+            return settle_strategy(ctx, prices, payoff_usd);
+        }
+
+        // Return the buyer's margin, then everything left to the seller
+        let strategy = &mut ctx.accounts.strategy;
+        let collateral = &ctx.accounts.collateral;
+        let buyer_margin_released = strategy.buyer_margin;
+        match strategy.collateral_mint {
+            None => debit_collateral(
+                &collateral.to_account_info(),
+                &ctx.accounts.buyer_escrow,
+                buyer_margin_released,
+            )?,
+            Some(_) => transfer_tokens_from_collateral(
+                required_token_account(ctx.accounts.collateral_vault.as_deref())?,
+                collateral,
+                required_token_account(ctx.accounts.buyer_token_escrow.as_deref())?,
+                required_token_account(ctx.accounts.token_program.as_ref())?,
+                buyer_margin_released,
+            )?,
+        }
+        if let Some(collateral_vault) = ctx.accounts.collateral_vault.as_mut() {
+            collateral_vault.reload()?;
+        }
+        let seller_margin_released = release_margin(
+            strategy.collateral_mint,
+            collateral,
+            &ctx.accounts.seller_escrow,
+            ctx.accounts.collateral_vault.as_deref(),
+            ctx.accounts.seller_token_escrow.as_deref(),
+            ctx.accounts.token_program.as_ref(),
+        )?;
+
+        strategy.status = ContractStatus::Expired;
+
+        emit!(StrategyExpiredEvent {
+            strategy: strategy.key(),
+            strategy_id: strategy.strategy_id,
+            buyer: strategy.buyer,
+            seller: strategy.seller,
+            buyer_margin_released,
+            seller_margin_released,
+        });
+        Ok(())
+    }
+
+    pub fn close_strategy(ctx: Context<CloseStrategy>) -> Result<()> {
+        let strategy = &ctx.accounts.strategy;
+        require!(
+            matches!(
+                strategy.status,
+                ContractStatus::Settled | ContractStatus::Expired | ContractStatus::Defaulted
+            ),
+            ErrorCode::ContractNotFinished
+        );
+        let caller = ctx.accounts.caller.key();
+        require!(
+            caller == strategy.buyer || caller == strategy.seller,
+            ErrorCode::UnauthorizedClose
+        );
+
+        if strategy.collateral_mint.is_some() {
+            close_collateral_vault(
+                required_token_account(ctx.accounts.collateral_vault.as_deref())?,
+                &ctx.accounts.collateral,
+                &ctx.accounts.rent_payer,
+                required_token_account(ctx.accounts.token_program.as_ref())?,
+            )?;
+        }
+
+        emit!(StrategyClosedEvent {
+            strategy: strategy.key(),
+            strategy_id: strategy.strategy_id,
+            buyer: strategy.buyer,
+            seller: strategy.seller,
+            rent_payer: strategy.rent_payer,
+        });
+        Ok(())
+    }
+
+    pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
+        let escrow_balance = ctx.accounts.user_escrow.lamports();
+        require!(escrow_balance >= amount, ErrorCode::InsufficientBalance);
+
+        // Transfer from user escrow to user wallet using PDA signing
+        let user_key = ctx.accounts.user.key();
+        let escrow_seeds = &[
+            b"escrow".as_ref(),
+            user_key.as_ref(),
+            &[ctx.bumps.user_escrow],
+        ];
+        let escrow_signer = &[&escrow_seeds[..]];
+        
+        anchor_lang::solana_program::program::invoke_signed(
+            &anchor_lang::solana_program::system_instruction::transfer(
+                ctx.accounts.user_escrow.key,
+                ctx.accounts.user.key,
+                amount,
+            ),
+            &[
+                ctx.accounts.user_escrow.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+            escrow_signer,
+        )?;
+
+        emit!(WithdrawEvent {
+            user: user_key,
+            escrow: ctx.accounts.user_escrow.key(),
+            mint: None,
+            amount,
+        });
+        Ok(())
+    }
+
+    pub fn withdraw_tokens(ctx: Context<WithdrawTokens>, amount: u64) -> Result<()> {
+        require!(
+            ctx.accounts.token_escrow.amount >= amount,
+            ErrorCode::InsufficientBalance
+        );
+
+        transfer_tokens_from_escrow(
+            &ctx.accounts.token_escrow,
+            &ctx.accounts.user_escrow,
+            &ctx.accounts.user.key(),
+            ctx.bumps.user_escrow,
+            &ctx.accounts.user_token_account,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+
+        emit!(WithdrawEvent {
+            user: ctx.accounts.user.key(),
+            escrow: ctx.accounts.token_escrow.key(),
+            mint: Some(ctx.accounts.token_escrow.mint),
+            amount,
+        });
+        Ok(())
+    }
+
+    // Moves the program's view of time by `offset_seconds` for instructions
+    // given the test clock; only compiled into test builds
+    #[cfg(feature = "test-clock")]
+    pub fn warp_clock(ctx: Context<WarpClock>, offset_seconds: i64) -> Result<()> {
+        let test_clock = &mut ctx.accounts.test_clock;
+        test_clock.bump = ctx.bumps.test_clock;
+        test_clock.offset_seconds = offset_seconds;
+        Ok(())
+    }
+}
+
+fn emit_market_config(market_config: &MarketConfig) {
+    emit!(MarketConfigUpdatedEvent {
+        admin: market_config.admin,
+        oracle_authority: market_config.oracle_authority,
+        paused: market_config.paused,
+        min_margin_bps: market_config.min_margin_bps,
+        max_margin_bps: market_config.max_margin_bps,
+        min_expiry_seconds: market_config.min_expiry_seconds,
+        max_expiry_seconds: market_config.max_expiry_seconds,
+        premium_fee_bps: market_config.premium_fee_bps,
+        settlement_fee_bps: market_config.settlement_fee_bps,
+        crank_fee_bps: market_config.crank_fee_bps,
+    });
+}
+
+// Terms agreed between buyer and seller for a new contract
+struct ContractTerms {
+    underlying_asset: String,
+    num_units: u64,
+    strike_price: u64,
+    expiration_date: i64,
+    option_type: OptionType,
+    exercise_style: ExerciseStyle,
+    premium: u64,
+    margin_requirement_bps: u16,
+    underlying_price_feed: Pubkey,
+    sol_price_feed: Pubkey,
+    collateral_mint: Option<Pubkey>,
+    // Set for physically settled contracts
+    underlying_mint: Option<Pubkey>,
+    // Precision of the strike, copied from the asset registry
+    price_expo: i32,
+}
+
+// Token accounts used by a contract collateralized with an SPL mint
+struct TokenCollateral<'a, 'info> {
+    mint: &'a Account<'info, Mint>,
+    buyer_token_escrow: &'a Account<'info, TokenAccount>,
+    seller_token_escrow: &'a Account<'info, TokenAccount>,
+    collateral_vault: &'a Account<'info, TokenAccount>,
+    // Only needed when a premium fee is charged
+    treasury_vault: Option<&'a Account<'info, TokenAccount>>,
+    token_program: &'a Program<'info, Token>,
+}
+
+impl<'a, 'info> TokenCollateral<'a, 'info> {
+    // Lamport contracts pass no mint, token contracts must pass every token account
+    fn from_accounts(
+        mint: Option<&'a Account<'info, Mint>>,
+        buyer_token_escrow: Option<&'a Account<'info, TokenAccount>>,
+        seller_token_escrow: Option<&'a Account<'info, TokenAccount>>,
+        collateral_vault: Option<&'a Account<'info, TokenAccount>>,
+        treasury_vault: Option<&'a Account<'info, TokenAccount>>,
+        token_program: Option<&'a Program<'info, Token>>,
+    ) -> Result<Option<Self>> {
+        let Some(mint) = mint else {
+            return Ok(None);
+        };
+
+        Ok(Some(Self {
+            mint,
+            buyer_token_escrow: required_token_account(buyer_token_escrow)?,
+            seller_token_escrow: required_token_account(seller_token_escrow)?,
+            collateral_vault: required_token_account(collateral_vault)?,
+            treasury_vault,
+            token_program: required_token_account(token_program)?,
+        }))
+    }
+}
+
+// Underlying token accounts of a physically settled contract
+struct UnderlyingDelivery<'a, 'info> {
+    mint: &'a Account<'info, Mint>,
+    // Only needed for calls, where the seller locks the underlying up front
+    seller_underlying_escrow: Option<&'a Account<'info, TokenAccount>>,
+    underlying_vault: Option<&'a Account<'info, TokenAccount>>,
+}
+
+impl<'a, 'info> UnderlyingDelivery<'a, 'info> {
+    // Cash-settled contracts pass no underlying mint
+    fn from_accounts(
+        mint: Option<&'a Account<'info, Mint>>,
+        seller_underlying_escrow: Option<&'a Account<'info, TokenAccount>>,
+        underlying_vault: Option<&'a Account<'info, TokenAccount>>,
+    ) -> Option<Self> {
+        mint.map(|mint| Self {
+            mint,
+            seller_underlying_escrow,
+            underlying_vault,
+        })
+    }
+}
+
+// Accounts touched when a contract is opened, shared by every creation path
+struct OpenContract<'a, 'info> {
+    contract: &'a mut Account<'info, OptionContract>,
+    contract_bump: u8,
+    buyer_account: &'a mut Account<'info, UserAccount>,
+    seller_account: &'a mut Account<'info, UserAccount>,
+    buyer_page: &'a mut Account<'info, UserContractPage>,
+    buyer_page_bump: u8,
+    seller_page: &'a mut Account<'info, UserContractPage>,
+    seller_page_bump: u8,
+    buyer_escrow: &'a AccountInfo<'info>,
+    buyer_escrow_bump: u8,
+    seller_escrow: &'a AccountInfo<'info>,
+    seller_escrow_bump: u8,
+    collateral: &'a mut Account<'info, ContractCollateral>,
+    collateral_bump: u8,
+    seller: &'a AccountInfo<'info>,
+    rent_payer: Pubkey,
+    system_program: &'a AccountInfo<'info>,
+    market_config: &'a MarketConfig,
+    underlying_price_feed: &'a PriceFeed,
+    sol_price_feed: &'a PriceFeed,
+    treasury: &'a AccountInfo<'info>,
+    token: Option<TokenCollateral<'a, 'info>>,
+    underlying: Option<UnderlyingDelivery<'a, 'info>>,
+    current_time: i64,
+}
+
+impl<'a, 'info> OpenContract<'a, 'info> {
+    fn from_accounts(
+        accounts: &'a mut OpenContractAccounts<'info>,
+        bumps: &OpenContractAccountsBumps,
+        seller: &'a AccountInfo<'info>,
+        underlying_price_feed: &'a PriceFeed,
+        sol_price_feed: &'a PriceFeed,
+        current_time: i64,
+    ) -> Result<Self> {
+        Ok(Self {
+            contract: &mut accounts.contract,
+            contract_bump: bumps.contract,
+            buyer_account: &mut accounts.buyer_account,
+            seller_account: &mut accounts.seller_account,
+            buyer_page: &mut accounts.buyer_page,
+            buyer_page_bump: bumps.buyer_page,
+            seller_page: &mut accounts.seller_page,
+            seller_page_bump: bumps.seller_page,
+            buyer_escrow: &accounts.buyer_escrow,
+            buyer_escrow_bump: bumps.buyer_escrow,
+            seller_escrow: &accounts.seller_escrow,
+            seller_escrow_bump: bumps.seller_escrow,
+            collateral: &mut accounts.collateral,
+            collateral_bump: bumps.collateral,
+            seller,
+            rent_payer: accounts.payer.key(),
+            system_program: accounts.system_program.as_ref(),
+            market_config: &accounts.market_config,
+            underlying_price_feed,
+            sol_price_feed,
+            treasury: accounts.treasury.as_ref(),
+            token: TokenCollateral::from_accounts(
+                accounts.collateral_mint.as_deref(),
+                accounts.buyer_token_escrow.as_deref(),
+                accounts.seller_token_escrow.as_deref(),
+                accounts.collateral_vault.as_deref(),
+                accounts.treasury_vault.as_deref(),
+                accounts.token_program.as_ref(),
+            )?,
+            underlying: UnderlyingDelivery::from_accounts(
+                accounts.underlying_mint.as_deref(),
+                accounts.seller_underlying_escrow.as_deref(),
+                accounts.underlying_vault.as_deref(),
+            ),
+            current_time,
+        })
+    }
+}
+
+// Collects the premium, locks the seller's margin and records a new active contract
+fn open_contract(accounts: OpenContract, terms: ContractTerms) -> Result<()> {
+    require!(
+        terms.underlying_asset.len() <= MAX_TICKER_LENGTH,
+        ErrorCode::AssetTickerTooLong
+    );
+    let current_time = accounts.current_time;
+    accounts.market_config.check_terms(
+        terms.margin_requirement_bps,
+        terms.expiration_date,
+        current_time,
+    )?;
+    accounts.market_config.check_price_feeds(
+        &terms.underlying_asset,
+        accounts.underlying_price_feed,
+        accounts.sol_price_feed,
+    )?;
+
+    // Token contracts must be opened with the mint named in the terms
+    require!(
+        accounts.token.as_ref().map(|token| token.mint.key()) == terms.collateral_mint,
+        ErrorCode::CollateralMintMismatch
+    );
+
+    // Physically settled contracts must be opened with the underlying mint
+    // named in the terms, and pay the strike in a token collateral
+    require!(
+        accounts.underlying.as_ref().map(|underlying| underlying.mint.key())
+            == terms.underlying_mint,
+        ErrorCode::UnderlyingMintMismatch
+    );
+    require!(
+        terms.underlying_mint.is_none() || terms.collateral_mint.is_some(),
+        ErrorCode::PhysicalSettlementRequiresMint
+    );
+    let settlement_kind = match terms.underlying_mint {
+        None => SettlementKind::Cash,
+        Some(_) => SettlementKind::Physical,
+    };
+    let locks_underlying =
+        settlement_kind == SettlementKind::Physical && terms.option_type == OptionType::Call;
+
+    let buyer_account = accounts.buyer_account;
+    let seller_account = accounts.seller_account;
+
+    // Calculate margin amount, held in the mint's base units for token contracts
+    // and in lamports at the current SOL price otherwise, like payoffs. The
+    // underlying locked for a physical call covers it without margin.
+    let margin_notional = if locks_underlying {
+        0
+    } else {
+        let notional = (terms.num_units as u128)
+            .checked_mul(terms.strike_price as u128)
+            .ok_or(ErrorCode::CalculationError)?
+            .checked_mul(terms.margin_requirement_bps as u128)
+            .ok_or(ErrorCode::CalculationError)?;
+        div_rounded(notional, 10000, Rounding::Up)?
+    };
+    let prices = settlement_prices(
+        terms.collateral_mint,
+        terms.price_expo,
+        accounts.underlying_price_feed,
+        accounts.sol_price_feed,
+        current_time,
+    )?;
+    let collateral_decimals = accounts.token.as_ref().map_or(0, |token| token.mint.decimals);
+    let margin_amount =
+        usd_to_collateral(margin_notional, &prices, collateral_decimals, Rounding::Up)?;
+
+    // The protocol fee is taken out of the premium before it reaches the seller
+    let premium_fee = fee_amount(terms.premium, accounts.market_config.premium_fee_bps)?;
+    let seller_premium = terms.premium - premium_fee;
+
+    match &accounts.token {
+        None => {
+            // Check buyer escrow has sufficient premium
+            require!(
+                accounts.buyer_escrow.lamports() >= terms.premium,
+                ErrorCode::InsufficientBalance
+            );
+
+            // Check seller escrow has sufficient margin
+            require!(
+                accounts.seller_escrow.lamports() >= margin_amount,
+                ErrorCode::InsufficientBalance
+            );
+
+            // Transfer premium from buyer escrow to seller wallet and treasury
+            transfer_from_escrow(
+                accounts.buyer_escrow,
+                &buyer_account.owner,
+                accounts.buyer_escrow_bump,
+                accounts.seller,
+                accounts.system_program,
+                seller_premium,
+            )?;
+            transfer_from_escrow(
+                accounts.buyer_escrow,
+                &buyer_account.owner,
+                accounts.buyer_escrow_bump,
+                accounts.treasury,
+                accounts.system_program,
+                premium_fee,
+            )?;
+
+            // Lock the margin in the contract's collateral account
+            transfer_from_escrow(
+                accounts.seller_escrow,
+                &seller_account.owner,
+                accounts.seller_escrow_bump,
+                &accounts.collateral.to_account_info(),
+                accounts.system_program,
+                margin_amount,
+            )?;
+        }
+        Some(token) => {
+            require!(
+                token.buyer_token_escrow.amount >= terms.premium,
+                ErrorCode::InsufficientBalance
+            );
+            require!(
+                token.seller_token_escrow.amount >= margin_amount,
+                ErrorCode::InsufficientBalance
+            );
+
+            // Premium goes to the seller's token escrow, margin into the collateral vault
+            transfer_tokens_from_escrow(
+                token.buyer_token_escrow,
+                accounts.buyer_escrow,
+                &buyer_account.owner,
+                accounts.buyer_escrow_bump,
+                token.seller_token_escrow,
+                token.token_program,
+                seller_premium,
+            )?;
+            if premium_fee > 0 {
+                transfer_tokens_from_escrow(
+                    token.buyer_token_escrow,
+                    accounts.buyer_escrow,
+                    &buyer_account.owner,
+                    accounts.buyer_escrow_bump,
+                    required_token_account(token.treasury_vault)?,
+                    token.token_program,
+                    premium_fee,
+                )?;
+            }
+            transfer_tokens_from_escrow(
+                token.seller_token_escrow,
+                accounts.seller_escrow,
+                &seller_account.owner,
+                accounts.seller_escrow_bump,
+                token.collateral_vault,
+                token.token_program,
+                margin_amount,
+            )?;
+
+            // A physical call locks one whole underlying token per unit
+            if let Some(underlying) = accounts.underlying.as_ref().filter(|_| locks_underlying) {
+                let seller_underlying_escrow =
+                    required_token_account(underlying.seller_underlying_escrow)?;
+                let underlying_amount =
+                    underlying_amount(terms.num_units, underlying.mint.decimals)?;
+                require!(
+                    seller_underlying_escrow.amount >= underlying_amount,
+                    ErrorCode::InsufficientBalance
+                );
+                transfer_tokens_from_escrow(
+                    seller_underlying_escrow,
+                    accounts.seller_escrow,
+                    &seller_account.owner,
+                    accounts.seller_escrow_bump,
+                    required_token_account(underlying.underlying_vault)?,
+                    token.token_program,
+                    underlying_amount,
+                )?;
+            }
+        }
+    }
+
+    // Initialize contract
+    let contract = accounts.contract;
+    contract.bump = accounts.contract_bump;
+    contract.contract_id = buyer_account.contract_count;
+    contract.creation_date = current_time;
+    contract.underlying_asset = terms.underlying_asset;
+    contract.num_units = terms.num_units;
+    contract.strike_price = terms.strike_price;
+    contract.expiration_date = terms.expiration_date;
+    contract.option_type = terms.option_type;
+    contract.exercise_style = terms.exercise_style;
+    contract.premium = terms.premium;
+    contract.buyer = buyer_account.owner;
+    contract.original_buyer = buyer_account.owner;
+    contract.seller = seller_account.owner;
+    contract.buyer_escrow = accounts.buyer_escrow.key();
+    contract.seller_escrow = accounts.seller_escrow.key();
+    contract.seller_pending_balance = 0;
+    contract.buyer_pending_balance = 0;
+    contract.status = ContractStatus::Active;
+    contract.margin_requirement_bps = terms.margin_requirement_bps;
+    contract.margin_amount = margin_amount;
+    contract.underlying_price_feed = terms.underlying_price_feed;
+    contract.sol_price_feed = terms.sol_price_feed;
+    contract.rent_payer = accounts.rent_payer;
+    contract.collateral_mint = terms.collateral_mint;
+    contract.collateral_decimals = collateral_decimals;
+    contract.units_exercised = 0;
+    contract.required_margin = margin_amount;
+    contract.margin_call_deadline = None;
+    contract.settlement_fee_bps = accounts.market_config.settlement_fee_bps;
+    contract.crank_fee_bps = accounts.market_config.crank_fee_bps;
+    contract.premium_fee = premium_fee;
+    contract.settlement_fee = 0;
+    contract.settlement_kind = settlement_kind;
+    contract.underlying_mint = terms.underlying_mint;
+    contract.underlying_decimals = accounts
+        .underlying
+        .as_ref()
+        .map_or(0, |underlying| underlying.mint.decimals);
+    contract.price_expo = terms.price_expo;
+
+    let collateral = accounts.collateral;
+    collateral.bump = accounts.collateral_bump;
+    collateral.contract = contract.key();
+
+    // Index the contract on the tail page of both users
+    contract.buyer_page = buyer_account.push_contract(
+        accounts.buyer_page,
+        accounts.buyer_page_bump,
+        UserContract {
+            contract_address: contract.key(),
+            role: UserRole::Buyer,
+            status: ContractStatus::Active,
+        },
+    );
+    buyer_account.contract_count += 1;
+
+    contract.seller_page = seller_account.push_contract(
+        accounts.seller_page,
+        accounts.seller_page_bump,
+        UserContract {
+            contract_address: contract.key(),
+            role: UserRole::Seller,
+            status: ContractStatus::Active,
+        },
+    );
+
+    emit!(ContractCreatedEvent {
+        contract: contract.key(),
+        contract_id: contract.contract_id,
+        buyer: contract.buyer,
+        seller: contract.seller,
+        underlying_asset: contract.underlying_asset.clone(),
+        num_units: contract.num_units,
+        strike_price: contract.strike_price,
+        expiration_date: contract.expiration_date,
+        option_type: contract.option_type,
+        exercise_style: contract.exercise_style,
+        premium: contract.premium,
+        margin_amount: contract.margin_amount,
+        premium_fee,
+        collateral_mint: contract.collateral_mint,
+        settlement_kind,
+        underlying_mint: contract.underlying_mint,
+        price_expo: contract.price_expo,
+    });
+
+    Ok(())
+}
+
+// Moves lamports out of a user's escrow PDA, signing with the escrow seeds
+fn transfer_from_escrow<'info>(
+    escrow: &AccountInfo<'info>,
+    owner: &Pubkey,
+    bump: u8,
+    to: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let escrow_seeds = &[b"escrow".as_ref(), owner.as_ref(), &[bump]];
+    let escrow_signer = &[&escrow_seeds[..]];
+
+    anchor_lang::solana_program::program::invoke_signed(
+        &anchor_lang::solana_program::system_instruction::transfer(escrow.key, to.key, amount),
+        &[escrow.clone(), to.clone(), system_program.clone()],
+        escrow_signer,
+    )?;
+
+    Ok(())
+}
+
+// Lamports held by a collateral account above its rent-exempt reserve
+fn collateral_balance(collateral: &AccountInfo) -> Result<u64> {
+    let rent_reserve = Rent::get()?.minimum_balance(collateral.data_len());
+    Ok(collateral.lamports().saturating_sub(rent_reserve))
+}
+
+// Moves lamports out of a program-owned collateral account
+fn debit_collateral(collateral: &AccountInfo, to: &AccountInfo, amount: u64) -> Result<()> {
+    if amount > 0 {
+        collateral.sub_lamports(amount)?;
+        to.add_lamports(amount)?;
+    }
+    Ok(())
+}
+
+// Accounts that pay a contract's buyer out of its locked collateral
+struct CollateralPayout<'a, 'info> {
+    collateral_mint: Option<Pubkey>,
+    seller: Pubkey,
+    collateral: &'a Account<'info, ContractCollateral>,
+    buyer_escrow: &'a AccountInfo<'info>,
+    seller_escrow: &'a AccountInfo<'info>,
+    seller_escrow_bump: u8,
+    system_program: &'a Program<'info, System>,
+    collateral_vault: Option<&'a Account<'info, TokenAccount>>,
+    buyer_token_escrow: Option<&'a Account<'info, TokenAccount>>,
+    seller_token_escrow: Option<&'a Account<'info, TokenAccount>>,
+    token_program: Option<&'a Program<'info, Token>>,
+    treasury: &'a AccountInfo<'info>,
+    // Only needed when a settlement fee is charged
+    treasury_vault: Option<&'a Account<'info, TokenAccount>>,
+    fee_bps: u16,
+}
+
+// Margin still locked for a contract, in lamports or tokens
+fn locked_collateral(
+    collateral_mint: Option<Pubkey>,
+    collateral: &Account<ContractCollateral>,
+    collateral_vault: Option<&Account<TokenAccount>>,
+) -> Result<u64> {
+    match collateral_mint {
+        None => collateral_balance(&collateral.to_account_info()),
+        Some(_) => Ok(required_token_account(collateral_vault)?.amount),
+    }
+}
+
+impl CollateralPayout<'_, '_> {
+    fn locked_margin(&self) -> Result<u64> {
+        locked_collateral(self.collateral_mint, self.collateral, self.collateral_vault)
+    }
+
+    // Most the seller can pay out of `margin` and their escrow. A lamport
+    // escrow keeps its rent-exempt reserve so it can be drained safely.
+    fn seller_collateral(&self, margin: u64) -> Result<u64> {
+        let escrow = match self.collateral_mint {
+            None => {
+                let rent_reserve = Rent::get()?.minimum_balance(0);
+                self.seller_escrow.lamports().saturating_sub(rent_reserve)
+            }
+            Some(_) => required_token_account(self.seller_token_escrow)?.amount,
+        };
+        margin.checked_add(escrow).ok_or(error!(ErrorCode::CalculationError))
+    }
+
+    // Pays `amount` to the buyer out of `margin`, taking any excess from the
+    // seller escrow, and releases the rest of `margin` back to the seller. The
+    // settlement fee is taken out of the buyer's proceeds, from the margin first
+    fn pay_buyer(&self, margin: u64, amount: u64) -> Result<PayoutAmounts> {
+        let from_collateral = margin.min(amount);
+        let shortfall = amount - from_collateral;
+        let fee = fee_amount(amount, self.fee_bps)?;
+        let fee_from_collateral = fee.min(from_collateral);
+        let fee_from_escrow = fee - fee_from_collateral;
+        let amounts = PayoutAmounts {
+            paid_from_margin: from_collateral,
+            paid_from_seller_escrow: shortfall,
+            margin_released: margin - from_collateral,
+            fee,
+        };
+
+        match self.collateral_mint {
+            None => {
+                require!(
+                    self.seller_escrow.lamports() >= shortfall,
+                    ErrorCode::InsufficientSellerEscrow
+                );
+
+                let system_program = self.system_program.to_account_info();
+                transfer_from_escrow(
+                    self.seller_escrow,
+                    &self.seller,
+                    self.seller_escrow_bump,
+                    self.buyer_escrow,
+                    &system_program,
+                    shortfall - fee_from_escrow,
+                )?;
+                transfer_from_escrow(
+                    self.seller_escrow,
+                    &self.seller,
+                    self.seller_escrow_bump,
+                    self.treasury,
+                    &system_program,
+                    fee_from_escrow,
+                )?;
+
+                let collateral = self.collateral.to_account_info();
+                debit_collateral(&collateral, self.treasury, fee_from_collateral)?;
+                debit_collateral(
+                    &collateral,
+                    self.buyer_escrow,
+                    from_collateral - fee_from_collateral,
+                )?;
+                debit_collateral(&collateral, self.seller_escrow, margin - from_collateral)?;
+            }
+            Some(_) => {
+                let collateral_vault = required_token_account(self.collateral_vault)?;
+                let buyer_token_escrow = required_token_account(self.buyer_token_escrow)?;
+                let seller_token_escrow = required_token_account(self.seller_token_escrow)?;
+                let token_program = required_token_account(self.token_program)?;
+
+                require!(
+                    seller_token_escrow.amount >= shortfall,
+                    ErrorCode::InsufficientSellerEscrow
+                );
+
+                transfer_tokens_from_escrow(
+                    seller_token_escrow,
+                    self.seller_escrow,
+                    &self.seller,
+                    self.seller_escrow_bump,
+                    buyer_token_escrow,
+                    token_program,
+                    shortfall - fee_from_escrow,
+                )?;
+                transfer_tokens_from_collateral(
+                    collateral_vault,
+                    self.collateral,
+                    buyer_token_escrow,
+                    token_program,
+                    from_collateral - fee_from_collateral,
+                )?;
+                transfer_tokens_from_collateral(
+                    collateral_vault,
+                    self.collateral,
+                    seller_token_escrow,
+                    token_program,
+                    margin - from_collateral,
+                )?;
+
+                if fee > 0 {
+                    let treasury_vault = required_token_account(self.treasury_vault)?;
+                    transfer_tokens_from_escrow(
+                        seller_token_escrow,
+                        self.seller_escrow,
+                        &self.seller,
+                        self.seller_escrow_bump,
+                        treasury_vault,
+                        token_program,
+                        fee_from_escrow,
+                    )?;
+                    transfer_tokens_from_collateral(
+                        collateral_vault,
+                        self.collateral,
+                        treasury_vault,
+                        token_program,
+                        fee_from_collateral,
+                    )?;
+                }
+            }
+        }
+
+        Ok(amounts)
+    }
+}
+
+// One side of a strategy, which either pays or receives the net payoff
+struct StrategySide<'a, 'info> {
+    owner: Pubkey,
+    escrow: &'a AccountInfo<'info>,
+    escrow_bump: u8,
+    token_escrow: Option<&'a Account<'info, TokenAccount>>,
+    margin: u64,
+}
+
+// Pays the net `payoff_usd` of a strategy to whichever side is owed it. The
+// side that owes it pays from its margin, then its escrow, and whatever it
+// cannot cover is a default, drawn from the insurance fund when it is passed.
+// Both remaining margins are released.
+fn settle_strategy(
+    ctx: Context<SettleStrategy>,
+    prices: SettlementPrices,
+    payoff_usd: i128,
+) -> Result<()> {
+    let strategy = &mut ctx.accounts.strategy;
+    let payoff_amount = usd_to_collateral(
+        payoff_usd.unsigned_abs(),
+        &prices,
+        strategy.collateral_decimals,
+        Rounding::Down,
+    )?;
+
+    // CollateralPayout pays its buyer out of its seller's margin, so the
+    // roles swap when the strategy buyer owes the seller
+    let buyer_side = StrategySide {
+        owner: strategy.buyer,
+        escrow: &ctx.accounts.buyer_escrow,
+        escrow_bump: ctx.bumps.buyer_escrow,
+        token_escrow: ctx.accounts.buyer_token_escrow.as_deref(),
+        margin: strategy.buyer_margin,
+    };
+    let seller_side = StrategySide {
+        owner: strategy.seller,
+        escrow: &ctx.accounts.seller_escrow,
+        escrow_bump: ctx.bumps.seller_escrow,
+        token_escrow: ctx.accounts.seller_token_escrow.as_deref(),
+        margin: strategy.seller_margin,
+    };
+    let (payee, payer) = if payoff_usd < 0 {
+        (seller_side, buyer_side)
+    } else {
+        (buyer_side, seller_side)
+    };
+
+    let payout = CollateralPayout {
+        collateral_mint: strategy.collateral_mint,
+        seller: payer.owner,
+        collateral: &ctx.accounts.collateral,
+        buyer_escrow: payee.escrow,
+        seller_escrow: payer.escrow,
+        seller_escrow_bump: payer.escrow_bump,
+        system_program: &ctx.accounts.system_program,
+        collateral_vault: ctx.accounts.collateral_vault.as_deref(),
+        buyer_token_escrow: payee.token_escrow,
+        seller_token_escrow: payer.token_escrow,
+        token_program: ctx.accounts.token_program.as_ref(),
+        treasury: &ctx.accounts.treasury.to_account_info(),
+        treasury_vault: ctx.accounts.treasury_vault.as_deref(),
+        fee_bps: strategy.settlement_fee_bps,
+    };
+    let covered = payout
+        .seller_collateral(payer.margin)?
+        .min(payoff_amount);
+    let shortfall = payoff_amount - covered;
+    let amounts = payout.pay_buyer(payer.margin, covered)?;
+
+    let paid_from_insurance = match ctx.accounts.insurance_fund.as_ref() {
+        _ if shortfall == 0 => 0,
+        Some(insurance_fund) => draw_insurance(
+            strategy.collateral_mint,
+            insurance_fund,
+            ctx.accounts.insurance_vault.as_deref(),
+            payee.escrow,
+            payee.token_escrow,
+            ctx.accounts.token_program.as_ref(),
+            shortfall,
+        )?,
+        // Only the side owed the payoff, which bears the loss, can settle a
+        // default without drawing on the insurance fund
+        None => {
+            require!(
+                ctx.accounts.caller.key() == payee.owner,
+                ErrorCode::InsuranceFundRequired
+            );
+            0
+        }
+    };
+
+    // Only the payee's margin is left in the collateral; the vault balance
+    // was read before the payout
+    if let Some(collateral_vault) = ctx.accounts.collateral_vault.as_mut() {
+        collateral_vault.reload()?;
+    }
+    let payee_margin_released = release_margin(
+        strategy.collateral_mint,
+        &ctx.accounts.collateral,
+        payee.escrow,
+        ctx.accounts.collateral_vault.as_deref(),
+        payee.token_escrow,
+        ctx.accounts.token_program.as_ref(),
+    )?;
+
+    let status = if shortfall > 0 {
+        ContractStatus::Defaulted
+    } else {
+        ContractStatus::Settled
+    };
+    strategy.settlement_fee = amounts.fee;
+    strategy.default_shortfall = shortfall;
+    strategy.paid_from_insurance = paid_from_insurance;
+    strategy.status = status;
+
+    emit!(StrategySettledEvent {
+        strategy: strategy.key(),
+        strategy_id: strategy.strategy_id,
+        buyer: strategy.buyer,
+        seller: strategy.seller,
+        underlying_price: prices.underlying,
+        sol_price: prices.sol,
+        payee: payee.owner,
+        payoff_amount,
+        paid_from_margin: amounts.paid_from_margin,
+        paid_from_escrow: amounts.paid_from_seller_escrow,
+        margin_released: amounts.margin_released + payee_margin_released,
+        settlement_fee: amounts.fee,
+        status,
+    });
+    if shortfall > 0 {
+        emit!(StrategyDefaultedEvent {
+            strategy: strategy.key(),
+            strategy_id: strategy.strategy_id,
+            buyer: strategy.buyer,
+            seller: strategy.seller,
+            payee: payee.owner,
+            shortfall,
+            paid_from_insurance,
+        });
+    }
+    Ok(())
+}
+
+// Split of a payout, reported in settlement events
+struct PayoutAmounts {
+    paid_from_margin: u64,
+    paid_from_seller_escrow: u64,
+    margin_released: u64,
+    // Part of the payout kept by the treasury instead of reaching the buyer
+    fee: u64,
+}
+
+// Cluster time; builds with the `test-clock` feature shift it by the test clock
+#[cfg(not(feature = "test-clock"))]
+fn current_time(_remaining_accounts: &[AccountInfo]) -> Result<i64> {
+    Ok(Clock::get()?.unix_timestamp)
+}
+
+// Oracle prices a contract is valued at, in units of 10^price_expo USD
+struct SettlementPrices {
+    underlying: u64,
+    // Only read for lamport contracts, token contracts settle in a USD stablecoin
+    sol: Option<u64>,
+    price_expo: i32,
+}
+
+// Reads settlement prices from the oracle feeds fixed at creation, at the
+// contract's price precision
+fn settlement_prices(
+    collateral_mint: Option<Pubkey>,
+    price_expo: i32,
+    underlying_price_feed: &PriceFeed,
+    sol_price_feed: &PriceFeed,
+    current_time: i64,
+) -> Result<SettlementPrices> {
+    let underlying = oracle_price(underlying_price_feed, price_expo, current_time)?;
+    let sol = match collateral_mint {
+        None => Some(oracle_price(sol_price_feed, price_expo, current_time)?),
+        Some(_) => None,
+    };
+    Ok(SettlementPrices {
+        underlying,
+        sol,
+        price_expo,
+    })
+}
+
+// Payoff of `units` at `prices`, in the contract's collateral units
+fn exercise_payoff(
+    contract: &OptionContract,
+    units: u64,
+    prices: &SettlementPrices,
+) -> Result<u64> {
+    let profit_per_share = match contract.option_type {
+        OptionType::Call => prices.underlying.saturating_sub(contract.strike_price),
+        OptionType::Put => contract.strike_price.saturating_sub(prices.underlying),
+    };
+    let total_profit = (profit_per_share as u128)
+        .checked_mul(units as u128)
+        .ok_or(ErrorCode::CalculationError)?;
+    usd_to_collateral(total_profit, prices, contract.collateral_decimals, Rounding::Down)
+}
+
+// Direction an amount is rounded in when it changes precision. Amounts owed
+// round down so nobody is paid more than the exact payoff, and margins round
+// up so they never fall short of the requirement.
+#[derive(Clone, Copy)]
+enum Rounding {
+    Down,
+    Up,
+}
+
+fn div_rounded(numerator: u128, denominator: u128, rounding: Rounding) -> Result<u128> {
+    let quotient = numerator
+        .checked_div(denominator)
+        .ok_or(ErrorCode::CalculationError)?;
+    Ok(match rounding {
+        Rounding::Up if quotient * denominator != numerator => quotient + 1,
+        _ => quotient,
+    })
+}
+
+// `value * 10^expo`, rounded when `expo` is negative
+fn scale_pow10(value: u128, expo: i32, rounding: Rounding) -> Result<u128> {
+    let scale = 10u128
+        .checked_pow(expo.unsigned_abs())
+        .ok_or(ErrorCode::CalculationError)?;
+    if expo >= 0 {
+        value
+            .checked_mul(scale)
+            .ok_or_else(|| error!(ErrorCode::CalculationError))
+    } else {
+        div_rounded(value, scale, rounding)
+    }
+}
+
+fn checked_u64(value: u128) -> Result<u64> {
+    u64::try_from(value).map_err(|_| error!(ErrorCode::CalculationError))
+}
+
+// Converts a USD amount at the precision of `prices` to collateral units.
+// Lamport collateral converts at the SOL price, token collateral is a USD
+// stablecoin and is only rescaled to the mint's decimals.
+fn usd_to_collateral(
+    amount: u128,
+    prices: &SettlementPrices,
+    collateral_decimals: u8,
+    rounding: Rounding,
+) -> Result<u64> {
+    match prices.sol {
+        Some(sol_price) => {
+            let lamports = amount
+                .checked_mul(LAMPORTS_PER_SOL as u128)
+                .ok_or(ErrorCode::CalculationError)?;
+            checked_u64(div_rounded(lamports, sol_price as u128, rounding)?)
+        }
+        None => usd_to_token_units(amount, prices.price_expo, collateral_decimals, rounding),
+    }
+}
+
+// Net payoff of every leg to the strategy buyer at `price`, in units of
+// 10^price_expo USD. Negative when the buyer owes the seller.
+fn strategy_payoff(legs: &[StrategyLeg], price: u64) -> Result<i128> {
+    legs.iter().try_fold(0i128, |total, leg| {
+        total
+            .checked_add(leg.payoff(price)?)
+            .ok_or_else(|| error!(ErrorCode::CalculationError))
+    })
+}
+
+// Maximum loss of each side of a strategy, in units of 10^price_expo USD
+struct StrategyLosses {
+    buyer: u128,
+    seller: u128,
+}
+
+// The payoff is linear between strikes, so up to the top strike its extremes
+// lie at zero or at a strike. Above it the payoff follows the calls the buyer
+// holds net, without bound, so the side short those calls is margined at
+// `margin_requirement_bps` of their top strike notional like a single contract.
+fn strategy_max_losses(
+    legs: &[StrategyLeg],
+    margin_requirement_bps: u16,
+) -> Result<StrategyLosses> {
+    let mut highest = 0i128;
+    let mut lowest = 0i128;
+    for price in std::iter::once(0).chain(legs.iter().map(|leg| leg.strike_price)) {
+        let payoff = strategy_payoff(legs, price)?;
+        highest = highest.max(payoff);
+        lowest = lowest.min(payoff);
+    }
+
+    let net_calls: i128 = legs
+        .iter()
+        .filter(|leg| leg.option_type == OptionType::Call)
+        .map(|leg| match leg.side {
+            LegSide::Long => leg.num_units as i128,
+            LegSide::Short => -(leg.num_units as i128),
+        })
+        .sum();
+    let top_strike = legs.iter().map(|leg| leg.strike_price).max().unwrap_or(0);
+    let uncovered_notional = net_calls
+        .unsigned_abs()
+        .checked_mul(top_strike as u128)
+        .and_then(|notional| notional.checked_mul(margin_requirement_bps as u128))
+        .ok_or(ErrorCode::CalculationError)?;
+    let uncovered = div_rounded(uncovered_notional, 10000, Rounding::Up)?;
+    let (buyer_tail, seller_tail) = if net_calls > 0 {
+        (0, uncovered)
+    } else {
+        (uncovered, 0)
+    };
+
+    let total = |loss: u128, tail: u128| {
+        loss.checked_add(tail)
+            .ok_or_else(|| error!(ErrorCode::CalculationError))
+    };
+    Ok(StrategyLosses {
+        buyer: total(lowest.unsigned_abs(), buyer_tail)?,
+        seller: total(highest.unsigned_abs(), seller_tail)?,
+    })
+}
+
+// Protocol fee of `bps` on `amount`, rounding down
+fn fee_amount(amount: u64, bps: u16) -> Result<u64> {
+    let fee = (amount as u128)
+        .checked_mul(bps as u128)
+        .ok_or(ErrorCode::CalculationError)?
+        / 10000;
+    u64::try_from(fee).map_err(|_| error!(ErrorCode::CalculationError))
+}
+
+// Margin a seller must hold against the open units: the payoff owed at the
+// current prices plus their share of the margin locked at creation
+fn maintenance_margin(contract: &OptionContract, payoff_amount: u64) -> Result<u64> {
+    let base_margin = (contract.margin_amount as u128)
+        .checked_mul(contract.unexercised_units() as u128)
+        .ok_or(ErrorCode::CalculationError)?
+        .checked_div(contract.num_units as u128)
+        .ok_or(ErrorCode::CalculationError)?;
+    (base_margin as u64)
+        .checked_add(payoff_amount)
+        .ok_or_else(|| error!(ErrorCode::CalculationError))
+}
+
+// Returns the whole locked margin to the seller, in lamports or tokens,
+// and reports how much was released
+fn release_margin<'info>(
+    collateral_mint: Option<Pubkey>,
+    collateral: &Account<'info, ContractCollateral>,
+    seller_escrow: &AccountInfo<'info>,
+    collateral_vault: Option<&Account<'info, TokenAccount>>,
+    seller_token_escrow: Option<&Account<'info, TokenAccount>>,
+    token_program: Option<&Program<'info, Token>>,
+) -> Result<u64> {
+    let locked_margin = locked_collateral(collateral_mint, collateral, collateral_vault)?;
+    match collateral_mint {
+        None => debit_collateral(&collateral.to_account_info(), seller_escrow, locked_margin)?,
+        Some(_) => transfer_tokens_from_collateral(
+            required_token_account(collateral_vault)?,
+            collateral,
+            required_token_account(seller_token_escrow)?,
+            required_token_account(token_program)?,
+            locked_margin,
+        )?,
+    }
+    Ok(locked_margin)
+}
+
+fn required_token_account<T>(account: Option<T>) -> Result<T> {
+    account.ok_or_else(|| error!(ErrorCode::MissingTokenAccount))
+}
+
+// Moves tokens out of a user's token escrow, signing with the escrow seeds
+fn transfer_tokens_from_escrow<'info>(
+    from: &Account<'info, TokenAccount>,
+    escrow: &AccountInfo<'info>,
+    owner: &Pubkey,
+    bump: u8,
+    to: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let escrow_seeds = &[b"escrow".as_ref(), owner.as_ref(), &[bump]];
+    let escrow_signer = &[&escrow_seeds[..]];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            Transfer {
+                from: from.to_account_info(),
+                to: to.to_account_info(),
+                authority: escrow.clone(),
+            },
+            escrow_signer,
+        ),
+        amount,
+    )
+}
+
+// Moves tokens out of a contract's collateral vault, signing with the collateral seeds
+fn transfer_tokens_from_collateral<'info>(
+    collateral_vault: &Account<'info, TokenAccount>,
+    collateral: &Account<'info, ContractCollateral>,
+    to: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let collateral_seeds = &[
+        b"collateral".as_ref(),
+        collateral.contract.as_ref(),
+        &[collateral.bump],
+    ];
+    let collateral_signer = &[&collateral_seeds[..]];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            Transfer {
+                from: collateral_vault.to_account_info(),
+                to: to.to_account_info(),
+                authority: collateral.to_account_info(),
+            },
+            collateral_signer,
+        ),
+        amount,
+    )
+}
+
+// Closes a drained vault owned by the collateral account, returning its rent
+// to `destination`
+fn close_collateral_vault<'info>(
+    collateral_vault: &Account<'info, TokenAccount>,
+    collateral: &Account<'info, ContractCollateral>,
+    destination: &AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    let collateral_seeds = &[
+        b"collateral".as_ref(),
+        collateral.contract.as_ref(),
+        &[collateral.bump],
+    ];
+    let collateral_signer = &[&collateral_seeds[..]];
+
+    token::close_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        CloseAccount {
+            account: collateral_vault.to_account_info(),
+            destination: destination.clone(),
+            authority: collateral.to_account_info(),
+        },
+        collateral_signer,
+    ))
+}
+
+// Moves tokens out of a treasury vault, signing with the treasury seeds
+fn transfer_tokens_from_treasury<'info>(
+    treasury_vault: &Account<'info, TokenAccount>,
+    treasury: &Account<'info, Treasury>,
+    to: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    let treasury_seeds = &[b"treasury".as_ref(), &[treasury.bump]];
+    let treasury_signer = &[&treasury_seeds[..]];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            Transfer {
+                from: treasury_vault.to_account_info(),
+                to: to.to_account_info(),
+                authority: treasury.to_account_info(),
+            },
+            treasury_signer,
+        ),
+        amount,
+    )
+}
+
+// Moves tokens out of an insurance vault, signing with the insurance fund seeds
+fn transfer_tokens_from_insurance<'info>(
+    insurance_vault: &Account<'info, TokenAccount>,
+    insurance_fund: &Account<'info, InsuranceFund>,
+    to: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+
+    let insurance_seeds = &[b"insurance_fund".as_ref(), &[insurance_fund.bump]];
+    let insurance_signer = &[&insurance_seeds[..]];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            Transfer {
+                from: insurance_vault.to_account_info(),
+                to: to.to_account_info(),
+                authority: insurance_fund.to_account_info(),
+            },
+            insurance_signer,
+        ),
+        amount,
+    )
+}
+
+// Pays as much of a defaulted `shortfall` as the insurance fund holds to the
+// buyer, in lamports or from the insurance vault for the collateral mint
+fn draw_insurance<'info>(
+    collateral_mint: Option<Pubkey>,
+    insurance_fund: &Account<'info, InsuranceFund>,
+    insurance_vault: Option<&Account<'info, TokenAccount>>,
+    buyer_escrow: &AccountInfo<'info>,
+    buyer_token_escrow: Option<&Account<'info, TokenAccount>>,
+    token_program: Option<&Program<'info, Token>>,
+    shortfall: u64,
+) -> Result<u64> {
+    match collateral_mint {
+        None => {
+            let insurance_info = insurance_fund.to_account_info();
+            let amount = collateral_balance(&insurance_info)?.min(shortfall);
+            debit_collateral(&insurance_info, buyer_escrow, amount)?;
+            Ok(amount)
+        }
+        Some(_) => {
+            let insurance_vault = required_token_account(insurance_vault)?;
+            let amount = insurance_vault.amount.min(shortfall);
+            transfer_tokens_from_insurance(
+                insurance_vault,
+                insurance_fund,
+                required_token_account(buyer_token_escrow)?,
+                required_token_account(token_program)?,
+                amount,
+            )?;
+            Ok(amount)
+        }
+    }
+}
+
+// Converts a USD amount in units of 10^price_expo to base units of a USD
+// stablecoin
+fn usd_to_token_units(
+    amount: u128,
+    price_expo: i32,
+    decimals: u8,
+    rounding: Rounding,
+) -> Result<u64> {
+    let expo = price_expo
+        .checked_add(decimals as i32)
+        .ok_or(ErrorCode::CalculationError)?;
+    checked_u64(scale_pow10(amount, expo, rounding)?)
+}
+
+// Base units of the underlying delivered for `units`, one whole token per unit
+fn underlying_amount(units: u64, decimals: u8) -> Result<u64> {
+    10u64
+        .checked_pow(decimals as u32)
+        .and_then(|scale| scale.checked_mul(units))
+        .ok_or_else(|| error!(ErrorCode::CalculationError))
+}
+
+// Validates an oracle quote and converts it to units of 10^price_expo USD,
+// rounding down
+fn oracle_price(feed: &PriceFeed, price_expo: i32, current_time: i64) -> Result<u64> {
+    require!(feed.price > 0, ErrorCode::InvalidOraclePrice);
+    require!(
+        feed.publish_time <= current_time,
+        ErrorCode::FutureOraclePrice
+    );
+    require!(
+        current_time - feed.publish_time <= MAX_PRICE_AGE_SECONDS,
+        ErrorCode::StaleOraclePrice
+    );
+
+    let price = feed.price as u64;
+    let max_conf = price
+        .checked_mul(MAX_PRICE_CONFIDENCE_BPS)
+        .ok_or(ErrorCode::CalculationError)?
+        / 10000;
+    require!(feed.conf <= max_conf, ErrorCode::OracleConfidenceTooWide);
+
+    let expo = feed
+        .expo
+        .checked_sub(price_expo)
+        .ok_or(ErrorCode::CalculationError)?;
+    let price = checked_u64(scale_pow10(price as u128, expo, Rounding::Down)?)?;
+    require!(price > 0, ErrorCode::InvalidOraclePrice);
+
+    Ok(price)
+}
+
+#[derive(Accounts)]
+pub struct InitializeUser<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + UserAccount::INIT_SPACE,
+        seeds = [b"user", user.key().as_ref()],
+        bump
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeMarketConfig<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + MarketConfig::INIT_SPACE,
+        seeds = [b"market_config"],
+        bump
+    )]
+    pub market_config: Account<'info, MarketConfig>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + Treasury::INIT_SPACE,
+        seeds = [b"treasury"],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::Basic>,
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key())
+            @ ErrorCode::UnauthorizedAdmin
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMarketConfig<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"market_config"],
+        bump = market_config.bump,
+        has_one = admin @ ErrorCode::UnauthorizedAdmin
+    )]
+    pub market_config: Account<'info, MarketConfig>,
+}
+
+#[derive(Accounts)]
+#[instruction(ticker: String)]
+pub struct RegisterUnderlyingAsset<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"market_config"],
+        bump = market_config.bump,
+        has_one = admin @ ErrorCode::UnauthorizedAdmin
+    )]
+    pub market_config: Account<'info, MarketConfig>,
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + UnderlyingAsset::INIT_SPACE,
+        seeds = [b"underlying_asset", ticker.as_bytes()],
+        bump
+    )]
+    pub asset: Account<'info, UnderlyingAsset>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeTreasuryVault<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    pub mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"treasury_vault", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = treasury
+    )]
+    pub treasury_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CollectFees<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"market_config"],
+        bump = market_config.bump,
+        has_one = admin @ ErrorCode::UnauthorizedAdmin
+    )]
+    pub market_config: Account<'info, MarketConfig>,
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    /// CHECK: Any account chosen by the admin to receive lamport fees
+    #[account(mut)]
+    pub destination: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"treasury_vault", treasury_vault.mint.as_ref()],
+        bump
+    )]
+    pub treasury_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut)]
+    pub destination_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeInsuranceFund<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + InsuranceFund::INIT_SPACE,
+        seeds = [b"insurance_fund"],
+        bump
+    )]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeInsuranceVault<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(seeds = [b"insurance_fund"], bump = insurance_fund.bump)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+    pub mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"insurance_vault", mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = insurance_fund
+    )]
+    pub insurance_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FundInsurance<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        seeds = [b"market_config"],
+        bump = market_config.bump,
+        has_one = admin @ ErrorCode::UnauthorizedAdmin
+    )]
+    pub market_config: Account<'info, MarketConfig>,
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    #[account(mut, seeds = [b"insurance_fund"], bump = insurance_fund.bump)]
+    pub insurance_fund: Account<'info, InsuranceFund>,
+    #[account(
+        mut,
+        seeds = [b"treasury_vault", treasury_vault.mint.as_ref()],
+        bump
+    )]
+    pub treasury_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"insurance_vault", insurance_vault.mint.as_ref()],
+        bump
+    )]
+    pub insurance_vault: Option<Box<Account<'info, TokenAccount>>>,
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    pub new_admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"market_config"],
+        bump = market_config.bump,
+        constraint = market_config.pending_admin == Some(new_admin.key())
+            @ ErrorCode::UnauthorizedAdmin
+    )]
+    pub market_config: Account<'info, MarketConfig>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeEscrow<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"escrow", user.key().as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as an escrow account
+    pub user_escrow: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Deposit<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"escrow", user.key().as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as an escrow account
+    pub user_escrow: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeTokenEscrow<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        seeds = [b"escrow", user.key().as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as an escrow account
+    pub user_escrow: AccountInfo<'info>,
+    pub mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = user,
+        seeds = [b"token_escrow", user.key().as_ref(), mint.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = user_escrow
+    )]
+    pub token_escrow: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DepositTokens<'info> {
+    pub user: Signer<'info>,
+    #[account(mut, token::authority = user)]
+    pub user_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"token_escrow", user.key().as_ref(), token_escrow.mint.as_ref()],
+        bump
+    )]
+    pub token_escrow: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(underlying_asset: String)]
+pub struct CreateContract<'info> {
+    // Checked before the asset PDA is derived from the ticker, which fails
+    // with a generic seeds error for tickers longer than a seed
+    #[account(
+        mut,
+        constraint = underlying_asset.len() <= MAX_TICKER_LENGTH @ ErrorCode::AssetTickerTooLong,
+        constraint = open.buyer_account.owner == buyer.key() @ ErrorCode::UserAccountMismatch
+    )]
+    pub buyer: Signer<'info>,
+    #[account(
+        mut,
+        constraint = open.seller_account.owner == seller.key() @ ErrorCode::UserAccountMismatch
+    )]
+    pub seller: Signer<'info>,
+    #[account(
+        seeds = [b"underlying_asset", underlying_asset.as_bytes()],
+        bump = asset.bump
+    )]
+    pub asset: Box<Account<'info, UnderlyingAsset>>,
+    pub underlying_price_feed: Account<'info, PriceFeed>,
+    pub sol_price_feed: Account<'info, PriceFeed>,
+    pub open: OpenContractAccounts<'info>,
+}
+
+// Accounts every contract creation path opens or funds, with the buyer and
+// seller identified by their user accounts
+#[derive(Accounts)]
+pub struct OpenContractAccounts<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        seeds = [b"market_config"],
+        bump = market_config.bump,
+        constraint = !market_config.paused @ ErrorCode::MarketPaused
+    )]
+    pub market_config: Account<'info, MarketConfig>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + OptionContract::INIT_SPACE,
+        seeds = [
+            b"contract",
+            buyer_account.owner.as_ref(),
+            seller_account.owner.as_ref(),
+            buyer_account.contract_count.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub contract: Account<'info, OptionContract>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + ContractCollateral::INIT_SPACE,
+        seeds = [b"collateral", contract.key().as_ref()],
+        bump
+    )]
+    pub collateral: Account<'info, ContractCollateral>,
+    #[account(
+        mut,
+        seeds = [b"user", buyer_account.owner.as_ref()],
+        bump = buyer_account.bump
+    )]
+    pub buyer_account: Account<'info, UserAccount>,
+    #[account(
+        mut,
+        seeds = [b"user", seller_account.owner.as_ref()],
+        bump = seller_account.bump
+    )]
+    pub seller_account: Account<'info, UserAccount>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + UserContractPage::INIT_SPACE,
+        seeds = [
+            b"user_page",
+            buyer_account.owner.as_ref(),
+            buyer_account.tail_page().to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub buyer_page: Account<'info, UserContractPage>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + UserContractPage::INIT_SPACE,
+        seeds = [
+            b"user_page",
+            seller_account.owner.as_ref(),
+            seller_account.tail_page().to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub seller_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [b"escrow", buyer_account.owner.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as an escrow account
+    pub buyer_escrow: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"escrow", seller_account.owner.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as an escrow account
+    pub seller_escrow: AccountInfo<'info>,
+    pub collateral_mint: Option<Box<Account<'info, Mint>>>,
+    #[account(
+        mut,
+        token::mint = collateral_mint,
+        token::authority = buyer_escrow
+    )]
+    pub buyer_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        token::mint = collateral_mint,
+        token::authority = seller_escrow
+    )]
+    pub seller_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"collateral_vault", contract.key().as_ref()],
+        bump,
+        token::mint = collateral_mint,
+        token::authority = collateral
+    )]
+    pub collateral_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    #[account(
+        mut,
+        seeds = [b"treasury_vault", treasury_vault.mint.as_ref()],
+        bump,
+        token::mint = collateral_mint
+    )]
+    pub treasury_vault: Option<Box<Account<'info, TokenAccount>>>,
+    pub underlying_mint: Option<Box<Account<'info, Mint>>>,
+    #[account(
+        mut,
+        token::mint = underlying_mint,
+        token::authority = seller_escrow
+    )]
+    pub seller_underlying_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"underlying_vault", contract.key().as_ref()],
+        bump,
+        token::mint = underlying_mint,
+        token::authority = collateral
+    )]
+    pub underlying_vault: Option<Box<Account<'info, TokenAccount>>>,
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(offer_id: u64, underlying_asset: String)]
+pub struct ProposeContract<'info> {
+    #[account(
+        mut,
+        constraint = underlying_asset.len() <= MAX_TICKER_LENGTH @ ErrorCode::AssetTickerTooLong
+    )]
+    pub buyer: Signer<'info>,
+    /// CHECK: Seller consents later by signing accept_contract
+    pub seller: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + OptionOffer::INIT_SPACE,
+        seeds = [
+            b"offer",
+            buyer.key().as_ref(),
+            seller.key().as_ref(),
+            offer_id.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub offer: Account<'info, OptionOffer>,
+    #[account(
+        seeds = [b"user", buyer.key().as_ref()],
+        bump = buyer_account.bump
+    )]
+    pub buyer_account: Account<'info, UserAccount>,
+    #[account(
+        seeds = [b"user", seller.key().as_ref()],
+        bump = seller_account.bump
+    )]
+    pub seller_account: Account<'info, UserAccount>,
+    #[account(
+        seeds = [b"underlying_asset", underlying_asset.as_bytes()],
+        bump = asset.bump
+    )]
+    pub asset: Account<'info, UnderlyingAsset>,
+    pub underlying_price_feed: Account<'info, PriceFeed>,
+    pub sol_price_feed: Account<'info, PriceFeed>,
+    pub collateral_mint: Option<Account<'info, Mint>>,
+    pub underlying_mint: Option<Account<'info, Mint>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptContract<'info> {
+    #[account(
+        mut,
+        constraint = open.seller_account.owner == seller.key() @ ErrorCode::UserAccountMismatch
+    )]
+    pub seller: Signer<'info>,
+    /// CHECK: Receives the offer rent, validated against the offer
+    #[account(
+        mut,
+        constraint = open.buyer_account.owner == buyer.key() @ ErrorCode::UserAccountMismatch
+    )]
+    pub buyer: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = buyer,
+        seeds = [
+            b"offer",
+            offer.buyer.as_ref(),
+            offer.seller.as_ref(),
+            offer.offer_id.to_le_bytes().as_ref()
+        ],
+        bump = offer.bump,
+        has_one = buyer @ ErrorCode::OfferPartyMismatch,
+        has_one = seller @ ErrorCode::OfferPartyMismatch
+    )]
+    pub offer: Account<'info, OptionOffer>,
+    #[account(address = offer.underlying_price_feed @ ErrorCode::PriceFeedMismatch)]
+    pub underlying_price_feed: Account<'info, PriceFeed>,
+    #[account(address = offer.sol_price_feed @ ErrorCode::PriceFeedMismatch)]
+    pub sol_price_feed: Account<'info, PriceFeed>,
+    pub open: OpenContractAccounts<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RejectOffer<'info> {
+    pub seller: Signer<'info>,
+    /// CHECK: Receives the offer rent, validated against the offer
+    #[account(mut)]
+    pub buyer: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = buyer,
+        seeds = [
+            b"offer",
+            offer.buyer.as_ref(),
+            offer.seller.as_ref(),
+            offer.offer_id.to_le_bytes().as_ref()
+        ],
+        bump = offer.bump,
+        has_one = buyer @ ErrorCode::OfferPartyMismatch,
+        has_one = seller @ ErrorCode::OfferPartyMismatch
+    )]
+    pub offer: Account<'info, OptionOffer>,
+}
+
+#[derive(Accounts)]
+pub struct CancelOffer<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    #[account(
+        mut,
+        close = buyer,
+        seeds = [
+            b"offer",
+            offer.buyer.as_ref(),
+            offer.seller.as_ref(),
+            offer.offer_id.to_le_bytes().as_ref()
+        ],
+        bump = offer.bump,
+        has_one = buyer @ ErrorCode::OfferPartyMismatch
+    )]
+    pub offer: Account<'info, OptionOffer>,
+}
+
+#[derive(Accounts)]
+#[instruction(quote_id: u64, underlying_asset: String)]
+pub struct PostQuote<'info> {
+    #[account(
+        mut,
+        constraint = underlying_asset.len() <= MAX_TICKER_LENGTH @ ErrorCode::AssetTickerTooLong
+    )]
+    pub seller: Signer<'info>,
+    #[account(
+        init,
+        payer = seller,
+        space = 8 + Quote::INIT_SPACE,
+        seeds = [b"quote", seller.key().as_ref(), quote_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub quote: Account<'info, Quote>,
+    #[account(
+        seeds = [b"user", seller.key().as_ref()],
+        bump = seller_account.bump
+    )]
+    pub seller_account: Account<'info, UserAccount>,
+    #[account(
+        seeds = [b"underlying_asset", underlying_asset.as_bytes()],
+        bump = asset.bump
+    )]
+    pub asset: Account<'info, UnderlyingAsset>,
+    pub underlying_price_feed: Account<'info, PriceFeed>,
+    pub sol_price_feed: Account<'info, PriceFeed>,
+    pub collateral_mint: Option<Account<'info, Mint>>,
+    pub underlying_mint: Option<Account<'info, Mint>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FillQuote<'info> {
+    #[account(
+        mut,
+        constraint = open.buyer_account.owner == buyer.key() @ ErrorCode::UserAccountMismatch
+    )]
+    pub buyer: Signer<'info>,
+    /// CHECK: Receives the premium and quote rent, validated against the quote
+    #[account(
+        mut,
+        constraint = open.seller_account.owner == seller.key() @ ErrorCode::UserAccountMismatch
+    )]
+    pub seller: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"quote", quote.seller.as_ref(), quote.quote_id.to_le_bytes().as_ref()],
+        bump = quote.bump,
+        has_one = seller
+    )]
+    pub quote: Account<'info, Quote>,
+    #[account(address = quote.underlying_price_feed @ ErrorCode::PriceFeedMismatch)]
+    pub underlying_price_feed: Account<'info, PriceFeed>,
+    #[account(address = quote.sol_price_feed @ ErrorCode::PriceFeedMismatch)]
+    pub sol_price_feed: Account<'info, PriceFeed>,
+    pub open: OpenContractAccounts<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelQuote<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        close = seller,
+        seeds = [b"quote", quote.seller.as_ref(), quote.quote_id.to_le_bytes().as_ref()],
+        bump = quote.bump,
+        has_one = seller
+    )]
+    pub quote: Account<'info, Quote>,
+}
+
+#[derive(Accounts)]
+pub struct CloseExpiredQuote<'info> {
+    pub caller: Signer<'info>,
+    /// CHECK: Receives the quote rent, validated against the quote
+    #[account(mut)]
+    pub seller: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = seller,
+        seeds = [b"quote", quote.seller.as_ref(), quote.quote_id.to_le_bytes().as_ref()],
+        bump = quote.bump,
+        has_one = seller
+    )]
+    pub quote: Account<'info, Quote>,
+}
+
+#[derive(Accounts)]
+pub struct Exercise<'info> {
+    pub buyer: Signer<'info>,
+    #[account(
+        seeds = [b"market_config"],
+        bump = market_config.bump,
+        constraint = !market_config.paused @ ErrorCode::MarketPaused
+    )]
+    pub market_config: Account<'info, MarketConfig>,
+    #[account(
+        mut,
+        seeds = [
+            b"contract",
+            contract.original_buyer.as_ref(),
+            contract.seller.as_ref(),
+            contract.contract_id.to_le_bytes().as_ref()
+        ],
+        bump = contract.bump
+    )]
+    pub contract: Account<'info, OptionContract>,
+    #[account(
+        mut,
+        seeds = [
+            b"user_page",
+            contract.buyer.as_ref(),
+            contract.buyer_page.to_le_bytes().as_ref()
+        ],
+        bump = buyer_page.bump
+    )]
+    pub buyer_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [
+            b"user_page",
+            contract.seller.as_ref(),
+            contract.seller_page.to_le_bytes().as_ref()
+        ],
+        bump = seller_page.bump
+    )]
+    pub seller_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [b"collateral", contract.key().as_ref()],
+        bump = collateral.bump
+    )]
+    pub collateral: Account<'info, ContractCollateral>,
+    #[account(
+        mut,
+        seeds = [b"escrow", contract.seller.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as an escrow account
+    pub seller_escrow: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", contract.key().as_ref()],
+        bump
+    )]
+    pub collateral_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        token::authority = seller_escrow,
+        constraint = Some(seller_token_escrow.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub seller_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    pub token_program: Option<Program<'info, Token>>,
+    #[account(address = contract.underlying_price_feed @ ErrorCode::PriceFeedMismatch)]
+    pub underlying_price_feed: Account<'info, PriceFeed>,
+    #[account(address = contract.sol_price_feed @ ErrorCode::PriceFeedMismatch)]
+    pub sol_price_feed: Account<'info, PriceFeed>,
+}
+
+#[derive(Accounts)]
+pub struct ExerciseUnits<'info> {
+    pub buyer: Signer<'info>,
+    #[account(
+        seeds = [b"market_config"],
+        bump = market_config.bump,
+        constraint = !market_config.paused @ ErrorCode::MarketPaused
+    )]
+    pub market_config: Account<'info, MarketConfig>,
+    #[account(
+        mut,
+        seeds = [
+            b"contract",
+            contract.original_buyer.as_ref(),
+            contract.seller.as_ref(),
+            contract.contract_id.to_le_bytes().as_ref()
+        ],
+        bump = contract.bump
+    )]
+    pub contract: Account<'info, OptionContract>,
+    #[account(
+        mut,
+        seeds = [b"collateral", contract.key().as_ref()],
+        bump = collateral.bump
+    )]
+    pub collateral: Account<'info, ContractCollateral>,
+    #[account(
+        mut,
+        seeds = [
+            b"user_page",
+            contract.buyer.as_ref(),
+            contract.buyer_page.to_le_bytes().as_ref()
+        ],
+        bump = buyer_page.bump
+    )]
+    pub buyer_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [
+            b"user_page",
+            contract.seller.as_ref(),
+            contract.seller_page.to_le_bytes().as_ref()
+        ],
+        bump = seller_page.bump
+    )]
+    pub seller_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [b"escrow", contract.buyer.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as an escrow account
+    pub buyer_escrow: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"escrow", contract.seller.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as an escrow account
+    pub seller_escrow: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", contract.key().as_ref()],
+        bump
+    )]
+    pub collateral_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    #[account(
+        mut,
+        seeds = [b"treasury_vault", treasury_vault.mint.as_ref()],
+        bump,
+        constraint = Some(treasury_vault.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub treasury_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        token::authority = buyer_escrow,
+        constraint = Some(buyer_token_escrow.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub buyer_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        token::authority = seller_escrow,
+        constraint = Some(seller_token_escrow.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub seller_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    pub token_program: Option<Program<'info, Token>>,
+    #[account(address = contract.underlying_price_feed @ ErrorCode::PriceFeedMismatch)]
+    pub underlying_price_feed: Account<'info, PriceFeed>,
+    #[account(address = contract.sol_price_feed @ ErrorCode::PriceFeedMismatch)]
+    pub sol_price_feed: Account<'info, PriceFeed>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CrankExpiry<'info> {
+    // The keeper, paid the crank bounty in lamports
+    #[account(mut)]
+    pub caller: Signer<'info>,
+    #[account(
+        seeds = [b"market_config"],
+        bump = market_config.bump,
+        constraint = !market_config.paused @ ErrorCode::MarketPaused
+    )]
+    pub market_config: Account<'info, MarketConfig>,
+    #[account(
+        mut,
+        seeds = [
+            b"contract",
+            contract.original_buyer.as_ref(),
+            contract.seller.as_ref(),
+            contract.contract_id.to_le_bytes().as_ref()
+        ],
+        bump = contract.bump
+    )]
+    pub contract: Account<'info, OptionContract>,
+    #[account(
+        mut,
+        seeds = [b"collateral", contract.key().as_ref()],
+        bump = collateral.bump
+    )]
+    pub collateral: Account<'info, ContractCollateral>,
+    #[account(
+        mut,
+        seeds = [
+            b"user_page",
+            contract.buyer.as_ref(),
+            contract.buyer_page.to_le_bytes().as_ref()
+        ],
+        bump = buyer_page.bump
+    )]
+    pub buyer_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [
+            b"user_page",
+            contract.seller.as_ref(),
+            contract.seller_page.to_le_bytes().as_ref()
+        ],
+        bump = seller_page.bump
+    )]
+    pub seller_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [b"escrow", contract.buyer.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as an escrow account
+    pub buyer_escrow: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"escrow", contract.seller.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as an escrow account
+    pub seller_escrow: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", contract.key().as_ref()],
+        bump
+    )]
+    pub collateral_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    #[account(
+        mut,
+        seeds = [b"treasury_vault", treasury_vault.mint.as_ref()],
+        bump,
+        constraint = Some(treasury_vault.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub treasury_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        token::authority = buyer_escrow,
+        constraint = Some(buyer_token_escrow.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub buyer_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        token::authority = seller_escrow,
+        constraint = Some(seller_token_escrow.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub seller_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    // Receives the bounty of a token contract
+    #[account(
+        mut,
+        constraint = Some(caller_token_account.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub caller_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    pub token_program: Option<Program<'info, Token>>,
+    #[account(address = contract.underlying_price_feed @ ErrorCode::PriceFeedMismatch)]
+    pub underlying_price_feed: Account<'info, PriceFeed>,
+    #[account(address = contract.sol_price_feed @ ErrorCode::PriceFeedMismatch)]
+    pub sol_price_feed: Account<'info, PriceFeed>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExercisePhysical<'info> {
+    pub buyer: Signer<'info>,
+    #[account(
+        seeds = [b"market_config"],
+        bump = market_config.bump,
+        constraint = !market_config.paused @ ErrorCode::MarketPaused
+    )]
+    pub market_config: Account<'info, MarketConfig>,
+    #[account(
+        mut,
+        seeds = [
+            b"contract",
+            contract.original_buyer.as_ref(),
+            contract.seller.as_ref(),
+            contract.contract_id.to_le_bytes().as_ref()
+        ],
+        bump = contract.bump
+    )]
+    pub contract: Account<'info, OptionContract>,
+    #[account(
+        mut,
+        seeds = [b"collateral", contract.key().as_ref()],
+        bump = collateral.bump
+    )]
+    pub collateral: Account<'info, ContractCollateral>,
+    #[account(
+        mut,
+        seeds = [
+            b"user_page",
+            contract.buyer.as_ref(),
+            contract.buyer_page.to_le_bytes().as_ref()
+        ],
+        bump = buyer_page.bump
+    )]
+    pub buyer_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [
+            b"user_page",
+            contract.seller.as_ref(),
+            contract.seller_page.to_le_bytes().as_ref()
+        ],
+        bump = seller_page.bump
+    )]
+    pub seller_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [b"escrow", contract.buyer.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as an escrow account
+    pub buyer_escrow: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"escrow", contract.seller.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as an escrow account
+    pub seller_escrow: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", contract.key().as_ref()],
+        bump
+    )]
+    pub collateral_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        token::authority = buyer_escrow,
+        constraint = Some(buyer_token_escrow.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub buyer_token_escrow: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        token::authority = seller_escrow,
+        constraint = Some(seller_token_escrow.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub seller_token_escrow: Box<Account<'info, TokenAccount>>,
+    // Only holds the underlying of calls
+    #[account(
+        mut,
+        seeds = [b"underlying_vault", contract.key().as_ref()],
+        bump
+    )]
+    pub underlying_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        token::authority = buyer_escrow,
+        constraint = Some(buyer_underlying_escrow.mint) == contract.underlying_mint
+            @ ErrorCode::UnderlyingMintMismatch
+    )]
+    pub buyer_underlying_escrow: Box<Account<'info, TokenAccount>>,
+    // Only receives the underlying of puts
+    #[account(
+        mut,
+        token::authority = seller_escrow,
+        constraint = Some(seller_underlying_escrow.mint) == contract.underlying_mint
+            @ ErrorCode::UnderlyingMintMismatch
+    )]
+    pub seller_underlying_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Settle<'info> {
+    pub caller: Signer<'info>,
+    #[account(
+        seeds = [b"market_config"],
+        bump = market_config.bump,
+        constraint = !market_config.paused @ ErrorCode::MarketPaused
+    )]
+    pub market_config: Account<'info, MarketConfig>,
+    #[account(
+        mut,
+        seeds = [
+            b"contract",
+            contract.original_buyer.as_ref(),
+            contract.seller.as_ref(),
+            contract.contract_id.to_le_bytes().as_ref()
+        ],
+        bump = contract.bump
+    )]
+    pub contract: Account<'info, OptionContract>,
+    #[account(
+        mut,
+        seeds = [b"collateral", contract.key().as_ref()],
+        bump = collateral.bump
+    )]
+    pub collateral: Account<'info, ContractCollateral>,
+    #[account(
+        mut,
+        seeds = [
+            b"user_page",
+            contract.buyer.as_ref(),
+            contract.buyer_page.to_le_bytes().as_ref()
+        ],
+        bump = buyer_page.bump
+    )]
+    pub buyer_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [
+            b"user_page",
+            contract.seller.as_ref(),
+            contract.seller_page.to_le_bytes().as_ref()
+        ],
+        bump = seller_page.bump
+    )]
+    pub seller_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [b"escrow", contract.buyer.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as an escrow account
+    pub buyer_escrow: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"escrow", contract.seller.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as an escrow account
+    pub seller_escrow: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", contract.key().as_ref()],
+        bump
+    )]
+    pub collateral_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    #[account(
+        mut,
+        seeds = [b"treasury_vault", treasury_vault.mint.as_ref()],
+        bump,
+        constraint = Some(treasury_vault.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub treasury_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        token::authority = buyer_escrow,
+        constraint = Some(buyer_token_escrow.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub buyer_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        token::authority = seller_escrow,
+        constraint = Some(seller_token_escrow.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub seller_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    // Covers what a defaulting seller cannot pay, when passed
+    #[account(mut, seeds = [b"insurance_fund"], bump = insurance_fund.bump)]
+    pub insurance_fund: Option<Account<'info, InsuranceFund>>,
+    #[account(
+        mut,
+        seeds = [b"insurance_vault", insurance_vault.mint.as_ref()],
+        bump,
+        constraint = Some(insurance_vault.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub insurance_vault: Option<Box<Account<'info, TokenAccount>>>,
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Expire<'info> {
+    pub caller: Signer<'info>,
+    #[account(
+        seeds = [b"market_config"],
+        bump = market_config.bump,
+        constraint = !market_config.paused @ ErrorCode::MarketPaused
+    )]
+    pub market_config: Account<'info, MarketConfig>,
+    #[account(
+        mut,
+        seeds = [
+            b"contract",
+            contract.original_buyer.as_ref(),
+            contract.seller.as_ref(),
+            contract.contract_id.to_le_bytes().as_ref()
+        ],
+        bump = contract.bump
+    )]
+    pub contract: Account<'info, OptionContract>,
+    #[account(
+        mut,
+        seeds = [b"collateral", contract.key().as_ref()],
+        bump = collateral.bump
+    )]
+    pub collateral: Account<'info, ContractCollateral>,
+    #[account(
+        mut,
+        seeds = [
+            b"user_page",
+            contract.buyer.as_ref(),
+            contract.buyer_page.to_le_bytes().as_ref()
+        ],
+        bump = buyer_page.bump
+    )]
+    pub buyer_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [
+            b"user_page",
+            contract.seller.as_ref(),
+            contract.seller_page.to_le_bytes().as_ref()
+        ],
+        bump = seller_page.bump
+    )]
+    pub seller_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [b"escrow", contract.seller.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as an escrow account
+    pub seller_escrow: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", contract.key().as_ref()],
+        bump
+    )]
+    pub collateral_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        token::authority = seller_escrow,
+        constraint = Some(seller_token_escrow.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub seller_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"underlying_vault", contract.key().as_ref()],
+        bump
+    )]
+    pub underlying_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        token::authority = seller_escrow,
+        constraint = Some(seller_underlying_escrow.mint) == contract.underlying_mint
+            @ ErrorCode::UnderlyingMintMismatch
+    )]
+    pub seller_underlying_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    pub token_program: Option<Program<'info, Token>>,
+    #[account(address = contract.underlying_price_feed @ ErrorCode::PriceFeedMismatch)]
+    pub underlying_price_feed: Account<'info, PriceFeed>,
+    #[account(address = contract.sol_price_feed @ ErrorCode::PriceFeedMismatch)]
+    pub sol_price_feed: Account<'info, PriceFeed>,
+}
+
+#[derive(Accounts)]
+pub struct MarkToMarket<'info> {
+    pub caller: Signer<'info>,
+    #[account(
+        seeds = [b"market_config"],
+        bump = market_config.bump,
+        constraint = !market_config.paused @ ErrorCode::MarketPaused
+    )]
+    pub market_config: Account<'info, MarketConfig>,
+    #[account(
+        mut,
+        seeds = [
+            b"contract",
+            contract.original_buyer.as_ref(),
+            contract.seller.as_ref(),
+            contract.contract_id.to_le_bytes().as_ref()
+        ],
+        bump = contract.bump
+    )]
+    pub contract: Account<'info, OptionContract>,
+    #[account(
+        seeds = [b"collateral", contract.key().as_ref()],
+        bump = collateral.bump
+    )]
+    pub collateral: Account<'info, ContractCollateral>,
+    #[account(
+        seeds = [b"collateral_vault", contract.key().as_ref()],
+        bump
+    )]
+    pub collateral_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(address = contract.underlying_price_feed @ ErrorCode::PriceFeedMismatch)]
+    pub underlying_price_feed: Account<'info, PriceFeed>,
+    #[account(address = contract.sol_price_feed @ ErrorCode::PriceFeedMismatch)]
+    pub sol_price_feed: Account<'info, PriceFeed>,
+}
 
-        Ok(())
-    }
+#[derive(Accounts)]
+pub struct TopUpMargin<'info> {
+    pub seller: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"contract",
+            contract.original_buyer.as_ref(),
+            contract.seller.as_ref(),
+            contract.contract_id.to_le_bytes().as_ref()
+        ],
+        bump = contract.bump,
+        constraint = contract.seller == seller.key() @ ErrorCode::UnauthorizedTopUp
+    )]
+    pub contract: Account<'info, OptionContract>,
+    #[account(
+        mut,
+        seeds = [b"collateral", contract.key().as_ref()],
+        bump = collateral.bump
+    )]
+    pub collateral: Account<'info, ContractCollateral>,
+    #[account(
+        mut,
+        seeds = [b"escrow", contract.seller.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as an escrow account
+    pub seller_escrow: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", contract.key().as_ref()],
+        bump
+    )]
+    pub collateral_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        token::authority = seller_escrow,
+        constraint = Some(seller_token_escrow.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub seller_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct InitializeUser<'info> {
+pub struct Liquidate<'info> {
     #[account(mut)]
-    pub user: Signer<'info>,
+    pub liquidator: Signer<'info>,
     #[account(
-        init,
-        payer = user,
-        space = 8 + UserAccount::INIT_SPACE,
-        seeds = [b"user", user.key().as_ref()],
+        seeds = [b"market_config"],
+        bump = market_config.bump,
+        constraint = !market_config.paused @ ErrorCode::MarketPaused
+    )]
+    pub market_config: Account<'info, MarketConfig>,
+    #[account(
+        mut,
+        seeds = [
+            b"contract",
+            contract.original_buyer.as_ref(),
+            contract.seller.as_ref(),
+            contract.contract_id.to_le_bytes().as_ref()
+        ],
+        bump = contract.bump
+    )]
+    pub contract: Account<'info, OptionContract>,
+    #[account(
+        mut,
+        seeds = [b"collateral", contract.key().as_ref()],
+        bump = collateral.bump
+    )]
+    pub collateral: Account<'info, ContractCollateral>,
+    #[account(
+        mut,
+        seeds = [
+            b"user_page",
+            contract.buyer.as_ref(),
+            contract.buyer_page.to_le_bytes().as_ref()
+        ],
+        bump = buyer_page.bump
+    )]
+    pub buyer_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [
+            b"user_page",
+            contract.seller.as_ref(),
+            contract.seller_page.to_le_bytes().as_ref()
+        ],
+        bump = seller_page.bump
+    )]
+    pub seller_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [b"escrow", contract.buyer.as_ref()],
         bump
     )]
-    pub user_account: Account<'info, UserAccount>,
+    /// CHECK: This is a PDA used as an escrow account
+    pub buyer_escrow: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"escrow", contract.seller.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as an escrow account
+    pub seller_escrow: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", contract.key().as_ref()],
+        bump
+    )]
+    pub collateral_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    #[account(
+        mut,
+        seeds = [b"treasury_vault", treasury_vault.mint.as_ref()],
+        bump,
+        constraint = Some(treasury_vault.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub treasury_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        token::authority = buyer_escrow,
+        constraint = Some(buyer_token_escrow.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub buyer_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        token::authority = seller_escrow,
+        constraint = Some(seller_token_escrow.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub seller_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = Some(liquidator_token_account.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub liquidator_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    // Covers what a defaulting seller cannot pay, when passed
+    #[account(mut, seeds = [b"insurance_fund"], bump = insurance_fund.bump)]
+    pub insurance_fund: Option<Account<'info, InsuranceFund>>,
+    #[account(
+        mut,
+        seeds = [b"insurance_vault", insurance_vault.mint.as_ref()],
+        bump,
+        constraint = Some(insurance_vault.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub insurance_vault: Option<Box<Account<'info, TokenAccount>>>,
+    pub token_program: Option<Program<'info, Token>>,
+    #[account(address = contract.underlying_price_feed @ ErrorCode::PriceFeedMismatch)]
+    pub underlying_price_feed: Account<'info, PriceFeed>,
+    #[account(address = contract.sol_price_feed @ ErrorCode::PriceFeedMismatch)]
+    pub sol_price_feed: Account<'info, PriceFeed>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct InitializeEscrow<'info> {
+pub struct CloseContract<'info> {
+    pub caller: Signer<'info>,
+    /// CHECK: Receives the reclaimed rent, validated against the contract
+    #[account(mut, address = contract.rent_payer)]
+    pub rent_payer: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = rent_payer,
+        seeds = [
+            b"contract",
+            contract.original_buyer.as_ref(),
+            contract.seller.as_ref(),
+            contract.contract_id.to_le_bytes().as_ref()
+        ],
+        bump = contract.bump
+    )]
+    pub contract: Account<'info, OptionContract>,
+    #[account(
+        mut,
+        close = rent_payer,
+        seeds = [b"collateral", contract.key().as_ref()],
+        bump = collateral.bump
+    )]
+    pub collateral: Account<'info, ContractCollateral>,
+    #[account(
+        mut,
+        seeds = [
+            b"user_page",
+            contract.buyer.as_ref(),
+            contract.buyer_page.to_le_bytes().as_ref()
+        ],
+        bump = buyer_page.bump
+    )]
+    pub buyer_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [
+            b"user_page",
+            contract.seller.as_ref(),
+            contract.seller_page.to_le_bytes().as_ref()
+        ],
+        bump = seller_page.bump
+    )]
+    pub seller_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", contract.key().as_ref()],
+        bump
+    )]
+    pub collateral_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"underlying_vault", contract.key().as_ref()],
+        bump
+    )]
+    pub underlying_vault: Option<Box<Account<'info, TokenAccount>>>,
+    pub token_program: Option<Program<'info, Token>>,
+}
+
+#[derive(Accounts)]
+pub struct TransferPosition<'info> {
+    pub holder: Signer<'info>,
     #[account(mut)]
-    pub user: Signer<'info>,
+    pub new_holder: Signer<'info>,
+    #[account(
+        seeds = [b"market_config"],
+        bump = market_config.bump,
+        constraint = !market_config.paused @ ErrorCode::MarketPaused
+    )]
+    pub market_config: Account<'info, MarketConfig>,
     #[account(
         mut,
-        seeds = [b"escrow", user.key().as_ref()],
+        seeds = [
+            b"contract",
+            contract.original_buyer.as_ref(),
+            contract.seller.as_ref(),
+            contract.contract_id.to_le_bytes().as_ref()
+        ],
+        bump = contract.bump,
+        constraint = contract.buyer == holder.key() @ ErrorCode::UnauthorizedTransfer
+    )]
+    pub contract: Account<'info, OptionContract>,
+    #[account(
+        mut,
+        seeds = [
+            b"user_page",
+            contract.buyer.as_ref(),
+            contract.buyer_page.to_le_bytes().as_ref()
+        ],
+        bump = holder_page.bump
+    )]
+    pub holder_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [b"user", new_holder.key().as_ref()],
+        bump = new_holder_account.bump
+    )]
+    pub new_holder_account: Account<'info, UserAccount>,
+    #[account(
+        init_if_needed,
+        payer = new_holder,
+        space = 8 + UserContractPage::INIT_SPACE,
+        seeds = [
+            b"user_page",
+            new_holder.key().as_ref(),
+            new_holder_account.tail_page().to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub new_holder_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [b"escrow", holder.key().as_ref()],
         bump
     )]
     /// CHECK: This is a PDA used as an escrow account
-    pub user_escrow: AccountInfo<'info>,
+    pub holder_escrow: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"escrow", new_holder.key().as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as an escrow account
+    pub new_holder_escrow: AccountInfo<'info>,
+    #[account(
+        mut,
+        token::authority = holder_escrow,
+        constraint = Some(holder_token_escrow.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub holder_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        token::authority = new_holder_escrow,
+        constraint = Some(new_holder_token_escrow.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub new_holder_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    pub token_program: Option<Program<'info, Token>>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct Deposit<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
+pub struct TerminateContract<'info> {
+    pub buyer: Signer<'info>,
+    pub seller: Signer<'info>,
+    #[account(
+        seeds = [b"market_config"],
+        bump = market_config.bump,
+        constraint = !market_config.paused @ ErrorCode::MarketPaused
+    )]
+    pub market_config: Account<'info, MarketConfig>,
     #[account(
         mut,
-        seeds = [b"escrow", user.key().as_ref()],
+        seeds = [
+            b"contract",
+            contract.original_buyer.as_ref(),
+            contract.seller.as_ref(),
+            contract.contract_id.to_le_bytes().as_ref()
+        ],
+        bump = contract.bump,
+        has_one = buyer @ ErrorCode::UnauthorizedTermination,
+        has_one = seller @ ErrorCode::UnauthorizedTermination
+    )]
+    pub contract: Account<'info, OptionContract>,
+    #[account(
+        mut,
+        seeds = [b"collateral", contract.key().as_ref()],
+        bump = collateral.bump
+    )]
+    pub collateral: Account<'info, ContractCollateral>,
+    #[account(
+        mut,
+        seeds = [
+            b"user_page",
+            contract.buyer.as_ref(),
+            contract.buyer_page.to_le_bytes().as_ref()
+        ],
+        bump = buyer_page.bump
+    )]
+    pub buyer_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [
+            b"user_page",
+            contract.seller.as_ref(),
+            contract.seller_page.to_le_bytes().as_ref()
+        ],
+        bump = seller_page.bump
+    )]
+    pub seller_page: Account<'info, UserContractPage>,
+    #[account(
+        mut,
+        seeds = [b"escrow", contract.buyer.as_ref()],
         bump
     )]
     /// CHECK: This is a PDA used as an escrow account
-    pub user_escrow: AccountInfo<'info>,
+    pub buyer_escrow: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"escrow", contract.seller.as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as an escrow account
+    pub seller_escrow: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", contract.key().as_ref()],
+        bump
+    )]
+    pub collateral_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        token::authority = buyer_escrow,
+        constraint = Some(buyer_token_escrow.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub buyer_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        token::authority = seller_escrow,
+        constraint = Some(seller_token_escrow.mint) == contract.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub seller_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        seeds = [b"underlying_vault", contract.key().as_ref()],
+        bump
+    )]
+    pub underlying_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        token::authority = seller_escrow,
+        constraint = Some(seller_underlying_escrow.mint) == contract.underlying_mint
+            @ ErrorCode::UnderlyingMintMismatch
+    )]
+    pub seller_underlying_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    pub token_program: Option<Program<'info, Token>>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct CreateContract<'info> {
-    #[account(mut)]
+#[instruction(underlying_asset: String)]
+pub struct CreateStrategy<'info> {
+    #[account(
+        mut,
+        constraint = underlying_asset.len() <= MAX_TICKER_LENGTH @ ErrorCode::AssetTickerTooLong
+    )]
     pub buyer: Signer<'info>,
-    /// CHECK: Seller does not need to sign
     #[account(mut)]
-    pub seller: AccountInfo<'info>,
+    pub seller: Signer<'info>,
+    #[account(
+        seeds = [b"market_config"],
+        bump = market_config.bump,
+        constraint = !market_config.paused @ ErrorCode::MarketPaused
+    )]
+    pub market_config: Account<'info, MarketConfig>,
+    #[account(
+        seeds = [b"underlying_asset", underlying_asset.as_bytes()],
+        bump = asset.bump
+    )]
+    pub asset: Box<Account<'info, UnderlyingAsset>>,
     #[account(
         init,
         payer = buyer,
-        space = 8 + OptionContract::INIT_SPACE,
+        space = 8 + OptionStrategy::INIT_SPACE,
         seeds = [
-            b"contract",
+            b"strategy",
             buyer.key().as_ref(),
             seller.key().as_ref(),
             buyer_account.contract_count.to_le_bytes().as_ref()
         ],
         bump
     )]
-    pub contract: Account<'info, OptionContract>,
+    pub strategy: Account<'info, OptionStrategy>,
+    #[account(
+        init,
+        payer = buyer,
+        space = 8 + ContractCollateral::INIT_SPACE,
+        seeds = [b"collateral", strategy.key().as_ref()],
+        bump
+    )]
+    pub collateral: Account<'info, ContractCollateral>,
     #[account(
         mut,
         seeds = [b"user", buyer.key().as_ref()],
@@ -430,7 +5069,6 @@ pub struct CreateContract<'info> {
     )]
     pub buyer_account: Account<'info, UserAccount>,
     #[account(
-        mut,
         seeds = [b"user", seller.key().as_ref()],
         bump = seller_account.bump
     )]
@@ -449,80 +5087,166 @@ pub struct CreateContract<'info> {
     )]
     /// CHECK: This is a PDA used as an escrow account
     pub seller_escrow: AccountInfo<'info>,
-    pub system_program: Program<'info, System>,
-}
-
-#[derive(Accounts)]
-pub struct Exercise<'info> {
-    pub buyer: Signer<'info>,
+    pub collateral_mint: Option<Box<Account<'info, Mint>>>,
     #[account(
         mut,
-        seeds = [
-            b"contract",
-            contract.buyer.as_ref(),
-            contract.seller.as_ref(),
-            contract.contract_id.to_le_bytes().as_ref()
-        ],
-        bump = contract.bump
+        token::mint = collateral_mint,
+        token::authority = buyer_escrow
     )]
-    pub contract: Account<'info, OptionContract>,
+    pub buyer_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
     #[account(
         mut,
-        seeds = [b"user", contract.buyer.as_ref()],
-        bump = buyer_account.bump
+        token::mint = collateral_mint,
+        token::authority = seller_escrow
     )]
-    pub buyer_account: Account<'info, UserAccount>,
+    pub seller_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        init,
+        payer = buyer,
+        seeds = [b"collateral_vault", strategy.key().as_ref()],
+        bump,
+        token::mint = collateral_mint,
+        token::authority = collateral
+    )]
+    pub collateral_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
     #[account(
         mut,
-        seeds = [b"user", contract.seller.as_ref()],
-        bump = seller_account.bump
+        seeds = [b"treasury_vault", treasury_vault.mint.as_ref()],
+        bump,
+        token::mint = collateral_mint
     )]
-    pub seller_account: Account<'info, UserAccount>,
+    pub treasury_vault: Option<Box<Account<'info, TokenAccount>>>,
+    pub token_program: Option<Program<'info, Token>>,
+    pub underlying_price_feed: Account<'info, PriceFeed>,
+    pub sol_price_feed: Account<'info, PriceFeed>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct Settle<'info> {
+pub struct SettleStrategy<'info> {
     pub caller: Signer<'info>,
     #[account(
-        mut,
-        seeds = [
-            b"contract",
-            contract.buyer.as_ref(),
-            contract.seller.as_ref(),
-            contract.contract_id.to_le_bytes().as_ref()
-        ],
-        bump = contract.bump
+        seeds = [b"market_config"],
+        bump = market_config.bump,
+        constraint = !market_config.paused @ ErrorCode::MarketPaused
     )]
-    pub contract: Account<'info, OptionContract>,
+    pub market_config: Account<'info, MarketConfig>,
     #[account(
         mut,
-        seeds = [b"user", contract.buyer.as_ref()],
-        bump = buyer_account.bump
+        seeds = [
+            b"strategy",
+            strategy.buyer.as_ref(),
+            strategy.seller.as_ref(),
+            strategy.strategy_id.to_le_bytes().as_ref()
+        ],
+        bump = strategy.bump
     )]
-    pub buyer_account: Account<'info, UserAccount>,
+    pub strategy: Account<'info, OptionStrategy>,
     #[account(
         mut,
-        seeds = [b"user", contract.seller.as_ref()],
-        bump = seller_account.bump
+        seeds = [b"collateral", strategy.key().as_ref()],
+        bump = collateral.bump
     )]
-    pub seller_account: Account<'info, UserAccount>,
+    pub collateral: Account<'info, ContractCollateral>,
     #[account(
         mut,
-        seeds = [b"escrow", contract.buyer.as_ref()],
+        seeds = [b"escrow", strategy.buyer.as_ref()],
         bump
     )]
     /// CHECK: This is a PDA used as an escrow account
     pub buyer_escrow: AccountInfo<'info>,
     #[account(
         mut,
-        seeds = [b"escrow", contract.seller.as_ref()],
+        seeds = [b"escrow", strategy.seller.as_ref()],
         bump
     )]
     /// CHECK: This is a PDA used as an escrow account
     pub seller_escrow: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", strategy.key().as_ref()],
+        bump
+    )]
+    pub collateral_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    #[account(
+        mut,
+        seeds = [b"treasury_vault", treasury_vault.mint.as_ref()],
+        bump,
+        constraint = Some(treasury_vault.mint) == strategy.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub treasury_vault: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        token::authority = buyer_escrow,
+        constraint = Some(buyer_token_escrow.mint) == strategy.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub buyer_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        token::authority = seller_escrow,
+        constraint = Some(seller_token_escrow.mint) == strategy.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub seller_token_escrow: Option<Box<Account<'info, TokenAccount>>>,
+    // Covers what a defaulting side cannot pay, when passed
+    #[account(mut, seeds = [b"insurance_fund"], bump = insurance_fund.bump)]
+    pub insurance_fund: Option<Account<'info, InsuranceFund>>,
+    #[account(
+        mut,
+        seeds = [b"insurance_vault", insurance_vault.mint.as_ref()],
+        bump,
+        constraint = Some(insurance_vault.mint) == strategy.collateral_mint
+            @ ErrorCode::CollateralMintMismatch
+    )]
+    pub insurance_vault: Option<Box<Account<'info, TokenAccount>>>,
+    pub token_program: Option<Program<'info, Token>>,
+    #[account(address = strategy.underlying_price_feed @ ErrorCode::PriceFeedMismatch)]
+    pub underlying_price_feed: Account<'info, PriceFeed>,
+    #[account(address = strategy.sol_price_feed @ ErrorCode::PriceFeedMismatch)]
+    pub sol_price_feed: Account<'info, PriceFeed>,
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct CloseStrategy<'info> {
+    pub caller: Signer<'info>,
+    /// CHECK: Receives the reclaimed rent, validated against the strategy
+    #[account(mut, address = strategy.rent_payer)]
+    pub rent_payer: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = rent_payer,
+        seeds = [
+            b"strategy",
+            strategy.buyer.as_ref(),
+            strategy.seller.as_ref(),
+            strategy.strategy_id.to_le_bytes().as_ref()
+        ],
+        bump = strategy.bump
+    )]
+    pub strategy: Account<'info, OptionStrategy>,
+    #[account(
+        mut,
+        close = rent_payer,
+        seeds = [b"collateral", strategy.key().as_ref()],
+        bump = collateral.bump
+    )]
+    pub collateral: Account<'info, ContractCollateral>,
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", strategy.key().as_ref()],
+        bump
+    )]
+    pub collateral_vault: Option<Box<Account<'info, TokenAccount>>>,
+    pub token_program: Option<Program<'info, Token>>,
+}
+
 #[derive(Accounts)]
 pub struct Withdraw<'info> {
     #[account(mut)]
@@ -537,6 +5261,26 @@ pub struct Withdraw<'info> {
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+pub struct WithdrawTokens<'info> {
+    pub user: Signer<'info>,
+    #[account(
+        seeds = [b"escrow", user.key().as_ref()],
+        bump
+    )]
+    /// CHECK: This is a PDA used as an escrow account
+    pub user_escrow: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"token_escrow", user.key().as_ref(), token_escrow.mint.as_ref()],
+        bump
+    )]
+    pub token_escrow: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub user_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
 #[account]
 #[derive(InitSpace)]
 pub struct OptionContract {
@@ -549,6 +5293,7 @@ pub struct OptionContract {
     pub strike_price: u64,
     pub expiration_date: i64,
     pub option_type: OptionType,
+    pub exercise_style: ExerciseStyle,
     pub premium: u64,
     pub buyer: Pubkey,
     pub seller: Pubkey,
@@ -559,7 +5304,305 @@ pub struct OptionContract {
     pub status: ContractStatus,
     pub margin_requirement_bps: u16,
     pub margin_amount: u64,
-    pub is_test: bool,
+    pub underlying_price_feed: Pubkey,
+    pub sol_price_feed: Pubkey,
+    pub rent_payer: Pubkey,
+    pub buyer_page: u32,
+    pub seller_page: u32,
+    pub collateral_mint: Option<Pubkey>,
+    pub collateral_decimals: u8,
+    pub units_exercised: u64,
+    // Buyer at creation; seeds the contract PDA after the position is transferred
+    pub original_buyer: Pubkey,
+    // Margin required at the last mark, in the contract's collateral units
+    pub required_margin: u64,
+    // Set while the locked margin is short of `required_margin`
+    pub margin_call_deadline: Option<i64>,
+    // Settlement fee rate fixed when the contract was opened
+    pub settlement_fee_bps: u16,
+    // Fees paid to the treasury so far, in the contract's collateral units
+    pub premium_fee: u64,
+    pub settlement_fee: u64,
+    pub settlement_kind: SettlementKind,
+    // Token delivered by physically settled contracts, one whole token per unit
+    pub underlying_mint: Option<Pubkey>,
+    pub underlying_decimals: u8,
+    // Strike and settlement prices are in units of 10^price_expo USD
+    pub price_expo: i32,
+    // Part of the settled payoff the seller could not cover, and how much of it
+    // the insurance fund paid; both zero unless the contract defaulted
+    pub default_shortfall: u64,
+    pub paid_from_insurance: u64,
+    // Keeper bounty rate fixed when the contract was opened
+    pub crank_fee_bps: u16,
+}
+
+impl OptionContract {
+    // Checks that `buyer` may exercise the contract at `current_time`
+    pub fn check_exercisable(&self, buyer: Pubkey, current_time: i64) -> Result<()> {
+        require!(
+            self.status == ContractStatus::Active,
+            ErrorCode::ContractNotActive
+        );
+        require!(self.buyer == buyer, ErrorCode::UnauthorizedExercise);
+
+        // American contracts can be exercised at any time before the window
+        // closes, European contracts only once they have expired
+        if self.exercise_style == ExerciseStyle::European {
+            require!(
+                current_time >= self.expiration_date,
+                ErrorCode::ContractNotExpired
+            );
+        }
+        let exercise_deadline = self
+            .expiration_date
+            .checked_add(EXERCISE_WINDOW_SECONDS)
+            .ok_or(ErrorCode::CalculationError)?;
+        require!(
+            current_time < exercise_deadline,
+            ErrorCode::ExerciseWindowClosed
+        );
+
+        Ok(())
+    }
+
+    pub fn unexercised_units(&self) -> u64 {
+        self.num_units - self.units_exercised
+    }
+
+    pub fn check_settlement_kind(&self, settlement_kind: SettlementKind) -> Result<()> {
+        require!(
+            self.settlement_kind == settlement_kind,
+            ErrorCode::SettlementKindMismatch
+        );
+        Ok(())
+    }
+
+    // Physical calls hold the seller's underlying in the underlying vault
+    pub fn locks_underlying(&self) -> bool {
+        self.settlement_kind == SettlementKind::Physical && self.option_type == OptionType::Call
+    }
+}
+
+// Holds the seller's margin for a single contract until it is settled, or
+// both margins of a strategy
+#[account]
+#[derive(InitSpace)]
+pub struct ContractCollateral {
+    pub bump: u8,
+    // Contract or strategy the collateral belongs to
+    pub contract: Pubkey,
+}
+
+// Legs on one underlying and expiry between the same counterparties, opened,
+// margined and settled together
+#[account]
+#[derive(InitSpace)]
+pub struct OptionStrategy {
+    pub bump: u8,
+    // Taken from the buyer's contract counter, like a contract id
+    pub strategy_id: u64,
+    pub creation_date: i64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    #[max_len(32)]
+    pub underlying_asset: String,
+    pub expiration_date: i64,
+    #[max_len(4)]
+    pub legs: Vec<StrategyLeg>,
+    // Net premium paid by the buyer for every leg
+    pub premium: u64,
+    pub margin_requirement_bps: u16,
+    // Maximum loss of each side, locked in the collateral at creation
+    pub buyer_margin: u64,
+    pub seller_margin: u64,
+    pub underlying_price_feed: Pubkey,
+    pub sol_price_feed: Pubkey,
+    pub collateral_mint: Option<Pubkey>,
+    pub collateral_decimals: u8,
+    pub status: ContractStatus,
+    pub rent_payer: Pubkey,
+    pub settlement_fee_bps: u16,
+    pub premium_fee: u64,
+    pub settlement_fee: u64,
+    // Precision of every leg's strike, as for contracts
+    pub price_expo: i32,
+    // Part of the net payoff the paying side could not cover, and how much of
+    // it the insurance fund paid; both zero unless the strategy defaulted
+    pub default_shortfall: u64,
+    pub paid_from_insurance: u64,
+}
+
+impl OptionStrategy {
+    pub fn check_counterparty(&self, caller: Pubkey) -> Result<()> {
+        require!(
+            caller == self.buyer || caller == self.seller,
+            ErrorCode::NotStrategyCounterparty
+        );
+        Ok(())
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct OptionOffer {
+    pub bump: u8,
+    pub offer_id: u64,
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub created_at: i64,
+    pub valid_until: i64,
+    #[max_len(32)]
+    pub underlying_asset: String,
+    pub num_units: u64,
+    pub strike_price: u64,
+    pub expiration_date: i64,
+    pub option_type: OptionType,
+    pub exercise_style: ExerciseStyle,
+    pub premium: u64,
+    pub margin_requirement_bps: u16,
+    pub underlying_price_feed: Pubkey,
+    pub sol_price_feed: Pubkey,
+    pub collateral_mint: Option<Pubkey>,
+    pub underlying_mint: Option<Pubkey>,
+    pub price_expo: i32,
+}
+
+// Standing ask posted by a seller, filled by buyers in one or more contracts
+#[account]
+#[derive(InitSpace)]
+pub struct Quote {
+    pub bump: u8,
+    pub quote_id: u64,
+    pub seller: Pubkey,
+    pub created_at: i64,
+    pub valid_until: i64,
+    #[max_len(32)]
+    pub underlying_asset: String,
+    pub strike_price: u64,
+    pub expiration_date: i64,
+    pub option_type: OptionType,
+    pub exercise_style: ExerciseStyle,
+    pub premium_per_unit: u64,
+    pub remaining_units: u64,
+    pub margin_requirement_bps: u16,
+    pub underlying_price_feed: Pubkey,
+    pub sol_price_feed: Pubkey,
+    pub collateral_mint: Option<Pubkey>,
+    pub underlying_mint: Option<Pubkey>,
+    pub price_expo: i32,
+}
+
+// Market-wide limits and pause switch, seeded by [b"market_config"]
+#[account]
+#[derive(InitSpace)]
+pub struct MarketConfig {
+    pub bump: u8,
+    pub admin: Pubkey,
+    pub pending_admin: Option<Pubkey>,
+    // Publisher of the price feeds new contracts can settle against
+    pub oracle_authority: Pubkey,
+    pub paused: bool,
+    pub min_margin_bps: u16,
+    pub max_margin_bps: u16,
+    // Bounds on the time between contract creation and expiration
+    pub min_expiry_seconds: i64,
+    pub max_expiry_seconds: i64,
+    // Protocol fees on premiums and on settled payoffs
+    pub premium_fee_bps: u16,
+    pub settlement_fee_bps: u16,
+    // Bounty paid to the keeper that cranks an expired contract
+    pub crank_fee_bps: u16,
+}
+
+// Collects protocol fees, seeded by [b"treasury"]; token fees are held in
+// treasury vaults seeded by [b"treasury_vault", mint]
+#[account]
+#[derive(InitSpace)]
+pub struct Treasury {
+    pub bump: u8,
+}
+
+// Covers payoffs defaulted sellers cannot pay, seeded by [b"insurance_fund"].
+// It is funded with collected fees; token funds are held in insurance vaults
+// seeded by [b"insurance_vault", mint].
+#[account]
+#[derive(InitSpace)]
+pub struct InsuranceFund {
+    pub bump: u8,
+}
+
+// Price precision of an underlying asset, seeded by [b"underlying_asset", ticker]
+#[account]
+#[derive(InitSpace)]
+pub struct UnderlyingAsset {
+    pub bump: u8,
+    #[max_len(32)]
+    pub ticker: String,
+    pub price_expo: i32,
+}
+
+impl MarketConfig {
+    pub fn set_limits(
+        &mut self,
+        min_margin_bps: u16,
+        max_margin_bps: u16,
+        min_expiry_seconds: i64,
+        max_expiry_seconds: i64,
+    ) -> Result<()> {
+        require!(
+            min_margin_bps <= max_margin_bps && min_expiry_seconds <= max_expiry_seconds,
+            ErrorCode::InvalidMarketConfig
+        );
+
+        self.min_margin_bps = min_margin_bps;
+        self.max_margin_bps = max_margin_bps;
+        self.min_expiry_seconds = min_expiry_seconds;
+        self.max_expiry_seconds = max_expiry_seconds;
+        Ok(())
+    }
+
+    // Checks the margin requirement and expiry of a new contract or strategy
+    fn check_terms(
+        &self,
+        margin_requirement_bps: u16,
+        expiration_date: i64,
+        current_time: i64,
+    ) -> Result<()> {
+        require!(
+            (self.min_margin_bps..=self.max_margin_bps).contains(&margin_requirement_bps),
+            ErrorCode::MarginOutOfRange
+        );
+
+        let expiry_horizon = expiration_date
+            .checked_sub(current_time)
+            .ok_or(ErrorCode::CalculationError)?;
+        require!(
+            (self.min_expiry_seconds..=self.max_expiry_seconds).contains(&expiry_horizon),
+            ErrorCode::ExpiryOutOfRange
+        );
+
+        Ok(())
+    }
+
+    // Checks that the feeds of a new contract or strategy are the market
+    // oracle's quotes for its ticker and for SOL, so neither party can settle
+    // against a feed they publish themselves
+    fn check_price_feeds(
+        &self,
+        ticker: &str,
+        underlying_price_feed: &PriceFeed,
+        sol_price_feed: &PriceFeed,
+    ) -> Result<()> {
+        require!(
+            underlying_price_feed.authority == self.oracle_authority
+                && underlying_price_feed.symbol == ticker
+                && sol_price_feed.authority == self.oracle_authority
+                && sol_price_feed.symbol == SOL_SYMBOL,
+            ErrorCode::UntrustedPriceFeed
+        );
+        Ok(())
+    }
 }
 
 #[account]
@@ -568,31 +5611,137 @@ pub struct UserAccount {
     pub bump: u8,
     pub owner: Pubkey,
     pub contract_count: u64,
-    #[max_len(100)]
+    pub entry_count: u64,
+    pub page_count: u32,
+}
+
+impl UserAccount {
+    // Page that the next index entry is appended to
+    pub fn tail_page(&self) -> u32 {
+        (self.entry_count / USER_PAGE_SIZE as u64) as u32
+    }
+
+    // Appends an entry to the tail page, initializing the page on first use
+    pub fn push_contract(
+        &mut self,
+        page: &mut UserContractPage,
+        page_bump: u8,
+        entry: UserContract,
+    ) -> u32 {
+        let page_no = self.tail_page();
+        if page.owner == Pubkey::default() {
+            page.bump = page_bump;
+            page.owner = self.owner;
+            page.page_no = page_no;
+            self.page_count = page_no + 1;
+        }
+
+        page.contracts.push(entry);
+        self.entry_count += 1;
+        page_no
+    }
+}
+
+// One page of a user's contract index, seeded by [b"user_page", owner, page_no]
+#[account]
+#[derive(InitSpace)]
+pub struct UserContractPage {
+    pub bump: u8,
+    pub owner: Pubkey,
+    pub page_no: u32,
+    #[max_len(32)]
     pub contracts: Vec<UserContract>,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, InitSpace)]
+impl UserContractPage {
+    pub fn set_contract_status(&mut self, contract: Pubkey, status: ContractStatus) {
+        if let Some(user_contract) = self
+            .contracts
+            .iter_mut()
+            .find(|user_contract| user_contract.contract_address == contract)
+        {
+            user_contract.status = status;
+        }
+    }
+
+    pub fn remove_contract(&mut self, contract: Pubkey) {
+        self.contracts.retain(|user_contract| user_contract.contract_address != contract);
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, InitSpace)]
 pub struct UserContract {
     pub contract_address: Pubkey,
     pub role: UserRole,
     pub status: ContractStatus,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, InitSpace)]
 pub enum OptionType {
     Call,
     Put,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, InitSpace)]
+pub enum ExerciseStyle {
+    European,
+    American,
+}
+
+// How exercised contracts are settled: cash contracts pay the intrinsic value
+// in the collateral, physical contracts deliver the underlying for the strike
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, InitSpace)]
+pub enum SettlementKind {
+    Cash,
+    Physical,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, InitSpace)]
 pub enum ContractStatus {
     Active,
     Exercised,
     Settled,
+    Expired,
+    Liquidated,
+    // Settled with a shortfall the seller could not cover
+    Defaulted,
+    // Unwound early by agreement of both sides
+    Terminated,
+}
+
+// One option of a strategy, held long or short by the strategy buyer
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, InitSpace)]
+pub struct StrategyLeg {
+    pub option_type: OptionType,
+    pub side: LegSide,
+    pub strike_price: u64,
+    pub num_units: u64,
+}
+
+impl StrategyLeg {
+    // Payoff to the strategy buyer at `price`, at the strategy's price precision
+    pub fn payoff(&self, price: u64) -> Result<i128> {
+        let intrinsic = match self.option_type {
+            OptionType::Call => price.saturating_sub(self.strike_price),
+            OptionType::Put => self.strike_price.saturating_sub(price),
+        };
+        let payoff = (intrinsic as i128)
+            .checked_mul(self.num_units as i128)
+            .ok_or(ErrorCode::CalculationError)?;
+        Ok(match self.side {
+            LegSide::Long => payoff,
+            LegSide::Short => -payoff,
+        })
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, InitSpace)]
+pub enum LegSide {
+    Long,
+    Short,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, InitSpace)]
 pub enum UserRole {
     Buyer,
     Seller,
@@ -616,10 +5765,94 @@ pub enum ErrorCode {
     InsufficientSellerEscrow,
     #[msg("Calculation error: overflow or division by zero")]
     CalculationError,
+    // No longer returned since user indexes are paged. Kept so later error
+    // codes keep their numbers.
     #[msg("Maximum number of contracts reached")]
     MaxContractsReached,
     #[msg("Asset ticker exceeds maximum length")]
     AssetTickerTooLong,
     #[msg("Deposit amount must be greater than zero")]
     InvalidDepositAmount,
+    #[msg("Price feed does not match the one recorded on the contract")]
+    PriceFeedMismatch,
+    #[msg("Oracle price must be positive")]
+    InvalidOraclePrice,
+    #[msg("Oracle price is stale")]
+    StaleOraclePrice,
+    #[msg("Oracle price confidence interval is too wide")]
+    OracleConfidenceTooWide,
+    #[msg("Signer is not a party to this offer")]
+    OfferPartyMismatch,
+    #[msg("Contract cannot be expired until the exercise window has closed")]
+    GracePeriodNotElapsed,
+    #[msg("Contract must be settled, expired or liquidated before it can be closed")]
+    ContractNotFinished,
+    #[msg("Only the buyer or seller can close the contract")]
+    UnauthorizedClose,
+    #[msg("Exercise window for this contract has closed")]
+    ExerciseWindowClosed,
+    #[msg("Token accounts are required for contracts collateralized with a mint")]
+    MissingTokenAccount,
+    #[msg("Token account mint does not match the contract collateral mint")]
+    CollateralMintMismatch,
+    #[msg("Units to exercise must be positive and no more than the unexercised units")]
+    InvalidExerciseUnits,
+    #[msg("Only the current holder can transfer the position")]
+    UnauthorizedTransfer,
+    #[msg("Position can only be transferred to a user other than the holder and seller")]
+    InvalidTransferRecipient,
+    #[msg("Quote is no longer valid")]
+    QuoteExpired,
+    #[msg("Quote cannot be closed before it expires")]
+    QuoteNotExpired,
+    #[msg("Units must be positive and no more than the units left on the quote")]
+    InvalidQuoteUnits,
+    #[msg("Only the seller can top up the contract margin")]
+    UnauthorizedTopUp,
+    #[msg("Contract has no outstanding margin call")]
+    NoMarginCall,
+    #[msg("Margin call deadline has not passed")]
+    MarginCallNotDue,
+    #[msg("Contract margin covers the current requirement")]
+    ContractNotUndercollateralized,
+    #[msg("Signer is not the market admin")]
+    UnauthorizedAdmin,
+    #[msg("Minimum market limits must not exceed the maximums")]
+    InvalidMarketConfig,
+    #[msg("Market is paused")]
+    MarketPaused,
+    #[msg("Margin requirement is outside the market limits")]
+    MarginOutOfRange,
+    #[msg("Expiration date is outside the market limits")]
+    ExpiryOutOfRange,
+    #[msg("Protocol fee exceeds the maximum")]
+    FeeTooHigh,
+    #[msg("Insufficient balance in the treasury")]
+    InsufficientTreasuryBalance,
+    #[msg("Instruction does not support the contract's settlement kind")]
+    SettlementKindMismatch,
+    #[msg("Token account mint does not match the contract underlying mint")]
+    UnderlyingMintMismatch,
+    #[msg("Physically settled contracts must be collateralized with a mint")]
+    PhysicalSettlementRequiresMint,
+    #[msg("Strategies need 2 to 4 legs, each with units and a strike")]
+    InvalidStrategyLegs,
+    #[msg("Only the strategy's buyer or seller can settle it")]
+    NotStrategyCounterparty,
+    #[msg("Price exponent must be between -8 and 0")]
+    PriceExpoOutOfRange,
+    #[msg("Only the buyer can settle a default without the insurance fund")]
+    InsuranceFundRequired,
+    #[msg("Both the buyer and the seller must sign to terminate the contract")]
+    UnauthorizedTermination,
+    #[msg("Offer is no longer valid")]
+    OfferExpired,
+    #[msg("Price feed is not the market oracle's feed for the asset")]
+    UntrustedPriceFeed,
+    #[msg("Oracle price is published after the current time")]
+    FutureOraclePrice,
+    #[msg("Contract is in the money and must be exercised, not expired")]
+    ContractInTheMoney,
+    #[msg("User account does not belong to the contract party")]
+    UserAccountMismatch,
 }
@@ -0,0 +1,41 @@
+use anchor_lang::prelude::*;
+
+// Offset added to the cluster time, seeded by [b"test_clock"]. Instructions
+// that read the time pick it up when it is passed as a remaining account.
+#[account]
+#[derive(InitSpace)]
+pub struct TestClock {
+    pub bump: u8,
+    pub offset_seconds: i64,
+}
+
+#[derive(Accounts)]
+pub struct WarpClock<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + TestClock::INIT_SPACE,
+        seeds = [b"test_clock"],
+        bump
+    )]
+    pub test_clock: Account<'info, TestClock>,
+    pub system_program: Program<'info, System>,
+}
+
+// Cluster time shifted by the test clock when it is among `remaining_accounts`
+pub fn current_time(remaining_accounts: &[AccountInfo]) -> Result<i64> {
+    let now = Clock::get()?.unix_timestamp;
+    let (test_clock_key, _) = Pubkey::find_program_address(&[b"test_clock"], &crate::ID);
+    let Some(account) = remaining_accounts
+        .iter()
+        .find(|account| account.key() == test_clock_key && account.owner == &crate::ID)
+    else {
+        return Ok(now);
+    };
+
+    let test_clock = TestClock::try_deserialize(&mut &account.try_borrow_data()?[..])?;
+    now.checked_add(test_clock.offset_seconds)
+        .ok_or_else(|| error!(crate::ErrorCode::CalculationError))
+}
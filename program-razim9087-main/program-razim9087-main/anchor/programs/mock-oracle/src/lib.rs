@@ -0,0 +1,105 @@
+use anchor_lang::prelude::*;
+
+declare_id!("E4xdzXj7XNheBdhJJEcDGLR8Qu6xxXqzEbu7bNNRwRdz");
+
+const MAX_SYMBOL_LENGTH: usize = 32;
+
+/// Local stand-in for a Pyth-style price oracle. Anyone can create a feed and
+/// the feed authority can push arbitrary quotes into it, so this program is
+/// only meant for localnet and tests.
+#[program]
+pub mod mock_oracle {
+    use super::*;
+
+    pub fn initialize_feed(
+        ctx: Context<InitializeFeed>,
+        symbol: String,
+        price: i64,
+        conf: u64,
+        expo: i32,
+    ) -> Result<()> {
+        require!(
+            symbol.len() <= MAX_SYMBOL_LENGTH,
+            OracleError::SymbolTooLong
+        );
+
+        let feed = &mut ctx.accounts.price_feed;
+        feed.bump = ctx.bumps.price_feed;
+        feed.authority = ctx.accounts.authority.key();
+        feed.symbol = symbol;
+        feed.price = price;
+        feed.conf = conf;
+        feed.expo = expo;
+        feed.publish_time = Clock::get()?.unix_timestamp;
+        Ok(())
+    }
+
+    /// Pushes a new quote. `publish_time` defaults to the current cluster time
+    /// and can be overridden to simulate stale quotes.
+    pub fn update_price(
+        ctx: Context<UpdatePrice>,
+        price: i64,
+        conf: u64,
+        expo: i32,
+        publish_time: Option<i64>,
+    ) -> Result<()> {
+        let feed = &mut ctx.accounts.price_feed;
+        feed.price = price;
+        feed.conf = conf;
+        feed.expo = expo;
+        feed.publish_time = match publish_time {
+            Some(timestamp) => timestamp,
+            None => Clock::get()?.unix_timestamp,
+        };
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(symbol: String)]
+pub struct InitializeFeed<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + PriceFeed::INIT_SPACE,
+        seeds = [b"price_feed", authority.key().as_ref(), symbol.as_bytes()],
+        bump
+    )]
+    pub price_feed: Account<'info, PriceFeed>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePrice<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        has_one = authority @ OracleError::UnauthorizedUpdate
+    )]
+    pub price_feed: Account<'info, PriceFeed>,
+}
+
+/// Quote layout modelled on a Pyth price account: the price is
+/// `price * 10^expo` and `conf` is the confidence interval in the same units.
+#[account]
+#[derive(InitSpace)]
+pub struct PriceFeed {
+    pub bump: u8,
+    pub authority: Pubkey,
+    #[max_len(32)]
+    pub symbol: String,
+    pub price: i64,
+    pub conf: u64,
+    pub expo: i32,
+    pub publish_time: i64,
+}
+
+#[error_code]
+pub enum OracleError {
+    #[msg("Feed symbol exceeds maximum length")]
+    SymbolTooLong,
+    #[msg("Only the feed authority can update the price")]
+    UnauthorizedUpdate,
+}
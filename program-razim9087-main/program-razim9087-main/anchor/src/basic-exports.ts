@@ -10,11 +10,65 @@ export { Basic, BasicIDL }
 // The programId is imported from the program IDL.
 export const BASIC_PROGRAM_ID = new PublicKey(BasicIDL.address)
 
+// Program ID of the local stand-in price oracle (see programs/mock-oracle).
+export const MOCK_ORACLE_PROGRAM_ID = new PublicKey('E4xdzXj7XNheBdhJJEcDGLR8Qu6xxXqzEbu7bNNRwRdz')
+
 // This is a helper function to get the Basic Anchor program.
 export function getBasicProgram(provider: AnchorProvider, address?: PublicKey): Program<Basic> {
   return new Program({ ...BasicIDL, address: address ? address.toBase58() : BasicIDL.address } as Basic, provider)
 }
 
+// Number of contract entries stored on each user index page.
+export const USER_PAGE_SIZE = 32
+
+// This is a helper function to derive a page of a user's contract index.
+export function getUserPageAddress(owner: PublicKey, pageNo: number, programId = BASIC_PROGRAM_ID) {
+  const pageBuffer = Buffer.alloc(4)
+  pageBuffer.writeUInt32LE(pageNo)
+  return PublicKey.findProgramAddressSync([Buffer.from('user_page'), owner.toBuffer(), pageBuffer], programId)[0]
+}
+
+// This is a helper function to list a user's contract index entries, one page at a time.
+export async function fetchUserContracts(program: Program<Basic>, owner: PublicKey) {
+  const [userAccountAddress] = PublicKey.findProgramAddressSync(
+    [Buffer.from('user'), owner.toBuffer()],
+    program.programId
+  )
+  const userAccount = await program.account.userAccount.fetch(userAccountAddress)
+  const entries = []
+  for (let pageNo = 0; pageNo < userAccount.pageCount; pageNo++) {
+    const page = await program.account.userContractPage.fetch(getUserPageAddress(owner, pageNo, program.programId))
+    entries.push(...page.contracts)
+  }
+  return entries
+}
+
+// This is a helper function to derive a user's token escrow for `mint`, owned by their escrow PDA.
+export function getTokenEscrowAddress(owner: PublicKey, mint: PublicKey, programId = BASIC_PROGRAM_ID) {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('token_escrow'), owner.toBuffer(), mint.toBuffer()],
+    programId
+  )[0]
+}
+
+// This is a helper function to derive the token vault holding a contract's margin.
+export function getCollateralVaultAddress(contract: PublicKey, programId = BASIC_PROGRAM_ID) {
+  return PublicKey.findProgramAddressSync([Buffer.from('collateral_vault'), contract.toBuffer()], programId)[0]
+}
+
+// This is a helper function to derive the market-wide config holding the admin, limits and pause flag.
+export function getMarketConfigAddress(programId = BASIC_PROGRAM_ID) {
+  return PublicKey.findProgramAddressSync([Buffer.from('market_config')], programId)[0]
+}
+
+// This is a helper function to derive the price feed published by `authority` for `symbol`.
+export function getPriceFeedAddress(authority: PublicKey, symbol: string, oracleProgramId = MOCK_ORACLE_PROGRAM_ID) {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from('price_feed'), authority.toBuffer(), Buffer.from(symbol)],
+    oracleProgramId
+  )[0]
+}
+
 // This is a helper function to get the program ID for the Basic program depending on the cluster.
 export function getBasicProgramId(cluster: Cluster) {
   switch (cluster) {
@@ -1,6 +1,8 @@
 import * as anchor from '@coral-xyz/anchor'
 import type { Basic } from '../target/types/basic.js'
+import type { MockOracle } from '../target/types/mock_oracle.js'
 import { PublicKey, Keypair, SystemProgram, LAMPORTS_PER_SOL } from '@solana/web3.js'
+import { TOKEN_PROGRAM_ID, createAssociatedTokenAccount, createMint, getAccount, mintTo } from '@solana/spl-token'
 import { BN } from 'bn.js'
 import { expect } from 'chai'
 
@@ -9,6 +11,7 @@ describe('OTC Options Trading', () => {
   anchor.setProvider(provider)
 
   const program = anchor.workspace.Basic as anchor.Program<Basic>
+  const oracle = anchor.workspace.MockOracle as anchor.Program<MockOracle>
   
   // Test keypairs
   let buyer: Keypair
@@ -19,6 +22,7 @@ describe('OTC Options Trading', () => {
   let sellerAccount: PublicKey
   let buyerEscrow: PublicKey
   let sellerEscrow: PublicKey
+  let solPriceFeed: PublicKey
   
   // Helper function to get PDAs
   const getUserAccountPDA = (user: PublicKey): [PublicKey, number] => {
@@ -45,6 +49,177 @@ describe('OTC Options Trading', () => {
     )
   }
   
+  const getPriceFeedPDA = (symbol: string): [PublicKey, number] => {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from('price_feed'), provider.wallet.publicKey.toBuffer(), Buffer.from(symbol)],
+      oracle.programId
+    )
+  }
+  
+  // Helper to publish an oracle quote in cents, creating the feed on first use
+  const setPrice = async (
+    symbol: string,
+    price: BN,
+    options: { conf?: BN; publishTime?: BN } = {}
+  ): Promise<PublicKey> => {
+    const [priceFeed] = getPriceFeedPDA(symbol)
+    const conf = options.conf ?? new BN(1)
+    const existing = await provider.connection.getAccountInfo(priceFeed)
+    
+    if (!existing) {
+      await oracle.methods
+        .initializeFeed(symbol, price, conf, -2)
+        .accounts({
+          authority: provider.wallet.publicKey,
+          priceFeed,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc()
+    }
+    
+    await oracle.methods
+      .updatePrice(price, conf, -2, options.publishTime ?? null)
+      .accounts({
+        authority: provider.wallet.publicKey,
+        priceFeed,
+      })
+      .rpc()
+    
+    return priceFeed
+  }
+  
+  const USER_PAGE_SIZE = 32
+  
+  const getUserPagePDA = (owner: PublicKey, pageNo: number): [PublicKey, number] => {
+    const pageBuffer = Buffer.alloc(4)
+    pageBuffer.writeUInt32LE(pageNo)
+    
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from('user_page'), owner.toBuffer(), pageBuffer],
+      program.programId
+    )
+  }
+  
+  // New index entries are appended to the page after the last full one
+  const getTailPagePDA = async (owner: PublicKey): Promise<PublicKey> => {
+    const userAccount = await program.account.userAccount.fetch(getUserAccountPDA(owner)[0])
+    return getUserPagePDA(owner, Math.floor(userAccount.entryCount.toNumber() / USER_PAGE_SIZE))[0]
+  }
+  
+  // Index pages that hold a contract's buyer and seller entries
+  const getContractPagePDAs = async (contractPDA: PublicKey) => {
+    const contract = await program.account.optionContract.fetch(contractPDA)
+    return {
+      buyerPage: getUserPagePDA(contract.buyer, contract.buyerPage)[0],
+      sellerPage: getUserPagePDA(contract.seller, contract.sellerPage)[0],
+    }
+  }
+  
+  // Walks every index page of a user
+  const fetchUserContracts = async (owner: PublicKey) => {
+    const userAccount = await program.account.userAccount.fetch(getUserAccountPDA(owner)[0])
+    const pages = await Promise.all(
+      Array.from({ length: userAccount.pageCount }, (_, pageNo) =>
+        program.account.userContractPage.fetch(getUserPagePDA(owner, pageNo)[0])
+      )
+    )
+    return pages.flatMap((page) => page.contracts)
+  }
+  
+  const getCollateralPDA = (contract: PublicKey): [PublicKey, number] => {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from('collateral'), contract.toBuffer()],
+      program.programId
+    )
+  }
+  
+  const getOfferPDA = (buyer: PublicKey, seller: PublicKey, offerId: number): [PublicKey, number] => {
+    const idBuffer = Buffer.alloc(8)
+    idBuffer.writeBigUInt64LE(BigInt(offerId))
+    
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from('offer'), buyer.toBuffer(), seller.toBuffer(), idBuffer],
+      program.programId
+    )
+  }
+  
+  const getQuotePDA = (seller: PublicKey, quoteId: number): [PublicKey, number] => {
+    const idBuffer = Buffer.alloc(8)
+    idBuffer.writeBigUInt64LE(BigInt(quoteId))
+    
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from('quote'), seller.toBuffer(), idBuffer],
+      program.programId
+    )
+  }
+  
+  const getTokenEscrowPDA = (user: PublicKey, mint: PublicKey): [PublicKey, number] => {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from('token_escrow'), user.toBuffer(), mint.toBuffer()],
+      program.programId
+    )
+  }
+  
+  const getMarketConfigPDA = (): [PublicKey, number] => {
+    return PublicKey.findProgramAddressSync([Buffer.from('market_config')], program.programId)
+  }
+  
+  const getTreasuryPDA = (): [PublicKey, number] => {
+    return PublicKey.findProgramAddressSync([Buffer.from('treasury')], program.programId)
+  }
+  
+  const getUnderlyingAssetPDA = (ticker: string): [PublicKey, number] => {
+    return PublicKey.findProgramAddressSync([Buffer.from('underlying_asset'), Buffer.from(ticker)], program.programId)
+  }
+  
+  // Tickers traded by the suite, all priced in cents
+  const TICKERS = ['AAPL', 'ADBE', 'AMD', 'AMZN', 'CSCO', 'GOOGL', 'IBM', 'INTC', 'META', 'MSFT', 'NFLX', 'NVDA', 'ORCL', 'QCOM', 'TSLA']
+  
+  // Only present in builds with the `test-clock` feature
+  const getTestClockPDA = (): [PublicKey, number] => {
+    return PublicKey.findProgramAddressSync([Buffer.from('test_clock')], program.programId)
+  }
+  
+  // Shifts the program's clock for instructions given the test clock account
+  const warpClock = async (offsetSeconds: number) =>
+    program.methods
+      .warpClock(new BN(offsetSeconds))
+      .accounts({
+        payer: provider.wallet.publicKey,
+        testClock: getTestClockPDA()[0],
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc()
+  
+  const BPF_LOADER_UPGRADEABLE_ID = new PublicKey('BPFLoaderUpgradeab1e11111111111111111111111')
+  
+  // Market limits used by the suite; backdated expirations are allowed so the
+  // expiry paths can be tested without waiting on the clock
+  const MIN_MARGIN_BPS = 500
+  const MAX_MARGIN_BPS = 5000
+  const MIN_EXPIRY_SECONDS = new BN(-3 * 86400)
+  const MAX_EXPIRY_SECONDS = new BN(30 * 86400)
+  
+  const getCollateralVaultPDA = (contract: PublicKey): [PublicKey, number] => {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from('collateral_vault'), contract.toBuffer()],
+      program.programId
+    )
+  }
+  
+  const closeContract = async (contractPDA: PublicKey, caller: Keypair, rentPayer: PublicKey) =>
+    program.methods
+      .closeContract()
+      .accounts({
+        caller: caller.publicKey,
+        rentPayer,
+        contract: contractPDA,
+        collateral: getCollateralPDA(contractPDA)[0],
+        ...(await getContractPagePDAs(contractPDA)),
+      })
+      .signers([caller])
+      .rpc()
+  
   // Helper to airdrop SOL
   const airdrop = async (pubkey: PublicKey, amount: number) => {
     const signature = await provider.connection.requestAirdrop(
@@ -61,13 +236,81 @@ describe('OTC Options Trading', () => {
     
     // Airdrop SOL to test accounts
     await airdrop(buyer.publicKey, 10)
-    await airdrop(seller.publicKey, 50)
+    await airdrop(seller.publicKey, 500)
     
     // Derive PDAs
     ;[buyerAccount] = getUserAccountPDA(buyer.publicKey)
     ;[sellerAccount] = getUserAccountPDA(seller.publicKey)
     ;[buyerEscrow] = getEscrowPDA(buyer.publicKey)
     ;[sellerEscrow] = getEscrowPDA(seller.publicKey)
+    
+    // SOL/USD quote used to convert payoffs to lamports
+    solPriceFeed = await setPrice('SOL', new BN(10000))
+    
+    // The provider wallet deployed the program, so it can create the market config
+    await program.methods
+      .initializeMarketConfig(MIN_MARGIN_BPS, MAX_MARGIN_BPS, MIN_EXPIRY_SECONDS, MAX_EXPIRY_SECONDS)
+      .accounts({
+        admin: provider.wallet.publicKey,
+        marketConfig: getMarketConfigPDA()[0],
+        treasury: getTreasuryPDA()[0],
+        program: program.programId,
+        programData: PublicKey.findProgramAddressSync([program.programId.toBuffer()], BPF_LOADER_UPGRADEABLE_ID)[0],
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc()
+    
+    for (const ticker of TICKERS) {
+      await program.methods
+        .registerUnderlyingAsset(ticker, -2)
+        .accounts({ admin: provider.wallet.publicKey })
+        .rpc()
+    }
+  })
+
+  describe('Underlying Assets', () => {
+    it('should record the price precision of a registered ticker', async () => {
+      const asset = await program.account.underlyingAsset.fetch(getUnderlyingAssetPDA('AAPL')[0])
+      expect(asset.ticker).to.equal('AAPL')
+      expect(asset.priceExpo).to.equal(-2)
+    })
+    
+    it('should register a ticker quoted in hundredths of a cent', async () => {
+      await program.methods
+        .registerUnderlyingAsset('AVGO', -4)
+        .accounts({ admin: provider.wallet.publicKey })
+        .rpc()
+      
+      const asset = await program.account.underlyingAsset.fetch(getUnderlyingAssetPDA('AVGO')[0])
+      expect(asset.priceExpo).to.equal(-4)
+    })
+    
+    it('should reject a price exponent outside the supported range', async () => {
+      try {
+        await program.methods
+          .registerUnderlyingAsset('PEP', -9)
+          .accounts({ admin: provider.wallet.publicKey })
+          .rpc()
+        
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('PriceExpoOutOfRange')
+      }
+    })
+    
+    it('should reject registration by anyone but the admin', async () => {
+      try {
+        await program.methods
+          .registerUnderlyingAsset('PEP', -2)
+          .accounts({ admin: buyer.publicKey })
+          .signers([buyer])
+          .rpc()
+        
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('UnauthorizedAdmin')
+      }
+    })
   })
 
   describe('User Account Initialization', () => {
@@ -85,7 +328,8 @@ describe('OTC Options Trading', () => {
       const account = await program.account.userAccount.fetch(buyerAccount)
       expect(account.owner.toString()).to.equal(buyer.publicKey.toString())
       expect(account.contractCount.toNumber()).to.equal(0)
-      expect(account.contracts.length).to.equal(0)
+      expect(account.entryCount.toNumber()).to.equal(0)
+      expect(account.pageCount).to.equal(0)
     })
     
     it('should initialize seller user account', async () => {
@@ -154,7 +398,7 @@ describe('OTC Options Trading', () => {
     })
     
     it('should deposit to seller escrow', async () => {
-      const depositAmount = new BN(25 * LAMPORTS_PER_SOL)
+      const depositAmount = new BN(200 * LAMPORTS_PER_SOL)
       
       await program.methods
         .deposit(depositAmount)
@@ -191,12 +435,12 @@ describe('OTC Options Trading', () => {
 
   describe('Call Option - In The Money (ITM)', () => {
     let contractPDA: PublicKey
+    let underlyingPriceFeed: PublicKey
     const strikePrice = new BN(15000) // $150.00
     const underlyingPrice = new BN(17000) // $170.00 (ITM for call)
     const numUnits = new BN(100)
     const premium = new BN(0.1 * LAMPORTS_PER_SOL)
     const marginRequirementBps = 2000 // 20%
-    const solPrice = new BN(10000) // $100.00 per SOL
     
     it('should create a call option contract', async () => {
       const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
@@ -206,6 +450,8 @@ describe('OTC Options Trading', () => {
       
       const expirationDate = new BN(Math.floor(Date.now() / 1000) - 3600) // 1 hour ago (expired)
       
+      underlyingPriceFeed = await setPrice('AAPL', underlyingPrice)
+      
       await program.methods
         .createContract(
           'AAPL',
@@ -213,21 +459,29 @@ describe('OTC Options Trading', () => {
           strikePrice,
           expirationDate,
           { call: {} },
+          { european: {} },
           premium,
-          marginRequirementBps,
-          true // is_test mode
+          marginRequirementBps
         )
         .accounts({
           buyer: buyer.publicKey,
           seller: seller.publicKey,
-          contract: contractPDA,
-          buyerAccount: buyerAccount,
-          sellerAccount: sellerAccount,
-          buyerEscrow: buyerEscrow,
-          sellerEscrow: sellerEscrow,
-          systemProgram: SystemProgram.programId,
+          underlyingPriceFeed,
+          solPriceFeed,
+          open: {
+            payer: buyer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: sellerAccount,
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(seller.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: sellerEscrow,
+            systemProgram: SystemProgram.programId,
+          },
         })
-        .signers([buyer])
+        .signers([buyer, seller])
         .rpc()
       
       const contract = await program.account.optionContract.fetch(contractPDA)
@@ -236,17 +490,30 @@ describe('OTC Options Trading', () => {
       expect(contract.numUnits.toNumber()).to.equal(numUnits.toNumber())
       expect(contract.buyer.toString()).to.equal(buyer.publicKey.toString())
       expect(contract.seller.toString()).to.equal(seller.publicKey.toString())
+      expect(contract.underlyingPriceFeed.toString()).to.equal(underlyingPriceFeed.toString())
+      expect(contract.solPriceFeed.toString()).to.equal(solPriceFeed.toString())
       expect(contract.status).to.deep.equal({ active: {} })
+      
+      // Margin is locked in the contract's collateral account, not left in the seller escrow
+      const [collateralPDA] = getCollateralPDA(contractPDA)
+      const collateralInfo = await provider.connection.getAccountInfo(collateralPDA)
+      const rentReserve = await provider.connection.getMinimumBalanceForRentExemption(collateralInfo!.data.length)
+      expect(collateralInfo!.lamports - rentReserve).to.equal(contract.marginAmount.toNumber())
+      // 20% of 100 units at $150 is $3000, or 30 SOL at $100/SOL
+      expect(contract.marginAmount.toNumber()).to.equal(30 * LAMPORTS_PER_SOL)
     })
     
     it('should exercise the ITM call option', async () => {
       await program.methods
-        .exercise(underlyingPrice, solPrice)
+        .exercise()
         .accounts({
           buyer: buyer.publicKey,
           contract: contractPDA,
-          buyerAccount: buyerAccount,
-          sellerAccount: sellerAccount,
+          collateral: getCollateralPDA(contractPDA)[0],
+          sellerEscrow: sellerEscrow,
+          ...(await getContractPagePDAs(contractPDA)),
+          underlyingPriceFeed,
+          solPriceFeed,
         })
         .signers([buyer])
         .rpc()
@@ -271,8 +538,8 @@ describe('OTC Options Trading', () => {
         .accounts({
           caller: buyer.publicKey,
           contract: contractPDA,
-          buyerAccount: buyerAccount,
-          sellerAccount: sellerAccount,
+          collateral: getCollateralPDA(contractPDA)[0],
+          ...(await getContractPagePDAs(contractPDA)),
           buyerEscrow: buyerEscrow,
           sellerEscrow: sellerEscrow,
           systemProgram: SystemProgram.programId,
@@ -290,17 +557,42 @@ describe('OTC Options Trading', () => {
       
       expect(buyerEscrowAfter).to.be.greaterThan(buyerEscrowBefore)
       expect(sellerEscrowAfter).to.be.lessThan(sellerEscrowBefore)
+      
+      // The collateral account is drained down to its rent reserve
+      const [collateralPDA] = getCollateralPDA(contractPDA)
+      const collateralInfo = await provider.connection.getAccountInfo(collateralPDA)
+      const rentReserve = await provider.connection.getMinimumBalanceForRentExemption(collateralInfo!.data.length)
+      expect(collateralInfo!.lamports).to.equal(rentReserve)
+    })
+    
+    it('should close the settled contract and refund rent to the buyer', async () => {
+      const contractRent = (await provider.connection.getAccountInfo(contractPDA))!.lamports
+      const collateralRent = (await provider.connection.getAccountInfo(getCollateralPDA(contractPDA)[0]))!.lamports
+      const buyerWalletBefore = await provider.connection.getBalance(buyer.publicKey)
+      
+      await closeContract(contractPDA, seller, buyer.publicKey)
+      
+      expect(await provider.connection.getAccountInfo(contractPDA)).to.be.null
+      expect(await provider.connection.getAccountInfo(getCollateralPDA(contractPDA)[0])).to.be.null
+      
+      const buyerWalletAfter = await provider.connection.getBalance(buyer.publicKey)
+      expect(buyerWalletAfter - buyerWalletBefore).to.equal(contractRent + collateralRent)
+      
+      const buyerContracts = await fetchUserContracts(buyer.publicKey)
+      const sellerContracts = await fetchUserContracts(seller.publicKey)
+      expect(buyerContracts.some((c) => c.contractAddress.equals(contractPDA))).to.be.false
+      expect(sellerContracts.some((c) => c.contractAddress.equals(contractPDA))).to.be.false
     })
   })
 
   describe('Call Option - Out of The Money (OTM)', () => {
     let contractPDA: PublicKey
+    let underlyingPriceFeed: PublicKey
     const strikePrice = new BN(20000) // $200.00
     const underlyingPrice = new BN(18000) // $180.00 (OTM for call)
     const numUnits = new BN(50)
     const premium = new BN(0.05 * LAMPORTS_PER_SOL)
     const marginRequirementBps = 2000
-    const solPrice = new BN(10000)
     
     it('should create an OTM call option contract', async () => {
       const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
@@ -310,6 +602,8 @@ describe('OTC Options Trading', () => {
       
       const expirationDate = new BN(Math.floor(Date.now() / 1000) - 3600)
       
+      underlyingPriceFeed = await setPrice('TSLA', underlyingPrice)
+      
       await program.methods
         .createContract(
           'TSLA',
@@ -317,21 +611,29 @@ describe('OTC Options Trading', () => {
           strikePrice,
           expirationDate,
           { call: {} },
+          { european: {} },
           premium,
-          marginRequirementBps,
-          true
+          marginRequirementBps
         )
         .accounts({
           buyer: buyer.publicKey,
           seller: seller.publicKey,
-          contract: contractPDA,
-          buyerAccount: buyerAccount,
-          sellerAccount: sellerAccount,
-          buyerEscrow: buyerEscrow,
-          sellerEscrow: sellerEscrow,
-          systemProgram: SystemProgram.programId,
+          underlyingPriceFeed,
+          solPriceFeed,
+          open: {
+            payer: buyer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: sellerAccount,
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(seller.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: sellerEscrow,
+            systemProgram: SystemProgram.programId,
+          },
         })
-        .signers([buyer])
+        .signers([buyer, seller])
         .rpc()
       
       const contract = await program.account.optionContract.fetch(contractPDA)
@@ -340,35 +642,44 @@ describe('OTC Options Trading', () => {
     
     it('should exercise the OTM call option with zero profit', async () => {
       await program.methods
-        .exercise(underlyingPrice, solPrice)
+        .exercise()
         .accounts({
           buyer: buyer.publicKey,
           contract: contractPDA,
-          buyerAccount: buyerAccount,
-          sellerAccount: sellerAccount,
+          collateral: getCollateralPDA(contractPDA)[0],
+          sellerEscrow: sellerEscrow,
+          ...(await getContractPagePDAs(contractPDA)),
+          underlyingPriceFeed,
+          solPriceFeed,
         })
         .signers([buyer])
         .rpc()
       
+      // Nothing to settle, so the contract expires and the margin goes back to the seller
       const contract = await program.account.optionContract.fetch(contractPDA)
-      expect(contract.status).to.deep.equal({ exercised: {} })
+      expect(contract.status).to.deep.equal({ expired: {} })
       expect(contract.buyerPendingBalance.toNumber()).to.equal(0)
       expect(contract.sellerPendingBalance.toNumber()).to.equal(0)
+      
+      const [collateralPDA] = getCollateralPDA(contractPDA)
+      const collateralInfo = await provider.connection.getAccountInfo(collateralPDA)
+      const rentReserve = await provider.connection.getMinimumBalanceForRentExemption(collateralInfo!.data.length)
+      expect(collateralInfo!.lamports).to.equal(rentReserve)
     })
   })
 
   describe('Put Option - In The Money (ITM)', () => {
     let contractPDA: PublicKey
+    let underlyingPriceFeed: PublicKey
     const strikePrice = new BN(25000) // $250.00
     const underlyingPrice = new BN(22000) // $220.00 (ITM for put)
     const numUnits = new BN(75)
     const premium = new BN(0.15 * LAMPORTS_PER_SOL)
     const marginRequirementBps = 2000
-    const solPrice = new BN(10000)
     
     before(async () => {
       // Refill seller escrow for put option test
-      const depositAmount = new BN(25 * LAMPORTS_PER_SOL)
+      const depositAmount = new BN(200 * LAMPORTS_PER_SOL)
       await program.methods
         .deposit(depositAmount)
         .accounts({
@@ -388,6 +699,8 @@ describe('OTC Options Trading', () => {
       
       const expirationDate = new BN(Math.floor(Date.now() / 1000) - 3600)
       
+      underlyingPriceFeed = await setPrice('NVDA', underlyingPrice)
+      
       await program.methods
         .createContract(
           'NVDA',
@@ -395,21 +708,29 @@ describe('OTC Options Trading', () => {
           strikePrice,
           expirationDate,
           { put: {} },
+          { european: {} },
           premium,
-          marginRequirementBps,
-          true
+          marginRequirementBps
         )
         .accounts({
           buyer: buyer.publicKey,
           seller: seller.publicKey,
-          contract: contractPDA,
-          buyerAccount: buyerAccount,
-          sellerAccount: sellerAccount,
-          buyerEscrow: buyerEscrow,
-          sellerEscrow: sellerEscrow,
-          systemProgram: SystemProgram.programId,
+          underlyingPriceFeed,
+          solPriceFeed,
+          open: {
+            payer: buyer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: sellerAccount,
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(seller.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: sellerEscrow,
+            systemProgram: SystemProgram.programId,
+          },
         })
-        .signers([buyer])
+        .signers([buyer, seller])
         .rpc()
       
       const contract = await program.account.optionContract.fetch(contractPDA)
@@ -420,12 +741,15 @@ describe('OTC Options Trading', () => {
     
     it('should exercise the ITM put option', async () => {
       await program.methods
-        .exercise(underlyingPrice, solPrice)
+        .exercise()
         .accounts({
           buyer: buyer.publicKey,
           contract: contractPDA,
-          buyerAccount: buyerAccount,
-          sellerAccount: sellerAccount,
+          collateral: getCollateralPDA(contractPDA)[0],
+          sellerEscrow: sellerEscrow,
+          ...(await getContractPagePDAs(contractPDA)),
+          underlyingPriceFeed,
+          solPriceFeed,
         })
         .signers([buyer])
         .rpc()
@@ -449,8 +773,8 @@ describe('OTC Options Trading', () => {
         .accounts({
           caller: seller.publicKey,
           contract: contractPDA,
-          buyerAccount: buyerAccount,
-          sellerAccount: sellerAccount,
+          collateral: getCollateralPDA(contractPDA)[0],
+          ...(await getContractPagePDAs(contractPDA)),
           buyerEscrow: buyerEscrow,
           sellerEscrow: sellerEscrow,
           systemProgram: SystemProgram.programId,
@@ -468,12 +792,12 @@ describe('OTC Options Trading', () => {
 
   describe('Put Option - Out of The Money (OTM)', () => {
     let contractPDA: PublicKey
+    let underlyingPriceFeed: PublicKey
     const strikePrice = new BN(18000) // $180.00
     const underlyingPrice = new BN(20000) // $200.00 (OTM for put)
     const numUnits = new BN(60)
     const premium = new BN(0.08 * LAMPORTS_PER_SOL)
     const marginRequirementBps = 2000
-    const solPrice = new BN(10000)
     
     it('should create an OTM put option contract', async () => {
       const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
@@ -483,6 +807,8 @@ describe('OTC Options Trading', () => {
       
       const expirationDate = new BN(Math.floor(Date.now() / 1000) - 3600)
       
+      underlyingPriceFeed = await setPrice('GOOGL', underlyingPrice)
+      
       await program.methods
         .createContract(
           'GOOGL',
@@ -490,21 +816,29 @@ describe('OTC Options Trading', () => {
           strikePrice,
           expirationDate,
           { put: {} },
+          { european: {} },
           premium,
-          marginRequirementBps,
-          true
+          marginRequirementBps
         )
         .accounts({
           buyer: buyer.publicKey,
           seller: seller.publicKey,
-          contract: contractPDA,
-          buyerAccount: buyerAccount,
-          sellerAccount: sellerAccount,
-          buyerEscrow: buyerEscrow,
-          sellerEscrow: sellerEscrow,
-          systemProgram: SystemProgram.programId,
+          underlyingPriceFeed,
+          solPriceFeed,
+          open: {
+            payer: buyer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: sellerAccount,
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(seller.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: sellerEscrow,
+            systemProgram: SystemProgram.programId,
+          },
         })
-        .signers([buyer])
+        .signers([buyer, seller])
         .rpc()
       
       const contract = await program.account.optionContract.fetch(contractPDA)
@@ -513,68 +847,2393 @@ describe('OTC Options Trading', () => {
     
     it('should exercise the OTM put option with zero profit', async () => {
       await program.methods
-        .exercise(underlyingPrice, solPrice)
+        .exercise()
         .accounts({
           buyer: buyer.publicKey,
           contract: contractPDA,
-          buyerAccount: buyerAccount,
-          sellerAccount: sellerAccount,
+          collateral: getCollateralPDA(contractPDA)[0],
+          sellerEscrow: sellerEscrow,
+          ...(await getContractPagePDAs(contractPDA)),
+          underlyingPriceFeed,
+          solPriceFeed,
         })
         .signers([buyer])
         .rpc()
       
       const contract = await program.account.optionContract.fetch(contractPDA)
-      expect(contract.status).to.deep.equal({ exercised: {} })
+      expect(contract.status).to.deep.equal({ expired: {} })
       expect(contract.buyerPendingBalance.toNumber()).to.equal(0)
+      
+      const buyerContracts = await fetchUserContracts(buyer.publicKey)
+      const entry = buyerContracts.find((c) => c.contractAddress.equals(contractPDA))
+      expect(entry!.status).to.deep.equal({ expired: {} })
     })
   })
 
-  describe('Withdraw Functionality', () => {
-    it('should allow buyer to withdraw from escrow', async () => {
-      const buyerWalletBefore = await provider.connection.getBalance(buyer.publicKey)
-      const withdrawAmount = new BN(0.5 * LAMPORTS_PER_SOL)
+  describe('Expiry Without Exercise', () => {
+    let underlyingPriceFeed: PublicKey
+    
+    const createExpiringContract = async (expirationDate: BN): Promise<PublicKey> => {
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      const [contractPDA] = getContractPDA(buyer.publicKey, seller.publicKey, buyerAccountData.contractCount.toNumber())
       
       await program.methods
-        .withdraw(withdrawAmount)
+        .createContract(
+          'ADBE',
+          new BN(10),
+          new BN(50000),
+          expirationDate,
+          { put: {} },
+          { european: {} },
+          new BN(0.01 * LAMPORTS_PER_SOL),
+          2000
+        )
         .accounts({
-          user: buyer.publicKey,
-          userEscrow: buyerEscrow,
-          systemProgram: SystemProgram.programId,
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          underlyingPriceFeed,
+          solPriceFeed,
+          open: {
+            payer: buyer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: sellerAccount,
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(seller.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: sellerEscrow,
+            systemProgram: SystemProgram.programId,
+          },
         })
-        .signers([buyer])
+        .signers([buyer, seller])
         .rpc()
       
-      const buyerWalletAfter = await provider.connection.getBalance(buyer.publicKey)
-      expect(buyerWalletAfter).to.be.greaterThan(buyerWalletBefore)
-    })
+      return contractPDA
+    }
     
-    it('should allow seller to withdraw from escrow', async () => {
-      const sellerWalletBefore = await provider.connection.getBalance(seller.publicKey)
-      const withdrawAmount = new BN(0.3 * LAMPORTS_PER_SOL)
-      
-      await program.methods
-        .withdraw(withdrawAmount)
+    const expire = async (contractPDA: PublicKey) =>
+      program.methods
+        .expire()
         .accounts({
-          user: seller.publicKey,
-          userEscrow: sellerEscrow,
-          systemProgram: SystemProgram.programId,
+          caller: provider.wallet.publicKey,
+          contract: contractPDA,
+          collateral: getCollateralPDA(contractPDA)[0],
+          ...(await getContractPagePDAs(contractPDA)),
+          sellerEscrow: sellerEscrow,
+          underlyingPriceFeed,
+          solPriceFeed,
         })
-        .signers([seller])
         .rpc()
+    
+    before(async () => {
+      underlyingPriceFeed = await setPrice('ADBE', new BN(52000))
+      await setPrice('SOL', new BN(10000))
+    })
+    
+    it('should fail to expire during the grace period', async () => {
+      const contractPDA = await createExpiringContract(new BN(Math.floor(Date.now() / 1000) - 3600))
       
-      const sellerWalletAfter = await provider.connection.getBalance(seller.publicKey)
-      expect(sellerWalletAfter).to.be.greaterThan(sellerWalletBefore)
+      try {
+        await expire(contractPDA)
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('GracePeriodNotElapsed')
+      }
+      
+      try {
+        await closeContract(contractPDA, buyer, buyer.publicKey)
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('ContractNotFinished')
+      }
+    })
+    
+    it('should let anyone expire an unexercised contract after the grace period', async () => {
+      const contractPDA = await createExpiringContract(new BN(Math.floor(Date.now() / 1000) - 2 * 86400))
+      const contractBefore = await program.account.optionContract.fetch(contractPDA)
+      const sellerEscrowBefore = await provider.connection.getBalance(sellerEscrow)
+      
+      await expire(contractPDA)
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.status).to.deep.equal({ expired: {} })
+      
+      const sellerEscrowAfter = await provider.connection.getBalance(sellerEscrow)
+      expect(sellerEscrowAfter - sellerEscrowBefore).to.equal(contractBefore.marginAmount.toNumber())
+      
+      const buyerEntry = (await fetchUserContracts(buyer.publicKey)).find((c) => c.contractAddress.equals(contractPDA))
+      const sellerEntry = (await fetchUserContracts(seller.publicKey)).find((c) => c.contractAddress.equals(contractPDA))
+      expect(buyerEntry!.status).to.deep.equal({ expired: {} })
+      expect(sellerEntry!.status).to.deep.equal({ expired: {} })
+      
+      await closeContract(contractPDA, buyer, buyer.publicKey)
+      expect(await provider.connection.getAccountInfo(contractPDA)).to.be.null
     })
   })
 
-  describe('Error Cases', () => {
-    it('should fail when non-buyer tries to exercise', async () => {
+  describe('Exercise Styles', () => {
+    let underlyingPriceFeed: PublicKey
+    const now = () => Math.floor(Date.now() / 1000)
+    
+    const createStyledContract = async (
+      exerciseStyle: { european: {} } | { american: {} },
+      expirationDate: BN
+    ): Promise<PublicKey> => {
       const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
-      const contractCount = buyerAccountData.contractCount.toNumber()
-      
-      const [contractPDA] = getContractPDA(buyer.publicKey, seller.publicKey, contractCount)
-      
-      const expirationDate = new BN(Math.floor(Date.now() / 1000) - 3600)
+      const [contractPDA] = getContractPDA(buyer.publicKey, seller.publicKey, buyerAccountData.contractCount.toNumber())
+      
+      await program.methods
+        .createContract(
+          'META',
+          new BN(1),
+          new BN(30000),
+          expirationDate,
+          { call: {} },
+          exerciseStyle,
+          new BN(0.01 * LAMPORTS_PER_SOL),
+          2000
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          underlyingPriceFeed,
+          solPriceFeed,
+          open: {
+            payer: buyer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: sellerAccount,
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(seller.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: sellerEscrow,
+            systemProgram: SystemProgram.programId,
+          },
+        })
+        .signers([buyer, seller])
+        .rpc()
+      
+      return contractPDA
+    }
+    
+    const exercise = async (contractPDA: PublicKey) =>
+      program.methods
+        .exercise()
+        .accounts({
+          buyer: buyer.publicKey,
+          contract: contractPDA,
+          collateral: getCollateralPDA(contractPDA)[0],
+          sellerEscrow: sellerEscrow,
+          ...(await getContractPagePDAs(contractPDA)),
+          underlyingPriceFeed,
+          solPriceFeed,
+        })
+        .signers([buyer])
+        .rpc()
+    
+    const settle = async (contractPDA: PublicKey) =>
+      program.methods
+        .settle()
+        .accounts({
+          caller: buyer.publicKey,
+          contract: contractPDA,
+          collateral: getCollateralPDA(contractPDA)[0],
+          ...(await getContractPagePDAs(contractPDA)),
+          buyerEscrow: buyerEscrow,
+          sellerEscrow: sellerEscrow,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc()
+    
+    before(async () => {
+      // $310 against a $300 strike: $10 profit, 0.1 SOL at $100/SOL
+      underlyingPriceFeed = await setPrice('META', new BN(31000))
+    })
+    
+    it('should exercise, settle and close an American contract before expiry', async () => {
+      const contractPDA = await createStyledContract({ american: {} }, new BN(now() + 86400))
+      
+      let contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.exerciseStyle).to.deep.equal({ american: {} })
+      
+      await exercise(contractPDA)
+      contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.status).to.deep.equal({ exercised: {} })
+      expect(contract.buyerPendingBalance.toString()).to.equal(new BN(0.1 * LAMPORTS_PER_SOL).toString())
+      
+      await settle(contractPDA)
+      contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.status).to.deep.equal({ settled: {} })
+      
+      await closeContract(contractPDA, buyer, buyer.publicKey)
+      expect(await provider.connection.getAccountInfo(contractPDA)).to.be.null
+    })
+    
+    it('should reject exercising a European contract before expiry', async () => {
+      const contractPDA = await createStyledContract({ european: {} }, new BN(now() + 86400))
+      
+      try {
+        await exercise(contractPDA)
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('ContractNotExpired')
+      }
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.status).to.deep.equal({ active: {} })
+    })
+    
+    it('should exercise, settle and close a European contract inside the exercise window', async () => {
+      const contractPDA = await createStyledContract({ european: {} }, new BN(now() - 3600))
+      
+      await exercise(contractPDA)
+      let contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.status).to.deep.equal({ exercised: {} })
+      
+      await settle(contractPDA)
+      contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.status).to.deep.equal({ settled: {} })
+      
+      await closeContract(contractPDA, seller, buyer.publicKey)
+      expect(await provider.connection.getAccountInfo(contractPDA)).to.be.null
+    })
+    
+    for (const exerciseStyle of [{ european: {} }, { american: {} }]) {
+      it(`should reject exercise once the window has closed (${Object.keys(exerciseStyle)[0]})`, async () => {
+        const contractPDA = await createStyledContract(exerciseStyle, new BN(now() - 2 * 86400))
+        
+        try {
+          await exercise(contractPDA)
+          expect.fail('Should have thrown an error')
+        } catch (error) {
+          expect(error.message).to.include('ExerciseWindowClosed')
+        }
+        
+        // The call is still in the money, so the seller cannot expire it and
+        // take back the margin the buyer is owed
+        try {
+          await program.methods
+            .expire()
+            .accounts({
+              caller: seller.publicKey,
+              contract: contractPDA,
+              collateral: getCollateralPDA(contractPDA)[0],
+              ...(await getContractPagePDAs(contractPDA)),
+              sellerEscrow: sellerEscrow,
+              underlyingPriceFeed,
+              solPriceFeed,
+            })
+            .signers([seller])
+            .rpc()
+          expect.fail('Should have thrown an error')
+        } catch (error) {
+          expect(error.message).to.include('ContractInTheMoney')
+        }
+        
+        const contract = await program.account.optionContract.fetch(contractPDA)
+        expect(contract.status).to.deep.equal({ active: {} })
+      })
+    }
+  })
+
+  describe('Partial Exercise', () => {
+    let underlyingPriceFeed: PublicKey
+    let contractPDA: PublicKey
+    // $120 against a $100 strike: $20 per unit, 0.2 SOL at $100/SOL
+    const payoffPerUnit = 0.2 * LAMPORTS_PER_SOL
+    
+    const createPartialContract = async (): Promise<PublicKey> => {
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      const [pendingContract] = getContractPDA(buyer.publicKey, seller.publicKey, buyerAccountData.contractCount.toNumber())
+      
+      await program.methods
+        .createContract(
+          'AMD',
+          new BN(10),
+          new BN(10000),
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          { call: {} },
+          { american: {} },
+          new BN(0.01 * LAMPORTS_PER_SOL),
+          2000
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          underlyingPriceFeed,
+          solPriceFeed,
+          open: {
+            payer: buyer.publicKey,
+            contract: pendingContract,
+            collateral: getCollateralPDA(pendingContract)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: sellerAccount,
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(seller.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: sellerEscrow,
+            systemProgram: SystemProgram.programId,
+          },
+        })
+        .signers([buyer, seller])
+        .rpc()
+      
+      return pendingContract
+    }
+    
+    const exerciseUnits = async (contract: PublicKey, units: number) =>
+      program.methods
+        .exerciseUnits(new BN(units))
+        .accounts({
+          buyer: buyer.publicKey,
+          contract,
+          collateral: getCollateralPDA(contract)[0],
+          ...(await getContractPagePDAs(contract)),
+          buyerEscrow: buyerEscrow,
+          sellerEscrow: sellerEscrow,
+          underlyingPriceFeed,
+          solPriceFeed,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc()
+    
+    const lockedMargin = async (contract: PublicKey) => {
+      const collateralInfo = await provider.connection.getAccountInfo(getCollateralPDA(contract)[0])
+      const rentReserve = await provider.connection.getMinimumBalanceForRentExemption(collateralInfo!.data.length)
+      return collateralInfo!.lamports - rentReserve
+    }
+    
+    before(async () => {
+      underlyingPriceFeed = await setPrice('AMD', new BN(12000))
+      // Refresh the SOL quote so it is not stale by the time these tests run
+      await setPrice('SOL', new BN(10000))
+      contractPDA = await createPartialContract()
+    })
+    
+    it('should exercise part of the position and keep the contract active', async () => {
+      const marginBefore = await lockedMargin(contractPDA)
+      const buyerEscrowBefore = await provider.connection.getBalance(buyerEscrow)
+      
+      await exerciseUnits(contractPDA, 4)
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.status).to.deep.equal({ active: {} })
+      expect(contract.unitsExercised.toNumber()).to.equal(4)
+      
+      const buyerEscrowAfter = await provider.connection.getBalance(buyerEscrow)
+      expect(buyerEscrowAfter - buyerEscrowBefore).to.equal(4 * payoffPerUnit)
+      
+      // 4 of 10 units release 40% of the margin
+      expect(await lockedMargin(contractPDA)).to.equal(marginBefore - Math.floor((marginBefore * 4) / 10))
+    })
+    
+    it('should reject exercising more units than remain', async () => {
+      for (const units of [0, 7]) {
+        try {
+          await exerciseUnits(contractPDA, units)
+          expect.fail('Should have thrown an error')
+        } catch (error) {
+          expect(error.message).to.include('InvalidExerciseUnits')
+        }
+      }
+    })
+    
+    it('should settle the contract once every unit is exercised', async () => {
+      const buyerEscrowBefore = await provider.connection.getBalance(buyerEscrow)
+      
+      await exerciseUnits(contractPDA, 6)
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.status).to.deep.equal({ settled: {} })
+      expect(contract.unitsExercised.toNumber()).to.equal(10)
+      expect(await lockedMargin(contractPDA)).to.equal(0)
+      
+      const buyerEscrowAfter = await provider.connection.getBalance(buyerEscrow)
+      expect(buyerEscrowAfter - buyerEscrowBefore).to.equal(6 * payoffPerUnit)
+      
+      const buyerEntry = (await fetchUserContracts(buyer.publicKey)).find((c) => c.contractAddress.equals(contractPDA))
+      expect(buyerEntry!.status).to.deep.equal({ settled: {} })
+    })
+    
+    it('should exercise only the remaining units with a full exercise', async () => {
+      const partialContract = await createPartialContract()
+      await exerciseUnits(partialContract, 3)
+      
+      await program.methods
+        .exercise()
+        .accounts({
+          buyer: buyer.publicKey,
+          contract: partialContract,
+          collateral: getCollateralPDA(partialContract)[0],
+          sellerEscrow: sellerEscrow,
+          ...(await getContractPagePDAs(partialContract)),
+          underlyingPriceFeed,
+          solPriceFeed,
+        })
+        .signers([buyer])
+        .rpc()
+      
+      const contract = await program.account.optionContract.fetch(partialContract)
+      expect(contract.status).to.deep.equal({ exercised: {} })
+      expect(contract.buyerPendingBalance.toNumber()).to.equal(7 * payoffPerUnit)
+    })
+  })
+
+  describe('Quotes', () => {
+    let underlyingPriceFeed: PublicKey
+    const premiumPerUnit = new BN(0.001 * LAMPORTS_PER_SOL)
+    
+    const postQuote = async (quoteId: number, maxUnits: number, validUntil: number): Promise<PublicKey> => {
+      const [quotePDA] = getQuotePDA(seller.publicKey, quoteId)
+      
+      await program.methods
+        .postQuote(
+          new BN(quoteId),
+          'ORCL',
+          new BN(15000),
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          { call: {} },
+          { american: {} },
+          premiumPerUnit,
+          new BN(maxUnits),
+          2000,
+          new BN(validUntil)
+        )
+        .accounts({
+          seller: seller.publicKey,
+          quote: quotePDA,
+          sellerAccount: sellerAccount,
+          underlyingPriceFeed,
+          solPriceFeed,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([seller])
+        .rpc()
+      
+      return quotePDA
+    }
+    
+    const fillQuote = async (quotePDA: PublicKey, units: number): Promise<PublicKey> => {
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      const [contractPDA] = getContractPDA(buyer.publicKey, seller.publicKey, buyerAccountData.contractCount.toNumber())
+      const quoteData = await program.account.quote.fetch(quotePDA)
+      
+      await program.methods
+        .fillQuote(new BN(units))
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          quote: quotePDA,
+          underlyingPriceFeed: quoteData.underlyingPriceFeed,
+          solPriceFeed: quoteData.solPriceFeed,
+          open: {
+            payer: buyer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: sellerAccount,
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(seller.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: sellerEscrow,
+            systemProgram: SystemProgram.programId,
+          },
+        })
+        .signers([buyer])
+        .rpc()
+      
+      return contractPDA
+    }
+    
+    const now = () => Math.floor(Date.now() / 1000)
+    
+    before(async () => {
+      underlyingPriceFeed = await setPrice('ORCL', new BN(15500))
+    })
+    
+    it('should write a contract from a partial fill and move the premium', async () => {
+      const quotePDA = await postQuote(1, 10, now() + 3600)
+      const sellerWalletBefore = await provider.connection.getBalance(seller.publicKey)
+      
+      const contractPDA = await fillQuote(quotePDA, 4)
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.status).to.deep.equal({ active: {} })
+      expect(contract.seller.toString()).to.equal(seller.publicKey.toString())
+      expect(contract.numUnits.toNumber()).to.equal(4)
+      expect(contract.premium.toString()).to.equal(premiumPerUnit.muln(4).toString())
+      
+      const sellerWalletAfter = await provider.connection.getBalance(seller.publicKey)
+      expect(sellerWalletAfter - sellerWalletBefore).to.equal(premiumPerUnit.muln(4).toNumber())
+      
+      const quote = await program.account.quote.fetch(quotePDA)
+      expect(quote.remainingUnits.toNumber()).to.equal(6)
+    })
+    
+    it('should reject a fill larger than the units left on the quote', async () => {
+      const [quotePDA] = getQuotePDA(seller.publicKey, 1)
+      
+      try {
+        await fillQuote(quotePDA, 7)
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('InvalidQuoteUnits')
+      }
+    })
+    
+    it('should close the quote once it is fully filled', async () => {
+      const [quotePDA] = getQuotePDA(seller.publicKey, 1)
+      
+      await fillQuote(quotePDA, 6)
+      
+      expect(await provider.connection.getAccountInfo(quotePDA)).to.be.null
+    })
+    
+    it('should only let the seller cancel a quote', async () => {
+      const quotePDA = await postQuote(2, 5, now() + 3600)
+      
+      try {
+        await program.methods
+          .cancelQuote()
+          .accounts({ seller: buyer.publicKey, quote: quotePDA })
+          .signers([buyer])
+          .rpc()
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('ConstraintHasOne')
+      }
+      
+      await program.methods
+        .cancelQuote()
+        .accounts({ seller: seller.publicKey, quote: quotePDA })
+        .signers([seller])
+        .rpc()
+      
+      expect(await provider.connection.getAccountInfo(quotePDA)).to.be.null
+    })
+    
+    it('should refuse fills on an expired quote and let anyone close it', async () => {
+      const quotePDA = await postQuote(3, 5, now() - 60)
+      
+      try {
+        await fillQuote(quotePDA, 1)
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('QuoteExpired')
+      }
+      
+      const sellerWalletBefore = await provider.connection.getBalance(seller.publicKey)
+      const quoteRent = (await provider.connection.getAccountInfo(quotePDA))!.lamports
+      
+      await program.methods
+        .closeExpiredQuote()
+        .accounts({ caller: provider.wallet.publicKey, seller: seller.publicKey, quote: quotePDA })
+        .rpc()
+      
+      expect(await provider.connection.getAccountInfo(quotePDA)).to.be.null
+      const sellerWalletAfter = await provider.connection.getBalance(seller.publicKey)
+      expect(sellerWalletAfter - sellerWalletBefore).to.equal(quoteRent)
+    })
+    
+    it('should not close a live quote as expired', async () => {
+      const quotePDA = await postQuote(4, 5, now() + 3600)
+      
+      try {
+        await program.methods
+          .closeExpiredQuote()
+          .accounts({ caller: provider.wallet.publicKey, seller: seller.publicKey, quote: quotePDA })
+          .rpc()
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('QuoteNotExpired')
+      }
+    })
+  })
+
+  describe('Position Transfer', () => {
+    let newHolder: Keypair
+    let newHolderEscrow: PublicKey
+    let underlyingPriceFeed: PublicKey
+    let contractPDA: PublicKey
+    const salePrice = new BN(0.05 * LAMPORTS_PER_SOL)
+    
+    const transferPosition = async (holder: Keypair, price: BN) =>
+      program.methods
+        .transferPosition(price)
+        .accounts({
+          holder: holder.publicKey,
+          newHolder: newHolder.publicKey,
+          contract: contractPDA,
+          holderPage: (await getContractPagePDAs(contractPDA)).buyerPage,
+          newHolderAccount: getUserAccountPDA(newHolder.publicKey)[0],
+          newHolderPage: await getTailPagePDA(newHolder.publicKey),
+          holderEscrow: getEscrowPDA(holder.publicKey)[0],
+          newHolderEscrow,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([holder, newHolder])
+        .rpc()
+    
+    const exerciseAs = async (signer: Keypair) =>
+      program.methods
+        .exercise()
+        .accounts({
+          buyer: signer.publicKey,
+          contract: contractPDA,
+          collateral: getCollateralPDA(contractPDA)[0],
+          sellerEscrow: sellerEscrow,
+          ...(await getContractPagePDAs(contractPDA)),
+          underlyingPriceFeed,
+          solPriceFeed,
+        })
+        .signers([signer])
+        .rpc()
+    
+    before(async () => {
+      newHolder = Keypair.generate()
+      await airdrop(newHolder.publicKey, 5)
+      ;[newHolderEscrow] = getEscrowPDA(newHolder.publicKey)
+      
+      await program.methods
+        .initializeUser()
+        .accounts({
+          user: newHolder.publicKey,
+          userAccount: getUserAccountPDA(newHolder.publicKey)[0],
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([newHolder])
+        .rpc()
+      await program.methods
+        .initializeEscrow()
+        .accounts({
+          user: newHolder.publicKey,
+          userEscrow: newHolderEscrow,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([newHolder])
+        .rpc()
+      await program.methods
+        .deposit(new BN(1 * LAMPORTS_PER_SOL))
+        .accounts({
+          user: newHolder.publicKey,
+          userEscrow: newHolderEscrow,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([newHolder])
+        .rpc()
+      
+      underlyingPriceFeed = await setPrice('INTC', new BN(5000))
+      await setPrice('SOL', new BN(10000))
+      
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      ;[contractPDA] = getContractPDA(buyer.publicKey, seller.publicKey, buyerAccountData.contractCount.toNumber())
+      
+      await program.methods
+        .createContract(
+          'INTC',
+          new BN(10),
+          new BN(4000),
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          { call: {} },
+          { american: {} },
+          new BN(0.01 * LAMPORTS_PER_SOL),
+          2000
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          underlyingPriceFeed,
+          solPriceFeed,
+          open: {
+            payer: buyer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: sellerAccount,
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(seller.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: sellerEscrow,
+            systemProgram: SystemProgram.programId,
+          },
+        })
+        .signers([buyer, seller])
+        .rpc()
+    })
+    
+    it('should reject a transfer signed by someone other than the holder', async () => {
+      try {
+        await transferPosition(seller, new BN(0))
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('UnauthorizedTransfer')
+      }
+    })
+    
+    it('should transfer the long side for a sale price', async () => {
+      const holderEscrowBefore = await provider.connection.getBalance(buyerEscrow)
+      const newHolderEscrowBefore = await provider.connection.getBalance(newHolderEscrow)
+      
+      await transferPosition(buyer, salePrice)
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.buyer.toString()).to.equal(newHolder.publicKey.toString())
+      expect(contract.originalBuyer.toString()).to.equal(buyer.publicKey.toString())
+      expect(contract.buyerEscrow.toString()).to.equal(newHolderEscrow.toString())
+      
+      expect((await provider.connection.getBalance(buyerEscrow)) - holderEscrowBefore).to.equal(salePrice.toNumber())
+      expect(newHolderEscrowBefore - (await provider.connection.getBalance(newHolderEscrow))).to.equal(salePrice.toNumber())
+      
+      const oldHolderContracts = await fetchUserContracts(buyer.publicKey)
+      const newHolderContracts = await fetchUserContracts(newHolder.publicKey)
+      expect(oldHolderContracts.some((c) => c.contractAddress.equals(contractPDA))).to.be.false
+      const entry = newHolderContracts.find((c) => c.contractAddress.equals(contractPDA))
+      expect(entry!.role).to.deep.equal({ buyer: {} })
+    })
+    
+    it('should only let the new holder exercise and receive the payoff', async () => {
+      try {
+        await exerciseAs(buyer)
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('UnauthorizedExercise')
+      }
+      
+      await exerciseAs(newHolder)
+      
+      const newHolderEscrowBefore = await provider.connection.getBalance(newHolderEscrow)
+      await program.methods
+        .settle()
+        .accounts({
+          caller: newHolder.publicKey,
+          contract: contractPDA,
+          collateral: getCollateralPDA(contractPDA)[0],
+          ...(await getContractPagePDAs(contractPDA)),
+          buyerEscrow: newHolderEscrow,
+          sellerEscrow: sellerEscrow,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([newHolder])
+        .rpc()
+      
+      // $50 against a $40 strike on 10 units: $100, or 1 SOL at $100/SOL
+      const newHolderEscrowAfter = await provider.connection.getBalance(newHolderEscrow)
+      expect(newHolderEscrowAfter - newHolderEscrowBefore).to.equal(1 * LAMPORTS_PER_SOL)
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.status).to.deep.equal({ settled: {} })
+    })
+  })
+
+  describe('Margin and Liquidation', () => {
+    let keeper: Keypair
+    let underlyingPriceFeed: PublicKey
+    let contractPDA: PublicKey
+    let liquidatedContract: PublicKey
+    const MARGIN_CALL_PERIOD_SECONDS = 3600
+    // $120 against a $100 strike on 10 units: $200, or 2 SOL at $100/SOL
+    const itmPayoff = 2 * LAMPORTS_PER_SOL
+    
+    const createMarginContract = async (): Promise<PublicKey> => {
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      const [pendingContract] = getContractPDA(buyer.publicKey, seller.publicKey, buyerAccountData.contractCount.toNumber())
+      
+      await program.methods
+        .createContract(
+          'NFLX',
+          new BN(10),
+          new BN(10000),
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          { call: {} },
+          { american: {} },
+          new BN(0.01 * LAMPORTS_PER_SOL),
+          2000
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          underlyingPriceFeed,
+          solPriceFeed,
+          open: {
+            payer: buyer.publicKey,
+            contract: pendingContract,
+            collateral: getCollateralPDA(pendingContract)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: sellerAccount,
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(seller.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: sellerEscrow,
+            systemProgram: SystemProgram.programId,
+          },
+        })
+        .signers([buyer, seller])
+        .rpc()
+      
+      return pendingContract
+    }
+    
+    const markToMarket = async (contract: PublicKey) =>
+      program.methods
+        .markToMarket()
+        .accounts({
+          caller: keeper.publicKey,
+          contract,
+          collateral: getCollateralPDA(contract)[0],
+          underlyingPriceFeed,
+          solPriceFeed,
+        })
+        .signers([keeper])
+        .rpc()
+    
+    const topUpMargin = async (signer: Keypair, contract: PublicKey, amount: number) =>
+      program.methods
+        .topUpMargin(new BN(amount))
+        .accounts({
+          seller: signer.publicKey,
+          contract,
+          collateral: getCollateralPDA(contract)[0],
+          sellerEscrow: sellerEscrow,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([signer])
+        .rpc()
+    
+    const liquidate = async (contract: PublicKey, warped = false) =>
+      program.methods
+        .liquidate()
+        .accounts({
+          liquidator: keeper.publicKey,
+          contract,
+          collateral: getCollateralPDA(contract)[0],
+          ...(await getContractPagePDAs(contract)),
+          buyerEscrow: buyerEscrow,
+          sellerEscrow: sellerEscrow,
+          underlyingPriceFeed,
+          solPriceFeed,
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts(
+          warped ? [{ pubkey: getTestClockPDA()[0], isSigner: false, isWritable: false }] : []
+        )
+        .signers([keeper])
+        .rpc()
+    
+    const lockedMargin = async (contract: PublicKey) => {
+      const collateralInfo = await provider.connection.getAccountInfo(getCollateralPDA(contract)[0])
+      const rentReserve = await provider.connection.getMinimumBalanceForRentExemption(collateralInfo!.data.length)
+      return collateralInfo!.lamports - rentReserve
+    }
+    
+    before(async () => {
+      keeper = Keypair.generate()
+      await airdrop(keeper.publicKey, 1)
+      
+      underlyingPriceFeed = await setPrice('NFLX', new BN(10000))
+      await setPrice('SOL', new BN(10000))
+      contractPDA = await createMarginContract()
+    })
+    
+    after(async () => {
+      await warpClock(0)
+    })
+    
+    it('should not open a margin call while the margin covers the requirement', async () => {
+      await markToMarket(contractPDA)
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.requiredMargin.toNumber()).to.equal(contract.marginAmount.toNumber())
+      expect(contract.marginCallDeadline).to.be.null
+      
+      try {
+        await liquidate(contractPDA)
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('NoMarginCall')
+      }
+    })
+    
+    it('should open a margin call when the position moves against the seller', async () => {
+      await setPrice('NFLX', new BN(12000))
+      await markToMarket(contractPDA)
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.requiredMargin.toNumber()).to.equal(contract.marginAmount.toNumber() + itmPayoff)
+      expect(contract.marginCallDeadline).to.not.be.null
+    })
+    
+    it('should only let the seller top up the margin', async () => {
+      try {
+        await topUpMargin(buyer, contractPDA, 1000)
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('UnauthorizedTopUp')
+      }
+    })
+    
+    it('should answer the margin call once the seller tops up', async () => {
+      const contractBefore = await program.account.optionContract.fetch(contractPDA)
+      const shortfall = contractBefore.requiredMargin.toNumber() - (await lockedMargin(contractPDA))
+      
+      await topUpMargin(seller, contractPDA, shortfall)
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.marginCallDeadline).to.be.null
+      expect(await lockedMargin(contractPDA)).to.equal(contract.requiredMargin.toNumber())
+      
+      try {
+        await liquidate(contractPDA)
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('NoMarginCall')
+      }
+    })
+    
+    it('should not liquidate before the margin call deadline', async () => {
+      await setPrice('SOL', new BN(10000))
+      liquidatedContract = await createMarginContract()
+      await markToMarket(liquidatedContract)
+      
+      try {
+        await liquidate(liquidatedContract)
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('MarginCallNotDue')
+      }
+    })
+    
+    it('should liquidate an undercollateralized contract once the deadline passes', async () => {
+      // Move past the one hour margin call period and publish quotes at the warped time
+      const offsetSeconds = MARGIN_CALL_PERIOD_SECONDS + 60
+      await warpClock(offsetSeconds)
+      const publishTime = new BN(Math.floor(Date.now() / 1000) + offsetSeconds)
+      await setPrice('NFLX', new BN(12000), { publishTime })
+      await setPrice('SOL', new BN(10000), { publishTime })
+      
+      const marginBefore = await lockedMargin(liquidatedContract)
+      const buyerEscrowBefore = await provider.connection.getBalance(buyerEscrow)
+      const keeperBefore = await provider.connection.getBalance(keeper.publicKey)
+      
+      await liquidate(liquidatedContract, true)
+      
+      const contract = await program.account.optionContract.fetch(liquidatedContract)
+      expect(contract.status).to.deep.equal({ liquidated: {} })
+      expect(await lockedMargin(liquidatedContract)).to.equal(0)
+      
+      // The keeper earns 5% of the locked margin, the buyer the full payoff
+      const keeperAfter = await provider.connection.getBalance(keeper.publicKey)
+      expect(keeperAfter - keeperBefore).to.equal(Math.floor((marginBefore * 500) / 10000))
+      const buyerEscrowAfter = await provider.connection.getBalance(buyerEscrow)
+      expect(buyerEscrowAfter - buyerEscrowBefore).to.equal(itmPayoff)
+      
+      const sellerEntry = (await fetchUserContracts(seller.publicKey)).find((c) =>
+        c.contractAddress.equals(liquidatedContract)
+      )
+      expect(sellerEntry!.status).to.deep.equal({ liquidated: {} })
+    })
+  })
+
+  describe('Token Collateral', () => {
+    let usdcMint: PublicKey
+    let buyerTokenAccount: PublicKey
+    let sellerTokenAccount: PublicKey
+    let buyerTokenEscrow: PublicKey
+    let sellerTokenEscrow: PublicKey
+    let underlyingPriceFeed: PublicKey
+    let contractPDA: PublicKey
+    const usdc = (amount: number) => new BN(amount * 1_000_000)
+    const tokenBalance = async (account: PublicKey) => (await getAccount(provider.connection, account)).amount
+    
+    const tokenAccounts = (contract: PublicKey) => ({
+      collateralVault: getCollateralVaultPDA(contract)[0],
+      buyerTokenEscrow,
+      sellerTokenEscrow,
+      tokenProgram: TOKEN_PROGRAM_ID,
+    })
+    
+    before(async () => {
+      const payer = (provider.wallet as anchor.Wallet).payer
+      usdcMint = await createMint(provider.connection, payer, payer.publicKey, null, 6)
+      buyerTokenAccount = await createAssociatedTokenAccount(provider.connection, payer, usdcMint, buyer.publicKey)
+      sellerTokenAccount = await createAssociatedTokenAccount(provider.connection, payer, usdcMint, seller.publicKey)
+      await mintTo(provider.connection, payer, usdcMint, buyerTokenAccount, payer, BigInt(usdc(1000).toString()))
+      await mintTo(provider.connection, payer, usdcMint, sellerTokenAccount, payer, BigInt(usdc(1000).toString()))
+      
+      ;[buyerTokenEscrow] = getTokenEscrowPDA(buyer.publicKey, usdcMint)
+      ;[sellerTokenEscrow] = getTokenEscrowPDA(seller.publicKey, usdcMint)
+      
+      // $90 against a $100 put strike on 10 units: $100 payoff
+      underlyingPriceFeed = await setPrice('AMZN', new BN(9000))
+    })
+    
+    it('should initialize token escrows and deposit USDC', async () => {
+      for (const [user, userTokenAccount, tokenEscrow] of [
+        [buyer, buyerTokenAccount, buyerTokenEscrow],
+        [seller, sellerTokenAccount, sellerTokenEscrow],
+      ] as [Keypair, PublicKey, PublicKey][]) {
+        await program.methods
+          .initializeTokenEscrow()
+          .accounts({
+            user: user.publicKey,
+            userEscrow: getEscrowPDA(user.publicKey)[0],
+            mint: usdcMint,
+            tokenEscrow,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc()
+        
+        await program.methods
+          .depositTokens(usdc(500))
+          .accounts({
+            user: user.publicKey,
+            userTokenAccount,
+            tokenEscrow,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user])
+          .rpc()
+        
+        const escrow = await getAccount(provider.connection, tokenEscrow)
+        expect(escrow.owner.toString()).to.equal(getEscrowPDA(user.publicKey)[0].toString())
+        expect(escrow.amount.toString()).to.equal(usdc(500).toString())
+      }
+    })
+    
+    it('should fail to open a token contract without its token accounts', async () => {
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      const [pendingContract] = getContractPDA(buyer.publicKey, seller.publicKey, buyerAccountData.contractCount.toNumber())
+      
+      try {
+        await program.methods
+          .createContract(
+            'AMZN',
+            new BN(10),
+            new BN(10000),
+            new BN(Math.floor(Date.now() / 1000) - 3600),
+            { put: {} },
+            { european: {} },
+            usdc(5),
+            2000
+          )
+          .accounts({
+            buyer: buyer.publicKey,
+            seller: seller.publicKey,
+            underlyingPriceFeed,
+            solPriceFeed,
+            open: {
+              payer: buyer.publicKey,
+              contract: pendingContract,
+              collateral: getCollateralPDA(pendingContract)[0],
+              buyerAccount: buyerAccount,
+              sellerAccount: sellerAccount,
+              buyerPage: await getTailPagePDA(buyer.publicKey),
+              sellerPage: await getTailPagePDA(seller.publicKey),
+              buyerEscrow: buyerEscrow,
+              sellerEscrow: sellerEscrow,
+              collateralMint: usdcMint,
+              buyerTokenEscrow: null,
+              sellerTokenEscrow,
+              collateralVault: getCollateralVaultPDA(pendingContract)[0],
+              tokenProgram: TOKEN_PROGRAM_ID,
+              systemProgram: SystemProgram.programId,
+            },
+          })
+          .signers([buyer, seller])
+          .rpc()
+        
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('MissingTokenAccount')
+      }
+    })
+    
+    it('should lock USDC margin and pay the premium in USDC', async () => {
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      ;[contractPDA] = getContractPDA(buyer.publicKey, seller.publicKey, buyerAccountData.contractCount.toNumber())
+      const buyerEscrowLamportsBefore = await provider.connection.getBalance(buyerEscrow)
+      
+      await program.methods
+        .createContract(
+          'AMZN',
+          new BN(10),
+          new BN(10000),
+          new BN(Math.floor(Date.now() / 1000) - 3600),
+          { put: {} },
+          { european: {} },
+          usdc(5),
+          2000
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          underlyingPriceFeed,
+          solPriceFeed,
+          open: {
+            payer: buyer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: sellerAccount,
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(seller.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: sellerEscrow,
+            collateralMint: usdcMint,
+            ...tokenAccounts(contractPDA),
+            systemProgram: SystemProgram.programId,
+          },
+        })
+        .signers([buyer, seller])
+        .rpc()
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.collateralMint!.toString()).to.equal(usdcMint.toString())
+      expect(contract.collateralDecimals).to.equal(6)
+      
+      // 20% of 10 units at $100 is $200 of margin
+      expect(contract.marginAmount.toString()).to.equal(usdc(200).toString())
+      expect((await tokenBalance(getCollateralVaultPDA(contractPDA)[0])).toString()).to.equal(usdc(200).toString())
+      expect((await tokenBalance(buyerTokenEscrow)).toString()).to.equal(usdc(495).toString())
+      expect((await tokenBalance(sellerTokenEscrow)).toString()).to.equal(usdc(305).toString())
+      
+      // No lamports move for a token contract
+      expect(await provider.connection.getBalance(buyerEscrow)).to.equal(buyerEscrowLamportsBefore)
+    })
+    
+    it('should exercise without converting the payoff to SOL', async () => {
+      await program.methods
+        .exercise()
+        .accounts({
+          buyer: buyer.publicKey,
+          contract: contractPDA,
+          collateral: getCollateralPDA(contractPDA)[0],
+          sellerEscrow: sellerEscrow,
+          collateralVault: getCollateralVaultPDA(contractPDA)[0],
+          sellerTokenEscrow,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          ...(await getContractPagePDAs(contractPDA)),
+          underlyingPriceFeed,
+          solPriceFeed,
+        })
+        .signers([buyer])
+        .rpc()
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.status).to.deep.equal({ exercised: {} })
+      expect(contract.buyerPendingBalance.toString()).to.equal(usdc(100).toString())
+    })
+    
+    it('should settle in USDC and release the unused margin', async () => {
+      await program.methods
+        .settle()
+        .accounts({
+          caller: buyer.publicKey,
+          contract: contractPDA,
+          collateral: getCollateralPDA(contractPDA)[0],
+          ...(await getContractPagePDAs(contractPDA)),
+          buyerEscrow: buyerEscrow,
+          sellerEscrow: sellerEscrow,
+          ...tokenAccounts(contractPDA),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc()
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.status).to.deep.equal({ settled: {} })
+      expect((await tokenBalance(buyerTokenEscrow)).toString()).to.equal(usdc(595).toString())
+      expect((await tokenBalance(sellerTokenEscrow)).toString()).to.equal(usdc(405).toString())
+      expect((await tokenBalance(getCollateralVaultPDA(contractPDA)[0])).toString()).to.equal('0')
+    })
+    
+    it('should close the collateral vault with the contract', async () => {
+      await program.methods
+        .closeContract()
+        .accounts({
+          caller: buyer.publicKey,
+          rentPayer: buyer.publicKey,
+          contract: contractPDA,
+          collateral: getCollateralPDA(contractPDA)[0],
+          ...(await getContractPagePDAs(contractPDA)),
+          collateralVault: getCollateralVaultPDA(contractPDA)[0],
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc()
+      
+      expect(await provider.connection.getAccountInfo(contractPDA)).to.be.null
+      expect(await provider.connection.getAccountInfo(getCollateralVaultPDA(contractPDA)[0])).to.be.null
+    })
+    
+    it('should withdraw USDC from the token escrow', async () => {
+      await program.methods
+        .withdrawTokens(usdc(595))
+        .accounts({
+          user: buyer.publicKey,
+          userEscrow: buyerEscrow,
+          tokenEscrow: buyerTokenEscrow,
+          userTokenAccount: buyerTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc()
+      
+      expect((await tokenBalance(buyerTokenEscrow)).toString()).to.equal('0')
+      expect((await tokenBalance(buyerTokenAccount)).toString()).to.equal(usdc(1095).toString())
+    })
+  })
+
+  describe('Physical Settlement', () => {
+    let usdcMint: PublicKey
+    let underlyingMint: PublicKey
+    let underlyingPriceFeed: PublicKey
+    let contractPDA: PublicKey
+    const usdc = (amount: number) => new BN(amount * 1_000_000)
+    // One whole underlying token per unit, at 9 decimals
+    const shares = (amount: number) => new BN(amount).mul(new BN(1_000_000_000))
+    const tokenBalance = async (account: PublicKey) => (await getAccount(provider.connection, account)).amount
+    
+    const getUnderlyingVaultPDA = (contract: PublicKey): [PublicKey, number] => {
+      return PublicKey.findProgramAddressSync(
+        [Buffer.from('underlying_vault'), contract.toBuffer()],
+        program.programId
+      )
+    }
+    
+    // Opens a token escrow for `mint` and deposits `amount` into it
+    const fundTokenEscrow = async (user: Keypair, mint: PublicKey, amount: BN) => {
+      const payer = (provider.wallet as anchor.Wallet).payer
+      const userTokenAccount = await createAssociatedTokenAccount(provider.connection, payer, mint, user.publicKey)
+      await mintTo(provider.connection, payer, mint, userTokenAccount, payer, BigInt(amount.toString()))
+      
+      await program.methods
+        .initializeTokenEscrow()
+        .accounts({
+          user: user.publicKey,
+          userEscrow: getEscrowPDA(user.publicKey)[0],
+          mint,
+          tokenEscrow: getTokenEscrowPDA(user.publicKey, mint)[0],
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc()
+      
+      if (!amount.isZero()) {
+        await program.methods
+          .depositTokens(amount)
+          .accounts({
+            user: user.publicKey,
+            userTokenAccount,
+            tokenEscrow: getTokenEscrowPDA(user.publicKey, mint)[0],
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user])
+          .rpc()
+      }
+    }
+    
+    before(async () => {
+      const payer = (provider.wallet as anchor.Wallet).payer
+      usdcMint = await createMint(provider.connection, payer, payer.publicKey, null, 6)
+      underlyingMint = await createMint(provider.connection, payer, payer.publicKey, null, 9)
+      
+      await fundTokenEscrow(buyer, usdcMint, usdc(1500))
+      await fundTokenEscrow(seller, usdcMint, usdc(100))
+      await fundTokenEscrow(buyer, underlyingMint, new BN(0))
+      await fundTokenEscrow(seller, underlyingMint, shares(10))
+      
+      underlyingPriceFeed = await setPrice('TSLA', new BN(10000))
+    })
+    
+    it('should lock the seller underlying of a physical call', async () => {
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      ;[contractPDA] = getContractPDA(buyer.publicKey, seller.publicKey, buyerAccountData.contractCount.toNumber())
+      
+      await program.methods
+        .createContract(
+          'TSLA',
+          new BN(10),
+          new BN(10000),
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          { call: {} },
+          { american: {} },
+          usdc(50),
+          2000
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          underlyingPriceFeed,
+          solPriceFeed,
+          open: {
+            payer: buyer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: sellerAccount,
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(seller.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: sellerEscrow,
+            collateralMint: usdcMint,
+            collateralVault: getCollateralVaultPDA(contractPDA)[0],
+            buyerTokenEscrow: getTokenEscrowPDA(buyer.publicKey, usdcMint)[0],
+            sellerTokenEscrow: getTokenEscrowPDA(seller.publicKey, usdcMint)[0],
+            underlyingMint,
+            sellerUnderlyingEscrow: getTokenEscrowPDA(seller.publicKey, underlyingMint)[0],
+            underlyingVault: getUnderlyingVaultPDA(contractPDA)[0],
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          },
+        })
+        .signers([buyer, seller])
+        .rpc()
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.settlementKind).to.deep.equal({ physical: {} })
+      expect(contract.underlyingMint!.toString()).to.equal(underlyingMint.toString())
+      
+      // The underlying stands in for the margin
+      expect(contract.marginAmount.toString()).to.equal('0')
+      expect((await tokenBalance(getUnderlyingVaultPDA(contractPDA)[0])).toString()).to.equal(shares(10).toString())
+      expect((await tokenBalance(getTokenEscrowPDA(seller.publicKey, underlyingMint)[0])).toString()).to.equal('0')
+    })
+    
+    it('should reject cash exercise of a physical contract', async () => {
+      try {
+        await program.methods
+          .exercise()
+          .accounts({
+            buyer: buyer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            sellerEscrow: sellerEscrow,
+            collateralVault: getCollateralVaultPDA(contractPDA)[0],
+            sellerTokenEscrow: getTokenEscrowPDA(seller.publicKey, usdcMint)[0],
+            tokenProgram: TOKEN_PROGRAM_ID,
+            ...(await getContractPagePDAs(contractPDA)),
+            underlyingPriceFeed,
+            solPriceFeed,
+          })
+          .signers([buyer])
+          .rpc()
+        
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('SettlementKindMismatch')
+      }
+    })
+    
+    it('should deliver the underlying against the strike', async () => {
+      await program.methods
+        .exercisePhysical()
+        .accounts({
+          buyer: buyer.publicKey,
+          contract: contractPDA,
+          collateral: getCollateralPDA(contractPDA)[0],
+          ...(await getContractPagePDAs(contractPDA)),
+          buyerEscrow: buyerEscrow,
+          sellerEscrow: sellerEscrow,
+          collateralVault: getCollateralVaultPDA(contractPDA)[0],
+          buyerTokenEscrow: getTokenEscrowPDA(buyer.publicKey, usdcMint)[0],
+          sellerTokenEscrow: getTokenEscrowPDA(seller.publicKey, usdcMint)[0],
+          underlyingVault: getUnderlyingVaultPDA(contractPDA)[0],
+          buyerUnderlyingEscrow: getTokenEscrowPDA(buyer.publicKey, underlyingMint)[0],
+          sellerUnderlyingEscrow: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc()
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.status).to.deep.equal({ settled: {} })
+      
+      // 10 units at a $100 strike is $1,000, on top of the $50 premium
+      expect((await tokenBalance(getTokenEscrowPDA(buyer.publicKey, underlyingMint)[0])).toString()).to.equal(shares(10).toString())
+      expect((await tokenBalance(getTokenEscrowPDA(buyer.publicKey, usdcMint)[0])).toString()).to.equal(usdc(450).toString())
+      expect((await tokenBalance(getTokenEscrowPDA(seller.publicKey, usdcMint)[0])).toString()).to.equal(usdc(1150).toString())
+      expect((await tokenBalance(getUnderlyingVaultPDA(contractPDA)[0])).toString()).to.equal('0')
+    })
+    
+    it('should close the underlying vault with the contract', async () => {
+      await program.methods
+        .closeContract()
+        .accounts({
+          caller: buyer.publicKey,
+          rentPayer: buyer.publicKey,
+          contract: contractPDA,
+          collateral: getCollateralPDA(contractPDA)[0],
+          ...(await getContractPagePDAs(contractPDA)),
+          collateralVault: getCollateralVaultPDA(contractPDA)[0],
+          underlyingVault: getUnderlyingVaultPDA(contractPDA)[0],
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc()
+      
+      expect(await provider.connection.getAccountInfo(contractPDA)).to.be.null
+      expect(await provider.connection.getAccountInfo(getUnderlyingVaultPDA(contractPDA)[0])).to.be.null
+    })
+  })
+
+  describe('Option Strategies', () => {
+    let underlyingPriceFeed: PublicKey
+    let strategyPDA: PublicKey
+    const UNITS = new BN(10)
+    // Expired an hour ago so the strategy settles within its exercise window
+    const expirationDate = () => new BN(Math.floor(Date.now() / 1000) - 3600)
+    const bullCallSpread = [
+      { optionType: { call: {} }, side: { long: {} }, strikePrice: new BN(10000), numUnits: UNITS },
+      { optionType: { call: {} }, side: { short: {} }, strikePrice: new BN(11000), numUnits: UNITS },
+    ]
+    
+    const getStrategyPDA = (buyer: PublicKey, seller: PublicKey, strategyId: number): [PublicKey, number] => {
+      const idBuffer = Buffer.alloc(8)
+      idBuffer.writeBigUInt64LE(BigInt(strategyId))
+      
+      return PublicKey.findProgramAddressSync(
+        [Buffer.from('strategy'), buyer.toBuffer(), seller.toBuffer(), idBuffer],
+        program.programId
+      )
+    }
+    
+    const createStrategy = async (strategy: PublicKey, legs: typeof bullCallSpread) =>
+      program.methods
+        .createStrategy('NVDA', expirationDate(), legs, new BN(LAMPORTS_PER_SOL / 100), 2000)
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          strategy,
+          collateral: getCollateralPDA(strategy)[0],
+          buyerAccount: buyerAccount,
+          sellerAccount: sellerAccount,
+          buyerEscrow: buyerEscrow,
+          sellerEscrow: sellerEscrow,
+          collateralMint: null,
+          buyerTokenEscrow: null,
+          sellerTokenEscrow: null,
+          collateralVault: null,
+          treasury: getTreasuryPDA()[0],
+          treasuryVault: null,
+          tokenProgram: null,
+          underlyingPriceFeed,
+          solPriceFeed,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer, seller])
+        .rpc()
+    
+    const exerciseStrategy = async (caller: PublicKey, signers: Keypair[]) =>
+      program.methods
+        .exerciseStrategy()
+        .accounts({
+          caller,
+          strategy: strategyPDA,
+          collateral: getCollateralPDA(strategyPDA)[0],
+          buyerEscrow: buyerEscrow,
+          sellerEscrow: sellerEscrow,
+          collateralVault: null,
+          treasury: getTreasuryPDA()[0],
+          treasuryVault: null,
+          buyerTokenEscrow: null,
+          sellerTokenEscrow: null,
+          insuranceFund: null,
+          insuranceVault: null,
+          tokenProgram: null,
+          underlyingPriceFeed,
+          solPriceFeed,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers(signers)
+        .rpc()
+    
+    before(async () => {
+      underlyingPriceFeed = await setPrice('NVDA', new BN(10000))
+      await setPrice('SOL', new BN(10000))
+    })
+    
+    it('should reject a strategy with a single leg', async () => {
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      const [pendingStrategy] = getStrategyPDA(buyer.publicKey, seller.publicKey, buyerAccountData.contractCount.toNumber())
+      
+      try {
+        await createStrategy(pendingStrategy, bullCallSpread.slice(0, 1))
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('InvalidStrategyLegs')
+      }
+    })
+    
+    it('should margin a bull call spread at the spread width', async () => {
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      ;[strategyPDA] = getStrategyPDA(buyer.publicKey, seller.publicKey, buyerAccountData.contractCount.toNumber())
+      
+      await createStrategy(strategyPDA, bullCallSpread)
+      
+      // The short call caps the seller's loss at $10 a unit: $100, or 1 SOL at $100/SOL
+      const strategy = await program.account.optionStrategy.fetch(strategyPDA)
+      expect(strategy.status).to.deep.equal({ active: {} })
+      expect(strategy.legs.length).to.equal(2)
+      expect(strategy.sellerMargin.toNumber()).to.equal(LAMPORTS_PER_SOL)
+      expect(strategy.buyerMargin.toNumber()).to.equal(0)
+    })
+    
+    it('should reject settlement by anyone but the counterparties', async () => {
+      try {
+        await exerciseStrategy(provider.wallet.publicKey, [])
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('NotStrategyCounterparty')
+      }
+    })
+    
+    it('should pay the buyer the net payoff of every leg', async () => {
+      // At $125 both calls are in the money: $10 a unit on 10 units is 1 SOL at $100
+      await setPrice('NVDA', new BN(12500))
+      const buyerEscrowBefore = await provider.connection.getBalance(buyerEscrow)
+      
+      await exerciseStrategy(buyer.publicKey, [buyer])
+      
+      const strategy = await program.account.optionStrategy.fetch(strategyPDA)
+      expect(strategy.status).to.deep.equal({ settled: {} })
+      const buyerEscrowAfter = await provider.connection.getBalance(buyerEscrow)
+      expect(buyerEscrowAfter - buyerEscrowBefore).to.equal(LAMPORTS_PER_SOL)
+    })
+    
+    it('should close a settled strategy', async () => {
+      await program.methods
+        .closeStrategy()
+        .accounts({
+          caller: seller.publicKey,
+          rentPayer: buyer.publicKey,
+          strategy: strategyPDA,
+          collateral: getCollateralPDA(strategyPDA)[0],
+          collateralVault: null,
+          tokenProgram: null,
+        })
+        .signers([seller])
+        .rpc()
+      
+      expect(await provider.connection.getAccountInfo(strategyPDA)).to.be.null
+      expect(await provider.connection.getAccountInfo(getCollateralPDA(strategyPDA)[0])).to.be.null
+    })
+  })
+
+  describe('Events', () => {
+    let underlyingPriceFeed: PublicKey
+    
+    // Decodes the program events logged by a confirmed transaction
+    const eventsOf = async (signature: string) => {
+      await provider.connection.confirmTransaction(signature, 'confirmed')
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: 'confirmed',
+        maxSupportedTransactionVersion: 0,
+      })
+      const parser = new anchor.EventParser(program.programId, new anchor.BorshCoder(program.idl))
+      return Array.from(parser.parseLogs(tx!.meta!.logMessages!))
+    }
+    
+    before(async () => {
+      underlyingPriceFeed = await setPrice('ORCL', new BN(15000))
+      await setPrice('SOL', new BN(10000))
+    })
+    
+    it('should emit a deposit event', async () => {
+      const signature = await program.methods
+        .deposit(new BN(1000))
+        .accounts({
+          user: buyer.publicKey,
+          userEscrow: buyerEscrow,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc()
+      
+      const [event] = await eventsOf(signature)
+      expect(event.name).to.equal('depositEvent')
+      expect(event.data.user.toString()).to.equal(buyer.publicKey.toString())
+      expect(event.data.escrow.toString()).to.equal(buyerEscrow.toString())
+      expect(event.data.mint).to.be.null
+      expect(event.data.amount.toNumber()).to.equal(1000)
+    })
+    
+    it('should emit contract creation, exercise and settlement events', async () => {
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      const contractId = buyerAccountData.contractCount.toNumber()
+      const [contractPDA] = getContractPDA(buyer.publicKey, seller.publicKey, contractId)
+      
+      const createSignature = await program.methods
+        .createContract(
+          'ORCL',
+          new BN(1),
+          new BN(10000),
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          { call: {} },
+          { american: {} },
+          new BN(0.01 * LAMPORTS_PER_SOL),
+          2000
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          underlyingPriceFeed,
+          solPriceFeed,
+          open: {
+            payer: buyer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: sellerAccount,
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(seller.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: sellerEscrow,
+            systemProgram: SystemProgram.programId,
+          },
+        })
+        .signers([buyer, seller])
+        .rpc()
+      
+      const [created] = await eventsOf(createSignature)
+      expect(created.name).to.equal('contractCreatedEvent')
+      expect(created.data.contract.toString()).to.equal(contractPDA.toString())
+      expect(created.data.contractId.toNumber()).to.equal(contractId)
+      expect(created.data.buyer.toString()).to.equal(buyer.publicKey.toString())
+      expect(created.data.seller.toString()).to.equal(seller.publicKey.toString())
+      
+      const exerciseSignature = await program.methods
+        .exercise()
+        .accounts({
+          buyer: buyer.publicKey,
+          contract: contractPDA,
+          collateral: getCollateralPDA(contractPDA)[0],
+          sellerEscrow: sellerEscrow,
+          ...(await getContractPagePDAs(contractPDA)),
+          underlyingPriceFeed,
+          solPriceFeed,
+        })
+        .signers([buyer])
+        .rpc()
+      
+      // $150 against a $100 strike: $50, or 0.5 SOL at $100/SOL
+      const [exercised] = await eventsOf(exerciseSignature)
+      expect(exercised.name).to.equal('contractExercisedEvent')
+      expect(exercised.data.underlyingPrice.toNumber()).to.equal(15000)
+      expect(exercised.data.solPrice.toNumber()).to.equal(10000)
+      expect(exercised.data.payoffAmount.toNumber()).to.equal(0.5 * LAMPORTS_PER_SOL)
+      expect(exercised.data.status).to.deep.equal({ exercised: {} })
+      
+      const settleSignature = await program.methods
+        .settle()
+        .accounts({
+          caller: buyer.publicKey,
+          contract: contractPDA,
+          collateral: getCollateralPDA(contractPDA)[0],
+          ...(await getContractPagePDAs(contractPDA)),
+          buyerEscrow: buyerEscrow,
+          sellerEscrow: sellerEscrow,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc()
+      
+      // The payoff exceeds the 20% margin, so the rest comes from the seller escrow
+      const [settled] = await eventsOf(settleSignature)
+      expect(settled.name).to.equal('contractSettledEvent')
+      const margin = created.data.marginAmount.toNumber()
+      expect(settled.data.paidFromMargin.toNumber()).to.equal(margin)
+      expect(settled.data.paidFromSellerEscrow.toNumber()).to.equal(0.5 * LAMPORTS_PER_SOL - margin)
+      expect(settled.data.marginReleased.toNumber()).to.equal(0)
+    })
+  })
+
+  describe('Market Config', () => {
+    let newAdmin: Keypair
+    let underlyingPriceFeed: PublicKey
+    
+    const createWithTerms = async (marginBps: number, expirationDate: BN) => {
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      const [contractPDA] = getContractPDA(buyer.publicKey, seller.publicKey, buyerAccountData.contractCount.toNumber())
+      
+      await program.methods
+        .createContract(
+          'CSCO',
+          new BN(1),
+          new BN(5000),
+          expirationDate,
+          { call: {} },
+          { american: {} },
+          new BN(0.01 * LAMPORTS_PER_SOL),
+          marginBps
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          underlyingPriceFeed,
+          solPriceFeed,
+          open: {
+            payer: buyer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: sellerAccount,
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(seller.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: sellerEscrow,
+            systemProgram: SystemProgram.programId,
+          },
+        })
+        .signers([buyer, seller])
+        .rpc()
+      
+      return contractPDA
+    }
+    
+    const setPaused = async (paused: boolean, admin?: Keypair) =>
+      program.methods
+        .setMarketPaused(paused)
+        .accounts({
+          admin: admin?.publicKey ?? provider.wallet.publicKey,
+          marketConfig: getMarketConfigPDA()[0],
+        })
+        .signers(admin ? [admin] : [])
+        .rpc()
+    
+    const inOneDay = () => new BN(Math.floor(Date.now() / 1000) + 86400)
+    
+    before(async () => {
+      newAdmin = Keypair.generate()
+      await airdrop(newAdmin.publicKey, 1)
+      underlyingPriceFeed = await setPrice('CSCO', new BN(5000))
+      await setPrice('SOL', new BN(10000))
+    })
+    
+    it('should record the admin and limits', async () => {
+      const config = await program.account.marketConfig.fetch(getMarketConfigPDA()[0])
+      expect(config.admin.toString()).to.equal(provider.wallet.publicKey.toString())
+      expect(config.paused).to.be.false
+      expect(config.minMarginBps).to.equal(MIN_MARGIN_BPS)
+      expect(config.maxMarginBps).to.equal(MAX_MARGIN_BPS)
+    })
+    
+    it('should reject contracts outside the margin and expiry limits', async () => {
+      for (const marginBps of [MIN_MARGIN_BPS - 1, MAX_MARGIN_BPS + 1]) {
+        try {
+          await createWithTerms(marginBps, inOneDay())
+          expect.fail('Should have thrown an error')
+        } catch (error) {
+          expect(error.message).to.include('MarginOutOfRange')
+        }
+      }
+      
+      try {
+        await createWithTerms(2000, new BN(Math.floor(Date.now() / 1000) + 31 * 86400))
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('ExpiryOutOfRange')
+      }
+    })
+    
+    it('should only let the admin change the config', async () => {
+      try {
+        await setPaused(true, newAdmin)
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('UnauthorizedAdmin')
+      }
+      
+      try {
+        await program.methods
+          .updateMarketConfig(MAX_MARGIN_BPS, MIN_MARGIN_BPS, MIN_EXPIRY_SECONDS, MAX_EXPIRY_SECONDS)
+          .accounts({
+            admin: provider.wallet.publicKey,
+            marketConfig: getMarketConfigPDA()[0],
+          })
+          .rpc()
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('InvalidMarketConfig')
+      }
+    })
+    
+    it('should block contract creation and exercise while paused', async () => {
+      const contractPDA = await createWithTerms(2000, inOneDay())
+      await setPaused(true)
+      
+      try {
+        await createWithTerms(2000, inOneDay())
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('MarketPaused')
+      }
+      
+      try {
+        await program.methods
+          .exercise()
+          .accounts({
+            buyer: buyer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            sellerEscrow: sellerEscrow,
+            ...(await getContractPagePDAs(contractPDA)),
+            underlyingPriceFeed,
+            solPriceFeed,
+          })
+          .signers([buyer])
+          .rpc()
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('MarketPaused')
+      }
+      
+      await setPaused(false)
+      await createWithTerms(2000, inOneDay())
+    })
+    
+    it('should hand over the admin role in two steps', async () => {
+      await program.methods
+        .proposeAdmin(newAdmin.publicKey)
+        .accounts({
+          admin: provider.wallet.publicKey,
+          marketConfig: getMarketConfigPDA()[0],
+        })
+        .rpc()
+      
+      // Proposing does not hand over control until the new admin accepts
+      try {
+        await setPaused(true, newAdmin)
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('UnauthorizedAdmin')
+      }
+      
+      await program.methods
+        .acceptAdmin()
+        .accounts({
+          newAdmin: newAdmin.publicKey,
+          marketConfig: getMarketConfigPDA()[0],
+        })
+        .signers([newAdmin])
+        .rpc()
+      let config = await program.account.marketConfig.fetch(getMarketConfigPDA()[0])
+      expect(config.admin.toString()).to.equal(newAdmin.publicKey.toString())
+      expect(config.pendingAdmin).to.be.null
+      
+      // Hand the role back so later tests keep using the provider wallet
+      await program.methods
+        .proposeAdmin(provider.wallet.publicKey)
+        .accounts({
+          admin: newAdmin.publicKey,
+          marketConfig: getMarketConfigPDA()[0],
+        })
+        .signers([newAdmin])
+        .rpc()
+      await program.methods
+        .acceptAdmin()
+        .accounts({
+          newAdmin: provider.wallet.publicKey,
+          marketConfig: getMarketConfigPDA()[0],
+        })
+        .rpc()
+      config = await program.account.marketConfig.fetch(getMarketConfigPDA()[0])
+      expect(config.admin.toString()).to.equal(provider.wallet.publicKey.toString())
+    })
+  })
+
+  describe('Protocol Fees', () => {
+    let underlyingPriceFeed: PublicKey
+    let contractPDA: PublicKey
+    const premium = 0.1 * LAMPORTS_PER_SOL
+    const PREMIUM_FEE_BPS = 100
+    const SETTLEMENT_FEE_BPS = 200
+    
+    const setFees = async (premiumFeeBps: number, settlementFeeBps: number) =>
+      program.methods
+        .setProtocolFees(premiumFeeBps, settlementFeeBps)
+        .accounts({
+          admin: provider.wallet.publicKey,
+          marketConfig: getMarketConfigPDA()[0],
+        })
+        .rpc()
+    
+    // Lamports held by the treasury above its rent-exempt reserve
+    const treasuryBalance = async () => {
+      const treasuryInfo = await provider.connection.getAccountInfo(getTreasuryPDA()[0])
+      const rentReserve = await provider.connection.getMinimumBalanceForRentExemption(treasuryInfo!.data.length)
+      return treasuryInfo!.lamports - rentReserve
+    }
+    
+    before(async () => {
+      underlyingPriceFeed = await setPrice('QCOM', new BN(15000))
+      await setPrice('SOL', new BN(10000))
+      await setFees(PREMIUM_FEE_BPS, SETTLEMENT_FEE_BPS)
+    })
+    
+    after(async () => {
+      await setFees(0, 0)
+    })
+    
+    it('should reject fees above the maximum', async () => {
+      try {
+        await setFees(1001, 0)
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('FeeTooHigh')
+      }
+    })
+    
+    it('should take the premium fee when a contract is opened', async () => {
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      ;[contractPDA] = getContractPDA(buyer.publicKey, seller.publicKey, buyerAccountData.contractCount.toNumber())
+      const treasuryBefore = await treasuryBalance()
+      const sellerBefore = await provider.connection.getBalance(seller.publicKey)
+      
+      await program.methods
+        .createContract(
+          'QCOM',
+          new BN(1),
+          new BN(10000),
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          { call: {} },
+          { american: {} },
+          new BN(premium),
+          2000
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          underlyingPriceFeed,
+          solPriceFeed,
+          open: {
+            payer: buyer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: sellerAccount,
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(seller.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: sellerEscrow,
+            systemProgram: SystemProgram.programId,
+          },
+        })
+        .signers([buyer, seller])
+        .rpc()
+      
+      const premiumFee = (premium * PREMIUM_FEE_BPS) / 10000
+      expect((await treasuryBalance()) - treasuryBefore).to.equal(premiumFee)
+      expect((await provider.connection.getBalance(seller.publicKey)) - sellerBefore).to.equal(premium - premiumFee)
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.premiumFee.toNumber()).to.equal(premiumFee)
+      expect(contract.settlementFeeBps).to.equal(SETTLEMENT_FEE_BPS)
+    })
+    
+    it('should take the settlement fee out of the buyer payoff', async () => {
+      await program.methods
+        .exercise()
+        .accounts({
+          buyer: buyer.publicKey,
+          contract: contractPDA,
+          collateral: getCollateralPDA(contractPDA)[0],
+          sellerEscrow: sellerEscrow,
+          ...(await getContractPagePDAs(contractPDA)),
+          underlyingPriceFeed,
+          solPriceFeed,
+        })
+        .signers([buyer])
+        .rpc()
+      
+      const treasuryBefore = await treasuryBalance()
+      const buyerEscrowBefore = await provider.connection.getBalance(buyerEscrow)
+      
+      await program.methods
+        .settle()
+        .accounts({
+          caller: buyer.publicKey,
+          contract: contractPDA,
+          collateral: getCollateralPDA(contractPDA)[0],
+          ...(await getContractPagePDAs(contractPDA)),
+          buyerEscrow: buyerEscrow,
+          sellerEscrow: sellerEscrow,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc()
+      
+      // $150 against a $100 strike: $50, or 0.5 SOL at $100/SOL
+      const payoff = 0.5 * LAMPORTS_PER_SOL
+      const settlementFee = (payoff * SETTLEMENT_FEE_BPS) / 10000
+      expect((await treasuryBalance()) - treasuryBefore).to.equal(settlementFee)
+      expect((await provider.connection.getBalance(buyerEscrow)) - buyerEscrowBefore).to.equal(payoff - settlementFee)
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.settlementFee.toNumber()).to.equal(settlementFee)
+    })
+    
+    it('should move collected fees into the insurance fund', async () => {
+      const [insuranceFund] = PublicKey.findProgramAddressSync([Buffer.from('insurance_fund')], program.programId)
+      await program.methods
+        .initializeInsuranceFund()
+        .accounts({ payer: provider.wallet.publicKey })
+        .rpc()
+      
+      const treasuryBefore = await treasuryBalance()
+      const insuranceBefore = await provider.connection.getBalance(insuranceFund)
+      const amount = Math.floor(treasuryBefore / 2)
+      await program.methods
+        .fundInsurance(new BN(amount))
+        .accounts({
+          admin: provider.wallet.publicKey,
+          treasuryVault: null,
+          insuranceVault: null,
+          tokenProgram: null,
+        })
+        .rpc()
+      
+      expect(treasuryBefore - (await treasuryBalance())).to.equal(amount)
+      expect((await provider.connection.getBalance(insuranceFund)) - insuranceBefore).to.equal(amount)
+    })
+    
+    it('should only let the admin collect fees', async () => {
+      const destination = Keypair.generate().publicKey
+      const collectFees = (admin: PublicKey, amount: number) =>
+        program.methods
+          .collectFees(new BN(amount))
+          .accounts({
+            admin,
+            marketConfig: getMarketConfigPDA()[0],
+            treasury: getTreasuryPDA()[0],
+            destination,
+          })
+      
+      try {
+        await collectFees(buyer.publicKey, 1).signers([buyer]).rpc()
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('UnauthorizedAdmin')
+      }
+      
+      const collected = await treasuryBalance()
+      try {
+        await collectFees(provider.wallet.publicKey, collected + 1).rpc()
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('InsufficientTreasuryBalance')
+      }
+      
+      await collectFees(provider.wallet.publicKey, collected).rpc()
+      expect(await provider.connection.getBalance(destination)).to.equal(collected)
+      expect(await treasuryBalance()).to.equal(0)
+    })
+  })
+
+  describe('Early Termination', () => {
+    let underlyingPriceFeed: PublicKey
+    let contractPDA: PublicKey
+    
+    before(async () => {
+      underlyingPriceFeed = await setPrice('ORCL', new BN(12000))
+      await setPrice('SOL', new BN(10000))
+      
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      ;[contractPDA] = getContractPDA(buyer.publicKey, seller.publicKey, buyerAccountData.contractCount.toNumber())
+      await program.methods
+        .createContract(
+          'ORCL',
+          new BN(1),
+          new BN(12000),
+          new BN(Math.floor(Date.now() / 1000) + 86400),
+          { put: {} },
+          { american: {} },
+          new BN(0.01 * LAMPORTS_PER_SOL),
+          2000
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          underlyingPriceFeed,
+          solPriceFeed,
+          open: {
+            payer: buyer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: sellerAccount,
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(seller.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: sellerEscrow,
+            systemProgram: SystemProgram.programId,
+          },
+        })
+        .signers([buyer, seller])
+        .rpc()
+    })
+    
+    const terminate = async (payment: number, paidBy: object, signers: Keypair[]) =>
+      program.methods
+        .terminateContract(new BN(payment), paidBy)
+        .accounts({
+          buyer: signers[0].publicKey,
+          seller: signers[1].publicKey,
+          contract: contractPDA,
+          collateral: getCollateralPDA(contractPDA)[0],
+          ...(await getContractPagePDAs(contractPDA)),
+          buyerEscrow: buyerEscrow,
+          sellerEscrow: sellerEscrow,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers(signers)
+        .rpc()
+    
+    it('should require both the buyer and the seller to sign', async () => {
+      const stranger = Keypair.generate()
+      try {
+        await terminate(0, { buyer: {} }, [stranger, seller])
+        
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('UnauthorizedTermination')
+      }
+    })
+    
+    it('should pay the agreed amount and release the margin', async () => {
+      const contractBefore = await program.account.optionContract.fetch(contractPDA)
+      const buyerEscrowBefore = await provider.connection.getBalance(buyerEscrow)
+      const sellerEscrowBefore = await provider.connection.getBalance(sellerEscrow)
+      const payment = 0.005 * LAMPORTS_PER_SOL
+      
+      await terminate(payment, { buyer: {} }, [buyer, seller])
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.status).to.deep.equal({ terminated: {} })
+      expect(buyerEscrowBefore - (await provider.connection.getBalance(buyerEscrow))).to.equal(payment)
+      expect((await provider.connection.getBalance(sellerEscrow)) - sellerEscrowBefore).to.equal(
+        payment + contractBefore.marginAmount.toNumber()
+      )
+      
+      const buyerPage = await program.account.userContractPage.fetch(getUserPagePDA(buyer.publicKey, contract.buyerPage)[0])
+      const entry = buyerPage.contracts.find((c) => c.contractAddress.equals(contractPDA))
+      expect(entry!.status).to.deep.equal({ terminated: {} })
+    })
+    
+    it('should not terminate a contract twice', async () => {
+      try {
+        await terminate(0, { seller: {} }, [buyer, seller])
+        
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('ContractNotActive')
+      }
+    })
+  })
+
+  describe('Keeper Crank', () => {
+    let underlyingPriceFeed: PublicKey
+    const keeper = Keypair.generate()
+    const CRANK_FEE_BPS = 100
+    
+    const setCrankFee = async (crankFeeBps: number) =>
+      program.methods
+        .setCrankFee(crankFeeBps)
+        .accounts({
+          admin: provider.wallet.publicKey,
+          marketConfig: getMarketConfigPDA()[0],
+        })
+        .rpc()
+    
+    const createExpiredCall = async (strike: number, expirationDate: BN): Promise<PublicKey> => {
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      const [contractPDA] = getContractPDA(buyer.publicKey, seller.publicKey, buyerAccountData.contractCount.toNumber())
+      
+      await program.methods
+        .createContract(
+          'IBM',
+          new BN(10),
+          new BN(strike),
+          expirationDate,
+          { call: {} },
+          { european: {} },
+          new BN(0.01 * LAMPORTS_PER_SOL),
+          2000
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          underlyingPriceFeed,
+          solPriceFeed,
+          open: {
+            payer: buyer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: sellerAccount,
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(seller.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: sellerEscrow,
+            systemProgram: SystemProgram.programId,
+          },
+        })
+        .signers([buyer, seller])
+        .rpc()
+      
+      return contractPDA
+    }
+    
+    const crank = async (contractPDA: PublicKey) =>
+      program.methods
+        .crankExpiry()
+        .accounts({
+          caller: keeper.publicKey,
+          contract: contractPDA,
+          collateral: getCollateralPDA(contractPDA)[0],
+          ...(await getContractPagePDAs(contractPDA)),
+          buyerEscrow: buyerEscrow,
+          sellerEscrow: sellerEscrow,
+          collateralVault: null,
+          treasuryVault: null,
+          buyerTokenEscrow: null,
+          sellerTokenEscrow: null,
+          callerTokenAccount: null,
+          tokenProgram: null,
+          underlyingPriceFeed,
+          solPriceFeed,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([keeper])
+        .rpc()
+    
+    before(async () => {
+      underlyingPriceFeed = await setPrice('IBM', new BN(21000))
+      await setPrice('SOL', new BN(10000))
+      await setCrankFee(CRANK_FEE_BPS)
+      
+      const signature = await provider.connection.requestAirdrop(keeper.publicKey, LAMPORTS_PER_SOL)
+      await provider.connection.confirmTransaction(signature)
+    })
+    
+    after(async () => {
+      await setCrankFee(0)
+    })
+    
+    it('should leave in-the-money contracts to the buyer during the exercise window', async () => {
+      const contractPDA = await createExpiredCall(20000, new BN(Math.floor(Date.now() / 1000) - 3600))
+      
+      try {
+        await crank(contractPDA)
+        
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('GracePeriodNotElapsed')
+      }
+    })
+    
+    it('should exercise an in-the-money contract after the exercise window and pay the keeper', async () => {
+      const contractPDA = await createExpiredCall(20000, new BN(Math.floor(Date.now() / 1000) - 2 * 86400))
+      const contractBefore = await program.account.optionContract.fetch(contractPDA)
+      expect(contractBefore.crankFeeBps).to.equal(CRANK_FEE_BPS)
+      const buyerEscrowBefore = await provider.connection.getBalance(buyerEscrow)
+      const keeperBefore = await provider.connection.getBalance(keeper.publicKey)
+      
+      await crank(contractPDA)
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.status).to.deep.equal({ settled: {} })
+      expect(contract.unitsExercised.toNumber()).to.equal(10)
+      
+      // $10 a unit over 10 units at $100 a SOL is 1 SOL, capped at the margin
+      const payoff = LAMPORTS_PER_SOL
+      const bounty = Math.min((payoff * CRANK_FEE_BPS) / 10000, contractBefore.marginAmount.toNumber())
+      expect((await provider.connection.getBalance(keeper.publicKey)) - keeperBefore).to.equal(bounty)
+      expect((await provider.connection.getBalance(buyerEscrow)) - buyerEscrowBefore).to.equal(payoff - bounty)
+    })
+    
+    it('should leave out-of-the-money contracts to the exercise window', async () => {
+      const contractPDA = await createExpiredCall(22000, new BN(Math.floor(Date.now() / 1000) - 3600))
+      
+      try {
+        await crank(contractPDA)
+        
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('GracePeriodNotElapsed')
+      }
+    })
+    
+    it('should expire an out-of-the-money contract after the exercise window', async () => {
+      const contractPDA = await createExpiredCall(22000, new BN(Math.floor(Date.now() / 1000) - 2 * 86400))
+      const contractBefore = await program.account.optionContract.fetch(contractPDA)
+      const sellerEscrowBefore = await provider.connection.getBalance(sellerEscrow)
+      
+      await crank(contractPDA)
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.status).to.deep.equal({ expired: {} })
+      
+      const margin = contractBefore.marginAmount.toNumber()
+      const bounty = Math.floor((margin * CRANK_FEE_BPS) / 10000)
+      expect((await provider.connection.getBalance(sellerEscrow)) - sellerEscrowBefore).to.equal(margin - bounty)
+    })
+  })
+
+  describe('Withdraw Functionality', () => {
+    it('should allow buyer to withdraw from escrow', async () => {
+      const buyerWalletBefore = await provider.connection.getBalance(buyer.publicKey)
+      const withdrawAmount = new BN(0.5 * LAMPORTS_PER_SOL)
+      
+      await program.methods
+        .withdraw(withdrawAmount)
+        .accounts({
+          user: buyer.publicKey,
+          userEscrow: buyerEscrow,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc()
+      
+      const buyerWalletAfter = await provider.connection.getBalance(buyer.publicKey)
+      expect(buyerWalletAfter).to.be.greaterThan(buyerWalletBefore)
+    })
+    
+    it('should allow seller to withdraw from escrow', async () => {
+      const sellerWalletBefore = await provider.connection.getBalance(seller.publicKey)
+      const withdrawAmount = new BN(0.3 * LAMPORTS_PER_SOL)
+      
+      await program.methods
+        .withdraw(withdrawAmount)
+        .accounts({
+          user: seller.publicKey,
+          userEscrow: sellerEscrow,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([seller])
+        .rpc()
+      
+      const sellerWalletAfter = await provider.connection.getBalance(seller.publicKey)
+      expect(sellerWalletAfter).to.be.greaterThan(sellerWalletBefore)
+    })
+  })
+
+  describe('Error Cases', () => {
+    it('should fail when non-buyer tries to exercise', async () => {
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      const contractCount = buyerAccountData.contractCount.toNumber()
+      
+      const [contractPDA] = getContractPDA(buyer.publicKey, seller.publicKey, contractCount)
+      
+      const expirationDate = new BN(Math.floor(Date.now() / 1000) - 3600)
+      const underlyingPriceFeed = await setPrice('MSFT', new BN(32000))
       
       await program.methods
         .createContract(
@@ -583,31 +3242,42 @@ describe('OTC Options Trading', () => {
           new BN(30000),
           expirationDate,
           { call: {} },
+          { european: {} },
           new BN(0.1 * LAMPORTS_PER_SOL),
-          2000,
-          true
+          2000
         )
         .accounts({
           buyer: buyer.publicKey,
           seller: seller.publicKey,
-          contract: contractPDA,
-          buyerAccount: buyerAccount,
-          sellerAccount: sellerAccount,
-          buyerEscrow: buyerEscrow,
-          sellerEscrow: sellerEscrow,
-          systemProgram: SystemProgram.programId,
+          underlyingPriceFeed,
+          solPriceFeed,
+          open: {
+            payer: buyer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: sellerAccount,
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(seller.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: sellerEscrow,
+            systemProgram: SystemProgram.programId,
+          },
         })
-        .signers([buyer])
+        .signers([buyer, seller])
         .rpc()
       
       try {
         await program.methods
-          .exercise(new BN(32000), new BN(10000))
+          .exercise()
           .accounts({
             buyer: seller.publicKey,
             contract: contractPDA,
-            buyerAccount: buyerAccount,
-            sellerAccount: sellerAccount,
+            collateral: getCollateralPDA(contractPDA)[0],
+            sellerEscrow: sellerEscrow,
+            ...(await getContractPagePDAs(contractPDA)),
+            underlyingPriceFeed,
+            solPriceFeed,
           })
           .signers([seller])
           .rpc()
@@ -625,6 +3295,7 @@ describe('OTC Options Trading', () => {
       const [contractPDA] = getContractPDA(buyer.publicKey, seller.publicKey, contractCount)
       
       const expirationDate = new BN(Math.floor(Date.now() / 1000) - 3600)
+      const underlyingPriceFeed = await setPrice('AMZN', new BN(36000))
       
       await program.methods
         .createContract(
@@ -633,21 +3304,29 @@ describe('OTC Options Trading', () => {
           new BN(35000),
           expirationDate,
           { call: {} },
+          { european: {} },
           new BN(0.05 * LAMPORTS_PER_SOL),
-          2000,
-          true
+          2000
         )
         .accounts({
           buyer: buyer.publicKey,
           seller: seller.publicKey,
-          contract: contractPDA,
-          buyerAccount: buyerAccount,
-          sellerAccount: sellerAccount,
-          buyerEscrow: buyerEscrow,
-          sellerEscrow: sellerEscrow,
-          systemProgram: SystemProgram.programId,
+          underlyingPriceFeed,
+          solPriceFeed,
+          open: {
+            payer: buyer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: sellerAccount,
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(seller.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: sellerEscrow,
+            systemProgram: SystemProgram.programId,
+          },
         })
-        .signers([buyer])
+        .signers([buyer, seller])
         .rpc()
       
       try {
@@ -656,8 +3335,8 @@ describe('OTC Options Trading', () => {
           .accounts({
             caller: buyer.publicKey,
             contract: contractPDA,
-            buyerAccount: buyerAccount,
-            sellerAccount: sellerAccount,
+            collateral: getCollateralPDA(contractPDA)[0],
+            ...(await getContractPagePDAs(contractPDA)),
             buyerEscrow: buyerEscrow,
             sellerEscrow: sellerEscrow,
             systemProgram: SystemProgram.programId,
@@ -672,18 +3351,403 @@ describe('OTC Options Trading', () => {
     })
   })
 
+  describe('Offer and Acceptance Flow', () => {
+    let underlyingPriceFeed: PublicKey
+    const premium = new BN(0.02 * LAMPORTS_PER_SOL)
+    
+    const propose = async (
+      offerId: number,
+      validUntil = new BN(Math.floor(Date.now() / 1000) + 3600)
+    ): Promise<PublicKey> => {
+      const [offerPDA] = getOfferPDA(buyer.publicKey, seller.publicKey, offerId)
+      
+      await program.methods
+        .proposeContract(
+          new BN(offerId),
+          'NFLX',
+          new BN(10),
+          new BN(60000),
+          new BN(Math.floor(Date.now() / 1000) - 3600),
+          { call: {} },
+          { european: {} },
+          premium,
+          2000,
+          validUntil
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          offer: offerPDA,
+          buyerAccount: buyerAccount,
+          sellerAccount: sellerAccount,
+          underlyingPriceFeed,
+          solPriceFeed,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc()
+      
+      return offerPDA
+    }
+    
+    const accept = async (offerPDA: PublicKey, signer: Keypair, contractPDA: PublicKey) =>
+      program.methods
+        .acceptContract()
+        .accounts({
+          seller: signer.publicKey,
+          buyer: buyer.publicKey,
+          offer: offerPDA,
+          underlyingPriceFeed,
+          solPriceFeed,
+          open: {
+            payer: signer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: getUserAccountPDA(signer.publicKey)[0],
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(signer.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: getEscrowPDA(signer.publicKey)[0],
+            systemProgram: SystemProgram.programId,
+          },
+        })
+        .signers([signer])
+        .rpc()
+    
+    before(async () => {
+      underlyingPriceFeed = await setPrice('NFLX', new BN(65000))
+    })
+    
+    it('should store the proposed terms without moving the premium', async () => {
+      const buyerEscrowBefore = await provider.connection.getBalance(buyerEscrow)
+      const offerPDA = await propose(1)
+      
+      const offer = await program.account.optionOffer.fetch(offerPDA)
+      expect(offer.buyer.toString()).to.equal(buyer.publicKey.toString())
+      expect(offer.seller.toString()).to.equal(seller.publicKey.toString())
+      expect(offer.underlyingAsset).to.equal('NFLX')
+      expect(offer.premium.toString()).to.equal(premium.toString())
+      
+      const buyerEscrowAfter = await provider.connection.getBalance(buyerEscrow)
+      expect(buyerEscrowAfter).to.equal(buyerEscrowBefore)
+    })
+    
+    it('should fail when someone other than the seller accepts', async () => {
+      const [offerPDA] = getOfferPDA(buyer.publicKey, seller.publicKey, 1)
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      const [contractPDA] = getContractPDA(buyer.publicKey, buyer.publicKey, buyerAccountData.contractCount.toNumber())
+      
+      try {
+        await accept(offerPDA, buyer, contractPDA)
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('OfferPartyMismatch')
+      }
+    })
+    
+    it('should open an active contract once the seller accepts', async () => {
+      const [offerPDA] = getOfferPDA(buyer.publicKey, seller.publicKey, 1)
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      const contractCount = buyerAccountData.contractCount.toNumber()
+      const [contractPDA] = getContractPDA(buyer.publicKey, seller.publicKey, contractCount)
+      
+      const buyerEscrowBefore = await provider.connection.getBalance(buyerEscrow)
+      await accept(offerPDA, seller, contractPDA)
+      
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.status).to.deep.equal({ active: {} })
+      expect(contract.underlyingAsset).to.equal('NFLX')
+      expect(contract.contractId.toNumber()).to.equal(contractCount)
+      
+      const buyerEscrowAfter = await provider.connection.getBalance(buyerEscrow)
+      expect(buyerEscrowBefore - buyerEscrowAfter).to.equal(premium.toNumber())
+      
+      const offerInfo = await provider.connection.getAccountInfo(offerPDA)
+      expect(offerInfo).to.be.null
+    })
+    
+    it('should fail to accept an offer after it lapses', async () => {
+      const offerPDA = await propose(4, new BN(Math.floor(Date.now() / 1000) - 60))
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      const [contractPDA] = getContractPDA(buyer.publicKey, seller.publicKey, buyerAccountData.contractCount.toNumber())
+      
+      try {
+        await accept(offerPDA, seller, contractPDA)
+        
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('OfferExpired')
+      }
+      
+      await program.methods
+        .cancelOffer()
+        .accounts({
+          buyer: buyer.publicKey,
+          offer: offerPDA,
+        })
+        .signers([buyer])
+        .rpc()
+    })
+    
+    it('should close the offer when the seller rejects it', async () => {
+      const offerPDA = await propose(2)
+      
+      await program.methods
+        .rejectOffer()
+        .accounts({
+          seller: seller.publicKey,
+          buyer: buyer.publicKey,
+          offer: offerPDA,
+        })
+        .signers([seller])
+        .rpc()
+      
+      const offerInfo = await provider.connection.getAccountInfo(offerPDA)
+      expect(offerInfo).to.be.null
+    })
+    
+    it('should close the offer when the buyer cancels it', async () => {
+      const offerPDA = await propose(3)
+      
+      await program.methods
+        .cancelOffer()
+        .accounts({
+          buyer: buyer.publicKey,
+          offer: offerPDA,
+        })
+        .signers([buyer])
+        .rpc()
+      
+      const offerInfo = await provider.connection.getAccountInfo(offerPDA)
+      expect(offerInfo).to.be.null
+    })
+    
+    it('should fail to create a contract without the seller signature', async () => {
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      const [contractPDA] = getContractPDA(buyer.publicKey, seller.publicKey, buyerAccountData.contractCount.toNumber())
+      
+      try {
+        await program.methods
+          .createContract(
+            'NFLX',
+            new BN(10),
+            new BN(60000),
+            new BN(Math.floor(Date.now() / 1000) - 3600),
+            { call: {} },
+            { european: {} },
+            premium,
+            2000
+          )
+          .accounts({
+            buyer: buyer.publicKey,
+            seller: seller.publicKey,
+            underlyingPriceFeed,
+            solPriceFeed,
+            open: {
+              payer: buyer.publicKey,
+              contract: contractPDA,
+              collateral: getCollateralPDA(contractPDA)[0],
+              buyerAccount: buyerAccount,
+              sellerAccount: sellerAccount,
+              buyerPage: await getTailPagePDA(buyer.publicKey),
+              sellerPage: await getTailPagePDA(seller.publicKey),
+              buyerEscrow: buyerEscrow,
+              sellerEscrow: sellerEscrow,
+              systemProgram: SystemProgram.programId,
+            },
+          })
+          .signers([buyer])
+          .rpc()
+        
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('Signature verification failed')
+      }
+    })
+  })
+
+  describe('Oracle Price Validation', () => {
+    let contractPDA: PublicKey
+    let underlyingPriceFeed: PublicKey
+    
+    before(async () => {
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      const contractCount = buyerAccountData.contractCount.toNumber()
+      
+      ;[contractPDA] = getContractPDA(buyer.publicKey, seller.publicKey, contractCount)
+      underlyingPriceFeed = await setPrice('META', new BN(50000))
+      
+      await program.methods
+        .createContract(
+          'META',
+          new BN(10),
+          new BN(45000),
+          new BN(Math.floor(Date.now() / 1000) - 3600),
+          { call: {} },
+          { european: {} },
+          new BN(0.01 * LAMPORTS_PER_SOL),
+          2000
+        )
+        .accounts({
+          buyer: buyer.publicKey,
+          seller: seller.publicKey,
+          underlyingPriceFeed,
+          solPriceFeed,
+          open: {
+            payer: buyer.publicKey,
+            contract: contractPDA,
+            collateral: getCollateralPDA(contractPDA)[0],
+            buyerAccount: buyerAccount,
+            sellerAccount: sellerAccount,
+            buyerPage: await getTailPagePDA(buyer.publicKey),
+            sellerPage: await getTailPagePDA(seller.publicKey),
+            buyerEscrow: buyerEscrow,
+            sellerEscrow: sellerEscrow,
+            systemProgram: SystemProgram.programId,
+          },
+        })
+        .signers([buyer, seller])
+        .rpc()
+    })
+    
+    const exerciseWith = async (priceFeed: PublicKey) =>
+      program.methods
+        .exercise()
+        .accounts({
+          buyer: buyer.publicKey,
+          contract: contractPDA,
+          collateral: getCollateralPDA(contractPDA)[0],
+          sellerEscrow: sellerEscrow,
+          ...(await getContractPagePDAs(contractPDA)),
+          underlyingPriceFeed: priceFeed,
+          solPriceFeed,
+        })
+        .signers([buyer])
+        .rpc()
+    
+    it('should fail to exercise with a different price feed', async () => {
+      try {
+        await exerciseWith(getPriceFeedPDA('AAPL')[0])
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('PriceFeedMismatch')
+      }
+    })
+    
+    it('should fail to exercise with a stale price', async () => {
+      const publishTime = new BN(Math.floor(Date.now() / 1000) - 3600)
+      await setPrice('META', new BN(50000), { publishTime })
+      
+      try {
+        await exerciseWith(underlyingPriceFeed)
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('StaleOraclePrice')
+      }
+    })
+    
+    it('should fail to exercise with a price published in the future', async () => {
+      const publishTime = new BN(Math.floor(Date.now() / 1000) + 3600)
+      await setPrice('META', new BN(50000), { publishTime })
+      
+      try {
+        await exerciseWith(underlyingPriceFeed)
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('FutureOraclePrice')
+      }
+    })
+    
+    it('should fail to exercise with a wide confidence interval', async () => {
+      await setPrice('META', new BN(50000), { conf: new BN(5000) })
+      
+      try {
+        await exerciseWith(underlyingPriceFeed)
+        expect.fail('Should have thrown an error')
+      } catch (error) {
+        expect(error.message).to.include('OracleConfidenceTooWide')
+      }
+    })
+    
+    it('should exercise at the oracle price once the quote is valid', async () => {
+      await setPrice('META', new BN(50000))
+      await exerciseWith(underlyingPriceFeed)
+      
+      // ($500 - $450) * 10 units = $500 => 5 SOL at $100/SOL
+      const contract = await program.account.optionContract.fetch(contractPDA)
+      expect(contract.status).to.deep.equal({ exercised: {} })
+      expect(contract.buyerPendingBalance.toString()).to.equal(new BN(5 * LAMPORTS_PER_SOL).toString())
+    })
+  })
+
   describe('Contract Tracking', () => {
     it('should track contracts in user accounts', async () => {
-      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
-      expect(buyerAccountData.contracts.length).to.be.greaterThan(0)
+      const buyerContracts = await fetchUserContracts(buyer.publicKey)
+      expect(buyerContracts.length).to.be.greaterThan(0)
       
-      const sellerAccountData = await program.account.userAccount.fetch(sellerAccount)
-      expect(sellerAccountData.contracts.length).to.be.greaterThan(0)
+      const sellerContracts = await fetchUserContracts(seller.publicKey)
+      expect(sellerContracts.length).to.be.greaterThan(0)
     })
     
     it('should have correct contract count for buyer', async () => {
       const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
       expect(buyerAccountData.contractCount.toNumber()).to.be.greaterThan(0)
     })
+    
+    it('should allocate a new index page once the tail page is full', async () => {
+      const underlyingPriceFeed = await setPrice('QCOM', new BN(16000))
+      let contractPDA: PublicKey
+      
+      // Keep opening small contracts until the buyer's index spills onto a second page
+      while ((await program.account.userAccount.fetch(buyerAccount)).entryCount.toNumber() <= USER_PAGE_SIZE) {
+        const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+        ;[contractPDA] = getContractPDA(buyer.publicKey, seller.publicKey, buyerAccountData.contractCount.toNumber())
+        
+        await program.methods
+          .createContract(
+            'QCOM',
+            new BN(1),
+            new BN(15000),
+            new BN(Math.floor(Date.now() / 1000) + 86400),
+            { call: {} },
+            { european: {} },
+            new BN(1000),
+            2000
+          )
+          .accounts({
+            buyer: buyer.publicKey,
+            seller: seller.publicKey,
+            underlyingPriceFeed,
+            solPriceFeed,
+            open: {
+              payer: buyer.publicKey,
+              contract: contractPDA,
+              collateral: getCollateralPDA(contractPDA)[0],
+              buyerAccount: buyerAccount,
+              sellerAccount: sellerAccount,
+              buyerPage: await getTailPagePDA(buyer.publicKey),
+              sellerPage: await getTailPagePDA(seller.publicKey),
+              buyerEscrow: buyerEscrow,
+              sellerEscrow: sellerEscrow,
+              systemProgram: SystemProgram.programId,
+            },
+          })
+          .signers([buyer, seller])
+          .rpc()
+      }
+      
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      expect(buyerAccountData.pageCount).to.equal(2)
+      
+      const firstPage = await program.account.userContractPage.fetch(getUserPagePDA(buyer.publicKey, 0)[0])
+      expect(firstPage.contracts.length).to.be.at.most(USER_PAGE_SIZE)
+      
+      const contract = await program.account.optionContract.fetch(contractPDA!)
+      expect(contract.buyerPage).to.equal(1)
+      const secondPage = await program.account.userContractPage.fetch(getUserPagePDA(buyer.publicKey, 1)[0])
+      expect(secondPage.pageNo).to.equal(1)
+      expect(secondPage.contracts.some((c) => c.contractAddress.equals(contractPDA!))).to.be.true
+    })
   })
 })
@@ -1,6 +1,6 @@
 'use client'
 
-import { getBasicProgram, getBasicProgramId } from '@project/anchor'
+import { fetchUserContracts, getBasicProgram, getBasicProgramId } from '@project/anchor'
 import { useConnection, useWallet } from '@solana/wallet-adapter-react'
 import { Cluster, PublicKey, LAMPORTS_PER_SOL } from '@solana/web3.js'
 import { useMutation, useQuery } from '@tanstack/react-query'
@@ -68,9 +68,10 @@ export function useBasicProgram() {
   const getAllContracts = useQuery({
     queryKey: ['get-all-contracts', { cluster, publicKey: publicKey?.toString() }],
     queryFn: async () => {
-      if (!getUserAccount.data?.contracts) return []
+      if (!publicKey || !getUserAccount.data) return []
+      const userContracts = await fetchUserContracts(program, publicKey)
       const contracts = await Promise.all(
-        getUserAccount.data.contracts.map(async (uc) => {
+        userContracts.map(async (uc) => {
           try {
             const contractData = await program.account.optionContract.fetch(uc.contractAddress)
             return { address: uc.contractAddress, data: contractData, userRole: uc.role, userStatus: uc.status }
@@ -84,6 +85,25 @@ export function useBasicProgram() {
     enabled: !!getUserAccount.data,
   })
 
+  const getOffers = useQuery({
+    queryKey: ['get-offers', { cluster, publicKey: publicKey?.toString() }],
+    queryFn: async () => {
+      if (!publicKey) return { incoming: [], outgoing: [] }
+      // OptionOffer layout: discriminator (8) + bump (1) + offer_id (8) + buyer (32) + seller (32)
+      const [incoming, outgoing] = await Promise.all([
+        program.account.optionOffer.all([{ memcmp: { offset: 49, bytes: publicKey.toBase58() } }]),
+        program.account.optionOffer.all([{ memcmp: { offset: 17, bytes: publicKey.toBase58() } }]),
+      ])
+      return { incoming, outgoing }
+    },
+    enabled: !!publicKey,
+  })
+
+  const getQuotes = useQuery({
+    queryKey: ['get-quotes', { cluster }],
+    queryFn: () => program.account.quote.all(),
+  })
+
   const initializeUser = useMutation({
     mutationKey: ['initialize-user', { cluster }],
     mutationFn: async () => {
@@ -170,8 +190,8 @@ export function useBasicProgram() {
     },
   })
 
-  const createContract = useMutation({
-    mutationKey: ['create-contract', { cluster }],
+  const proposeContract = useMutation({
+    mutationKey: ['propose-contract', { cluster }],
     mutationFn: async (params: {
       seller: PublicKey
       underlyingAsset: string
@@ -179,49 +199,228 @@ export function useBasicProgram() {
       strikePrice: number
       expirationDate: number
       optionType: 'call' | 'put'
+      exerciseStyle: 'european' | 'american'
       premium: number
       marginRequirementBps: number
+      validUntil: number
+      underlyingPriceFeed: PublicKey
+      solPriceFeed: PublicKey
     }) => {
       if (!publicKey || !accounts) throw new Error('Wallet not connected')
 
       return program.methods
-        .createContract(
+        .proposeContract(
+          new BN(Date.now()),
           params.underlyingAsset,
           new BN(params.numUnits),
           new BN(params.strikePrice),
           new BN(params.expirationDate),
           params.optionType === 'call' ? { call: {} } : { put: {} },
+          params.exerciseStyle === 'american' ? { american: {} } : { european: {} },
           new BN(params.premium * LAMPORTS_PER_SOL),
           params.marginRequirementBps,
-          false // not test mode
+          new BN(params.validUntil)
         )
         .accounts({
           buyer: publicKey,
           seller: params.seller,
+          underlyingPriceFeed: params.underlyingPriceFeed,
+          solPriceFeed: params.solPriceFeed,
+        })
+        .rpc()
+    },
+    onSuccess: (signature) => {
+      transactionToast(signature)
+      getOffers.refetch()
+      toast.success('Offer sent to seller!')
+    },
+    onError: (error) => {
+      toast.error(`Failed to propose contract: ${error}`)
+    },
+  })
+
+  const acceptOffer = useMutation({
+    mutationKey: ['accept-offer', { cluster }],
+    mutationFn: async (params: { offer: PublicKey; buyer: PublicKey }) => {
+      if (!publicKey || !accounts) throw new Error('Wallet not connected')
+
+      const [buyerAccount] = PublicKey.findProgramAddressSync([Buffer.from('user'), params.buyer.toBuffer()], programId)
+      const buyerAccountData = await program.account.userAccount.fetch(buyerAccount)
+      const [contract] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from('contract'),
+          params.buyer.toBuffer(),
+          publicKey.toBuffer(),
+          buyerAccountData.contractCount.toArrayLike(Buffer, 'le', 8),
+        ],
+        programId
+      )
+
+      const offer = await program.account.optionOffer.fetch(params.offer)
+
+      return program.methods
+        .acceptContract()
+        .accounts({
+          seller: publicKey,
+          buyer: params.buyer,
+          offer: params.offer,
+          underlyingPriceFeed: offer.underlyingPriceFeed,
+          solPriceFeed: offer.solPriceFeed,
+          open: {
+            payer: publicKey,
+            contract,
+            buyerAccount,
+            sellerAccount: accounts.userAccount,
+          },
         })
         .rpc()
     },
     onSuccess: (signature) => {
       transactionToast(signature)
+      getOffers.refetch()
       getUserAccount.refetch()
       getAllContracts.refetch()
       getEscrowBalance.refetch()
       toast.success('Contract created successfully!')
     },
     onError: (error) => {
-      toast.error(`Failed to create contract: ${error}`)
+      toast.error(`Failed to accept offer: ${error}`)
+    },
+  })
+
+  const rejectOffer = useMutation({
+    mutationKey: ['reject-offer', { cluster }],
+    mutationFn: async (params: { offer: PublicKey; buyer: PublicKey }) => {
+      if (!publicKey) throw new Error('Wallet not connected')
+
+      return program.methods
+        .rejectOffer()
+        .accounts({
+          seller: publicKey,
+          buyer: params.buyer,
+          offer: params.offer,
+        })
+        .rpc()
+    },
+    onSuccess: (signature) => {
+      transactionToast(signature)
+      getOffers.refetch()
+      toast.success('Offer rejected')
+    },
+    onError: (error) => {
+      toast.error(`Failed to reject offer: ${error}`)
+    },
+  })
+
+  const cancelOffer = useMutation({
+    mutationKey: ['cancel-offer', { cluster }],
+    mutationFn: async (offer: PublicKey) => {
+      if (!publicKey) throw new Error('Wallet not connected')
+
+      return program.methods
+        .cancelOffer()
+        .accounts({
+          buyer: publicKey,
+          offer,
+        })
+        .rpc()
+    },
+    onSuccess: (signature) => {
+      transactionToast(signature)
+      getOffers.refetch()
+      toast.success('Offer cancelled')
+    },
+    onError: (error) => {
+      toast.error(`Failed to cancel offer: ${error}`)
+    },
+  })
+
+  const fillQuote = useMutation({
+    mutationKey: ['fill-quote', { cluster }],
+    mutationFn: async (params: { quote: PublicKey; seller: PublicKey; units: number }) => {
+      if (!publicKey || !accounts) throw new Error('Wallet not connected')
+
+      const buyerAccountData = await program.account.userAccount.fetch(accounts.userAccount)
+      const [contract] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from('contract'),
+          publicKey.toBuffer(),
+          params.seller.toBuffer(),
+          buyerAccountData.contractCount.toArrayLike(Buffer, 'le', 8),
+        ],
+        programId
+      )
+
+      const [sellerAccount] = PublicKey.findProgramAddressSync(
+        [Buffer.from('user'), params.seller.toBuffer()],
+        programId
+      )
+      const quote = await program.account.quote.fetch(params.quote)
+
+      return program.methods
+        .fillQuote(new BN(params.units))
+        .accounts({
+          buyer: publicKey,
+          seller: params.seller,
+          quote: params.quote,
+          underlyingPriceFeed: quote.underlyingPriceFeed,
+          solPriceFeed: quote.solPriceFeed,
+          open: {
+            payer: publicKey,
+            contract,
+            buyerAccount: accounts.userAccount,
+            sellerAccount,
+          },
+        })
+        .rpc()
+    },
+    onSuccess: (signature) => {
+      transactionToast(signature)
+      getQuotes.refetch()
+      getUserAccount.refetch()
+      getAllContracts.refetch()
+      getEscrowBalance.refetch()
+      toast.success('Quote filled!')
+    },
+    onError: (error) => {
+      toast.error(`Failed to fill quote: ${error}`)
+    },
+  })
+
+  const cancelQuote = useMutation({
+    mutationKey: ['cancel-quote', { cluster }],
+    mutationFn: async (quote: PublicKey) => {
+      if (!publicKey) throw new Error('Wallet not connected')
+
+      return program.methods
+        .cancelQuote()
+        .accounts({
+          seller: publicKey,
+          quote,
+        })
+        .rpc()
+    },
+    onSuccess: (signature) => {
+      transactionToast(signature)
+      getQuotes.refetch()
+      toast.success('Quote cancelled')
+    },
+    onError: (error) => {
+      toast.error(`Failed to cancel quote: ${error}`)
     },
   })
 
   const exercise = useMutation({
     mutationKey: ['exercise', { cluster }],
-    mutationFn: async (params: { contractAddress: PublicKey; underlyingPrice: number; solPrice: number }) => {
+    mutationFn: async (params: { contractAddress: PublicKey; underlyingPriceFeed: PublicKey; solPriceFeed: PublicKey }) => {
       if (!publicKey) throw new Error('Wallet not connected')
 
       return program.methods
-        .exercise(new BN(params.underlyingPrice), new BN(params.solPrice))
+        .exercise()
         .accounts({
           buyer: publicKey,
+          underlyingPriceFeed: params.underlyingPriceFeed,
+          solPriceFeed: params.solPriceFeed,
         })
         .remainingAccounts([{
           pubkey: params.contractAddress,
@@ -276,11 +475,18 @@ export function useBasicProgram() {
     getUserAccount,
     getEscrowBalance,
     getAllContracts,
+    getOffers,
+    getQuotes,
     initializeUser,
     initializeEscrow,
     deposit,
     withdraw,
-    createContract,
+    proposeContract,
+    acceptOffer,
+    rejectOffer,
+    cancelOffer,
+    fillQuote,
+    cancelQuote,
     exercise,
     settle,
   }
@@ -2,7 +2,7 @@
 
 import { useWallet } from '@solana/wallet-adapter-react'
 import { WalletButton } from '../solana/solana-provider'
-import { UserAccountStatus, EscrowManagement, CreateContractForm, ContractsList } from './basic-ui'
+import { UserAccountStatus, EscrowManagement, CreateContractForm, OffersList, QuotesList, ContractsList } from './basic-ui'
 import { AppHero } from '../app-hero'
 
 function ContractsHeaderWithActiveCount() {
@@ -29,6 +29,8 @@ export default function BasicFeature() {
           </div>
           <div className="lg:col-span-2 space-y-6 flex flex-col items-center">
             <CreateContractForm />
+            <OffersList />
+            <QuotesList />
             <div className="w-full flex flex-col items-center">
               <ContractsHeaderWithActiveCount />
             </div>
@@ -11,6 +11,12 @@ import { PublicKey, LAMPORTS_PER_SOL } from '@solana/web3.js'
 import { useWallet } from '@solana/wallet-adapter-react'
 import { ExplorerLink } from '../cluster/cluster-ui'
 import { ellipsify } from '@/lib/utils'
+import { getPriceFeedAddress } from '@project/anchor'
+
+// Strikes are stored in units of 10^priceExpo USD, as registered for the asset
+function formatStrike(strikePrice: { toNumber: () => number }, priceExpo: number) {
+  return (strikePrice.toNumber() * 10 ** priceExpo).toFixed(-priceExpo)
+}
 
 export function UserAccountStatus() {
   const { getUserAccount, getEscrowBalance, initializeUser, initializeEscrow } = useBasicProgram()
@@ -155,7 +161,7 @@ export function EscrowManagement() {
 }
 
 export function CreateContractForm() {
-  const { createContract } = useBasicProgram()
+  const { proposeContract } = useBasicProgram()
   const [formData, setFormData] = useState({
     seller: '',
     underlyingAsset: 'AAPL',
@@ -163,8 +169,10 @@ export function CreateContractForm() {
     strikePrice: '15000',
     expirationDays: '30',
     optionType: 'call' as 'call' | 'put',
+    exerciseStyle: 'european' as 'european' | 'american',
     premium: '0.5',
     marginRequirementBps: '2000',
+    oracleAuthority: process.env.NEXT_PUBLIC_ORACLE_AUTHORITY ?? '',
   })
 
   const handleSubmit = async (e: React.FormEvent) => {
@@ -172,20 +180,29 @@ export function CreateContractForm() {
     
     try {
       const sellerPubkey = new PublicKey(formData.seller)
+      const oracleAuthority = new PublicKey(formData.oracleAuthority)
       // Use client time only on client
       let expirationDate = 0
+      let validUntil = 0
       if (typeof window !== 'undefined') {
-        expirationDate = Math.floor(Date.now() / 1000) + parseInt(formData.expirationDays) * 86400
+        const now = Math.floor(Date.now() / 1000)
+        expirationDate = now + parseInt(formData.expirationDays) * 86400
+        // The seller has a day to accept the offer
+        validUntil = now + 86400
       }
-      await createContract.mutateAsync({
+      await proposeContract.mutateAsync({
         seller: sellerPubkey,
         underlyingAsset: formData.underlyingAsset,
         numUnits: parseInt(formData.numUnits),
         strikePrice: parseInt(formData.strikePrice),
         expirationDate,
         optionType: formData.optionType,
+        exerciseStyle: formData.exerciseStyle,
         premium: parseFloat(formData.premium),
         marginRequirementBps: parseInt(formData.marginRequirementBps),
+        validUntil,
+        underlyingPriceFeed: getPriceFeedAddress(oracleAuthority, formData.underlyingAsset),
+        solPriceFeed: getPriceFeedAddress(oracleAuthority, 'SOL'),
       })
 
       // Reset form
@@ -206,7 +223,7 @@ export function CreateContractForm() {
     <Card>
       <CardHeader>
         <CardTitle>Create Option Contract</CardTitle>
-        <CardDescription>Create a new call or put option contract</CardDescription>
+        <CardDescription>Offer a call or put option contract; it opens once the seller accepts</CardDescription>
       </CardHeader>
       <CardContent>
         <form onSubmit={handleSubmit} className="space-y-4">
@@ -286,6 +303,19 @@ export function CreateContractForm() {
               </select>
             </div>
 
+            <div className="space-y-2">
+              <Label htmlFor="exerciseStyle">Exercise Style</Label>
+              <select
+                id="exerciseStyle"
+                value={formData.exerciseStyle}
+                onChange={(e) => updateField('exerciseStyle', e.target.value)}
+                className="flex h-9 w-full rounded-md border border-input bg-transparent px-3 py-1 text-sm shadow-sm transition-colors"
+              >
+                <option value="european">European (at expiry)</option>
+                <option value="american">American (any time)</option>
+              </select>
+            </div>
+
             <div className="space-y-2">
               <Label htmlFor="premium">Premium (SOL)</Label>
               <Input
@@ -309,10 +339,21 @@ export function CreateContractForm() {
                 required
               />
             </div>
+
+            <div className="space-y-2">
+              <Label htmlFor="oracleAuthority">Oracle Authority</Label>
+              <Input
+                id="oracleAuthority"
+                placeholder="Price feed publisher's public key"
+                value={formData.oracleAuthority}
+                onChange={(e) => updateField('oracleAuthority', e.target.value)}
+                required
+              />
+            </div>
           </div>
 
-          <Button type="submit" disabled={createContract.isPending} className="w-full">
-            {createContract.isPending ? 'Creating Contract...' : 'Create Contract'}
+          <Button type="submit" disabled={proposeContract.isPending} className="w-full">
+            {proposeContract.isPending ? 'Sending Offer...' : 'Send Offer'}
           </Button>
         </form>
       </CardContent>
@@ -320,10 +361,133 @@ export function CreateContractForm() {
   )
 }
 
+export function OffersList() {
+  const { getOffers, acceptOffer, rejectOffer, cancelOffer } = useBasicProgram()
+  const incoming = getOffers.data?.incoming ?? []
+  const outgoing = getOffers.data?.outgoing ?? []
+
+  if (incoming.length === 0 && outgoing.length === 0) return null
+
+  return (
+    <Card className="w-full">
+      <CardHeader>
+        <CardTitle>Pending Offers</CardTitle>
+        <CardDescription>Offers waiting for the seller to accept</CardDescription>
+      </CardHeader>
+      <CardContent className="space-y-3">
+        {incoming.map(({ publicKey: offer, account }) => (
+          <div key={offer.toString()} className="flex items-center justify-between gap-2">
+            <p className="text-sm">
+              {account.optionType.call ? 'Call' : 'Put'} {account.underlyingAsset} x{account.numUnits.toString()} @ $
+              {formatStrike(account.strikePrice, account.priceExpo)} from {ellipsify(account.buyer.toString())}
+            </p>
+            <div className="flex gap-2">
+              <Button
+                onClick={() => acceptOffer.mutateAsync({ offer, buyer: account.buyer })}
+                disabled={acceptOffer.isPending}
+                size="sm"
+              >
+                Accept
+              </Button>
+              <Button
+                onClick={() => rejectOffer.mutateAsync({ offer, buyer: account.buyer })}
+                disabled={rejectOffer.isPending}
+                size="sm"
+                variant="outline"
+              >
+                Reject
+              </Button>
+            </div>
+          </div>
+        ))}
+        {outgoing.map(({ publicKey: offer, account }) => (
+          <div key={offer.toString()} className="flex items-center justify-between gap-2">
+            <p className="text-sm">
+              {account.optionType.call ? 'Call' : 'Put'} {account.underlyingAsset} x{account.numUnits.toString()} @ $
+              {formatStrike(account.strikePrice, account.priceExpo)} to {ellipsify(account.seller.toString())}
+            </p>
+            <Button
+              onClick={() => cancelOffer.mutateAsync(offer)}
+              disabled={cancelOffer.isPending}
+              size="sm"
+              variant="outline"
+            >
+              Cancel
+            </Button>
+          </div>
+        ))}
+      </CardContent>
+    </Card>
+  )
+}
+
+export function QuotesList() {
+  const { getQuotes, fillQuote, cancelQuote } = useBasicProgram()
+  const { publicKey } = useWallet()
+  const [fillUnits, setFillUnits] = useState<Record<string, string>>({})
+  const now = Date.now() / 1000
+  const quotes = (getQuotes.data ?? []).filter(({ account }) => account.validUntil.toNumber() > now)
+
+  if (quotes.length === 0) return null
+
+  return (
+    <Card className="w-full">
+      <CardHeader>
+        <CardTitle>Open Quotes</CardTitle>
+        <CardDescription>Standing asks from sellers, filled at the quoted premium per unit</CardDescription>
+      </CardHeader>
+      <CardContent className="space-y-3">
+        {quotes.map(({ publicKey: quote, account }) => {
+          const key = quote.toString()
+          const isOwnQuote = !!publicKey && account.seller.equals(publicKey)
+          return (
+            <div key={key} className="flex items-center justify-between gap-2">
+              <p className="text-sm">
+                {account.optionType.call ? 'Call' : 'Put'} {account.underlyingAsset} @ $
+                {formatStrike(account.strikePrice, account.priceExpo)},{' '}
+                {(account.premiumPerUnit.toNumber() / LAMPORTS_PER_SOL).toFixed(4)} SOL/unit,{' '}
+                {account.remainingUnits.toString()} left from {ellipsify(account.seller.toString())}
+              </p>
+              {isOwnQuote ? (
+                <Button
+                  onClick={() => cancelQuote.mutateAsync(quote)}
+                  disabled={cancelQuote.isPending}
+                  size="sm"
+                  variant="outline"
+                >
+                  Cancel
+                </Button>
+              ) : (
+                <div className="flex gap-2">
+                  <Input
+                    type="number"
+                    min="1"
+                    className="w-20"
+                    value={fillUnits[key] ?? '1'}
+                    onChange={(e) => setFillUnits({ ...fillUnits, [key]: e.target.value })}
+                  />
+                  <Button
+                    onClick={() =>
+                      fillQuote.mutateAsync({ quote, seller: account.seller, units: parseInt(fillUnits[key] ?? '1') })
+                    }
+                    disabled={fillQuote.isPending}
+                    size="sm"
+                  >
+                    Fill
+                  </Button>
+                </div>
+              )}
+            </div>
+          )
+        })}
+      </CardContent>
+    </Card>
+  )
+}
+
 export function ContractsList() {
   const { getAllContracts, exercise, settle } = useBasicProgram()
   const [selectedContract, setSelectedContract] = useState<string | null>(null)
-  const [exerciseData, setExerciseData] = useState({ underlyingPrice: '', solPrice: '10000' })
 
   if (getAllContracts.isLoading) {
     return {
@@ -364,24 +528,23 @@ export function ContractsList() {
     return typeof window !== 'undefined' && Date.now() >= expirationDateObj.getTime()
   }
 
-  const handleExercise = async (contractAddress: PublicKey) => {
-    const underlying = parseFloat(exerciseData.underlyingPrice)
-    const sol = parseFloat(exerciseData.solPrice)
-    if (isNaN(underlying) || isNaN(sol)) return
-
+  const handleExercise = async (contractAddress: PublicKey, underlyingPriceFeed: PublicKey, solPriceFeed: PublicKey) => {
     await exercise.mutateAsync({
       contractAddress,
-      underlyingPrice: underlying,
-      solPrice: sol,
+      underlyingPriceFeed,
+      solPriceFeed,
     })
     setSelectedContract(null)
-    setExerciseData({ underlyingPrice: '', solPrice: '10000' })
   }
 
-  const getStatusBadge = (status: { active?: unknown; exercised?: unknown; settled?: unknown }) => {
+  const getStatusBadge = (status: { active?: unknown; exercised?: unknown; settled?: unknown; expired?: unknown; liquidated?: unknown; defaulted?: unknown; terminated?: unknown }) => {
     if (status.active) return <span className="px-2 py-1 text-xs rounded-full bg-green-100 text-green-800 dark:bg-green-900 dark:text-green-200">Active</span>
     if (status.exercised) return <span className="px-2 py-1 text-xs rounded-full bg-yellow-100 text-yellow-800 dark:bg-yellow-900 dark:text-yellow-200">Exercised</span>
     if (status.settled) return <span className="px-2 py-1 text-xs rounded-full bg-blue-100 text-blue-800 dark:bg-blue-900 dark:text-blue-200">Settled</span>
+    if (status.expired) return <span className="px-2 py-1 text-xs rounded-full bg-gray-100 text-gray-800 dark:bg-gray-900 dark:text-gray-200">Expired</span>
+    if (status.liquidated) return <span className="px-2 py-1 text-xs rounded-full bg-red-100 text-red-800 dark:bg-red-900 dark:text-red-200">Liquidated</span>
+    if (status.defaulted) return <span className="px-2 py-1 text-xs rounded-full bg-red-100 text-red-800 dark:bg-red-900 dark:text-red-200">Defaulted</span>
+    if (status.terminated) return <span className="px-2 py-1 text-xs rounded-full bg-gray-100 text-gray-800 dark:bg-gray-900 dark:text-gray-200">Terminated</span>
     return <span className="px-2 py-1 text-xs rounded-full bg-gray-100 text-gray-800 dark:bg-gray-900 dark:text-gray-200">Unknown</span>
   }
 
@@ -389,16 +552,17 @@ export function ContractsList() {
     activeCount,
     element: (
       <div className="space-y-4">
-        {contracts.map((contract: { address: PublicKey; data: { optionType: { call?: unknown; put?: unknown }; strikePrice: { toNumber: () => number }; numUnits: { toString: () => string }; premium: { toNumber: () => number }; expirationDate: { toNumber: () => number }; status: { active?: unknown; exercised?: unknown; settled?: unknown }; sellerPendingBalance: { toNumber: () => number }; underlyingAsset: string }; userRole: { buyer?: unknown; seller?: unknown } } | null, idx: number) => {
+        {contracts.map((contract: { address: PublicKey; data: { optionType: { call?: unknown; put?: unknown }; exerciseStyle: { european?: unknown; american?: unknown }; strikePrice: { toNumber: () => number }; priceExpo: number; numUnits: { toString: () => string }; premium: { toNumber: () => number }; expirationDate: { toNumber: () => number }; status: { active?: unknown; exercised?: unknown; settled?: unknown; expired?: unknown; liquidated?: unknown; defaulted?: unknown; terminated?: unknown }; sellerPendingBalance: { toNumber: () => number }; requiredMargin: { toNumber: () => number }; marginCallDeadline: { toNumber: () => number } | null; underlyingAsset: string; underlyingPriceFeed: PublicKey; solPriceFeed: PublicKey }; userRole: { buyer?: unknown; seller?: unknown } } | null, idx: number) => {
           if (!contract) return null
           const { address, data, userRole } = contract
           const isBuyer = userRole.buyer !== undefined
           const optionType = data.optionType.call ? 'Call' : 'Put'
-          const strikePrice = data.strikePrice.toNumber() / 100
+          const isAmerican = data.exerciseStyle.american !== undefined
+          const strikePrice = formatStrike(data.strikePrice, data.priceExpo)
           const premium = data.premium.toNumber() / LAMPORTS_PER_SOL
           const expirationDateObj = new Date(data.expirationDate.toNumber() * 1000)
           const isExpired = isContractExpired(contract)
-          const canExercise = !!(isBuyer && data.status.active && isExpired)
+          const canExercise = !!(isBuyer && data.status.active && (isAmerican || isExpired))
           const canSettle = !!data.status.exercised
 
           return (
@@ -411,7 +575,9 @@ export function ContractsList() {
                         {isBuyer ? '📈 Buyer' : '📉 Seller'}
                       </span>
                       <span className="text-lg">{data.underlyingAsset}</span>
-                      <span className="text-sm font-normal text-muted-foreground">{optionType}</span>
+                      <span className="text-sm font-normal text-muted-foreground">
+                        {isAmerican ? 'American' : 'European'} {optionType}
+                      </span>
                     </CardTitle>
                     <CardDescription>
                       <ExplorerLink path={`account/${address.toString()}`} label={ellipsify(address.toString())} />
@@ -424,7 +590,7 @@ export function ContractsList() {
                 <div className="grid grid-cols-2 md:grid-cols-4 gap-4 text-sm">
                   <div>
                     <p className="text-muted-foreground">Strike Price</p>
-                    <p className="font-medium">${strikePrice.toFixed(2)}</p>
+                    <p className="font-medium">${strikePrice}</p>
                   </div>
                   <div>
                     <p className="text-muted-foreground">Units</p>
@@ -454,6 +620,16 @@ export function ContractsList() {
                   </div>
                 )}
 
+                {data.status.active !== undefined && data.marginCallDeadline && (
+                  <div className="bg-red-50 dark:bg-red-950 p-3 rounded-md text-sm">
+                    <p className="font-medium mb-1">Margin Call</p>
+                    <p className="text-muted-foreground" suppressHydrationWarning>
+                      Required margin: {(data.requiredMargin.toNumber() / LAMPORTS_PER_SOL).toFixed(4)} SOL, due{' '}
+                      {new Date(data.marginCallDeadline.toNumber() * 1000).toLocaleString()}
+                    </p>
+                  </div>
+                )}
+
                 <div className="flex gap-2">
                   {canExercise && selectedContract !== address.toString() && (
                     <Button
@@ -466,22 +642,9 @@ export function ContractsList() {
 
                   {canExercise && selectedContract === address.toString() && (
                     <div className="flex gap-2 w-full">
-                      <Input
-                        placeholder="Underlying price (cents)"
-                        type="number"
-                        value={exerciseData.underlyingPrice}
-                        onChange={(e) => setExerciseData({ ...exerciseData, underlyingPrice: e.target.value })}
-                        size={1}
-                      />
-                      <Input
-                        placeholder="SOL price (cents)"
-                        type="number"
-                        value={exerciseData.solPrice}
-                        onChange={(e) => setExerciseData({ ...exerciseData, solPrice: e.target.value })}
-                        size={1}
-                      />
+                      <p className="text-sm text-muted-foreground self-center">Settle at the current oracle price?</p>
                       <Button
-                        onClick={() => handleExercise(address)}
+                        onClick={() => handleExercise(address, data.underlyingPriceFeed, data.solPriceFeed)}
                         disabled={exercise.isPending}
                         size="sm"
                       >